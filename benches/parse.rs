@@ -0,0 +1,74 @@
+//! Parsing markdown into body ranges and building spans out of them run for every message on
+//! every sync, so they're measured on long messages with lots of formatting.
+#![expect(unused_crate_dependencies)]
+
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use foghorn_widgets as widget;
+use iced::{Font, advanced::text::Span};
+use std::hint::black_box;
+
+#[expect(dead_code)]
+#[path = "../src/parse.rs"]
+mod parse;
+
+/// The chat cache only looks up the names of mentions, which the benchmarks give directly.
+mod cache {
+    use presage::libsignal_service::protocol::ServiceId;
+
+    #[derive(Debug)]
+    pub struct Contact {
+        pub name: String,
+    }
+
+    #[derive(Debug)]
+    pub struct ChatCache;
+
+    impl ChatCache {
+        pub fn contact(&self, _id: ServiceId) -> Option<Contact> {
+            None
+        }
+    }
+}
+
+/// A message of about 12 kB, with every style, mentions and links.
+fn markdown() -> String {
+    "**Bold** and *italic* with ~~struck~~ `code`, a ||spoiler|| and ***nested **markers*** \
+     for [@Alice](mention:5a4b8a0a-7d93-4f5c-9c43-5e0f3d8d2f11), see https://example.org/path.\n"
+        .repeat(75)
+}
+
+fn parsing(c: &mut Criterion) {
+    let markdown = markdown();
+    let (body, ranges) = parse::markdown_to_body_ranges(&markdown);
+    let spans =
+        parse::body_ranges_to_signal_spans_with(Some(&body), &ranges, |_| Some("Alice".to_owned()))
+            .unwrap();
+
+    c.bench_function("markdown_to_body_ranges", |b| {
+        b.iter(|| parse::markdown_to_body_ranges(black_box(&markdown)));
+    });
+
+    c.bench_function("body_ranges_to_signal_spans", |b| {
+        b.iter(|| {
+            parse::body_ranges_to_signal_spans_with(black_box(Some(&body)), &ranges, |_| {
+                Some("Alice".to_owned())
+            })
+        });
+    });
+
+    c.bench_function("signal_spans_to_spans", |b| {
+        b.iter_batched(
+            || spans.clone(),
+            |spans| {
+                spans
+                    .into_iter()
+                    .map(Span::<'_, String, Font>::from)
+                    .collect::<Vec<_>>()
+            },
+            BatchSize::SmallInput,
+        );
+    });
+}
+
+criterion_group!(benches, parsing);
+criterion_main!(benches);
@@ -1,5 +1,46 @@
-// generated by `sqlx migrate build-script`
+// generated by `sqlx migrate build-script`, with the Lucide icons added
+use std::{env, fmt::Write as _, fs, path::Path};
+
 fn main() {
     // trigger recompilation when a new migration is added
     println!("cargo:rerun-if-changed=migrations");
+
+    lucide_codepoints();
+}
+
+/// Writes the codepoints of every Lucide icon as constants named like the icons, from the
+/// metadata `just font` downloads along with the font.
+fn lucide_codepoints() {
+    println!("cargo:rerun-if-changed=Lucide.json");
+
+    let info = fs::read_to_string("Lucide.json")
+        .expect("Lucide.json is missing, `just font` downloads it");
+    let info = serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(&info)
+        .expect("Lucide.json isn't the font's info.json");
+
+    let mut codepoints = String::new();
+
+    for (name, icon) in info {
+        let Some(codepoint) = icon["encodedCode"]
+            .as_str()
+            .and_then(|code| code.strip_prefix('\\'))
+            .and_then(|code| u32::from_str_radix(code, 16).ok())
+        else {
+            continue;
+        };
+
+        // raw identifiers, since some icons are named like keywords, such as `box`
+        writeln!(
+            codepoints,
+            "pub const r#{}: char = '\\u{{{codepoint:x}}}';",
+            name.replace('-', "_")
+        )
+        .unwrap();
+    }
+
+    fs::write(
+        Path::new(&env::var("OUT_DIR").unwrap()).join("lucide.rs"),
+        codepoints,
+    )
+    .unwrap();
 }
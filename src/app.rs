@@ -1,295 +1,2986 @@
 use crate::{
+    avatars::Avatars,
+    backoff::Retry,
+    browser,
+    calendar::Calendar,
+    chat_menu::{ChatMenu, ChatSearch},
+    chat_set::ChatSet,
+    composer::{self, Composer, Draft},
+    config::Config,
+    crash,
+    deep_link::DeepLink,
     dialog::{Action, Dialog},
-    icons::edit,
-    log::warn,
-    manager_manager::{ManagerError, ManagerManager},
-    message::{self, SignalAction},
-    parse::body_ranges_to_markdown,
+    export,
+    gallery::{self, Gallery, Shared},
+    group_details::GroupDetails,
+    idle,
+    image::{self, Image, Staged, Upload},
+    instance::Request,
+    lightbox::Lightbox,
+    log::{debug, info, warn},
+    manager_manager::{ManagerError, ManagerManager, ManagerManagerError},
+    member_card::MemberCard,
+    message::{self, MessageId, SignalAction},
+    notification::{Notifier, Shown},
+    parse::{
+        body_ranges_to_markdown_with, body_ranges_to_signal_spans_with, markdown_to_body_ranges,
+        mentioned, signal_spans_to_markdown,
+    },
+    platform,
+    receipts::{MessageDetails, Receipts},
+    safety,
+    session::Session,
+    stickers::{InstalledPack, Stickers},
+    typing::{TYPING_TIMEOUT, Typing},
+    widget::SignalSpan,
 };
 use iced::{
-    Center, Element,
+    Center, Element, Event,
     Length::Fill,
-    Subscription, Task, border,
-    futures::channel::oneshot,
-    keyboard, padding,
-    time::every,
+    Subscription, Task, border, clipboard, event,
+    futures::{
+        Stream, StreamExt as _,
+        channel::{mpsc, oneshot},
+        future::try_join_all,
+        stream,
+    },
+    keyboard, padding, task, time,
     widget::{
-        button, column, container,
-        operation::{RelativeOffset, focus_next, snap_to},
-        qr_code, responsive, row, rule, scrollable, space, text, text_editor,
+        button, center, column, container,
+        operation::{RelativeOffset, focus, focus_next, focus_previous, snap_to},
+        qr_code, responsive, row, rule, scrollable, space, stack, text, text_input,
     },
+    window,
 };
 use iced_split::{Strategy, vertical_split};
-use jiff::{Timestamp, tz::TimeZone};
-use notify_rust::Notification;
-use presage::libsignal_service::{prelude::Uuid, provisioning::ProvisioningError};
+use jiff::{Timestamp, civil::Date, tz::TimeZone};
+use presage::{
+    libsignal_service::{
+        prelude::Uuid, protocol::ServiceId, provisioning::ProvisioningError,
+        zkgroup::GroupMasterKeyBytes,
+    },
+    proto::AttachmentPointer,
+};
 use std::{
     cmp::Reverse,
-    collections::{BTreeMap, HashMap},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    fs, io,
     mem::take,
+    path::PathBuf,
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
-use tokio::task::spawn_blocking;
+use tokio::{task::spawn_blocking, time::sleep};
 
 #[derive(Clone, Debug)]
 pub enum Message {
     ManagerError(Option<Arc<ManagerError>>),
+    Error(Arc<ManagerManagerError>),
     QrCode(String),
     LinkSecondary,
+    /// A second passed while the QR code to link with is shown.
+    LinkingTick(Instant),
+    CancelLinking,
+    ReportCrash,
+    DismissCrash,
+    DatabaseDamaged(String),
+    RecreateDatabase,
     Received((message::Chat, SignalAction)),
+    ReceivedBatch(Vec<(message::Chat, SignalAction)>),
+    /// The receipts of our messages received in earlier runs.
+    ReceiptsLoaded(Vec<(ServiceId, message::Receipt)>),
     CloseDialog,
     Now(Timestamp),
     Tz(TimeZone),
     OpenChat(message::Chat),
     NextChat,
     PreviousChat,
+    HighlightNext,
+    HighlightPrevious,
+    OpenHighlighted,
+    OpenNth(usize),
     Mention(Uuid),
+    CloseMemberCard,
+    MessageMember,
+    ShowSafetyNumber(ServiceId),
+    SafetyNumber(ServiceId, Option<String>),
+    LinkHovered(Option<String>),
+    LinkClicked(String),
+    OpenLink,
+    OpenDeepLink(String),
     Quote(Option<Arc<message::Message>>),
+    /// The text selected in a message changed, cleared when it's empty.
+    Selected(Arc<message::Message>, Vec<SignalSpan<'static, String>>),
+    RevealSpoiler(Arc<message::Message>, usize),
+    CopySelection,
+    CopySelectionAsMarkdown,
+    QuoteSelection,
+    QuickReact(Arc<message::Message>),
+    React(Arc<message::Message>, String),
     Edit(Option<Arc<message::Message>>),
+    ShowDetails(Arc<message::Message>),
+    CloseDetails,
     EditLast,
+    FocusOlder,
+    FocusNewer,
+    QuoteFocused,
+    EditFocused,
+    /// Shows the raw metadata of the focused message, for debugging.
+    InfoFocused,
+    FocusNext,
+    FocusPrevious,
     Escape,
     SplitAt(f32),
-    ContentEdit(text_editor::Action),
+    Composer(composer::Message),
     Send,
+    DownloadAttachments(Arc<message::Message>),
+    AttachmentDownloaded(message::Chat, MessageId, usize, Image),
+    QuoteThumbnailDownloaded(message::Chat, MessageId, usize, Image),
+    OpenLightbox(Arc<message::Message>, usize),
+    CloseLightbox,
+    LightboxNext,
+    LightboxPrevious,
+    NoteToSelf,
+    SendToNoteToSelf(Option<String>),
+    ShowContacts(bool),
+    ContactQuery(String),
+    OpenStickers,
+    StickerPacks(Vec<InstalledPack>),
+    CloseStickers,
+    InstallStickerPack(Vec<u8>, Vec<u8>),
+    UninstallStickerPack(Vec<u8>),
+    ToggleCalendar,
+    CalendarPrevious,
+    CalendarNext,
+    JumpToDate(Date),
+    ShowEarlier,
+    ToggleChatMenu,
+    ShowGroupDetails,
+    CloseGroupDetails,
+    ShowGallery,
+    CloseGallery,
+    GalleryTab(gallery::Tab),
+    /// What was shared in a chat was collected for the gallery.
+    Shared(message::Chat, Shared),
+    GalleryMore,
+    GalleryImage(message::Chat, usize, Image),
+    /// Closes the gallery and focuses the message sent at a time.
+    JumpToMessage(Timestamp),
+    CopyInviteLink(String),
+    ToggleMute,
+    SearchInChat,
+    ChatSearchQuery(String),
+    ChatSearch,
+    ChatSearchResults(Vec<Timestamp>),
+    ChatSearchOlder,
+    ChatSearchNewer,
+    CloseChatSearch,
+    SetTimer(u32),
+    ExportChat(export::Format),
+    Exported(Result<PathBuf, Arc<io::Error>>),
+    ClearHistory,
+    ConfirmClearHistory,
+    HistoryCleared(message::Chat),
+    RequestContacts,
+    /// Requesting the contacts failed, so they can be requested again.
+    ContactsRequestFailed(Arc<ManagerManagerError>),
+    ResyncGroups,
+    ResyncChat,
+    PickAttachments,
+    AttachFile(PathBuf),
+    SaveAttachment(message::Attachment),
+    SaveAttachmentFailed(Arc<io::Error>),
+    OpenAttachment(message::Attachment),
+    ConfirmOpenAttachment,
+    OpenAttachmentFailed(Arc<io::Error>),
+    Staged(Staged),
+    Sent(Option<MessageId>, (message::Chat, SignalAction)),
+    Retrying(message::Chat, Retry),
+    /// Sending failed, with what was written if it should go back into the composer.
+    SendFailed(
+        message::Chat,
+        Option<MessageId>,
+        Option<Draft>,
+        Arc<ManagerManagerError>,
+    ),
+    /// Signal wants a captcha solved before sending, with its token and the message to send
+    /// again once it's solved.
+    Challenged(message::Chat, Option<MessageId>, String, Draft),
+    /// Sends a message of the open chat again that failed to send.
+    RetryUnsent(MessageId),
+    /// Drops a message of the open chat that failed to send.
+    DiscardUnsent(MessageId),
+    OpenCaptcha,
+    PasteCaptcha,
+    /// The clipboard was read for the link the solved captcha opens.
+    SubmitCaptcha(Option<String>),
+    CaptchaSolved,
+    DismissSendState,
+    /// Lets the composer of a contact who left Signal be used again, in case they're back.
+    SendAnyway,
+    NotifierDetected(Notifier),
+    Notify,
+    Notified(message::Chat, Option<u32>),
+    /// A notification was closed, with the reply typed into it.
+    NotificationClosed(message::Chat, u32, Option<String>),
+    TypingExpired,
+    MarkRead,
+    AvatarAnimated(ServiceId, image::Animation),
+    AvatarFrame(Instant),
+    Rescaled(f32),
+    /// The open chat was scrolled, to the relative offset from the top.
+    Scrolled(f32),
+    WindowFocused(bool),
+    /// The window is being closed, which waits for the store writes to be flushed.
+    CloseRequested,
+    /// The screen saver started or stopped, telling whether the user is away.
+    Idle(bool),
+    Raise,
+}
+
+/// How sending to a chat is going, while it isn't going smoothly.
+#[derive(Debug)]
+enum SendState {
+    Retrying(Retry),
+    Failed(Arc<ManagerManagerError>),
+}
+
+/// A message Signal wouldn't send, or a profile it wouldn't fetch, before a captcha is solved.
+#[derive(Debug)]
+struct Challenged {
+    token: String,
+    chat: message::Chat,
+    /// The message to send again, and the pending one it replaces. There's none when fetching
+    /// the profile of the chat's contact was challenged.
+    resend: Option<(Option<MessageId>, Draft)>,
 }
 
+impl Challenged {
+    fn dialog(&self, problem: Option<&str>) -> Dialog {
+        let (title, hint) = if self.resend.is_some() {
+            ("Solve a challenge to keep sending", CAPTCHA_HINT)
+        } else {
+            ("Solve a challenge to see profiles", PROFILE_CAPTCHA_HINT)
+        };
+
+        let hint = match problem {
+            Some(problem) => format!("{hint}\n\n{problem}"),
+            None => hint.to_owned(),
+        };
+
+        Dialog::new(title, hint, None, Action::SolveCaptcha)
+    }
+}
+
+/// Where captchas are solved, which then opens a link with [`CAPTCHA_SCHEME`].
+const CAPTCHA_URL: &str = "https://signalcaptchas.org/challenge/generate.html";
+
+const CAPTCHA_SCHEME: &str = "signalcaptcha://";
+
+const CAPTCHA_HINT: &str = "Signal wants to make sure you're not sending spam before sending more messages.\n\n\
+    Open the challenge in your browser and solve it. Instead of following the \"Open Signal\" link it \
+    ends with, copy the link, then paste it here to send the message again.";
+
+const PROFILE_CAPTCHA_HINT: &str = "Signal wants to make sure you're not a bot before showing more profiles.\n\n\
+    Open the challenge in your browser and solve it. Instead of following the \"Open Signal\" link it \
+    ends with, copy the link, then paste it here to fetch the profiles again.";
+
+/// About how long Signal waits for a QR code to be scanned, before a new one is needed.
+const QR_CODE_LIFETIME: Duration = Duration::from_secs(90);
+
+/// How long linking waits before trying again when Signal's servers can't be reached,
+/// doubling with every failure up to `LINK_RETRY_MAX`.
+const LINK_RETRY_BASE: Duration = Duration::from_secs(2);
+
+const LINK_RETRY_MAX: Duration = Duration::from_secs(60);
+
+/// The maximum number of received messages applied in a single update.
+const RECEIVED_BATCH_SIZE: usize = 256;
+
+/// The number of recently opened chats whose images are kept in memory.
+const RESIDENT_CHATS: usize = 4;
+
+/// How long read receipts for messages arriving in the open chat wait for more, so they're
+/// sent together.
+const READ_RECEIPT_DELAY: Duration = Duration::from_secs(2);
+
+/// The number of messages whose reactions are kept until they're decoded. Reactions to messages
+/// that never come, like deleted ones, would otherwise pile up.
+const PENDING_REACTIONS: usize = 1024;
+
 pub struct App {
     manager_manager: ManagerManager,
     dialog: Dialog,
-    chats: HashMap<message::Chat, BTreeMap<Timestamp, Arc<message::Message>>>,
+    lightbox: Lightbox,
+    stickers: Stickers,
+    details: MessageDetails,
+    member_card: MemberCard,
+    calendar: Calendar,
+    chat_menu: ChatMenu,
+    group_details: GroupDetails,
+    gallery: Gallery,
+    chat_search: ChatSearch,
+    muted: ChatSet,
+    session: Session,
+    chats: HashMap<message::Chat, BTreeMap<MessageId, Arc<message::Message>>>,
+    avatars: Avatars,
+    receipts: Receipts,
+    send_states: HashMap<message::Chat, SendState>,
+    /// What was written for the shown messages that failed to send, to send them again.
+    unsent: HashMap<MessageId, Draft>,
+    challenged: Option<Challenged>,
+    /// The contacts that aren't on Signal anymore, whose chats can only be read.
+    unregistered: ChatSet,
+    /// The chat picked in the chat list with the arrow keys, to be opened with Enter.
+    highlighted: Option<message::Chat>,
+    typing: Typing,
+    /// The messages to notify about once the current batch is applied.
+    unnotified: Vec<(message::Chat, Arc<message::Message>)>,
+    /// The notification shown for each chat until it's opened, with how many messages it
+    /// summarizes.
+    notifications: HashMap<message::Chat, (Option<u32>, usize)>,
+    notifier: Notifier,
+    /// The messages received since each chat was last seen, by sender and timestamp.
+    unread: HashMap<message::Chat, Vec<(ServiceId, Timestamp)>>,
+    window_focused: bool,
+    /// Whether the screen saver is on, while nobody reads or needs to be interrupted.
+    idle: bool,
     now: Option<Timestamp>,
     tz: Option<TimeZone>,
     open_chat: Option<message::Chat>,
-    message_content: text_editor::Content,
-    quote: Option<message::Quote>,
-    editing: Option<Timestamp>,
+    /// Where the open chat's history starts, after jumping to a day.
+    history_from: Option<Timestamp>,
+    /// The messages mentioning contacts that aren't known yet.
+    unresolved_mentions: HashMap<ServiceId, Vec<(message::Chat, MessageId)>>,
+    /// Whether the left pane lists all contacts instead of the conversations.
+    show_contacts: bool,
+    /// Whether the contacts were requested again from the empty chat list.
+    contacts_requested: bool,
+    contact_query: String,
+    /// The recently opened chats, most recent first.
+    resident_chats: VecDeque<message::Chat>,
+    composer: Composer,
+    /// The message keyboard actions apply to, moved through with Alt+Up and Alt+Down.
+    focused_message: Option<MessageId>,
+    /// The message with selected text, and the parts of its spans that are selected.
+    selection: Option<(Arc<message::Message>, Vec<SignalSpan<'static, String>>)>,
+    hovered_link: Option<String>,
+    /// The link waiting to be confirmed before it's opened.
+    pending_link: Option<String>,
+    /// The attachment that could run code, waiting to be confirmed before it's opened.
+    pending_attachment: Option<message::Attachment>,
+    opened_attachments_dir: PathBuf,
+    /// The group of an invite link opened before the groups were loaded.
+    pending_invite: Option<GroupMasterKeyBytes>,
+    browser: Option<String>,
+    /// When the QR code to link with was shown, while it is.
+    qr_code_shown: Option<Instant>,
+    /// Whether linking was cancelled, so expired QR codes aren't replaced anymore.
+    linking_cancelled: bool,
+    /// The linking in progress, aborted when it's cancelled or started again.
+    linking: Option<task::Handle>,
+    /// How often in a row Signal's servers couldn't be reached to link with, which the next
+    /// attempt waits longer for.
+    link_failures: u32,
+    /// Whether animated avatars are kept still.
+    reduced_motion: bool,
+    sealed_sender_icons: bool,
+    reaction_notifications: bool,
+    read_receipts: bool,
+    /// Whether read receipts for the messages arriving in the open chat are about to be sent.
+    marking_read: bool,
+    /// The chats with reactions to our messages that weren't seen yet, marked with a dot.
+    reacted: HashSet<message::Chat>,
+    /// The scale factor of the window, which animated avatars are decoded for.
+    scale_factor: f32,
+    /// The emoji double clicking a message reacts with.
+    quick_reaction: String,
+    /// The reactions to messages that weren't decoded yet, by chat and message, with when the
+    /// first one came.
+    pending_reactions: HashMap<
+        (message::Chat, MessageId),
+        (Instant, Vec<(Arc<message::Contact>, Option<String>)>),
+    >,
+    crash_report: Option<String>,
     split_at: f32,
 }
 
-impl App {
-    pub fn create() -> (Self, Task<Message>) {
-        let manager_manager = ManagerManager::default();
-        let register = manager_manager.clone().load_registered();
+impl App {
+    pub fn create(
+        config: Config,
+        requests: Option<mpsc::UnboundedReceiver<Request>>,
+        link: Option<String>,
+    ) -> (Self, Task<Message>) {
+        let crash_report = crash::take_report(&config.state_dir());
+        let composer = Composer::new(&config);
+        let browser = config.browser.clone();
+        let quick_reaction = config.quick_reaction.clone();
+        let reduced_motion = config.reduced_motion;
+        let sealed_sender_icons = config.sealed_sender_icons;
+        let reaction_notifications = config.reaction_notifications;
+        let read_receipts = config.read_receipts;
+        let muted = ChatSet::load(config.muted_path(), "muted");
+        let unregistered = ChatSet::load(config.unregistered_path(), "unregistered");
+        let session = Session::load(config.session_path());
+        let opened_attachments_dir = config.opened_attachments_dir();
+        safety::remove_opened(&opened_attachments_dir);
+        let notifications = config.notifications;
+        let manager_manager = ManagerManager::new(config);
+
+        // linking replaces the dialog, so it waits until the crash report was dealt with
+        let (dialog, register) = match &crash_report {
+            Some(report) => (
+                Dialog::new(
+                    "Foghorn crashed the last time it ran",
+                    report.clone(),
+                    None,
+                    Action::ReportCrash,
+                )
+                .monospace(),
+                Task::none(),
+            ),
+            None => (Dialog::default(), Self::load_registered(&manager_manager)),
+        };
+
+        (
+            Self {
+                manager_manager,
+                dialog,
+                lightbox: Lightbox::default(),
+                stickers: Stickers::default(),
+                details: MessageDetails::default(),
+                member_card: MemberCard::default(),
+                calendar: Calendar::default(),
+                chat_menu: ChatMenu::default(),
+                group_details: GroupDetails::default(),
+                gallery: Gallery::default(),
+                chat_search: ChatSearch::default(),
+                muted,
+                session,
+                chats: HashMap::new(),
+                avatars: Avatars::default(),
+                receipts: Receipts::default(),
+                send_states: HashMap::new(),
+                unsent: HashMap::new(),
+                challenged: None,
+                unregistered,
+                highlighted: None,
+                typing: Typing::default(),
+                unnotified: vec![],
+                notifications: HashMap::new(),
+                // nothing is notified about before the queue is emptied, long after detection
+                notifier: Notifier::None,
+                unread: HashMap::new(),
+                window_focused: true,
+                idle: false,
+                now: None,
+                tz: None,
+                open_chat: None,
+                history_from: None,
+                unresolved_mentions: HashMap::new(),
+                show_contacts: false,
+                contacts_requested: false,
+                contact_query: String::new(),
+                resident_chats: VecDeque::new(),
+                composer,
+                focused_message: None,
+                selection: None,
+                hovered_link: None,
+                pending_link: None,
+                pending_attachment: None,
+                opened_attachments_dir,
+                pending_invite: None,
+                browser,
+                qr_code_shown: None,
+                linking_cancelled: false,
+                linking: None,
+                link_failures: 0,
+                reduced_motion,
+                sealed_sender_icons,
+                reaction_notifications,
+                read_receipts,
+                marking_read: false,
+                reacted: HashSet::new(),
+                scale_factor: 1.,
+                quick_reaction,
+                pending_reactions: HashMap::new(),
+                crash_report,
+                split_at: 313.5,
+            },
+            Task::batch([
+                Task::perform(async { TimeZone::system() }, Message::Tz),
+                Task::perform(async { Timestamp::now() }, Message::Now),
+                Task::perform(Notifier::detect(notifications), Message::NotifierDetected),
+                window::latest()
+                    .and_then(window::scale_factor)
+                    .map(Message::Rescaled),
+                register,
+                requests.map_or_else(Task::none, |requests| {
+                    Task::run(requests, |request| match request {
+                        Request::Raise => Message::Raise,
+                        Request::Open(link) => Message::OpenDeepLink(link),
+                    })
+                }),
+                link.map_or_else(Task::none, |link| Task::done(Message::OpenDeepLink(link))),
+            ]),
+        )
+    }
+
+    /// Decodes the frames of an avatar at the window's scale factor, if it's animated.
+    fn animate_avatar(&self, id: ServiceId, bytes: Vec<u8>) -> Task<Message> {
+        if self.reduced_motion {
+            return Task::none();
+        }
+
+        let size = (image::avatar_size() * self.scale_factor).ceil() as u32;
+
+        Task::future(image::Animation::decode(bytes, size))
+            .and_then(move |animation| Task::done(Message::AvatarAnimated(id, animation)))
+    }
+
+    /// Sends read receipts for the open chat's unread messages, once the window is focused,
+    /// unless they were turned off.
+    fn mark_read(&mut self) -> Task<Message> {
+        let Some(unread) = self
+            .open_chat
+            .as_ref()
+            .filter(|_| self.window_focused && !self.idle)
+            .and_then(|chat| self.unread.remove(chat))
+            .filter(|_| self.read_receipts)
+        else {
+            return Task::none();
+        };
+
+        let mut read = Vec::<(ServiceId, Vec<u64>)>::new();
+
+        for (sender, timestamp) in unread {
+            let timestamp = timestamp.as_millisecond() as u64;
+
+            match read.iter_mut().find(|(id, _)| *id == sender) {
+                Some((_, timestamps)) => timestamps.push(timestamp),
+                None => read.push((sender, vec![timestamp])),
+            }
+        }
+
+        Task::future(self.manager_manager.clone().send_read_receipts(read)).then(|result| {
+            if let Err(err) = result {
+                warn!("Failed to send read receipts: {err}");
+            }
+
+            Task::none()
+        })
+    }
+
+    /// Remembers the messages mentioning contacts that aren't known yet, so their names can be
+    /// filled in once they are.
+    fn track_mentions(&mut self, chat: &message::Chat, message: &message::Message) {
+        let ranges = message
+            .body_ranges
+            .iter()
+            .chain(message.quote.iter().flat_map(|quote| &quote.body_ranges));
+
+        for id in ranges.filter_map(mentioned) {
+            let known = self
+                .chats
+                .keys()
+                .any(|chat| chat.contact().is_some_and(|contact| contact.id == id));
+
+            if !known {
+                self.unresolved_mentions
+                    .entry(id)
+                    .or_default()
+                    .push((chat.clone(), message.id()));
+            }
+        }
+    }
+
+    /// Renders the messages mentioning a contact that just became known again.
+    fn resolve_mentions(&mut self, id: ServiceId) {
+        let Some(pending) = self.unresolved_mentions.remove(&id) else {
+            return;
+        };
+
+        let names = self
+            .chats
+            .keys()
+            .filter_map(message::Chat::contact)
+            .map(|contact| (contact.id, contact.name.clone()))
+            .collect::<HashMap<_, _>>();
+        let name = |id| names.get(&id).cloned();
+
+        for (chat, id) in pending {
+            let Some(message) = self
+                .chats
+                .get_mut(&chat)
+                .and_then(|messages| messages.get_mut(&id))
+            else {
+                continue;
+            };
+            let message = Arc::make_mut(message);
+
+            message.body = body_ranges_to_signal_spans_with(
+                message.original_body.as_deref(),
+                &message.body_ranges,
+                name,
+            );
+
+            if let Some(quote) = &mut message.quote {
+                quote.body = body_ranges_to_signal_spans_with(
+                    quote.original_body.as_deref(),
+                    &quote.body_ranges,
+                    name,
+                );
+            }
+        }
+    }
+
+    fn focused(&self) -> Option<Arc<message::Message>> {
+        self.chats
+            .get(self.open_chat.as_ref()?)?
+            .get(&self.focused_message?)
+            .cloned()
+    }
+
+    /// Moves the focused message by one, starting from the newest one, and scrolls to it.
+    /// Moving past the newest one leaves the messages.
+    fn move_focus(&mut self, older: bool) -> Task<Message> {
+        let Some(chat) = &self.open_chat else {
+            return Task::none();
+        };
+
+        let ids = self.chats[chat]
+            .range(MessageId::first_at(self.history_from.unwrap_or(Timestamp::MIN))..)
+            .filter(|(_, message)| message.system.is_none())
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+
+        let focused = self
+            .focused_message
+            .and_then(|focused| ids.iter().position(|id| *id == focused));
+        let index = match focused {
+            None if older => ids.len().checked_sub(1),
+            None => None,
+            Some(index) if older => Some(index.saturating_sub(1)),
+            Some(index) => Some(index + 1).filter(|index| *index < ids.len()),
+        };
+
+        self.focused_message = index.map(|index| ids[index]);
+
+        let Some(index) = index else {
+            return Task::none();
+        };
+
+        // messages differ in height, so this only gets close
+        let y = index as f32 / (ids.len() - 1).max(1) as f32;
+        snap_to("messages", RelativeOffset { x: 0.0, y })
+    }
+
+    /// Focuses a message, showing the history before a jumped to day if needed, and scrolls
+    /// to it.
+    fn focus_message(&mut self, timestamp: Timestamp) -> Task<Message> {
+        let Some(messages) = self
+            .open_chat
+            .as_ref()
+            .and_then(|chat| self.chats.get(chat))
+        else {
+            return Task::none();
+        };
+
+        let Some(id) = first_sent_at(messages, timestamp) else {
+            return Task::none();
+        };
+
+        if self.history_from.is_some_and(|from| timestamp < from) {
+            self.history_from = None;
+        }
+        self.focused_message = Some(id);
+
+        let from = MessageId::first_at(self.history_from.unwrap_or(Timestamp::MIN));
+        let index = messages.range(from..id).count();
+        let count = messages.range(from..).count();
+
+        // messages differ in height, so this only gets close
+        let y = index as f32 / (count.max(2) - 1) as f32;
+        snap_to("messages", RelativeOffset { x: 0.0, y })
+    }
+
+    /// Tells about a reaction to one of our messages, apart from the chat's notification about
+    /// new messages so it doesn't count as one.
+    fn notify_reaction(
+        &self,
+        chat: &message::Chat,
+        sender: &message::Contact,
+        emoji: &str,
+        message: &message::Message,
+    ) -> Task<Message> {
+        if !self.reaction_notifications || self.idle || self.muted.contains(chat) {
+            return Task::none();
+        }
+
+        let summary = match chat {
+            message::Chat::Contact(_) => format!("{} reacted {emoji} to your message", sender.name),
+            message::Chat::Group(group) => format!(
+                "{} reacted {emoji} to your message in {}",
+                sender.name, group.title
+            ),
+        };
+        let body = message
+            .body
+            .as_deref()
+            .map(|spans| {
+                spans
+                    .iter()
+                    .map(|span| span.text.as_ref())
+                    .collect::<String>()
+            })
+            .unwrap_or_default();
+
+        Task::future(self.notifier.show(summary, body, None)).discard()
+    }
+
+    /// Downloads the images of the gallery's thumbnails, which are told apart by their index.
+    fn download_thumbnails(
+        &self,
+        chat: &message::Chat,
+        thumbnails: Vec<(usize, AttachmentPointer)>,
+    ) -> Task<Message> {
+        Task::batch(thumbnails.into_iter().map(|(index, ptr)| {
+            let chat = chat.clone();
+
+            Task::future(self.manager_manager.clone().download_attachment(ptr)).then(move |bytes| {
+                match bytes {
+                    Ok(bytes) => {
+                        let chat = chat.clone();
+                        Task::future(Image::from_bytes(bytes))
+                            .map(move |image| Message::GalleryImage(chat.clone(), index, image))
+                    }
+                    // a missing thumbnail keeps its placeholder
+                    Err(err) => {
+                        warn!("Failed to download a thumbnail: {err}");
+                        Task::none()
+                    }
+                }
+            })
+        }))
+    }
+
+    /// Downloads the attachment and opens it with the system's default application, never
+    /// letting it run as a program by itself.
+    fn open_attachment(&self, attachment: message::Attachment) -> Task<Message> {
+        let manager_manager = self.manager_manager.clone();
+        let dir = self.opened_attachments_dir.clone();
+
+        let file_name = attachment.file_name();
+
+        Task::future(async move {
+            let opened = match manager_manager.download_attachment(attachment.ptr).await {
+                Ok(bytes) => spawn_blocking(move || {
+                    let path = safety::write_for_opening(&dir, &file_name, &bytes)?;
+                    opener::open(&path).map_err(io::Error::other)
+                })
+                .await
+                .unwrap_or_else(|err| Err(io::Error::other(err))),
+                Err(err) => Err(io::Error::other(err.to_string())),
+            };
+
+            opened.err()
+        })
+        .and_then(|err| Task::done(Message::OpenAttachmentFailed(Arc::new(err))))
+    }
+
+    /// Moves the highlight in the chat list one chat down or up, starting at the open chat.
+    fn highlight(&mut self, next: bool) {
+        let chats = self.conversations();
+        let from = self.highlighted.as_ref().or(self.open_chat.as_ref());
+
+        let index = match from.and_then(|from| chats.iter().position(|chat| *chat == from)) {
+            Some(index) if next => (index + 1).min(chats.len() - 1),
+            Some(index) => index.saturating_sub(1),
+            None => 0,
+        };
+
+        self.highlighted = chats.get(index).copied().cloned();
+    }
+
+    /// Sends or edits a message in the chat, showing new ones as pending until they're sent.
+    fn send(&mut self, chat: message::Chat, draft: Draft) -> Task<Message> {
+        let manager_manager = self.manager_manager.clone();
+        let (retries, retrying) = mpsc::unbounded();
+
+        self.send_states.remove(&chat);
+
+        let resend = draft.clone();
+
+        let (sent, pending) = if let Some(timestamp) = draft.editing {
+            (
+                Task::future(manager_manager.edit(chat.clone(), draft.content, timestamp, retries)),
+                None,
+            )
+        } else {
+            let Draft {
+                content,
+                quote,
+                attachments,
+                quality,
+                ..
+            } = draft;
+            let pending = self.show_pending(&chat, &content, quote.clone(), attachments.len());
+
+            let chat = chat.clone();
+
+            let sent = Task::future(async move {
+                let attachments = try_join_all(
+                    attachments
+                        .into_iter()
+                        .map(|staged| Upload::read(staged.path, quality)),
+                )
+                .await
+                .map_err(|err| ManagerManagerError::Upload(err.to_string()))?;
+
+                manager_manager
+                    .send(chat, content, quote, attachments, retries)
+                    .await
+            });
+
+            (sent, pending)
+        };
+
+        Task::batch([
+            Task::run(retrying, {
+                let chat = chat.clone();
+                move |retry| Message::Retrying(chat.clone(), retry)
+            }),
+            sent.map(move |result| match result {
+                Ok(sent) => Message::Sent(pending, sent),
+                Err(ManagerManagerError::ProofRequired(token)) => {
+                    Message::Challenged(chat.clone(), pending, token, resend.clone())
+                }
+                Err(err) => {
+                    Message::SendFailed(chat.clone(), pending, Some(resend.clone()), Arc::new(err))
+                }
+            }),
+        ])
+    }
+
+    /// Shows a message in its chat right away, marked as being sent, returning its timestamp.
+    fn show_pending(
+        &mut self,
+        chat: &message::Chat,
+        content: &str,
+        quote: Option<message::Quote>,
+        attachments: usize,
+    ) -> Option<MessageId> {
+        let me = self.chats.keys().find(|chat| chat.is_self())?.contact()?;
+
+        let (body, body_ranges) = markdown_to_body_ranges(content);
+        let name = |id| {
+            self.chats
+                .keys()
+                .filter_map(message::Chat::contact)
+                .find(|contact| contact.id == id)
+                .map(|contact| contact.name.clone())
+        };
+        let mut body = body_ranges_to_signal_spans_with(
+            Some(body.as_str()).filter(|body| !body.is_empty()),
+            &body_ranges,
+            name,
+        )
+        .unwrap_or_default();
+
+        // the attachments are only shown once they're uploaded
+        if attachments > 0 {
+            body.push(SignalSpan::new(format!(
+                "{}📎 {attachments} {}",
+                if body.is_empty() { "" } else { "\n" },
+                if attachments == 1 { "file" } else { "files" }
+            )));
+        }
+
+        let message = message::Message::pending(
+            Timestamp::now(),
+            me,
+            Some(body).filter(|body| !body.is_empty()),
+            quote,
+        );
+        let id = message.id();
+
+        self.chats
+            .entry(chat.clone())
+            .or_default()
+            .insert(id, Arc::new(message));
+
+        Some(id)
+    }
+
+    /// The line above the composer telling that sending to the chat is retried, or failed.
+    fn send_state<'a>(chat: &message::Chat, state: &SendState) -> Element<'a, Message> {
+        let label = match state {
+            SendState::Retrying(retry) => text!(
+                "Sending failed, trying again in {}s (attempt {} of {})",
+                retry.delay.as_secs(),
+                retry.attempt + 1,
+                retry.attempts
+            )
+            .style(text::secondary),
+            SendState::Failed(err) => match (&**err, chat) {
+                (ManagerManagerError::Unregistered, message::Chat::Contact(contact)) => {
+                    text!("Not sent, {} isn't on Signal anymore", contact.name)
+                }
+                (ManagerManagerError::Unregistered, message::Chat::Group(group)) => {
+                    text!(
+                        "Not sent, a member of {} isn't on Signal anymore",
+                        group.title
+                    )
+                }
+                (err, _) => text!("Not sent: {err}"),
+            }
+            .style(text::danger),
+        };
+
+        row![
+            label.size(12),
+            space::horizontal(),
+            button(text("✕").size(12))
+                .style(button::text)
+                .padding(0)
+                .on_press(Message::DismissSendState),
+        ]
+        .spacing(5)
+        .align_y(Center)
+        .into()
+    }
+
+    /// Whether nothing can be sent to the chat anymore, while its history can still be read.
+    fn is_read_only(&self, chat: &message::Chat) -> bool {
+        match chat {
+            message::Chat::Contact(_) => self.unregistered.contains(chat),
+            message::Chat::Group(group) => !group.is_member(),
+        }
+    }
+
+    /// What's shown instead of the composer in a chat that can only be read, telling why.
+    fn read_only_banner<'a>(chat: &message::Chat) -> Element<'a, Message> {
+        let (explanation, can_return) = match chat {
+            message::Chat::Contact(contact) => (
+                format!(
+                    "{} isn't on Signal anymore, so messages can't be sent to them. Your chat \
+                    history with them stays here.",
+                    contact.name
+                ),
+                true,
+            ),
+            message::Chat::Group(group) => (
+                format!(
+                    "You're not a member of {} anymore, so messages can't be sent to it. Its \
+                    history stays here.",
+                    group.title
+                ),
+                false,
+            ),
+        };
+
+        container(
+            row![
+                text(explanation).style(text::secondary).width(Fill),
+                can_return.then(|| {
+                    button(text("Try Again").size(14))
+                        .style(button::text)
+                        .on_press(Message::SendAnyway)
+                }),
+            ]
+            .spacing(5)
+            .align_y(Center),
+        )
+        .padding(10)
+        .width(Fill)
+        .style(container::rounded_box)
+        .into()
+    }
+
+    /// The buttons floating over the messages while text is selected in one of them.
+    fn selection_bar<'a>() -> Element<'a, Message> {
+        container(
+            container(
+                row![
+                    button(text("Copy").size(12))
+                        .style(button::text)
+                        .on_press(Message::CopySelection),
+                    button(text("Copy as markdown").size(12))
+                        .style(button::text)
+                        .on_press(Message::CopySelectionAsMarkdown),
+                    button(text("Quote").size(12))
+                        .style(button::text)
+                        .on_press(Message::QuoteSelection),
+                ]
+                .spacing(5),
+            )
+            .padding(2)
+            .style(container::rounded_box),
+        )
+        .align_right(Fill)
+        .align_bottom(Fill)
+        .padding(5)
+        .into()
+    }
+
+    /// What's shown while no chat is open. Until the primary device synced the contacts,
+    /// that's what's being waited for.
+    fn empty_chat(&self) -> Element<'_, Message> {
+        if self.chats.keys().any(|chat| !chat.is_self()) {
+            return Element::new(space::horizontal());
+        }
+
+        center(
+            column![
+                text("Waiting for contact sync…").size(20),
+                text("Your phone sends your contacts after linking, which can take a minute.")
+                    .style(text::secondary),
+                button(text(if self.contacts_requested {
+                    "Contacts requested"
+                } else {
+                    "Request contacts"
+                }))
+                .on_press_maybe((!self.contacts_requested).then_some(Message::RequestContacts)),
+                text(
+                    "Meanwhile, Note to Self keeps notes, links and files for yourself. \
+                    Ctrl+Shift+N sends it the clipboard."
+                )
+                .size(12)
+                .style(text::secondary),
+            ]
+            .spacing(10)
+            .align_x(Center)
+            .max_width(400),
+        )
+        .into()
+    }
+
+    /// The chats with messages, and the open one, most recently active first, with Note to
+    /// Self pinned at the top.
+    fn conversations(&self) -> Vec<&message::Chat> {
+        let mut chats = self
+            .chats
+            .iter()
+            .filter(|(chat, messages)| {
+                !messages.is_empty() || chat.is_self() || self.open_chat.as_ref() == Some(*chat)
+            })
+            .map(|(chat, _)| chat)
+            .collect::<Vec<_>>();
+        chats.sort_by_key(|c| {
+            (
+                !c.is_self(),
+                // messages with invalid times would keep their chats on top
+                Reverse(
+                    self.chats[c]
+                        .values()
+                        .rev()
+                        .find(|message| !message.has_invalid_time())
+                        .map(|message| message.id()),
+                ),
+            )
+        });
+        chats
+    }
+
+    /// Every contact, also those never messaged, grouped by the initial of their name.
+    fn contact_list(&self) -> Element<'_, Message> {
+        let query = self.contact_query.to_lowercase();
+
+        let mut contacts = self
+            .chats
+            .keys()
+            .filter(|chat| matches!(chat, message::Chat::Contact(_)))
+            .filter(|chat| chat.name().to_lowercase().contains(&query))
+            .collect::<Vec<_>>();
+        contacts.sort_by_cached_key(|chat| chat.name().to_lowercase());
+
+        let mut list = column![].spacing(5);
+        let mut group = None;
+
+        for chat in contacts {
+            let initial = chat
+                .name()
+                .chars()
+                .next()
+                .filter(|initial| initial.is_alphabetic())
+                .map_or('#', |initial| {
+                    initial.to_uppercase().next().unwrap_or(initial)
+                });
+
+            if group != Some(initial) {
+                group = Some(initial);
+                list = list.push(text(initial).size(12));
+            }
+
+            list = list.push(
+                button(chat.as_iced_widget())
+                    .on_press(Message::OpenChat(chat.clone()))
+                    .padding(5)
+                    .style(button::subtle),
+            );
+        }
+
+        column![
+            text_input("Search contacts or paste a link", &self.contact_query)
+                .on_input(Message::ContactQuery)
+                .padding(5),
+            scrollable(list).spacing(5).height(Fill),
+        ]
+        .spacing(5)
+        .into()
+    }
+
+    /// Replaces a chat with a newer version of itself, like a renamed contact.
+    fn update_chat(&mut self, chat: message::Chat) {
+        // chats are compared by their id, so the key has to be replaced as well
+        let messages = self.chats.remove(&chat).unwrap_or_default();
+
+        if self.open_chat.as_ref() == Some(&chat) {
+            self.open_chat = Some(chat.clone());
+        }
+
+        self.chats.insert(chat, messages);
+    }
+
+    /// Drops the decoded images and placeholders of a chat that isn't open anymore.
+    fn drop_images(&mut self, chat: &message::Chat) {
+        for message in self
+            .chats
+            .get_mut(chat)
+            .into_iter()
+            .flat_map(BTreeMap::values_mut)
+        {
+            if message
+                .shown_attachments()
+                .any(|attachment| attachment.image.is_some() || attachment.placeholder.is_some())
+            {
+                for attachment in Arc::make_mut(message).shown_attachments_mut() {
+                    attachment.image = None;
+                    attachment.placeholder = None;
+                }
+            }
+        }
+    }
+
+    /// Decodes the placeholders of a message in a chat that's kept in memory, and downloads
+    /// the thumbnails of the message it quotes.
+    fn load_images(&mut self, chat: &message::Chat, id: MessageId) -> Task<Message> {
+        let Some(message) = self
+            .chats
+            .get_mut(chat)
+            .and_then(|messages| messages.get_mut(&id))
+            .filter(|message| {
+                message
+                    .shown_attachments()
+                    .any(|attachment| attachment.mime.type_() == mime::IMAGE)
+            })
+        else {
+            return Task::none();
+        };
+
+        let message = Arc::make_mut(message);
+
+        for attachment in message.shown_attachments_mut() {
+            attachment.decode_placeholder();
+        }
+
+        let thumbnails = message
+            .quote
+            .iter()
+            .flat_map(|quote| quote.attachments.iter().enumerate())
+            .filter(|(_, attachment)| {
+                attachment.mime.type_() == mime::IMAGE && attachment.image.is_none()
+            })
+            .map(|(index, attachment)| (index, attachment.ptr.clone()))
+            .collect::<Vec<_>>();
+
+        Task::batch(thumbnails.into_iter().map(|(index, ptr)| {
+            let chat = chat.clone();
+
+            Task::future(self.manager_manager.clone().download_attachment(ptr)).then(move |bytes| {
+                match bytes {
+                    Ok(bytes) => {
+                        let chat = chat.clone();
+                        Task::future(Image::from_bytes(bytes)).map(move |image| {
+                            Message::QuoteThumbnailDownloaded(chat.clone(), id, index, image)
+                        })
+                    }
+                    // a missing thumbnail keeps its placeholder
+                    Err(err) => {
+                        warn!("Failed to download a quote thumbnail: {err}");
+                        Task::none()
+                    }
+                }
+            })
+        }))
+    }
+
+    /// Asks for a new QR code to link this device with after `delay`, and waits for it to be
+    /// scanned. The linking that was in progress is aborted.
+    fn relink(&mut self, delay: Duration) -> Task<Message> {
+        let (tx, rx) = oneshot::channel();
+        let manager_manager = self.manager_manager.clone();
+
+        let (task, handle) = Task::batch([
+            Task::future(async move {
+                sleep(delay).await;
+                manager_manager.link_secondary(tx).await
+            })
+            .map(Self::registered),
+            Task::future(async { rx.await.ok() }).and_then(|url| Task::done(Message::QrCode(url))),
+        ])
+        .abortable();
+
+        self.linking = Some(handle.abort_on_drop());
+
+        task
+    }
+
+    fn qr_code_hint(left: Duration) -> String {
+        if left.is_zero() {
+            "Scan the QR code below to link your device. It's about to expire, a new one is on \
+            its way."
+                .to_owned()
+        } else {
+            format!(
+                "Scan the QR code below to link your device. It expires in {}s, then a new one \
+                is shown.",
+                left.as_secs()
+            )
+        }
+    }
+
+    fn load_registered(manager_manager: &ManagerManager) -> Task<Message> {
+        Task::perform(manager_manager.clone().load_registered(), Self::registered)
+    }
+
+    /// What loading or linking the account ended with.
+    fn registered(result: Result<(), ManagerManagerError>) -> Message {
+        match result {
+            Ok(()) => Message::ManagerError(None),
+            Err(ManagerManagerError::Manager(err)) => Message::ManagerError(Some(Arc::new(err))),
+            Err(ManagerManagerError::Database(report)) => Message::DatabaseDamaged(report),
+            Err(err) => Message::Error(Arc::new(err)),
+        }
+    }
+
+    pub fn update(&mut self, message: Message) -> Task<Message> {
+        match message {
+            Message::ManagerError(manager_error) => {
+                if let Some(error) = manager_error {
+                    return match &*error {
+                        &ManagerError::NotYetRegisteredError => self.update(Message::LinkSecondary),
+                        // the QR code expired before it was scanned, so a new one is shown
+                        &ManagerError::NoProvisioningMessageReceived
+                        | &ManagerError::ProvisioningError(ProvisioningError::MissingMessage) => {
+                            self.qr_code_shown = None;
+
+                            if self.linking_cancelled {
+                                Task::none()
+                            } else {
+                                self.relink(Duration::ZERO)
+                            }
+                        }
+                        // Signal's servers can't be reached, which is tried again later and
+                        // later instead of right away
+                        err @ &ManagerError::ProvisioningError(
+                            ProvisioningError::WsClosing { .. } | ProvisioningError::WsError { .. },
+                        ) => {
+                            self.qr_code_shown = None;
+
+                            if self.linking_cancelled {
+                                Task::none()
+                            } else {
+                                let delay = LINK_RETRY_BASE
+                                    .saturating_mul(2_u32.saturating_pow(self.link_failures))
+                                    .min(LINK_RETRY_MAX);
+                                self.link_failures = self.link_failures.saturating_add(1);
+
+                                self.dialog = Dialog::new(
+                                    "Can't reach Signal",
+                                    format!(
+                                        "Linking your device failed: {err}\n\nTrying again in {}s.",
+                                        delay.as_secs()
+                                    ),
+                                    None,
+                                    Action::CancelLinking,
+                                );
+
+                                self.relink(delay)
+                            }
+                        }
+                        err => {
+                            self.qr_code_shown = None;
+                            self.dialog = Dialog::new(
+                                "Oops! Something went wrong.",
+                                err.to_string(),
+                                None,
+                                Action::RetryLinking,
+                            )
+                            .monospace();
+                            Task::none()
+                        }
+                    };
+                }
+
+                self.qr_code_shown = None;
+                self.dialog.close();
+
+                // apply everything that arrived since the last update at once, so a burst of
+                // messages during sync doesn't cause a relayout per message
+                let receipts =
+                    Task::future(self.manager_manager.clone().receipts()).then(|receipts| {
+                        match receipts {
+                            Ok(receipts) => Task::done(Message::ReceiptsLoaded(receipts)),
+                            Err(err) => {
+                                warn!("Failed to load the receipts: {err}");
+                                Task::none()
+                            }
+                        }
+                    });
+
+                return Task::batch([
+                    receipts,
+                    Task::future(self.manager_manager.clone().stream_mesages()).then(|stream| {
+                        match stream {
+                            Ok(stream) => Task::stream(stream.ready_chunks(RECEIVED_BATCH_SIZE))
+                                .map(Message::ReceivedBatch),
+                            Err(err) => Task::done(Message::Error(Arc::new(err))),
+                        }
+                    }),
+                ]);
+            }
+            Message::ReceiptsLoaded(receipts) => {
+                for (recipient, receipt) in receipts {
+                    self.receipts.apply(recipient, &receipt);
+                }
+            }
+            Message::Error(err) => {
+                self.dialog = Dialog::new(
+                    "Oops! Something went wrong.",
+                    err.to_string(),
+                    None,
+                    Action::Close,
+                );
+            }
+            Message::LinkSecondary => {
+                self.linking_cancelled = false;
+                self.link_failures = 0;
+                return self.relink(Duration::ZERO);
+            }
+            Message::QrCode(url) => {
+                if self.linking_cancelled {
+                    return Task::none();
+                }
+
+                self.link_failures = 0;
+
+                self.qr_code_shown = Some(Instant::now());
+                self.dialog = Dialog::new(
+                    "Link your device",
+                    Self::qr_code_hint(QR_CODE_LIFETIME),
+                    Some(qr_code::Data::new(url).unwrap()),
+                    Action::CancelLinking,
+                );
+            }
+            Message::LinkingTick(now) => {
+                if let Some(shown) = self.qr_code_shown {
+                    self.dialog.set_content(Self::qr_code_hint(
+                        QR_CODE_LIFETIME.saturating_sub(now - shown),
+                    ));
+                }
+            }
+            Message::CancelLinking => {
+                self.linking_cancelled = true;
+                self.linking = None;
+                self.qr_code_shown = None;
+                self.dialog = Dialog::new(
+                    "Linking cancelled",
+                    "Link this device whenever you're ready.",
+                    None,
+                    Action::RetryLinking,
+                );
+
+                return Task::future(self.manager_manager.clone().cancel_linking()).then(
+                    |result| match result {
+                        Ok(()) => Task::none(),
+                        Err(err) => Task::done(Message::Error(Arc::new(err))),
+                    },
+                );
+            }
+            Message::ReceivedBatch(batch) => {
+                return Task::batch(
+                    batch
+                        .into_iter()
+                        .map(|received| self.update(Message::Received(received))),
+                );
+            }
+            Message::Received((chat, message)) => match message {
+                SignalAction::Contact => {
+                    let contact = chat.contact();
+                    self.update_chat(chat.clone());
+
+                    let mut animate = Task::none();
+
+                    if let Some(contact) = contact {
+                        let avatar = self.avatars.update(&contact);
+                        self.resolve_mentions(contact.id);
+
+                        if let Some(bytes) = avatar {
+                            animate = self.animate_avatar(contact.id, bytes);
+                        }
+                    }
+
+                    if let message::Chat::Group(group) = &chat
+                        && self.pending_invite == Some(group.key)
+                    {
+                        self.pending_invite = None;
+                        return self.update(Message::OpenChat(chat));
+                    }
+
+                    // the chat that was open when the app was left, once it's loaded
+                    if self.open_chat.is_none() && self.session.was_open(&chat) {
+                        return Task::batch([animate, self.update(Message::OpenChat(chat))]);
+                    }
+
+                    return animate;
+                }
+                SignalAction::System(message) => {
+                    self.update_chat(chat.clone());
+                    let messages = self.chats.get_mut(&chat).unwrap();
+
+                    // a renamed contact nobody wrote with isn't worth a conversation
+                    if !messages.is_empty() || matches!(chat, message::Chat::Group(_)) {
+                        messages.insert(message.id(), message);
+                    }
+                }
+                SignalAction::Message(mut message, notif) => {
+                    let id = message.id();
+
+                    // envelopes are delivered again when their receipt didn't reach the server,
+                    // which shouldn't show or notify about the message twice
+                    if self
+                        .chats
+                        .get(&chat)
+                        .is_some_and(|messages| messages.contains_key(&id))
+                    {
+                        debug!("Dropped a message delivered twice: {id:?}");
+                        return Task::none();
+                    }
+
+                    self.track_mentions(&chat, &message);
+
+                    if let Some((_, reactions)) = self.pending_reactions.remove(&(chat.clone(), id))
+                    {
+                        let message = Arc::make_mut(&mut message);
+
+                        for (sender, emoji) in reactions {
+                            message.react(sender, emoji);
+                        }
+                    }
+
+                    self.typing.stop(&chat, message.sender.id);
+
+                    self.chats
+                        .entry(chat.clone())
+                        .or_default()
+                        .insert(id, message.clone());
+
+                    let thumbnails = if self.resident_chats.contains(&chat) {
+                        self.load_images(&chat, id)
+                    } else {
+                        Task::none()
+                    };
+
+                    if notif && !message.sender.is_self {
+                        self.unread
+                            .entry(chat.clone())
+                            .or_default()
+                            .push((message.sender.id, message.timestamp));
+                    }
+
+                    // nobody needs to be told about what's already on screen
+                    if self.window_focused && self.open_chat.as_ref() == Some(&chat) {
+                        if self.marking_read {
+                            return thumbnails;
+                        }
+
+                        self.marking_read = true;
+                        return Task::batch([
+                            thumbnails,
+                            Task::future(sleep(READ_RECEIPT_DELAY)).map(|()| Message::MarkRead),
+                        ]);
+                    }
+
+                    // the rest of a batch is applied before the notifications are shown, so a
+                    // burst of messages is summarized
+                    if notif && !self.muted.contains(&chat) {
+                        self.unnotified.push((chat, message));
+
+                        if self.unnotified.len() == 1 {
+                            return Task::batch([thumbnails, Task::done(Message::Notify)]);
+                        }
+                    }
+
+                    return thumbnails;
+                }
+                SignalAction::Typing(contact, started, at) => {
+                    if self
+                        .typing
+                        .apply(chat, contact, started, at, Timestamp::now())
+                        && started
+                    {
+                        return Task::future(sleep(TYPING_TIMEOUT))
+                            .map(|()| Message::TypingExpired);
+                    }
+                }
+                SignalAction::Challenge(token) => {
+                    // solving the challenge of a message being sent solves this one as well
+                    if self.challenged.is_none() {
+                        let challenged = Challenged {
+                            token,
+                            chat,
+                            resend: None,
+                        };
+                        self.dialog = challenged.dialog(None);
+                        self.challenged = Some(challenged);
+                    }
+                }
+                SignalAction::Receipt(receipt) => {
+                    if let Some(contact) = chat.contact() {
+                        self.receipts.apply(contact.id, &receipt);
+                    }
+                }
+                SignalAction::Replace(old, mut message) => {
+                    self.track_mentions(&chat, &message);
+                    let messages = self.chats.get_mut(&chat).unwrap();
+
+                    // reactions stay on the edited message
+                    if let Some(old) = messages.get(&old) {
+                        Arc::make_mut(&mut message).reactions = old.reactions.clone();
+                    }
+
+                    // the edit stays where the message was sent
+                    messages.insert(old, message);
+
+                    if self.resident_chats.contains(&chat) {
+                        return self.load_images(&chat, old);
+                    }
+                }
+                SignalAction::Delete(id) => {
+                    self.chats.get_mut(&chat).unwrap().remove(&id);
+                }
+                SignalAction::React(target, sender, emoji, live) => {
+                    // someone else reacting to one of our messages while the chat is out of
+                    // sight, not when their reaction is decoded from history
+                    let notify = if live
+                        && !sender.is_self
+                        && !(self.window_focused && self.open_chat.as_ref() == Some(&chat))
+                        && let Some(emoji) = &emoji
+                        && let Some(message) = self
+                            .chats
+                            .get(&chat)
+                            .and_then(|messages| messages.get(&target))
+                        && message.sender.is_self
+                    {
+                        self.reacted.insert(chat.clone());
+                        self.notify_reaction(&chat, &sender, emoji, message)
+                    } else {
+                        Task::none()
+                    };
+
+                    match self
+                        .chats
+                        .get_mut(&chat)
+                        .and_then(|messages| messages.get_mut(&target))
+                    {
+                        Some(message) => Arc::make_mut(message).react(sender, emoji),
+                        // history is decoded out of order, so the message may only come later
+                        None => {
+                            if self.pending_reactions.len() >= PENDING_REACTIONS
+                                && !self.pending_reactions.contains_key(&(chat.clone(), target))
+                                && let Some(oldest) = self
+                                    .pending_reactions
+                                    .iter()
+                                    .min_by_key(|(_, (since, _))| *since)
+                                    .map(|(key, _)| key.clone())
+                            {
+                                // the longer a message is waited for, the less likely it comes
+                                self.pending_reactions.remove(&oldest);
+                            }
+
+                            self.pending_reactions
+                                .entry((chat, target))
+                                .or_insert_with(|| (Instant::now(), vec![]))
+                                .1
+                                .push((sender, emoji));
+                        }
+                    }
+
+                    return notify;
+                }
+            },
+            Message::CloseDialog => {
+                self.dialog.close();
+
+                // a challenge that's dismissed comes back with the next message or profile
+                if let Some(Challenged {
+                    chat,
+                    resend: Some((pending, draft)),
+                    ..
+                }) = self.challenged.take()
+                {
+                    if let Some(pending) = pending {
+                        self.unsent.insert(pending, draft);
+                    } else if self.open_chat.as_ref() == Some(&chat) {
+                        self.composer.restore_draft(draft);
+                    }
+                }
+            }
+            Message::DatabaseDamaged(report) => {
+                self.dialog = Dialog::new(
+                    "The database can't be opened",
+                    format!(
+                        "Backing it up and starting over with an empty one means linking this device again.\n\n{report}"
+                    ),
+                    None,
+                    Action::RecreateDatabase,
+                )
+                .monospace();
+            }
+            Message::RecreateDatabase => {
+                self.dialog.close();
+
+                let manager_manager = self.manager_manager.clone();
+                return Task::future(self.manager_manager.clone().recreate_database()).then(
+                    move |result| match result {
+                        Ok(()) => Self::load_registered(&manager_manager),
+                        Err(err) => Task::done(Message::Error(Arc::new(err))),
+                    },
+                );
+            }
+            Message::ReportCrash => {
+                if let Some(report) = &self.crash_report {
+                    crash::open_issue(report);
+                }
+                return self.update(Message::DismissCrash);
+            }
+            Message::DismissCrash => {
+                self.crash_report = None;
+                self.dialog.close();
+                return Self::load_registered(&self.manager_manager);
+            }
+            Message::OpenChat(open_chat) => {
+                self.highlighted = None;
+                self.notifications.remove(&open_chat);
+                self.reacted.remove(&open_chat);
+                self.resident_chats.retain(|chat| *chat != open_chat);
+                self.resident_chats.push_front(open_chat.clone());
+
+                if self.resident_chats.len() > RESIDENT_CHATS
+                    && let Some(chat) = self.resident_chats.pop_back()
+                {
+                    self.drop_images(&chat);
+                }
+
+                let ids = self
+                    .chats
+                    .get(&open_chat)
+                    .into_iter()
+                    .flat_map(BTreeMap::keys)
+                    .copied()
+                    .collect::<Vec<_>>();
+                let thumbnails = Task::batch(
+                    ids.into_iter()
+                        .map(|id| self.load_images(&open_chat, id))
+                        .collect::<Vec<_>>(),
+                );
+
+                // bring back the images dropped while the chat was closed
+                let reload =
+                    self.chats
+                        .get(&open_chat)
+                        .into_iter()
+                        .flat_map(BTreeMap::values)
+                        .filter(|message| {
+                            message.attachments.iter().any(|attachment| {
+                                attachment.downloaded && attachment.image.is_none()
+                            })
+                        })
+                        .cloned()
+                        .collect::<Vec<_>>();
+
+                self.session.open(&open_chat);
+                self.session.save();
+                let scrolled = self.session.scrolled(&open_chat).unwrap_or(1.);
+
+                self.open_chat = Some(open_chat);
+                self.history_from = None;
+                self.focused_message = None;
+                self.selection = None;
+                self.calendar.close();
+                self.chat_menu.close();
+                self.group_details.close();
+                self.gallery.close();
+                self.chat_search.close();
+                self.composer.clear();
+                self.composer.set_quote(None);
+
+                return Task::batch(
+                    [
+                        focus_next(),
+                        snap_to(
+                            "messages",
+                            RelativeOffset {
+                                y: scrolled,
+                                ..RelativeOffset::END
+                            },
+                        ),
+                        self.mark_read(),
+                        thumbnails,
+                    ]
+                    .into_iter()
+                    .chain(
+                        reload
+                            .into_iter()
+                            .map(|message| self.update(Message::DownloadAttachments(message))),
+                    ),
+                );
+            }
+            Message::NextChat => {
+                let contacts = self.conversations();
+
+                let mut iter = contacts.iter().chain(contacts.iter());
+                if let Some(open_chat) = self.open_chat.as_ref() {
+                    iter.by_ref().find(|chat| **chat == open_chat);
+                }
+
+                if let Some(chat) = iter.next().copied().cloned() {
+                    return self.update(Message::OpenChat(chat));
+                }
+            }
+            Message::PreviousChat => {
+                let contacts = self.conversations();
+
+                let mut iter = contacts.iter().chain(contacts.iter());
+                if let Some(open_chat) = self.open_chat.as_ref() {
+                    iter.by_ref().rfind(|chat| **chat == open_chat);
+                }
+
+                if let Some(chat) = iter.next_back().copied().cloned() {
+                    return self.update(Message::OpenChat(chat));
+                }
+            }
+            Message::HighlightNext => self.highlight(true),
+            Message::HighlightPrevious => self.highlight(false),
+            Message::OpenHighlighted => {
+                if let Some(chat) = self.highlighted.clone() {
+                    return self.update(Message::OpenChat(chat));
+                }
+            }
+            Message::OpenNth(n) => {
+                if let Some(chat) = n
+                    .checked_sub(1)
+                    .and_then(|index| self.conversations().get(index).copied().cloned())
+                {
+                    return self.update(Message::OpenChat(chat));
+                }
+            }
+            Message::Mention(uuid) => {
+                // members of the open group may not have a chat of their own
+                let members = match &self.open_chat {
+                    Some(message::Chat::Group(group)) => group.members.as_slice(),
+                    _ => &[],
+                };
+
+                if let Some(contact) = self
+                    .chats
+                    .keys()
+                    .filter_map(message::Chat::contact)
+                    .chain(members.iter().cloned())
+                    .find(|contact| contact.id.raw_uuid() == uuid)
+                {
+                    self.member_card.open(contact);
+                }
+            }
+            Message::CloseMemberCard => self.member_card.close(),
+            Message::MessageMember => {
+                let Some(contact) = self.member_card.contact().cloned() else {
+                    return Task::none();
+                };
+                self.member_card.close();
+
+                let chat = message::Chat::Contact(contact);
+                self.chats.entry(chat.clone()).or_default();
+
+                return self.update(Message::OpenChat(chat));
+            }
+            Message::ShowSafetyNumber(id) => {
+                return Task::future(self.manager_manager.clone().safety_number(id)).map(
+                    move |result| match result {
+                        Ok(safety_number) => Message::SafetyNumber(id, safety_number),
+                        Err(err) => Message::Error(Arc::new(err)),
+                    },
+                );
+            }
+            Message::SafetyNumber(id, safety_number) => {
+                // another card may have been opened in the meantime
+                if self
+                    .member_card
+                    .contact()
+                    .is_some_and(|contact| contact.id == id)
+                {
+                    self.member_card.set_safety_number(safety_number);
+                }
+            }
+            Message::QuickReact(message) => {
+                return self.update(Message::React(message, self.quick_reaction.clone()));
+            }
+            Message::React(message, emoji) => {
+                let Some(chat) = self.open_chat.clone() else {
+                    return Task::none();
+                };
+
+                // reacting with the same emoji again takes the reaction back
+                let remove = message
+                    .reactions
+                    .iter()
+                    .any(|(reactor, reacted)| reactor.is_self && *reacted == emoji);
+
+                return Task::future(
+                    self.manager_manager
+                        .clone()
+                        .react(chat, message, emoji, remove),
+                )
+                .map(|result| match result {
+                    Ok(received) => Message::Received(received),
+                    Err(err) => Message::Error(Arc::new(err)),
+                });
+            }
+            Message::Quote(quote) => {
+                let was_editing = self.composer.is_editing();
+
+                self.composer
+                    .set_quote(quote.as_deref().cloned().map(Into::into));
+
+                if was_editing && quote.is_some() {
+                    _ = self.update(Message::Edit(None));
+                }
+                return focus_next();
+            }
+            Message::Selected(message, selected) => {
+                if !selected.is_empty() {
+                    self.selection = Some((message, selected));
+                } else if self
+                    .selection
+                    .as_ref()
+                    .is_some_and(|(selected, _)| selected.id() == message.id())
+                {
+                    // another message may already have taken over the selection
+                    self.selection = None;
+                }
+            }
+            Message::RevealSpoiler(message, tag) => {
+                if let Some(message) = self
+                    .open_chat
+                    .as_ref()
+                    .and_then(|chat| self.chats.get_mut(chat))
+                    .and_then(|messages| messages.get_mut(&message.id()))
+                {
+                    Arc::make_mut(message).revealed_spoilers.push(tag);
+                }
+            }
+            Message::AvatarAnimated(id, animation) => self.avatars.animate(id, animation),
+            Message::AvatarFrame(now) => self.avatars.tick(now),
+            Message::Rescaled(scale_factor) => {
+                if scale_factor == self.scale_factor {
+                    return Task::none();
+                }
+                self.scale_factor = scale_factor;
+
+                // the frames were shrunk for the old scale factor
+                return Task::batch(
+                    self.avatars
+                        .animated()
+                        .into_iter()
+                        .map(|(id, bytes)| self.animate_avatar(id, bytes)),
+                );
+            }
+            Message::CopySelection => {
+                if let Some((_, selected)) = self.selection.take() {
+                    return clipboard::write(selected.iter().map(|span| &*span.text).collect());
+                }
+            }
+            Message::CopySelectionAsMarkdown => {
+                if let Some((_, selected)) = self.selection.take() {
+                    return clipboard::write(signal_spans_to_markdown(&selected));
+                }
+            }
+            Message::QuoteSelection => {
+                let Some((message, selected)) = self.selection.take() else {
+                    return Task::none();
+                };
+
+                // the formatting and mentions of the selection are quoted with it
+                let (body, body_ranges) =
+                    markdown_to_body_ranges(&signal_spans_to_markdown(&selected));
+
+                let mut quote = message::Quote::from((*message).clone());
+                quote.body = Some(selected);
+                quote.original_body = Some(body);
+                quote.body_ranges = body_ranges;
+
+                let was_editing = self.composer.is_editing();
+                self.composer.set_quote(Some(quote));
+
+                if was_editing {
+                    _ = self.update(Message::Edit(None));
+                }
+                return focus_next();
+            }
+            Message::Edit(message) => {
+                if let Some(message) = message.as_deref() {
+                    let name = |id| {
+                        self.chats
+                            .keys()
+                            .filter_map(message::Chat::contact)
+                            .find(|contact| contact.id == id)
+                            .map(|contact| contact.name.clone())
+                    };
+
+                    self.composer.start_editing(
+                        message.timestamp,
+                        &body_ranges_to_markdown_with(
+                            message.original_body.as_deref(),
+                            &message.body_ranges,
+                            name,
+                        )
+                        .unwrap_or_default(),
+                    );
+                    return focus_next();
+                }
+
+                self.composer.stop_editing();
+            }
+            Message::ShowDetails(message) => {
+                if let Some(chat) = self.open_chat.clone() {
+                    self.details.open(chat, message);
+                }
+            }
+            Message::CloseDetails => self.details.close(),
+            Message::EditLast => {
+                let last_sent = self.open_chat.as_ref().and_then(|chat| {
+                    self.chats[chat]
+                        .values()
+                        .rfind(|message| message.sender.is_self)
+                });
+
+                return self.update(Message::Edit(last_sent.cloned()));
+            }
+            Message::FocusOlder => return self.move_focus(true),
+            Message::FocusNewer => return self.move_focus(false),
+            Message::QuoteFocused => {
+                if let Some(message) = self.focused() {
+                    self.focused_message = None;
+                    return self.update(Message::Quote(Some(message)));
+                }
+            }
+            Message::EditFocused => {
+                if let Some(message) = self.focused().filter(|message| message.sender.is_self) {
+                    self.focused_message = None;
+                    return self.update(Message::Edit(Some(message)));
+                }
+            }
+            Message::InfoFocused => {
+                if let Some(message) = self.focused() {
+                    self.dialog = Dialog::new("Message info", message.info(), None, Action::Close)
+                        .monospace();
+                }
+            }
+            Message::FocusNext => return focus_next(),
+            Message::FocusPrevious => return focus_previous(),
+            Message::Escape if self.lightbox.is_open() => self.lightbox.close(),
+            Message::Escape if self.stickers.is_open() => self.stickers.close(),
+            Message::Escape if self.details.is_open() => self.details.close(),
+            Message::Escape if self.member_card.is_open() => self.member_card.close(),
+            Message::Escape if self.group_details.is_open() => self.group_details.close(),
+            Message::Escape if self.gallery.is_open() => self.gallery.close(),
+            Message::Escape => {
+                _ = self.update(Message::Quote(None));
+                _ = self.update(Message::Edit(None));
+                self.calendar.close();
+                self.chat_menu.close();
+                self.chat_search.close();
+                self.focused_message = None;
+            }
+            Message::DownloadAttachments(message) => {
+                let Some(chat) = self.open_chat.clone() else {
+                    return Task::none();
+                };
+
+                return Task::batch(
+                    message
+                        .attachments
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, attachment)| {
+                            attachment.mime.type_() == mime::IMAGE && attachment.image.is_none()
+                        })
+                        .map(|(index, attachment)| {
+                            let chat = chat.clone();
+                            let id = message.id();
+
+                            Task::future(
+                                self.manager_manager
+                                    .clone()
+                                    .download_attachment(attachment.ptr.clone()),
+                            )
+                            .then(|bytes| match bytes {
+                                Ok(bytes) => Task::future(Image::from_bytes(bytes)).map(Ok),
+                                Err(err) => Task::done(Err(err)),
+                            })
+                            .map(move |image| match image {
+                                Ok(image) => {
+                                    Message::AttachmentDownloaded(chat.clone(), id, index, image)
+                                }
+                                Err(err) => Message::Error(Arc::new(err)),
+                            })
+                        }),
+                );
+            }
+            Message::AttachmentDownloaded(chat, id, index, image) => {
+                if let Some(message) = self
+                    .chats
+                    .get_mut(&chat)
+                    .and_then(|messages| messages.get_mut(&id))
+                {
+                    let attachment = &mut Arc::make_mut(message).attachments[index];
+                    attachment.image = Some(image);
+                    attachment.downloaded = true;
+                }
+            }
+            Message::QuoteThumbnailDownloaded(chat, id, index, image) => {
+                if let Some(quote) = self
+                    .chats
+                    .get_mut(&chat)
+                    .and_then(|messages| messages.get_mut(&id))
+                    .and_then(|message| Arc::make_mut(message).quote.as_mut())
+                {
+                    let attachment = &mut quote.attachments[index];
+                    attachment.image = Some(image);
+                    attachment.downloaded = true;
+                }
+            }
+            Message::OpenLightbox(message, index) => {
+                self.lightbox = Lightbox::new(message, index);
+            }
+            Message::CloseLightbox => self.lightbox.close(),
+            Message::LightboxNext => self.lightbox.next(),
+            Message::LightboxPrevious => self.lightbox.previous(),
+            Message::NoteToSelf => return clipboard::read().map(Message::SendToNoteToSelf),
+            Message::SendToNoteToSelf(content) => {
+                let Some(content) = content.filter(|content| !content.trim().is_empty()) else {
+                    return Task::none();
+                };
+
+                let Some(chat) = self.chats.keys().find(|chat| chat.is_self()).cloned() else {
+                    return Task::none();
+                };
+
+                return Task::future(self.manager_manager.clone().send(
+                    chat,
+                    content,
+                    None,
+                    vec![],
+                    mpsc::unbounded().0,
+                ))
+                .map(|result| match result {
+                    Ok(received) => Message::Received(received),
+                    Err(err) => Message::Error(Arc::new(err)),
+                });
+            }
+            Message::ShowContacts(show_contacts) => {
+                self.show_contacts = show_contacts;
+                self.contact_query.clear();
+            }
+            Message::ContactQuery(query) => {
+                // a pasted contact or invite link is opened right away
+                if DeepLink::parse(query.trim()).is_some() {
+                    self.show_contacts = false;
+                    self.contact_query.clear();
+                    return self.update(Message::OpenDeepLink(query.trim().to_owned()));
+                }
+
+                self.contact_query = query;
+            }
+            Message::OpenStickers => {
+                return Task::future(self.manager_manager.clone().sticker_packs()).map(|result| {
+                    match result {
+                        Ok(packs) => Message::StickerPacks(packs),
+                        Err(err) => Message::Error(Arc::new(err)),
+                    }
+                });
+            }
+            Message::StickerPacks(packs) => self.stickers.open(packs),
+            Message::CloseStickers => self.stickers.close(),
+            Message::InstallStickerPack(id, key) => {
+                self.composer.clear();
+
+                return Task::future(self.manager_manager.clone().install_sticker_pack(id, key))
+                    .map(|result| match result {
+                        Ok(()) => Message::OpenStickers,
+                        Err(err) => Message::Error(Arc::new(err)),
+                    });
+            }
+            Message::UninstallStickerPack(id) => {
+                return Task::future(self.manager_manager.clone().uninstall_sticker_pack(id)).map(
+                    |result| match result {
+                        Ok(()) => Message::OpenStickers,
+                        Err(err) => Message::Error(Arc::new(err)),
+                    },
+                );
+            }
+            Message::ToggleCalendar => {
+                if self.calendar.is_open() {
+                    self.calendar.close();
+                } else if let Some(now) = self.now
+                    && let Some(tz) = self.tz.clone()
+                {
+                    self.calendar.open(now.to_zoned(tz).date());
+                }
+            }
+            Message::CalendarPrevious => self.calendar.previous(),
+            Message::CalendarNext => self.calendar.next(),
+            Message::JumpToDate(day) => {
+                let Some(start) = self
+                    .tz
+                    .clone()
+                    .and_then(|tz| day.to_zoned(tz).ok())
+                    .map(|start| start.timestamp())
+                else {
+                    return Task::none();
+                };
+
+                // the whole history is in memory, so jumping only hides what came before
+                self.history_from = Some(start);
+                self.calendar.close();
+
+                return snap_to("messages", RelativeOffset::START);
+            }
+            Message::ShowEarlier => self.history_from = None,
+            Message::ToggleChatMenu => {
+                self.calendar.close();
+                self.chat_menu.toggle();
+            }
+            Message::ShowGroupDetails => {
+                self.chat_menu.close();
+                self.group_details.open();
+            }
+            Message::CloseGroupDetails => self.group_details.close(),
+            Message::ShowGallery => {
+                self.chat_menu.close();
+                self.group_details.close();
+
+                let Some(chat) = self.open_chat.clone() else {
+                    return Task::none();
+                };
+                self.gallery.open(chat.clone());
+
+                return Task::future(self.manager_manager.clone().shared(chat.clone())).map(
+                    move |result| match result {
+                        Ok(shared) => Message::Shared(chat.clone(), shared),
+                        Err(err) => Message::Error(Arc::new(err)),
+                    },
+                );
+            }
+            Message::CloseGallery => self.gallery.close(),
+            Message::GalleryTab(tab) => self.gallery.set_tab(tab),
+            Message::Shared(chat, shared) => {
+                // the gallery may have been closed or opened for another chat in the meantime
+                if self.gallery.is_open_for(&chat) {
+                    let thumbnails = self.gallery.set_shared(shared);
+                    return self.download_thumbnails(&chat, thumbnails);
+                }
+            }
+            Message::GalleryMore => {
+                if let Some(chat) = self.open_chat.clone() {
+                    let thumbnails = self.gallery.show_more();
+                    return self.download_thumbnails(&chat, thumbnails);
+                }
+            }
+            Message::GalleryImage(chat, index, image) => {
+                if self.gallery.is_open_for(&chat) {
+                    self.gallery.set_image(index, image);
+                }
+            }
+            Message::JumpToMessage(timestamp) => {
+                self.gallery.close();
+                return self.focus_message(timestamp);
+            }
+            Message::CopyInviteLink(link) => return clipboard::write(link),
+            Message::ToggleMute => {
+                self.chat_menu.close();
+
+                if let Some(chat) = &self.open_chat {
+                    self.muted.toggle(chat);
+                }
+            }
+            Message::SearchInChat => {
+                self.chat_menu.close();
+                self.chat_search.open();
+
+                return focus("chat-search");
+            }
+            Message::ChatSearchQuery(query) => self.chat_search.set_query(query),
+            Message::ChatSearch => {
+                let Some(chat) = &self.open_chat else {
+                    return Task::none();
+                };
+
+                return Task::future(
+                    self.manager_manager
+                        .clone()
+                        .search(self.chat_search.query().to_owned(), Some(chat.thread())),
+                )
+                .map(|result| match result {
+                    Ok(results) => Message::ChatSearchResults(
+                        results
+                            .into_iter()
+                            .map(|(_, timestamp)| timestamp)
+                            .collect(),
+                    ),
+                    Err(err) => Message::Error(Arc::new(err)),
+                });
+            }
+            Message::ChatSearchResults(results) => {
+                // the chat may have been left in the meantime
+                let Some(messages) = self
+                    .open_chat
+                    .as_ref()
+                    .and_then(|chat| self.chats.get(chat))
+                    .filter(|_| self.chat_search.is_open())
+                else {
+                    return Task::none();
+                };
+
+                let results = results
+                    .into_iter()
+                    .filter(|timestamp| first_sent_at(messages, *timestamp).is_some())
+                    .collect();
+
+                if let Some(timestamp) = self.chat_search.set_results(results) {
+                    return self.focus_message(timestamp);
+                }
+            }
+            Message::ChatSearchOlder => {
+                if let Some(timestamp) = self.chat_search.step(true) {
+                    return self.focus_message(timestamp);
+                }
+            }
+            Message::ChatSearchNewer => {
+                if let Some(timestamp) = self.chat_search.step(false) {
+                    return self.focus_message(timestamp);
+                }
+            }
+            Message::CloseChatSearch => {
+                self.chat_search.close();
+                self.focused_message = None;
+            }
+            Message::SetTimer(seconds) => {
+                self.chat_menu.close();
+
+                let Some(chat) = self.open_chat.clone() else {
+                    return Task::none();
+                };
+
+                return Task::future(self.manager_manager.clone().set_timer(chat, seconds)).map(
+                    |result| match result {
+                        Ok(received) => Message::Received(received),
+                        Err(err) => Message::Error(Arc::new(err)),
+                    },
+                );
+            }
+            Message::ExportChat(format) => {
+                self.chat_menu.close();
+
+                let (Some(chat), Some(tz)) = (self.open_chat.clone(), self.tz.clone()) else {
+                    return Task::none();
+                };
+                let messages = self.chats[&chat].values().cloned().collect::<Vec<_>>();
+                let name = export::file_name(&chat, format, &tz);
+                let manager_manager = self.manager_manager.clone();
+
+                return Task::future(async move {
+                    let path = platform::save_file("Export chat", &name).await?;
+
+                    // the images are embedded in the page, read from the attachment cache when
+                    // it has them and downloaded again otherwise, as an encrypted store has none
+                    let mut media = export::Media::new();
+                    if format == export::Format::Html {
+                        for message in &messages {
+                            for (index, attachment) in message.attachments.iter().enumerate() {
+                                if attachment.mime.type_() != mime::IMAGE {
+                                    continue;
+                                }
+
+                                match manager_manager
+                                    .clone()
+                                    .download_attachment(attachment.ptr.clone())
+                                    .await
+                                {
+                                    Ok(bytes) => _ = media.insert((message.id(), index), bytes),
+                                    Err(err) => warn!("Failed to export an image: {err}"),
+                                }
+                            }
+                        }
+                    }
+
+                    let written = spawn_blocking({
+                        let path = path.clone();
+                        move || {
+                            export::export(
+                                &path,
+                                format,
+                                &chat,
+                                messages.iter().map(AsRef::as_ref),
+                                &tz,
+                                &media,
+                            )
+                        }
+                    })
+                    .await
+                    .unwrap_or_else(|err| Err(io::Error::other(err)));
+
+                    Some(written.map(|()| path))
+                })
+                .and_then(|result| Task::done(Message::Exported(result.map_err(Arc::new))));
+            }
+            Message::Exported(Ok(path)) => {
+                self.dialog = Dialog::new(
+                    "Chat exported",
+                    format!("The chat was saved to {}", path.display()),
+                    None,
+                    Action::Close,
+                );
+            }
+            Message::Exported(Err(err)) => {
+                self.dialog = Dialog::new(
+                    "Exporting the chat failed",
+                    err.to_string(),
+                    None,
+                    Action::Close,
+                );
+            }
+            Message::ClearHistory => {
+                self.chat_menu.close();
+
+                if let Some(chat) = &self.open_chat {
+                    self.dialog = Dialog::new(
+                        format!("Clear the history of {}?", chat.name()),
+                        "The messages are deleted from this device. Your other devices keep them.",
+                        None,
+                        Action::ClearHistory,
+                    );
+                }
+            }
+            Message::ConfirmClearHistory => {
+                self.dialog.close();
+
+                let Some(chat) = self.open_chat.clone() else {
+                    return Task::none();
+                };
+
+                return Task::future(self.manager_manager.clone().clear_history(chat.clone())).map(
+                    move |result| match result {
+                        Ok(()) => Message::HistoryCleared(chat.clone()),
+                        Err(err) => Message::Error(Arc::new(err)),
+                    },
+                );
+            }
+            Message::HistoryCleared(chat) => {
+                if let Some(messages) = self.chats.get_mut(&chat) {
+                    messages.clear();
+                }
+
+                if self.open_chat.as_ref() == Some(&chat) {
+                    self.history_from = None;
+                    self.focused_message = None;
+                    self.chat_search.close();
+                }
+            }
+            Message::RequestContacts => {
+                self.contacts_requested = true;
+                self.chat_menu.close();
+
+                return Task::future(self.manager_manager.clone().request_contacts()).then(
+                    |result| match result {
+                        Ok(()) => Task::none(),
+                        Err(err) => Task::done(Message::ContactsRequestFailed(Arc::new(err))),
+                    },
+                );
+            }
+            Message::ContactsRequestFailed(err) => {
+                self.contacts_requested = false;
+                return self.update(Message::Error(err));
+            }
+            Message::ResyncGroups => {
+                self.chat_menu.close();
+
+                return Task::future(self.manager_manager.clone().resync_groups()).then(|result| {
+                    match result {
+                        Ok(()) => Task::none(),
+                        Err(err) => Task::done(Message::Error(Arc::new(err))),
+                    }
+                });
+            }
+            Message::ResyncChat => {
+                self.chat_menu.close();
+
+                let Some(chat) = self.open_chat.clone() else {
+                    return Task::none();
+                };
+
+                // messages that are gone from the store shouldn't linger
+                if let Some(messages) = self.chats.get_mut(&chat) {
+                    messages.retain(|_, message| message.sending.is_some());
+                }
+
+                return Task::future(self.manager_manager.clone().resync_chat(chat)).then(
+                    |result| match result {
+                        Ok(()) => Task::none(),
+                        Err(err) => Task::done(Message::Error(Arc::new(err))),
+                    },
+                );
+            }
+            Message::PickAttachments => {
+                return Task::future(platform::pick_files()).then(|paths| {
+                    Task::batch(paths.into_iter().map(Message::AttachFile).map(Task::done))
+                });
+            }
+            Message::SaveAttachment(attachment) => {
+                let manager_manager = self.manager_manager.clone();
+
+                return Task::future(async move {
+                    let path =
+                        platform::save_file("Save attachment", &attachment.file_name()).await?;
+
+                    // downloaded attachments are read back from the cache
+                    let saved = match manager_manager.download_attachment(attachment.ptr).await {
+                        Ok(bytes) => spawn_blocking(move || {
+                            fs::write(&path, bytes)?;
+                            safety::strip_execute(&path)
+                        })
+                        .await
+                        .unwrap_or_else(|err| Err(io::Error::other(err))),
+                        Err(err) => Err(io::Error::other(err.to_string())),
+                    };
+
+                    saved.err()
+                })
+                .and_then(|err| Task::done(Message::SaveAttachmentFailed(Arc::new(err))));
+            }
+            Message::SaveAttachmentFailed(err) => {
+                self.dialog = Dialog::new(
+                    "Saving the attachment failed",
+                    err.to_string(),
+                    None,
+                    Action::Close,
+                );
+            }
+            Message::OpenAttachment(attachment) => {
+                if !safety::is_safe_to_open(&attachment) {
+                    self.dialog = Dialog::new(
+                        "Open this file?",
+                        format!(
+                            "{}\n\nThis isn't a picture, video, sound, PDF or text file, so opening it may run it as a program. Only open it if you trust whoever sent it.",
+                            attachment.file_name()
+                        ),
+                        None,
+                        Action::OpenAttachment,
+                    );
+                    self.pending_attachment = Some(attachment);
+                } else {
+                    return self.open_attachment(attachment);
+                }
+            }
+            Message::ConfirmOpenAttachment => {
+                self.dialog.close();
+
+                if let Some(attachment) = self.pending_attachment.take() {
+                    return self.open_attachment(attachment);
+                }
+            }
+            Message::OpenAttachmentFailed(err) => {
+                self.dialog = Dialog::new(
+                    "Opening the attachment failed",
+                    err.to_string(),
+                    None,
+                    Action::Close,
+                );
+            }
+            Message::AttachFile(path) => {
+                if self.open_chat.is_some() && !self.composer.is_attached(&path) {
+                    return Task::future(Staged::read(path)).map(|result| match result {
+                        Ok(staged) => Message::Staged(staged),
+                        Err(err) => {
+                            Message::Error(Arc::new(ManagerManagerError::Upload(err.to_string())))
+                        }
+                    });
+                }
+            }
+            Message::Staged(staged) => {
+                // the chat may have been closed while the file was read
+                if self.open_chat.is_some() {
+                    self.composer.attach(staged);
+                }
+            }
+            Message::Raise => {
+                return window::latest().and_then(|id| {
+                    Task::batch([window::minimize(id, false), window::gain_focus(id)])
+                });
+            }
+            Message::LinkHovered(link) => self.hovered_link = link,
+            Message::LinkClicked(link) => {
+                if DeepLink::parse(&link).is_some() {
+                    return self.update(Message::OpenDeepLink(link));
+                } else if browser::is_unusual(&link) {
+                    self.dialog = Dialog::new(
+                        "Open this link?",
+                        format!(
+                            "{link}\n\nThis isn't a web page, opening it may start another application."
+                        ),
+                        None,
+                        Action::OpenLink,
+                    );
+                    self.pending_link = Some(link);
+                } else {
+                    browser::open(&link, self.browser.as_deref());
+                }
+            }
+            Message::OpenLink => {
+                self.dialog.close();
+
+                if let Some(link) = self.pending_link.take() {
+                    browser::open(&link, self.browser.as_deref());
+                }
+            }
+            Message::OpenDeepLink(link) => {
+                let lookup = |title: String, what| {
+                    Dialog::new(
+                        title,
+                        format!(
+                            "Foghorn can't look up contacts by {what} yet. Once they write to you, or you add them on your phone, they show up here."
+                        ),
+                        None,
+                        Action::Close,
+                    )
+                };
 
-        (
-            Self {
-                manager_manager,
-                dialog: Dialog::default(),
-                chats: HashMap::new(),
-                now: None,
-                tz: None,
-                open_chat: None,
-                message_content: text_editor::Content::new(),
-                quote: None,
-                editing: None,
-                split_at: 313.5,
-            },
-            Task::batch([
-                Task::perform(async { TimeZone::system() }, Message::Tz),
-                Task::perform(async { Timestamp::now() }, Message::Now),
-                Task::perform(register, |err| Message::ManagerError(err.map(Arc::new))),
-            ]),
-        )
-    }
+                match DeepLink::parse(&link) {
+                    Some(DeepLink::Phone(number)) => {
+                        self.dialog = lookup(format!("Add {number}"), "phone number");
+                    }
+                    Some(DeepLink::Username) => {
+                        self.dialog = lookup("Add contact".to_owned(), "username");
+                    }
+                    Some(DeepLink::Group(key)) => {
+                        let group = self
+                            .chats
+                            .keys()
+                            .find(|chat| {
+                                matches!(chat, message::Chat::Group(group) if group.key == key)
+                            })
+                            .cloned();
 
-    pub fn update(&mut self, message: Message) -> Task<Message> {
-        match message {
-            Message::ManagerError(manager_error) => {
-                if let Some(error) = manager_error {
-                    return match &*error {
-                        &ManagerError::NotYetRegisteredError
-                        | &ManagerError::NoProvisioningMessageReceived
-                        | &ManagerError::ProvisioningError(ProvisioningError::MissingMessage) => {
-                            self.update(Message::LinkSecondary)
-                        }
-                        err => {
+                        if let Some(group) = group {
+                            return Task::batch([
+                                self.update(Message::OpenChat(group)),
+                                self.update(Message::Raise),
+                            ]);
+                        } else if self.chats.is_empty() {
+                            // the group may still be on its way
+                            self.pending_invite = Some(key);
+                        } else {
                             self.dialog = Dialog::new(
-                                "Oops! Something went wrong.",
-                                err.to_string(),
+                                "Join group",
+                                "You aren't a member of this group. Foghorn can't join groups by invite link yet, ask one of its members to add you.",
                                 None,
-                                Action::RetryLinking,
-                            )
-                            .monospace();
-                            Task::none()
+                                Action::Close,
+                            );
                         }
-                    };
+                    }
+                    None => {}
                 }
 
-                self.dialog.close();
+                return self.update(Message::Raise);
+            }
+            Message::SplitAt(split_at) => self.split_at = split_at.clamp(153.0, 313.5),
+            Message::Now(now) => self.now = Some(now),
+            Message::Tz(tz) => self.tz = Some(tz),
+            Message::Composer(message) => {
+                // typing goes back to writing a message
+                if let composer::Message::Edit(action) = &message
+                    && action.is_edit()
+                {
+                    self.focused_message = None;
+                }
 
-                return Task::future(self.manager_manager.clone().stream_mesages())
-                    .then(Task::stream)
-                    .map(Message::Received);
+                if let Some(message) = self.composer.update(message) {
+                    return self.update(message);
+                }
             }
-            Message::LinkSecondary => {
-                let (tx, rx) = oneshot::channel();
+            Message::Send => {
+                if self
+                    .open_chat
+                    .as_ref()
+                    .is_some_and(|chat| self.is_read_only(chat))
+                {
+                    return Task::none();
+                }
 
-                return Task::batch([
-                    Task::perform(self.manager_manager.clone().link_secondary(tx), |err| {
-                        Message::ManagerError(err.map(Arc::new))
-                    }),
-                    Task::perform(rx, |url| Message::QrCode(url.unwrap())),
-                ]);
+                let Some(draft) = self.composer.take_draft() else {
+                    return Task::none();
+                };
+
+                let chat = self.open_chat.clone().unwrap();
+                return self.send(chat, draft);
             }
-            Message::QrCode(url) => {
-                self.dialog = Dialog::new(
-                    "Link your device",
-                    "Scan the QR code below to link your device.",
-                    Some(qr_code::Data::new(url).unwrap()),
-                    Action::None,
-                );
+            Message::Sent(pending, sent) => {
+                self.send_states.remove(&sent.0);
+
+                if let Some(pending) = pending
+                    && let Some(messages) = self.chats.get_mut(&sent.0)
+                {
+                    messages.remove(&pending);
+                }
+
+                return self.update(Message::Received(sent));
             }
-            Message::Received((chat, message)) => match message {
-                SignalAction::Contact => {
-                    self.chats.entry(chat).or_insert_with(|| [].into());
+            Message::Retrying(chat, retry) => {
+                self.send_states.insert(chat, SendState::Retrying(retry));
+            }
+            Message::SendFailed(chat, pending, draft, err) => {
+                // it reached the recipients, so it's neither kept for retrying nor put back
+                if matches!(*err, ManagerManagerError::Decode) {
+                    self.send_states.remove(&chat);
+
+                    if let Some(pending) = pending
+                        && let Some(messages) = self.chats.get_mut(&chat)
+                    {
+                        messages.remove(&pending);
+                    }
+
+                    return self.update(Message::Error(err));
                 }
-                SignalAction::Message(message, notif) => {
+
+                let failed = pending.filter(|pending| {
                     self.chats
-                        .entry(chat)
-                        .and_modify(|m| {
-                            m.insert(message.timestamp, message.clone());
-                        })
-                        .or_insert_with(|| [(message.timestamp, message.clone())].into());
-
-                    if notif {
-                        return Task::future(async move {
-                            let body = message
-                                .body
-                                .as_deref()
-                                .map(|spans| {
-                                    spans
-                                        .iter()
-                                        .map(|span| span.text.as_ref())
-                                        .collect::<String>()
-                                })
-                                .unwrap_or_default();
-
-                            if let Ok(Err(err)) = spawn_blocking(move || {
-                                Notification::new()
-                                    .summary(&message.sender.name)
-                                    .body(&body)
-                                    .show()
-                            })
-                            .await
-                            {
-                                warn!("{err}");
-                            }
-                        })
-                        .discard();
+                        .get_mut(&chat)
+                        .and_then(|messages| messages.get_mut(pending))
+                        .and_then(|message| Arc::make_mut(message).sending.as_mut())
+                        .map(|sending| sending.failed = true)
+                        .is_some()
+                });
+
+                // a message that's shown can be sent again from the chat, anything else is put
+                // back into the composer
+                if let Some(draft) = draft {
+                    if let Some(failed) = failed {
+                        self.unsent.insert(failed, draft);
+                    } else if self.open_chat.as_ref() == Some(&chat) {
+                        self.composer.restore_draft(draft);
                     }
                 }
-                SignalAction::Replace(old_ts, message) => {
-                    self.chats.get_mut(&chat).unwrap().insert(old_ts, message);
+
+                // nothing can be sent to a contact who left, but their history stays readable
+                if matches!(*err, ManagerManagerError::Unregistered)
+                    && let message::Chat::Contact(_) = chat
+                {
+                    self.send_states.remove(&chat);
+                    self.unregistered.insert(&chat);
+                    return Task::none();
                 }
-                SignalAction::Delete(timestamp) => {
-                    self.chats.get_mut(&chat).unwrap().remove(&timestamp);
+
+                // failures that retrying can't fix stay next to the composer, the rest pop up
+                if matches!(
+                    *err,
+                    ManagerManagerError::Unregistered
+                        | ManagerManagerError::NotAMember
+                        | ManagerManagerError::GaveUp(..)
+                        | ManagerManagerError::ProofRequired(_)
+                        | ManagerManagerError::RateLimited(_)
+                ) {
+                    self.send_states.insert(chat, SendState::Failed(err));
+                } else {
+                    self.send_states.remove(&chat);
+                    return self.update(Message::Error(err));
                 }
-            },
-            Message::CloseDialog => self.dialog.close(),
-            Message::OpenChat(open_chat) => {
-                self.open_chat = Some(open_chat);
-                self.message_content = text_editor::Content::new();
-                self.quote = None;
-                return Task::batch([focus_next(), snap_to("messages", RelativeOffset::END)]);
             }
-            Message::NextChat => {
-                let mut contacts = self.chats.keys().collect::<Vec<_>>();
-                contacts.sort_by_key(|c| Reverse(self.chats[c].last_key_value().map(|(k, _)| k)));
+            Message::Challenged(chat, pending, token, draft) => {
+                let challenged = Challenged {
+                    token: token.clone(),
+                    chat: chat.clone(),
+                    resend: Some((pending, draft)),
+                };
+                self.dialog = challenged.dialog(None);
+                self.challenged = Some(challenged);
 
-                let mut iter = contacts.iter().chain(contacts.iter());
-                if let Some(open_chat) = self.open_chat.as_ref() {
-                    iter.by_ref().find(|chat| **chat == open_chat);
-                }
+                // the draft is sent again once the captcha is solved, or put back if it isn't
+                return self.update(Message::SendFailed(
+                    chat,
+                    pending,
+                    None,
+                    Arc::new(ManagerManagerError::ProofRequired(token)),
+                ));
+            }
+            Message::OpenCaptcha => browser::open(CAPTCHA_URL, self.browser.as_deref()),
+            Message::PasteCaptcha => return clipboard::read().map(Message::SubmitCaptcha),
+            Message::SubmitCaptcha(pasted) => {
+                let Some(challenged) = &self.challenged else {
+                    return Task::none();
+                };
 
-                if let Some(chat) = iter.next().copied().cloned() {
-                    return self.update(Message::OpenChat(chat));
+                let Some(captcha) = pasted
+                    .as_deref()
+                    .and_then(|pasted| pasted.trim().strip_prefix(CAPTCHA_SCHEME))
+                    .filter(|captcha| !captcha.is_empty())
+                else {
+                    self.dialog = challenged.dialog(Some(
+                        "The clipboard doesn't hold the link of a solved challenge.",
+                    ));
+                    return Task::none();
+                };
+
+                return Task::future(
+                    self.manager_manager
+                        .clone()
+                        .submit_captcha(challenged.token.clone(), captcha.to_owned()),
+                )
+                .map(|result| match result {
+                    Ok(()) => Message::CaptchaSolved,
+                    Err(err) => Message::Error(Arc::new(err)),
+                });
+            }
+            Message::CaptchaSolved => {
+                self.dialog.close();
+
+                let Some(Challenged { chat, resend, .. }) = self.challenged.take() else {
+                    return Task::none();
+                };
+
+                // the contact is resolved again along with its history
+                let Some((pending, draft)) = resend else {
+                    return Task::future(self.manager_manager.clone().resync_chat(chat)).then(
+                        |result| match result {
+                            Ok(()) => Task::none(),
+                            Err(err) => Task::done(Message::Error(Arc::new(err))),
+                        },
+                    );
+                };
+
+                // the message is shown as pending again when it's sent again
+                if let Some(pending) = pending
+                    && let Some(messages) = self.chats.get_mut(&chat)
+                {
+                    messages.remove(&pending);
                 }
+
+                return self.send(chat, draft);
             }
-            Message::PreviousChat => {
-                let mut contacts = self.chats.keys().collect::<Vec<_>>();
-                contacts.sort_by_key(|c| Reverse(self.chats[c].last_key_value().map(|(k, _)| k)));
+            Message::RetryUnsent(pending) => {
+                let Some(chat) = self.open_chat.clone() else {
+                    return Task::none();
+                };
 
-                let mut iter = contacts.iter().chain(contacts.iter());
-                if let Some(open_chat) = self.open_chat.as_ref() {
-                    iter.by_ref().rfind(|chat| **chat == open_chat);
+                let Some(draft) = self.unsent.remove(&pending) else {
+                    return Task::none();
+                };
+
+                // the message is shown as pending again when it's sent again
+                if let Some(messages) = self.chats.get_mut(&chat) {
+                    messages.remove(&pending);
                 }
 
-                if let Some(chat) = iter.next_back().copied().cloned() {
-                    return self.update(Message::OpenChat(chat));
+                return self.send(chat, draft);
+            }
+            Message::DiscardUnsent(pending) => {
+                self.unsent.remove(&pending);
+
+                if let Some(chat) = &self.open_chat
+                    && let Some(messages) = self.chats.get_mut(chat)
+                {
+                    messages.remove(&pending);
                 }
             }
-            Message::Mention(uuid) => {
-                if let Some(chat) = self.chats.keys().find(|chat| chat.uuid() == Some(uuid)) {
-                    return self.update(Message::OpenChat(chat.clone()));
+            Message::Scrolled(offset) => {
+                if let Some(chat) = &self.open_chat
+                    && offset.is_finite()
+                {
+                    self.session.scroll(chat, offset);
                 }
             }
-            Message::Quote(quote) => {
-                let was_editing = self.editing.is_some();
+            Message::CloseRequested => {
+                self.session.save();
 
-                self.quote = quote.as_deref().cloned().map(Into::into);
+                // the store is flushed without blocking the window, which stays until then
+                return Task::future(self.manager_manager.clone().shutdown())
+                    .then(|()| iced::exit());
+            }
+            Message::WindowFocused(focused) => {
+                self.window_focused = focused;
 
-                if was_editing && quote.is_some() {
-                    _ = self.update(Message::Edit(None));
+                // the window may be closed next, which can't be waited for
+                if !focused {
+                    self.session.save();
                 }
-                return focus_next();
-            }
-            Message::Edit(message) => {
-                let was_editing = self.editing.is_some();
 
-                self.editing = message.as_deref().map(|message| message.timestamp);
-                if let Some(message) = message.as_deref() {
-                    _ = self.update(Message::Quote(None));
+                if focused {
+                    if let Some(chat) = &self.open_chat {
+                        self.notifications.remove(chat);
+                    }
 
-                    self.message_content = text_editor::Content::with_text(
-                        &body_ranges_to_markdown(
-                            message.original_body.as_deref(),
-                            &message.body_ranges,
-                        )
-                        .unwrap_or_default(),
-                    );
-                    return focus_next();
-                } else if was_editing {
-                    self.message_content = text_editor::Content::new();
+                    return self.mark_read();
                 }
             }
-            Message::EditLast => {
-                let last_sent = self.open_chat.as_ref().and_then(|chat| {
-                    self.chats[chat]
-                        .values()
-                        .rfind(|message| message.sender.is_self)
-                });
+            Message::Idle(idle) => {
+                self.idle = idle;
 
-                return self.update(Message::Edit(last_sent.cloned()));
+                // what arrived while away is summarized once the user is back
+                if !idle {
+                    return Task::batch([
+                        self.mark_read(),
+                        if self.unnotified.is_empty() {
+                            Task::none()
+                        } else {
+                            Task::done(Message::Notify)
+                        },
+                    ]);
+                }
             }
-            Message::Escape => {
-                _ = self.update(Message::Quote(None));
-                _ = self.update(Message::Edit(None));
+            Message::NotifierDetected(notifier) => {
+                info!("Notifications are shown through {notifier}");
+                self.notifier = notifier;
             }
-            Message::SplitAt(split_at) => self.split_at = split_at.clamp(153.0, 313.5),
-            Message::Now(now) => self.now = Some(now),
-            Message::Tz(tz) => self.tz = Some(tz),
-            Message::ContentEdit(action) => self.message_content.perform(action),
-            Message::Send => {
-                let content = take(&mut self.message_content).text().trim().to_owned();
+            // only the unread counts are updated while away
+            Message::Notify if self.idle => {}
+            Message::Notify => {
+                let mut chats = Vec::<(message::Chat, Vec<Arc<message::Message>>)>::new();
 
-                let manager_manager = self.manager_manager.clone();
+                for (chat, message) in take(&mut self.unnotified) {
+                    match chats.iter_mut().find(|(c, _)| *c == chat) {
+                        Some((_, messages)) => messages.push(message),
+                        None => chats.push((chat, vec![message])),
+                    }
+                }
 
-                return if let Some(timestamp) = self.editing.take() {
-                    Task::future(manager_manager.edit(
-                        self.open_chat.clone().unwrap(),
-                        content,
-                        timestamp,
-                    ))
-                } else {
-                    Task::future(manager_manager.send(
-                        self.open_chat.clone().unwrap(),
+                return Task::batch(chats.into_iter().map(|(chat, messages)| {
+                    let (id, count) = self.notifications.entry(chat.clone()).or_default();
+                    *count += messages.len();
+
+                    let latest = messages.last().unwrap();
+                    let body = latest
+                        .body
+                        .as_deref()
+                        .map(|spans| {
+                            spans
+                                .iter()
+                                .map(|span| span.text.as_ref())
+                                .collect::<String>()
+                        })
+                        .unwrap_or_default();
+
+                    let (summary, body) = match (&chat, *count) {
+                        (message::Chat::Contact(_), 1) => (latest.sender.name.clone(), body),
+                        (message::Chat::Group(group), 1) => (
+                            group.title.clone(),
+                            format!("{}: {body}", latest.sender.name),
+                        ),
+                        (message::Chat::Contact(contact), count) => {
+                            (format!("{count} new messages from {}", contact.name), body)
+                        }
+                        (message::Chat::Group(group), count) => (
+                            format!("{count} new messages in {}", group.title),
+                            format!("{}: {body}", latest.sender.name),
+                        ),
+                    };
+
+                    // replaces the chat's previous notification in place, which keeps waiting
+                    // for the reply of the first
+                    if let Some(id) = *id {
+                        return Task::future(self.notifier.show(summary, body, Some(id)))
+                            .map(move |id| Message::Notified(chat.clone(), id));
+                    }
+
+                    Task::run(
+                        self.notifier.show_new(summary, body),
+                        move |shown| match shown {
+                            Shown::Id(id) => Message::Notified(chat.clone(), id),
+                            Shown::Closed(id, reply) => {
+                                Message::NotificationClosed(chat.clone(), id, reply)
+                            }
+                        },
+                    )
+                }));
+            }
+            Message::TypingExpired => self.typing.expire(Timestamp::now()),
+            Message::MarkRead => {
+                self.marking_read = false;
+                return self.mark_read();
+            }
+            Message::Notified(chat, id) => {
+                // the chat may have been opened in the meantime, and a replaced notification
+                // keeps its id
+                if let Some(notification) = self.notifications.get_mut(&chat) {
+                    notification.0 = id.or(notification.0);
+                }
+            }
+            Message::NotificationClosed(chat, id, reply) => {
+                if self
+                    .notifications
+                    .get(&chat)
+                    .is_some_and(|(shown, _)| *shown == Some(id))
+                {
+                    self.notifications.remove(&chat);
+                }
+
+                let Some(content) = reply.filter(|reply| !reply.trim().is_empty()) else {
+                    return Task::none();
+                };
+
+                // answered without opening the chat, so nothing is pending in it
+                let (retries, retrying) = mpsc::unbounded();
+                let resend = Draft::text(content.clone());
+
+                return Task::batch([
+                    Task::run(retrying, {
+                        let chat = chat.clone();
+                        move |retry| Message::Retrying(chat.clone(), retry)
+                    }),
+                    Task::future(self.manager_manager.clone().send(
+                        chat.clone(),
                         content,
-                        self.quote.take(),
+                        None,
+                        vec![],
+                        retries,
                     ))
+                    .map(move |result| match result {
+                        Ok(sent) => Message::Sent(None, sent),
+                        Err(ManagerManagerError::ProofRequired(token)) => {
+                            Message::Challenged(chat.clone(), None, token, resend.clone())
+                        }
+                        Err(err) => Message::SendFailed(chat.clone(), None, None, Arc::new(err)),
+                    }),
+                ]);
+            }
+            Message::DismissSendState => {
+                if let Some(chat) = &self.open_chat {
+                    self.send_states.remove(chat);
+                }
+            }
+            Message::SendAnyway => {
+                if let Some(chat) = &self.open_chat {
+                    self.unregistered.remove(chat);
                 }
-                .and_then(Task::done)
-                .map(Message::Received);
             }
         }
 
@@ -298,23 +2989,75 @@ impl App {
 
     pub fn view(&self) -> Element<'_, Message> {
         responsive(|size| {
-            let mut contacts = self.chats.keys().collect::<Vec<_>>();
-            contacts.sort_by_key(|c| Reverse(self.chats[c].last_key_value().map(|(k, _)| k)));
+            let tab = |label, show_contacts| {
+                button(text(label))
+                    .style(if self.show_contacts == show_contacts {
+                        button::subtle
+                    } else {
+                        button::text
+                    })
+                    .padding([0, 5])
+                    .on_press(Message::ShowContacts(show_contacts))
+            };
+
             let contacts = column![
-                "Chats",
+                row![
+                    tab("Chats", false),
+                    tab("Contacts", true),
+                    space::horizontal(),
+                    button(text("Stickers").size(12))
+                        .style(button::text)
+                        .padding(0)
+                        .on_press(Message::OpenStickers),
+                ]
+                .align_y(Center),
                 rule::horizontal(1),
-                scrollable(
-                    column(contacts.into_iter().map(|c| {
-                        button(c.as_iced_widget())
+                if self.show_contacts {
+                    self.contact_list()
+                } else if self.conversations().is_empty() {
+                    center(text("No chats yet").style(text::secondary)).into()
+                } else {
+                    scrollable(
+                        column(self.conversations().into_iter().map(|c| {
+                            let typing = self
+                                .typing
+                                .is_typing(c)
+                                .then(|| text("✎").style(text::secondary));
+                            let reacted = self
+                                .reacted
+                                .contains(c)
+                                .then(|| text("●").size(10).style(text::primary));
+                            let highlighted = self.highlighted.as_ref() == Some(c);
+
+                            button(
+                                row![c.as_iced_widget(), space::horizontal(), typing, reacted]
+                                    .spacing(5)
+                                    .align_y(Center),
+                            )
                             .on_press(Message::OpenChat(c.clone()))
                             .padding(5)
-                            .style(button::subtle)
+                            .style(move |t: &iced::Theme, status| {
+                                let style = button::subtle(t, status);
+
+                                if highlighted {
+                                    button::Style {
+                                        border: border::rounded(5)
+                                            .color(t.palette().primary.base.color)
+                                            .width(1),
+                                        ..style
+                                    }
+                                } else {
+                                    style
+                                }
+                            })
                             .into()
-                    }))
+                        }))
+                        .spacing(5),
+                    )
+                    .auto_scroll(true)
                     .spacing(5)
-                )
-                .auto_scroll(true)
-                .spacing(5)
+                    .into()
+                }
             ]
             .spacing(5)
             .padding(padding::all(5).right(0));
@@ -324,95 +3067,171 @@ impl App {
                 && let Some(open_chat) = self.open_chat.as_ref()
             {
                 let now = now.to_zoned(tz.clone());
+                let messages = &self.chats[open_chat];
+
+                let days = if self.calendar.is_open() {
+                    messages
+                        .keys()
+                        .map(|id| id.timestamp.to_zoned(tz.clone()).date())
+                        .collect()
+                } else {
+                    BTreeSet::new()
+                };
+
+                let timer = messages
+                    .values()
+                    .rev()
+                    .find_map(|message| match &message.system {
+                        Some(message::SystemMessage::Timer { seconds, .. }) => Some(*seconds),
+                        _ => None,
+                    })
+                    .unwrap_or_default();
+
+                let about = open_chat.contact().and_then(|contact| {
+                    let about = [contact.about_emoji.as_deref(), contact.about.as_deref()]
+                        .into_iter()
+                        .flatten()
+                        .collect::<Vec<_>>()
+                        .join(" ");
+
+                    (!about.is_empty()).then(|| text(about).size(12).style(text::secondary))
+                });
 
                 column![
-                    text(open_chat.name()),
+                    row![
+                        column![text(open_chat.name()), about],
+                        space::horizontal(),
+                        button(text("Jump to date").size(12))
+                            .style(button::text)
+                            .padding(0)
+                            .on_press(Message::ToggleCalendar),
+                        button(text("⋯"))
+                            .style(button::text)
+                            .on_press(Message::ToggleChatMenu),
+                    ]
+                    .spacing(10)
+                    .align_y(Center),
+                    self.chat_search.as_iced_widget(),
                     rule::horizontal(1),
-                    scrollable(
-                        column(self.chats[open_chat].values().map(|message| {
-                            message.as_iced_widget(&now, tz, size.width - self.split_at)
-                        }),)
+                    stack![
+                        scrollable(
+                            column(
+                                self.history_from
+                                    .map(|_| {
+                                        Element::from(
+                                            button(text("Show earlier messages").size(12))
+                                                .style(button::secondary)
+                                                .on_press(Message::ShowEarlier),
+                                        )
+                                    })
+                                    .into_iter()
+                                    .chain(
+                                        messages
+                                            .range(
+                                                MessageId::first_at(
+                                                    self.history_from.unwrap_or(Timestamp::MIN),
+                                                )..,
+                                            )
+                                            .map(|(id, message)| {
+                                                let message = message.as_iced_widget(
+                                                    &now,
+                                                    tz,
+                                                    size.width - self.split_at,
+                                                    &self.avatars,
+                                                    self.sealed_sender_icons,
+                                                );
+
+                                                if self.focused_message == Some(*id) {
+                                                    container(message)
+                                                        .style(|t: &iced::Theme| container::Style {
+                                                            border: border::rounded(5)
+                                                                .color(
+                                                                    t.palette().primary.base.color,
+                                                                )
+                                                                .width(1),
+                                                            ..Default::default()
+                                                        })
+                                                        .into()
+                                                } else {
+                                                    message
+                                                }
+                                            })
+                                    )
+                            )
+                            .spacing(5),
+                        )
+                        .id("messages")
+                        .on_scroll(|viewport| Message::Scrolled(viewport.relative_offset().y))
+                        .auto_scroll(true)
+                        .height(Fill)
+                        .anchor_top()
                         .spacing(5),
-                    )
-                    .id("messages")
-                    .auto_scroll(true)
-                    .height(Fill)
-                    .anchor_top()
-                    .spacing(5),
-                    self.quote
+                        self.calendar
+                            .as_iced_widget(&days)
+                            .map(|calendar| container(calendar).align_right(Fill).padding(5)),
+                        self.chat_menu
+                            .as_iced_widget(open_chat, self.muted.contains(open_chat), timer)
+                            .map(|menu| container(menu).align_right(Fill).padding(5)),
+                        self.selection.as_ref().map(|_| Self::selection_bar()),
+                    ],
+                    self.open_chat
                         .as_ref()
-                        .map(|quote| quote.as_iced_widget(&now, tz)),
-                    self.editing.as_ref().and(Some(
-                        container(row![edit(), " Edit message"].align_y(Center))
-                            .padding(10)
-                            .style(|t: &iced::Theme| {
-                                let pair = t.palette().primary.weak;
-                                container::Style {
-                                    background: Some(pair.color.into()),
-                                    text_color: Some(pair.text),
-                                    border: border::rounded(5),
-                                    ..Default::default()
-                                }
-                            })
-                    )),
-                    rule::horizontal(1),
-                    text_editor(&self.message_content)
-                        .min_height(20)
-                        .on_action(Message::ContentEdit)
-                        .key_binding(|key_press| {
-                            let modifiers = key_press.modifiers;
-                            let binding = text_editor::Binding::from_key_press(key_press)?;
-
-                            Some(match binding {
-                                text_editor::Binding::Enter if !modifiers.shift() => {
-                                    text_editor::Binding::Custom(Message::Send)
-                                }
-                                text_editor::Binding::Backspace
-                                    if modifiers.command()
-                                        && self.message_content.selection().is_none() =>
-                                {
-                                    text_editor::Binding::Sequence(vec![
-                                        text_editor::Binding::Select(text_editor::Motion::WordLeft),
-                                        text_editor::Binding::Backspace,
-                                    ])
-                                }
-                                text_editor::Binding::Delete
-                                    if modifiers.command()
-                                        && self.message_content.selection().is_none() =>
-                                {
-                                    text_editor::Binding::Sequence(vec![
-                                        text_editor::Binding::Select(
-                                            text_editor::Motion::WordRight,
-                                        ),
-                                        text_editor::Binding::Delete,
-                                    ])
-                                }
-                                text_editor::Binding::Move(text_editor::Motion::Up)
-                                    if self
-                                        .message_content
-                                        .line(0)
-                                        .is_none_or(|line| line.text.is_empty())
-                                        && self.message_content.line_count() <= 1 =>
-                                {
-                                    text_editor::Binding::Custom(Message::EditLast)
-                                }
-                                binding => binding,
-                            })
-                        }),
+                        .and_then(|chat| self.typing.label(chat))
+                        .map(|label| text(label).size(12).style(text::secondary)),
+                    self.open_chat.as_ref().and_then(|chat| self
+                        .send_states
+                        .get(chat)
+                        .map(|state| Self::send_state(chat, state))),
+                    if self.is_read_only(open_chat) {
+                        Self::read_only_banner(open_chat)
+                    } else {
+                        self.composer
+                            .as_iced_widget(&now, tz, self.focused_message.is_some())
+                            .map(Message::Composer)
+                    },
                 ]
                 .spacing(5)
                 .padding(padding::all(5).left(0))
                 .into()
             } else {
-                Element::new(space::horizontal())
+                self.empty_chat()
             };
 
             let base = vertical_split(contacts, chat, self.split_at, Message::SplitAt)
                 .strategy(Strategy::Start);
 
+            let base = stack![
+                container(base).width(Fill).height(Fill),
+                self.hovered_link.as_deref().map(|link| {
+                    container(
+                        container(text(link).size(12))
+                            .padding([2, 6])
+                            .style(container::rounded_box),
+                    )
+                    .align_bottom(Fill)
+                    .padding(5)
+                })
+            ];
+
+            let base = self.stickers.as_iced_widget(base);
+            let base = match &self.tz {
+                Some(tz) => self.details.as_iced_widget(base, &self.receipts, tz),
+                None => base,
+            };
+            let base = self.group_details.as_iced_widget(
+                base,
+                match &self.open_chat {
+                    Some(message::Chat::Group(group)) => Some(group.as_ref()),
+                    _ => None,
+                },
+            );
+            let base = self.gallery.as_iced_widget(base);
+            let base = self.member_card.as_iced_widget(base);
+            let base = self.lightbox.as_iced_widget(base);
+
             let dialog = self
                 .dialog
-                .as_iced_dialog(container(base).width(Fill).height(Fill))
+                .as_iced_dialog(base)
                 .max_height(320)
                 .max_width(iced_dialog::dialog::DEFAULT_MAX_WIDTH);
 
@@ -421,10 +3240,30 @@ impl App {
         .into()
     }
 
-    #[expect(clippy::unused_self)]
     pub fn subscription(&self) -> Subscription<Message> {
         Subscription::batch([
-            every(Duration::from_secs(1)).map(|_| Message::Now(Timestamp::now())),
+            Subscription::run(minutes),
+            Subscription::run(idle::watch).map(Message::Idle),
+            window::close_requests().map(|_| Message::CloseRequested),
+            if self.avatars.is_animated() {
+                time::every(image::MIN_FRAME_DELAY).map(Message::AvatarFrame)
+            } else {
+                Subscription::none()
+            },
+            if self.qr_code_shown.is_some() {
+                time::every(Duration::from_secs(1)).map(Message::LinkingTick)
+            } else {
+                Subscription::none()
+            },
+            event::listen_with(|event, _, _| match event {
+                Event::Window(window::Event::FileDropped(path)) => Some(Message::AttachFile(path)),
+                Event::Window(window::Event::Rescaled(scale_factor)) => {
+                    Some(Message::Rescaled(scale_factor))
+                }
+                Event::Window(window::Event::Focused) => Some(Message::WindowFocused(true)),
+                Event::Window(window::Event::Unfocused) => Some(Message::WindowFocused(false)),
+                _ => None,
+            }),
             keyboard::listen().filter_map(|event| {
                 let keyboard::Event::KeyPressed { key, modifiers, .. } = event else {
                     return None;
@@ -437,10 +3276,98 @@ impl App {
                             Message::NextChat
                         })
                     }
+                    keyboard::Key::Named(keyboard::key::Named::Tab) => Some(if modifiers.shift() {
+                        Message::FocusPrevious
+                    } else {
+                        Message::FocusNext
+                    }),
                     keyboard::Key::Named(keyboard::key::Named::Escape) => Some(Message::Escape),
+                    keyboard::Key::Named(keyboard::key::Named::ArrowUp) if modifiers.alt() => {
+                        Some(Message::FocusOlder)
+                    }
+                    keyboard::Key::Named(keyboard::key::Named::ArrowDown) if modifiers.alt() => {
+                        Some(Message::FocusNewer)
+                    }
+                    keyboard::Key::Named(keyboard::key::Named::ArrowUp) => {
+                        Some(Message::HighlightPrevious)
+                    }
+                    keyboard::Key::Named(keyboard::key::Named::ArrowDown) => {
+                        Some(Message::HighlightNext)
+                    }
+                    keyboard::Key::Named(keyboard::key::Named::Enter) => {
+                        Some(Message::OpenHighlighted)
+                    }
+                    keyboard::Key::Character(c) if modifiers.alt() => c
+                        .parse()
+                        .ok()
+                        .filter(|n| (1..=9).contains(n))
+                        .map(Message::OpenNth),
+                    keyboard::Key::Character(c)
+                        if c.eq_ignore_ascii_case("r") && !modifiers.command() =>
+                    {
+                        Some(Message::QuoteFocused)
+                    }
+                    keyboard::Key::Character(c)
+                        if c.eq_ignore_ascii_case("e") && !modifiers.command() =>
+                    {
+                        Some(Message::EditFocused)
+                    }
+                    keyboard::Key::Character(c)
+                        if c.eq_ignore_ascii_case("i") && !modifiers.command() =>
+                    {
+                        Some(Message::InfoFocused)
+                    }
+                    keyboard::Key::Character(c)
+                        if c.eq_ignore_ascii_case("n")
+                            && modifiers.command()
+                            && modifiers.shift() =>
+                    {
+                        Some(Message::NoteToSelf)
+                    }
                     _ => None,
                 }
             }),
+            if self.lightbox.is_open() {
+                keyboard::listen().filter_map(|event| {
+                    let keyboard::Event::KeyPressed { key, .. } = event else {
+                        return None;
+                    };
+                    match key.as_ref() {
+                        keyboard::Key::Named(keyboard::key::Named::ArrowLeft) => {
+                            Some(Message::LightboxPrevious)
+                        }
+                        keyboard::Key::Named(keyboard::key::Named::ArrowRight) => {
+                            Some(Message::LightboxNext)
+                        }
+                        _ => None,
+                    }
+                })
+            } else {
+                Subscription::none()
+            },
         ])
     }
 }
+
+/// Ticks at the start of every minute, which is as often as the relative timestamps change.
+fn minutes() -> impl Stream<Item = Message> {
+    stream::unfold((), |()| async {
+        let into_minute = Timestamp::now().as_millisecond().rem_euclid(60_000);
+        sleep(Duration::from_millis((60_000 - into_minute) as u64)).await;
+
+        Some((Message::Now(Timestamp::now()), ()))
+    })
+}
+
+/// The first of the messages sent at a time, as search results only know when a message was
+/// sent.
+fn first_sent_at(
+    messages: &BTreeMap<MessageId, Arc<message::Message>>,
+    timestamp: Timestamp,
+) -> Option<MessageId> {
+    messages
+        .range(MessageId::first_at(timestamp)..)
+        .next()
+        .map(|(id, _)| *id)
+        .filter(|id| id.timestamp == timestamp)
+}
@@ -0,0 +1,207 @@
+use crate::log;
+use std::{collections::VecDeque, fs, path::PathBuf};
+use unicode_segmentation::UnicodeSegmentation as _;
+
+/// The number of recently used emoji offered after a colon.
+const RECENT_EMOJI: usize = 16;
+
+/// The markdown markers that come in pairs of the same char, closed as soon as they're opened.
+const PAIRED_MARKERS: [char; 3] = ['*', '~', '|'];
+
+/// The emoji of the sent messages, newest first, remembered across runs.
+#[derive(Debug, Default)]
+pub struct RecentEmoji {
+    path: PathBuf,
+    emoji: VecDeque<String>,
+}
+
+impl RecentEmoji {
+    pub fn load(path: PathBuf) -> Self {
+        let emoji = fs::read_to_string(&path)
+            .map(|recent| {
+                recent
+                    .lines()
+                    .filter(|emoji| is_emoji(emoji))
+                    .take(RECENT_EMOJI)
+                    .map(str::to_owned)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self { path, emoji }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.emoji.iter().map(String::as_str)
+    }
+
+    /// Moves the emoji used in `text` to the front.
+    pub fn record(&mut self, text: &str) {
+        let mut used = false;
+
+        for emoji in text.graphemes(true).filter(|grapheme| is_emoji(grapheme)) {
+            self.emoji.retain(|recent| recent != emoji);
+            self.emoji.push_front(emoji.to_owned());
+            used = true;
+        }
+
+        if !used {
+            return;
+        }
+
+        self.emoji.truncate(RECENT_EMOJI);
+
+        let recent = self
+            .emoji
+            .iter()
+            .fold(String::new(), |recent, emoji| recent + emoji + "\n");
+
+        if let Err(err) = self
+            .path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&self.path, recent))
+        {
+            log::error!("Failed to remember the recent emoji: {}", err);
+        }
+    }
+}
+
+/// Whether the grapheme starts with a pictographic char, so skin tones and joined sequences
+/// count as one emoji. Most of the older symbols and dingbats are only emoji when they're
+/// asked to be shown as one, or have a skin tone.
+fn is_emoji(grapheme: &str) -> bool {
+    let mut chars = grapheme.chars();
+
+    chars.next().is_some_and(|c| match c {
+        '\u{1f000}'..='\u{1faff}' => true,
+        '\u{2600}'..='\u{27bf}' => {
+            is_emoji_presentation(c)
+                || chars
+                    .next()
+                    .is_some_and(|c| matches!(c, '\u{fe0f}' | '\u{1f3fb}'..='\u{1f3ff}'))
+        }
+        _ => false,
+    })
+}
+
+/// Whether one of the symbols and dingbats is shown as an emoji by default.
+const fn is_emoji_presentation(c: char) -> bool {
+    matches!(
+        c,
+        '\u{2614}'..='\u{2615}'
+            | '\u{2648}'..='\u{2653}'
+            | '\u{267f}'
+            | '\u{2693}'
+            | '\u{26a1}'
+            | '\u{26aa}'..='\u{26ab}'
+            | '\u{26bd}'..='\u{26be}'
+            | '\u{26c4}'..='\u{26c5}'
+            | '\u{26ce}'
+            | '\u{26d4}'
+            | '\u{26ea}'
+            | '\u{26f2}'..='\u{26f3}'
+            | '\u{26f5}'
+            | '\u{26fa}'
+            | '\u{26fd}'
+            | '\u{2705}'
+            | '\u{270a}'..='\u{270b}'
+            | '\u{2728}'
+            | '\u{274c}'
+            | '\u{274e}'
+            | '\u{2753}'..='\u{2755}'
+            | '\u{2757}'
+            | '\u{2795}'..='\u{2797}'
+            | '\u{27b0}'
+            | '\u{27bf}'
+    )
+}
+
+/// The length in bytes of the `:query` being typed at the end of `before`, which the chosen
+/// emoji replaces. The colon has to start a word, so times and links aren't completed.
+pub fn emoji_query(before: &str) -> Option<usize> {
+    let start = before.rfind(':')?;
+    let query = &before[start + 1..];
+
+    let starts_word = before[..start]
+        .chars()
+        .next_back()
+        .is_none_or(char::is_whitespace);
+    let is_name = query.len() <= 32
+        && query
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '+'));
+
+    (starts_word && is_name).then_some(before.len() - start)
+}
+
+/// What typing a marker does, given the text `before` and `after` the cursor.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MarkerEdit {
+    /// Steps over the closing marker that was inserted when the pair was opened.
+    StepOver,
+    /// Completes the opening marker, inserting the closing one after the cursor.
+    Close,
+}
+
+pub fn marker_edit(before: &str, after: &str, typed: char) -> Option<MarkerEdit> {
+    if !PAIRED_MARKERS.contains(&typed) {
+        return None;
+    }
+
+    let mut following = after.chars();
+    if following.next() == Some(typed)
+        && (following.next() == Some(typed) || before.ends_with(typed))
+    {
+        return Some(MarkerEdit::StepOver);
+    }
+
+    // the second char of a marker starting a word, with nothing right after it to wrap
+    let mut preceding = before.chars().rev();
+    let opens = preceding.next() == Some(typed)
+        && preceding.next().is_none_or(char::is_whitespace)
+        && after.chars().next().is_none_or(char::is_whitespace);
+
+    opens.then_some(MarkerEdit::Close)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn completions() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut recent = RecentEmoji {
+            path: dir.path().join("recent-emoji"),
+            emoji: VecDeque::new(),
+        };
+        recent.record("hi 👋🏽 and 🎉, again 👋🏽");
+        assert_eq!(recent.iter().collect::<Vec<_>>(), ["👋🏽", "🎉"]);
+
+        recent.record("done ✓ ★ ✔ but ✅ ❤\u{fe0f} ☝🏽");
+        assert_eq!(
+            recent.iter().collect::<Vec<_>>(),
+            ["☝🏽", "❤\u{fe0f}", "✅", "👋🏽", "🎉"]
+        );
+
+        assert_eq!(emoji_query("hello :"), Some(1));
+        assert_eq!(emoji_query(":tada"), Some(5));
+        assert_eq!(emoji_query("at 10:30"), None);
+        assert_eq!(emoji_query("see https://"), None);
+        assert_eq!(emoji_query("hello : there"), None);
+
+        assert_eq!(marker_edit("some *", "", '*'), Some(MarkerEdit::Close));
+        assert_eq!(
+            marker_edit("some **bold", "**", '*'),
+            Some(MarkerEdit::StepOver)
+        );
+        assert_eq!(
+            marker_edit("some **bold*", "*", '*'),
+            Some(MarkerEdit::StepOver)
+        );
+        assert_eq!(marker_edit("2*", "", '*'), None);
+        assert_eq!(marker_edit("some *", "word", '*'), None);
+        assert_eq!(marker_edit("some ", "", '_'), None);
+    }
+}
@@ -0,0 +1,104 @@
+use crate::{image::Animation, message::Contact};
+use iced::widget::image::Handle;
+use presage::libsignal_service::protocol::ServiceId;
+use std::{collections::HashMap, time::Instant};
+
+/// The newest avatar of every contact. Messages keep the sender they were decoded with, so
+/// they're drawn with the avatar from here instead.
+#[derive(Debug, Default)]
+pub struct Avatars {
+    handles: HashMap<ServiceId, Option<Handle>>,
+    /// The frames of the avatars that are animated.
+    animations: HashMap<ServiceId, Animation>,
+    /// When the animations started, and the time of the frames shown.
+    clock: Option<(Instant, Instant)>,
+}
+
+impl Avatars {
+    /// Takes the avatar of a contact that was updated, including a removed one, returning its
+    /// encoded bytes to look for an animation in.
+    pub fn update(&mut self, contact: &Contact) -> Option<Vec<u8>> {
+        self.animations.remove(&contact.id);
+        self.handles.insert(contact.id, contact.avatar.clone());
+
+        match contact.avatar.as_ref()? {
+            Handle::Bytes(_, bytes) => Some(bytes.to_vec()),
+            _ => None,
+        }
+    }
+
+    /// Animates the avatar of a contact, unless it was replaced while it was decoded.
+    pub fn animate(&mut self, id: ServiceId, animation: Animation) {
+        if self.handles.get(&id).is_some_and(Option::is_some) {
+            self.animations.insert(id, animation);
+        }
+    }
+
+    /// The encoded bytes of the animated avatars, to decode them again at another size.
+    pub fn animated(&self) -> Vec<(ServiceId, Vec<u8>)> {
+        self.animations
+            .keys()
+            .filter_map(|id| match self.handles.get(id)? {
+                Some(Handle::Bytes(_, bytes)) => Some((*id, bytes.to_vec())),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn is_animated(&self) -> bool {
+        !self.animations.is_empty()
+    }
+
+    /// Moves the animations on to their frames at `now`.
+    pub fn tick(&mut self, now: Instant) {
+        let (_, shown) = self.clock.get_or_insert((now, now));
+        *shown = now;
+    }
+
+    pub fn get<'a>(&'a self, contact: &'a Contact) -> Option<&'a Handle> {
+        if let Some(animation) = self.animations.get(&contact.id) {
+            let elapsed = self
+                .clock
+                .map(|(started, shown)| shown - started)
+                .unwrap_or_default();
+
+            return Some(animation.frame(elapsed));
+        }
+
+        match self.handles.get(&contact.id) {
+            Some(handle) => handle.as_ref(),
+            None => contact.avatar.as_ref(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::message::test::{ALICE, contact};
+
+    fn alice(avatar: Option<Handle>) -> Contact {
+        Contact {
+            avatar,
+            ..contact(ALICE, "Alice")
+        }
+    }
+
+    #[test]
+    fn newest_avatar() {
+        let (old, new) = (
+            Handle::from_rgba(1, 1, vec![0; 4]),
+            Handle::from_rgba(1, 1, vec![255; 4]),
+        );
+        let sender = alice(Some(old.clone()));
+
+        let mut avatars = Avatars::default();
+        assert_eq!(avatars.get(&sender), Some(&old));
+
+        avatars.update(&alice(Some(new.clone())));
+        assert_eq!(avatars.get(&sender), Some(&new));
+
+        avatars.update(&alice(None));
+        assert_eq!(avatars.get(&sender), None);
+    }
+}
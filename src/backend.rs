@@ -0,0 +1,395 @@
+use crate::{
+    log,
+    manager_manager::{ManagerManagerError, RegisteredManager, challenge},
+};
+use presage::{
+    libsignal_service::{
+        prelude::{Content, ProfileKey},
+        protocol::ServiceId,
+        zkgroup::{GroupMasterKeyBytes, ProfileKeyBytes},
+    },
+    proto::{GroupContextV2, access_control::AccessRequired, member::Role},
+    store::{ContentsStore as _, Thread},
+};
+
+/// A group as stored by the backend.
+#[derive(Clone, Debug)]
+pub struct StoredGroup {
+    pub title: String,
+    pub revision: u32,
+    pub members: Vec<(ServiceId, ProfileKeyBytes)>,
+    pub description: Option<String>,
+    /// The members allowed to change the group.
+    pub admins: Vec<ServiceId>,
+    /// The secret of the group's invite link, while joining by link is allowed.
+    pub invite_link_password: Option<Vec<u8>>,
+}
+
+/// The parts of a profile shown for a contact.
+#[derive(Clone, Debug)]
+pub struct StoredProfile {
+    /// The name on the profile, unless it's left empty.
+    pub name: Option<String>,
+    pub about: Option<String>,
+    pub about_emoji: Option<String>,
+}
+
+/// Why a profile couldn't be fetched.
+#[derive(Debug)]
+pub enum ProfileError {
+    /// Signal wants a captcha solved before fetching more profiles, answered with the token.
+    ProofRequired(String),
+    Failed,
+}
+
+/// Everything the message decoding needs from Signal, so it can run against a mock.
+pub trait SignalBackend: Clone {
+    /// The service id of the linked account.
+    fn aci(&self) -> ServiceId;
+
+    /// The profile key of the linked account.
+    fn profile_key(&self) -> ProfileKeyBytes;
+
+    async fn contacts(&self) -> Vec<(ServiceId, ProfileKeyBytes)>;
+
+    async fn groups(&self) -> Vec<(GroupMasterKeyBytes, StoredGroup)>;
+
+    async fn group(&self, key: GroupMasterKeyBytes) -> Option<StoredGroup>;
+
+    /// The stored messages of a thread, oldest first.
+    async fn messages(&self, thread: &Thread) -> Vec<Content>;
+
+    /// Whether more than a single message of the thread is stored. Received messages are
+    /// stored before they're decoded, so the one being decoded doesn't count as history.
+    async fn has_history(&self, thread: &Thread) -> bool;
+
+    /// Returns whether the message was deleted.
+    async fn delete_message(&mut self, thread: &Thread, timestamp: u64) -> bool;
+
+    /// Fetches the stored profiles of the contacts again, keeping the stored ones that can't be
+    /// fetched, such as while offline. Returns whether the stored profiles could be replaced.
+    async fn refetch_profiles(&mut self) -> bool;
+
+    async fn profile(
+        &mut self,
+        id: ServiceId,
+        key: ProfileKey,
+    ) -> Result<StoredProfile, ProfileError>;
+
+    /// The phone number of a contact in E.164, if the primary device shared it.
+    async fn phone_number(&self, id: ServiceId) -> Option<String>;
+
+    async fn profile_avatar(&mut self, id: ServiceId, key: ProfileKey) -> Option<Vec<u8>>;
+
+    async fn group_avatar(&mut self, context: GroupContextV2) -> Option<Vec<u8>>;
+}
+
+impl SignalBackend for RegisteredManager {
+    fn aci(&self) -> ServiceId {
+        ServiceId::Aci(self.registration_data().service_ids.aci.into())
+    }
+
+    fn profile_key(&self) -> ProfileKeyBytes {
+        self.registration_data().profile_key().bytes
+    }
+
+    async fn contacts(&self) -> Vec<(ServiceId, ProfileKeyBytes)> {
+        self.store()
+            .contacts()
+            .await
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|contact| {
+                Some((
+                    ServiceId::Aci(contact.uuid.into()),
+                    contact.profile_key.try_into().ok()?,
+                ))
+            })
+            .collect()
+    }
+
+    async fn groups(&self) -> Vec<(GroupMasterKeyBytes, StoredGroup)> {
+        self.store()
+            .groups()
+            .await
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|(key, group)| (key, group.into()))
+            .collect()
+    }
+
+    async fn group(&self, key: GroupMasterKeyBytes) -> Option<StoredGroup> {
+        Some(self.store().group(key).await.ok()??.into())
+    }
+
+    async fn messages(&self, thread: &Thread) -> Vec<Content> {
+        self.store()
+            .messages(thread, ..)
+            .await
+            .into_iter()
+            .flatten()
+            .flatten()
+            .collect()
+    }
+
+    async fn has_history(&self, thread: &Thread) -> bool {
+        self.store()
+            .messages(thread, ..)
+            .await
+            .is_ok_and(|messages| messages.take(2).count() == 2)
+    }
+
+    async fn delete_message(&mut self, thread: &Thread, timestamp: u64) -> bool {
+        self.store()
+            .clone()
+            .delete_message(thread, timestamp)
+            .await
+            .is_ok()
+    }
+
+    async fn refetch_profiles(&mut self) -> bool {
+        let mut store = self.store().clone();
+
+        // fetching skips the stored profiles, so they're cleared first and put back on failure
+        let mut stored = vec![];
+        for (id, key) in self.contacts().await {
+            let key = ProfileKey::create(key);
+            if let Ok(Some(profile)) = store.profile(id.raw_uuid(), key).await {
+                stored.push((id, key, profile));
+            }
+        }
+
+        if store.clear_profiles().await.is_err() {
+            return false;
+        }
+
+        for (id, key, profile) in stored {
+            if let Err(err) = Box::pin(self.retrieve_profile_by_uuid(id.raw_uuid(), key)).await {
+                log::warn!(
+                    "Refetching a profile failed, keeping the stored one: {}",
+                    err
+                );
+                _ = store.save_profile(id.raw_uuid(), key, profile).await;
+            }
+        }
+
+        true
+    }
+
+    #[log::instrument(skip_all)]
+    async fn profile(
+        &mut self,
+        id: ServiceId,
+        key: ProfileKey,
+    ) -> Result<StoredProfile, ProfileError> {
+        let profile = Box::pin(self.retrieve_profile_by_uuid(id.raw_uuid(), key))
+            .await
+            .map_err(|err| {
+                log::warn!("Fetching a profile failed: {}", err);
+
+                match challenge(&err) {
+                    Some(ManagerManagerError::ProofRequired(token)) => {
+                        ProfileError::ProofRequired(token)
+                    }
+                    _ => ProfileError::Failed,
+                }
+            })?;
+
+        Ok(StoredProfile {
+            name: profile
+                .name
+                .map(|name| name.to_string())
+                .filter(|name| !name.is_empty()),
+            about: profile.about.filter(|about| !about.is_empty()),
+            about_emoji: profile.about_emoji.filter(|emoji| !emoji.is_empty()),
+        })
+    }
+
+    async fn phone_number(&self, id: ServiceId) -> Option<String> {
+        let contact = self.store().contact_by_id(&id.raw_uuid()).await.ok()??;
+        contact.phone_number.map(|number| number.to_string())
+    }
+
+    #[log::instrument(skip_all)]
+    async fn profile_avatar(&mut self, id: ServiceId, key: ProfileKey) -> Option<Vec<u8>> {
+        Box::pin(self.retrieve_profile_avatar_by_uuid(id.raw_uuid(), key))
+            .await
+            .ok()?
+    }
+
+    #[log::instrument(skip_all)]
+    async fn group_avatar(&mut self, context: GroupContextV2) -> Option<Vec<u8>> {
+        Box::pin(self.retrieve_group_avatar(context)).await.ok()?
+    }
+}
+
+impl From<presage::model::groups::Group> for StoredGroup {
+    fn from(group: presage::model::groups::Group) -> Self {
+        let link_enabled = group.access_control.as_ref().is_some_and(|access| {
+            matches!(
+                access.add_from_invite_link,
+                AccessRequired::Any | AccessRequired::Administrator
+            )
+        });
+
+        Self {
+            title: group.title,
+            revision: group.revision,
+            admins: group
+                .members
+                .iter()
+                .filter(|member| member.role == Role::Administrator)
+                .map(|member| member.aci.into())
+                .collect(),
+            members: group
+                .members
+                .into_iter()
+                .map(|member| (member.aci.into(), member.profile_key.bytes))
+                .collect(),
+            description: group
+                .description
+                .filter(|description| !description.is_empty()),
+            invite_link_password: Some(group.invite_link_password)
+                .filter(|password| link_enabled && !password.is_empty()),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod mock {
+    use super::{ProfileError, SignalBackend, StoredGroup, StoredProfile};
+    use presage::{
+        libsignal_service::{
+            prelude::{Content, ProfileKey},
+            protocol::ServiceId,
+            zkgroup::{GroupMasterKeyBytes, ProfileKeyBytes},
+        },
+        proto::GroupContextV2,
+        store::Thread,
+    };
+    use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+    /// An in-memory [`SignalBackend`].
+    #[derive(Clone, Debug)]
+    pub struct MockBackend {
+        pub me: ServiceId,
+        pub profiles: HashMap<ServiceId, (ProfileKeyBytes, String)>,
+        /// The about texts of the profiles, with their emoji.
+        pub abouts: HashMap<ServiceId, (String, String)>,
+        pub phones: HashMap<ServiceId, String>,
+        pub groups: HashMap<GroupMasterKeyBytes, StoredGroup>,
+        pub messages: HashMap<Thread, Vec<Content>>,
+        pub deleted: Rc<RefCell<Vec<(Thread, u64)>>>,
+    }
+
+    impl MockBackend {
+        pub fn new(me: ServiceId, name: &str) -> Self {
+            Self {
+                me,
+                profiles: HashMap::from([(me, ([0; 32], name.to_owned()))]),
+                abouts: HashMap::new(),
+                phones: HashMap::new(),
+                groups: HashMap::new(),
+                messages: HashMap::new(),
+                deleted: Rc::default(),
+            }
+        }
+
+        pub fn with_contact(mut self, id: ServiceId, key: ProfileKeyBytes, name: &str) -> Self {
+            self.profiles.insert(id, (key, name.to_owned()));
+            self
+        }
+
+        pub fn with_about(mut self, id: ServiceId, emoji: &str, about: &str) -> Self {
+            self.abouts.insert(id, (emoji.to_owned(), about.to_owned()));
+            self
+        }
+
+        pub fn with_phone(mut self, id: ServiceId, phone: &str) -> Self {
+            self.phones.insert(id, phone.to_owned());
+            self
+        }
+
+        pub fn with_group(mut self, key: GroupMasterKeyBytes, group: StoredGroup) -> Self {
+            self.groups.insert(key, group);
+            self
+        }
+    }
+
+    impl SignalBackend for MockBackend {
+        fn aci(&self) -> ServiceId {
+            self.me
+        }
+
+        fn profile_key(&self) -> ProfileKeyBytes {
+            self.profiles[&self.me].0
+        }
+
+        async fn contacts(&self) -> Vec<(ServiceId, ProfileKeyBytes)> {
+            self.profiles
+                .iter()
+                .filter(|(id, _)| **id != self.me)
+                .map(|(id, (key, _))| (*id, *key))
+                .collect()
+        }
+
+        async fn groups(&self) -> Vec<(GroupMasterKeyBytes, StoredGroup)> {
+            self.groups
+                .iter()
+                .map(|(key, group)| (*key, group.clone()))
+                .collect()
+        }
+
+        async fn group(&self, key: GroupMasterKeyBytes) -> Option<StoredGroup> {
+            self.groups.get(&key).cloned()
+        }
+
+        async fn messages(&self, thread: &Thread) -> Vec<Content> {
+            self.messages.get(thread).cloned().unwrap_or_default()
+        }
+
+        async fn has_history(&self, thread: &Thread) -> bool {
+            self.messages
+                .get(thread)
+                .is_some_and(|messages| messages.len() > 1)
+        }
+
+        async fn delete_message(&mut self, thread: &Thread, timestamp: u64) -> bool {
+            self.deleted.borrow_mut().push((thread.clone(), timestamp));
+            true
+        }
+
+        async fn refetch_profiles(&mut self) -> bool {
+            true
+        }
+
+        async fn profile(
+            &mut self,
+            id: ServiceId,
+            _key: ProfileKey,
+        ) -> Result<StoredProfile, ProfileError> {
+            let about = self.abouts.get(&id);
+            let (_, name) = self.profiles.get(&id).ok_or(ProfileError::Failed)?;
+
+            Ok(StoredProfile {
+                name: Some(name.clone()).filter(|name| !name.is_empty()),
+                about: about.map(|(_, about)| about.clone()),
+                about_emoji: about.map(|(emoji, _)| emoji.clone()),
+            })
+        }
+
+        async fn phone_number(&self, id: ServiceId) -> Option<String> {
+            self.phones.get(&id).cloned()
+        }
+
+        async fn profile_avatar(&mut self, _id: ServiceId, _key: ProfileKey) -> Option<Vec<u8>> {
+            None
+        }
+
+        async fn group_avatar(&mut self, _context: GroupContextV2) -> Option<Vec<u8>> {
+            None
+        }
+    }
+}
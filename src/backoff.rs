@@ -0,0 +1,103 @@
+use std::time::Duration;
+use tokio::time;
+
+/// A failed attempt that's tried again after `delay`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Retry {
+    /// The attempt that failed, counting from 1.
+    pub attempt: usize,
+    pub attempts: usize,
+    pub delay: Duration,
+}
+
+/// Runs `f` up to `attempts` times while it fails with transient errors, waiting for the
+/// fibonacci multiples of `base` in between.
+pub async fn retry_fib<T, E>(
+    base: Duration,
+    attempts: usize,
+    is_transient: impl Fn(&E) -> bool,
+    mut on_retry: impl FnMut(Retry),
+    mut f: impl AsyncFnMut() -> Result<T, E>,
+) -> Result<T, E> {
+    let (mut delay, mut next) = (base, base);
+    let mut attempt = 1;
+
+    loop {
+        match f().await {
+            Err(err) if attempt < attempts && is_transient(&err) => {
+                on_retry(Retry {
+                    attempt,
+                    attempts,
+                    delay,
+                });
+                time::sleep(delay).await;
+
+                (delay, next) = (next, delay + next);
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::runtime::Builder;
+
+    fn run<T>(future: impl Future<Output = T>) -> T {
+        Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn retries() {
+        let base = Duration::from_millis(1);
+        let mut retries = vec![];
+        let mut calls = 0;
+
+        let result = run(retry_fib(
+            base,
+            5,
+            |err: &&str| *err == "timeout",
+            |retry| retries.push(retry.delay),
+            async || {
+                calls += 1;
+                if calls < 5 { Err("timeout") } else { Ok(calls) }
+            },
+        ));
+
+        assert_eq!(result, Ok(5));
+        assert_eq!(retries, [base, base, base * 2, base * 3]);
+
+        // permanent errors and the last attempt aren't retried
+        let mut calls = 0;
+        let result: Result<(), _> = run(retry_fib(
+            base,
+            5,
+            |err: &&str| *err == "timeout",
+            |_| {},
+            async || {
+                calls += 1;
+                Err("unregistered")
+            },
+        ));
+        assert_eq!((result, calls), (Err("unregistered"), 1));
+
+        let mut calls = 0;
+        let result: Result<(), _> = run(retry_fib(
+            base,
+            3,
+            |err: &&str| *err == "timeout",
+            |_| {},
+            async || {
+                calls += 1;
+                Err("timeout")
+            },
+        ));
+        assert_eq!((result, calls), (Err("timeout"), 3));
+    }
+}
@@ -0,0 +1,52 @@
+use crate::log;
+use std::{io, process::Command};
+
+/// Opens a url with `command`, or with the system's default browser without one.
+///
+/// `%s` in the command stands for the url, which is appended to the command otherwise.
+pub fn open(url: &str, command: Option<&str>) {
+    let result = match command {
+        Some(command) => {
+            let mut args = command
+                .split_whitespace()
+                .map(|arg| arg.replace("%s", url))
+                .collect::<Vec<_>>();
+
+            if !command.contains("%s") {
+                args.push(url.to_owned());
+            }
+
+            Command::new(&args[0]).args(&args[1..]).spawn().map(drop)
+        }
+        // without going through a shell, which would run what follows a `&` in the url
+        None => opener::open(url).map_err(io::Error::other),
+    };
+
+    if let Err(err) = result {
+        log::error!("Failed to open {}: {}", url, err);
+    }
+}
+
+/// Whether opening a url can do more than show a web page, like calling someone or opening a
+/// local file.
+pub fn is_unusual(url: &str) -> bool {
+    let scheme = url
+        .split_once(':')
+        .map(|(scheme, _)| scheme.to_ascii_lowercase());
+
+    !matches!(scheme.as_deref(), Some("http" | "https"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unusual_schemes() {
+        assert!(!is_unusual("https://example.com"));
+        assert!(!is_unusual("HTTP://example.com"));
+        assert!(is_unusual("tel:+1234"));
+        assert!(is_unusual("file:///etc/passwd"));
+        assert!(is_unusual("sgnl://signal.group/#abc"));
+    }
+}
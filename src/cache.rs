@@ -0,0 +1,94 @@
+use crate::message::{Chat, Contact};
+use presage::{libsignal_service::protocol::ServiceId, store::Thread};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    rc::Rc,
+    sync::Arc,
+};
+
+/// The contacts and groups resolved by the background task, keyed by their thread.
+///
+/// Profiles, avatars and groups are persisted across runs by the presage store, which
+/// the cache is filled from, so the cache itself only lives in memory. Entries are
+/// cloned in and out and no borrow is ever handed out, so the cache can be used freely
+/// across awaits.
+#[derive(Clone, Debug, Default)]
+pub struct ChatCache {
+    chats: Rc<RefCell<HashMap<Thread, Chat>>>,
+    /// The contacts whose profile couldn't be fetched, which are kept to name them by but
+    /// resolved again the next time they're needed.
+    unresolved: Rc<RefCell<HashSet<Thread>>>,
+    /// The contact whose profile Signal wouldn't fetch before a captcha is solved, with the
+    /// token to answer with, until it's passed on.
+    challenge: Rc<RefCell<Option<(Chat, String)>>>,
+}
+
+impl ChatCache {
+    pub fn get(&self, thread: &Thread) -> Option<Chat> {
+        self.chats.borrow().get(thread).cloned()
+    }
+
+    pub fn contact(&self, id: ServiceId) -> Option<Arc<Contact>> {
+        self.get(&Thread::Contact(id))?.contact()
+    }
+
+    /// The group named by the identifier derived from its master key.
+    pub fn group(&self, id: &[u8]) -> Option<Chat> {
+        self.chats
+            .borrow()
+            .values()
+            .find(|chat| matches!(chat, Chat::Group(group) if group.id() == id))
+            .cloned()
+    }
+
+    pub fn insert(&self, chat: Chat) -> Chat {
+        self.unresolved.borrow_mut().remove(&chat.thread());
+        self.chats.borrow_mut().insert(chat.thread(), chat.clone());
+        chat
+    }
+
+    /// Inserts a contact whose profile couldn't be fetched, with a name to go by until it's
+    /// resolved again.
+    pub fn insert_unresolved(&self, chat: Chat) -> Chat {
+        self.unresolved.borrow_mut().insert(chat.thread());
+        self.chats.borrow_mut().insert(chat.thread(), chat.clone());
+        chat
+    }
+
+    pub fn is_unresolved(&self, thread: &Thread) -> bool {
+        self.unresolved.borrow().contains(thread)
+    }
+
+    /// Remembers a challenge to pass on, keeping the first until it is.
+    pub fn set_challenge(&self, chat: Chat, token: String) {
+        self.challenge.borrow_mut().get_or_insert((chat, token));
+    }
+
+    pub fn take_challenge(&self) -> Option<(Chat, String)> {
+        self.challenge.borrow_mut().take()
+    }
+
+    /// Removes a chat, so it is resolved again the next time it is needed.
+    pub fn invalidate(&self, thread: &Thread) {
+        self.unresolved.borrow_mut().remove(thread);
+        self.chats.borrow_mut().remove(thread);
+    }
+
+    /// Removes all contacts, keeping groups, which are invalidated by their revision, and
+    /// returns the removed contacts.
+    pub fn invalidate_contacts(&self) -> Vec<Arc<Contact>> {
+        let mut contacts = vec![];
+        self.unresolved.borrow_mut().clear();
+
+        self.chats.borrow_mut().retain(|_, chat| match chat {
+            Chat::Contact(contact) => {
+                contacts.push(contact.clone());
+                false
+            }
+            Chat::Group(_) => true,
+        });
+
+        contacts
+    }
+}
@@ -0,0 +1,104 @@
+use crate::app::Message;
+use iced::{
+    Center, Element, Fill,
+    widget::{button, column, container, row, space, text},
+};
+use jiff::{Span, civil::Date};
+use std::collections::BTreeSet;
+
+/// The width of a day in the month grid.
+const DAY_SIZE: f32 = 32.0;
+
+const WEEKDAYS: [&str; 7] = ["Mo", "Tu", "We", "Th", "Fr", "Sa", "Su"];
+
+/// The popover picking a day to jump to in the open chat's history.
+#[derive(Debug, Default)]
+pub struct Calendar {
+    /// The first day of the month shown, while open.
+    month: Option<Date>,
+}
+
+impl Calendar {
+    pub fn is_open(&self) -> bool {
+        self.month.is_some()
+    }
+
+    pub fn open(&mut self, day: Date) {
+        self.month = Some(day.first_of_month());
+    }
+
+    pub fn close(&mut self) {
+        self.month = None;
+    }
+
+    pub fn previous(&mut self) {
+        self.month = self
+            .month
+            .and_then(|month| month.checked_sub(Span::new().months(1)).ok());
+    }
+
+    pub fn next(&mut self) {
+        self.month = self
+            .month
+            .and_then(|month| month.checked_add(Span::new().months(1)).ok());
+    }
+
+    /// The month grid, highlighting the `days` with messages.
+    pub fn as_iced_widget<'a>(&self, days: &BTreeSet<Date>) -> Option<Element<'a, Message>> {
+        let month = self.month?;
+
+        // the days before the first of the month are left empty
+        let offset = month.weekday().to_monday_zero_offset() as usize;
+        let cells = (0..offset)
+            .map(|_| None)
+            .chain(
+                month
+                    .series(Span::new().days(1))
+                    .take(month.days_in_month() as usize)
+                    .map(Some),
+            )
+            .collect::<Vec<_>>();
+
+        let weeks = cells.chunks(7).map(|week| {
+            row(week.iter().map(|day| {
+                let Some(day) = *day else {
+                    return space::horizontal().width(DAY_SIZE).into();
+                };
+
+                button(text!("{}", day.day()).size(12).center().width(Fill))
+                    .width(DAY_SIZE)
+                    .padding([4, 0])
+                    .style(if days.contains(&day) {
+                        button::primary
+                    } else {
+                        button::text
+                    })
+                    .on_press(Message::JumpToDate(day))
+                    .into()
+            }))
+            .into()
+        });
+
+        let content = column![
+            row![
+                button(text("‹"))
+                    .style(button::text)
+                    .on_press(Message::CalendarPrevious),
+                text(month.strftime("%B %Y").to_string())
+                    .width(Fill)
+                    .center(),
+                button(text("›"))
+                    .style(button::text)
+                    .on_press(Message::CalendarNext),
+            ]
+            .align_y(Center),
+            row(WEEKDAYS.map(|weekday| text(weekday).size(12).width(DAY_SIZE).center().into())),
+            column(weeks).spacing(2),
+        ]
+        .spacing(5)
+        .padding(10)
+        .width(DAY_SIZE * 7.0 + 20.0);
+
+        Some(container(content).style(container::rounded_box).into())
+    }
+}
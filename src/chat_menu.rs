@@ -0,0 +1,228 @@
+use crate::{app::Message, export, message::Chat};
+use iced::{
+    Center, Element, Fill,
+    widget::{button, column, container, row, rule, text, text_input},
+};
+use jiff::Timestamp;
+
+/// The timers disappearing messages can be set to, in seconds.
+const TIMERS: [(u32, &str); 5] = [
+    (0, "Off"),
+    (60 * 60, "1h"),
+    (24 * 60 * 60, "1d"),
+    (7 * 24 * 60 * 60, "1w"),
+    (28 * 24 * 60 * 60, "4w"),
+];
+
+const WIDTH: f32 = 220.0;
+
+/// The popover with the actions on the open chat.
+#[derive(Debug, Default)]
+pub struct ChatMenu {
+    is_open: bool,
+}
+
+impl ChatMenu {
+    pub fn toggle(&mut self) {
+        self.is_open = !self.is_open;
+    }
+
+    pub fn close(&mut self) {
+        self.is_open = false;
+    }
+
+    /// The actions on `chat`, which is `muted` and has messages disappear after `timer`
+    /// seconds, if at all.
+    pub fn as_iced_widget<'a>(
+        &self,
+        chat: &Chat,
+        muted: bool,
+        timer: u32,
+    ) -> Option<Element<'a, Message>> {
+        if !self.is_open {
+            return None;
+        }
+
+        let item = |label, message| {
+            button(text(label).size(14))
+                .width(Fill)
+                .style(button::text)
+                .on_press(message)
+        };
+
+        // the timer of a group is part of its state, which can't be changed from here
+        let timers = matches!(chat, Chat::Contact(_)).then(|| {
+            column![
+                text("Disappearing messages").size(12),
+                row(TIMERS.map(|(seconds, label)| {
+                    button(text(label).size(12).center().width(Fill))
+                        .width(Fill)
+                        .padding([4, 0])
+                        .style(if seconds == timer {
+                            button::primary
+                        } else {
+                            button::text
+                        })
+                        .on_press(Message::SetTimer(seconds))
+                        .into()
+                }))
+                .spacing(2),
+            ]
+            .spacing(5)
+            .padding([0, 10])
+        });
+
+        let content = column![
+            matches!(chat, Chat::Group(_))
+                .then(|| item("Group details", Message::ShowGroupDetails)),
+            item(
+                if muted {
+                    "Unmute notifications"
+                } else {
+                    "Mute notifications"
+                },
+                Message::ToggleMute,
+            ),
+            item("Search in chat", Message::SearchInChat),
+            item("Media, links and docs", Message::ShowGallery),
+            timers,
+            item("Export chat", Message::ExportChat(export::Format::Text)),
+            item(
+                "Export as web page",
+                Message::ExportChat(export::Format::Html)
+            ),
+            rule::horizontal(1),
+            container(text("When something's out of sync").size(12)).padding([0, 10]),
+            item("Request contacts", Message::RequestContacts),
+            item("Fetch groups again", Message::ResyncGroups),
+            item("Sync this chat again", Message::ResyncChat),
+            rule::horizontal(1),
+            button(text("Clear history").size(14))
+                .width(Fill)
+                .style(button::danger)
+                .on_press(Message::ClearHistory),
+        ]
+        .spacing(5)
+        .padding(5)
+        .width(WIDTH);
+
+        Some(container(content).style(container::rounded_box).into())
+    }
+}
+
+/// Searching the open chat's messages, stepping through the matches from the newest one.
+#[derive(Debug, Default)]
+pub struct ChatSearch {
+    is_open: bool,
+    query: String,
+    /// The timestamps of the matching messages, oldest first.
+    results: Vec<Timestamp>,
+    current: Option<usize>,
+}
+
+impl ChatSearch {
+    pub fn is_open(&self) -> bool {
+        self.is_open
+    }
+
+    pub fn open(&mut self) {
+        self.is_open = true;
+    }
+
+    pub fn close(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn set_query(&mut self, query: String) {
+        self.query = query;
+        self.results.clear();
+        self.current = None;
+    }
+
+    /// Takes the matches of a search, returning the newest one.
+    pub fn set_results(&mut self, mut results: Vec<Timestamp>) -> Option<Timestamp> {
+        results.sort_unstable();
+        self.current = results.len().checked_sub(1);
+        self.results = results;
+
+        self.current()
+    }
+
+    pub fn current(&self) -> Option<Timestamp> {
+        self.results.get(self.current?).copied()
+    }
+
+    /// Steps to the next older or newer match, stopping at the ends.
+    pub fn step(&mut self, older: bool) -> Option<Timestamp> {
+        let current = self.current?;
+
+        self.current = Some(if older {
+            current.saturating_sub(1)
+        } else {
+            (current + 1).min(self.results.len() - 1)
+        });
+
+        self.current()
+    }
+
+    pub fn as_iced_widget<'a>(&'a self) -> Option<Element<'a, Message>> {
+        if !self.is_open {
+            return None;
+        }
+
+        let position = match self.current {
+            Some(current) => format!("{} of {}", current + 1, self.results.len()),
+            None => String::new(),
+        };
+
+        Some(
+            row![
+                text_input("Search in chat", &self.query)
+                    .id("chat-search")
+                    .on_input(Message::ChatSearchQuery)
+                    .on_submit(Message::ChatSearch)
+                    .padding(5),
+                text(position).size(12).style(text::secondary),
+                button(text("‹"))
+                    .style(button::text)
+                    .on_press_maybe(self.current.map(|_| Message::ChatSearchOlder)),
+                button(text("›"))
+                    .style(button::text)
+                    .on_press_maybe(self.current.map(|_| Message::ChatSearchNewer)),
+                button(text("✕"))
+                    .style(button::text)
+                    .on_press(Message::CloseChatSearch),
+            ]
+            .spacing(5)
+            .align_y(Center)
+            .into(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn search() {
+        let at = |s| Timestamp::from_second(s).unwrap();
+        let mut search = ChatSearch::default();
+        search.set_query("hello".to_owned());
+
+        // the index returns the best matches first
+        assert_eq!(search.set_results(vec![at(2), at(3), at(1)]), Some(at(3)));
+        assert_eq!(search.step(false), Some(at(3)));
+        assert_eq!(search.step(true), Some(at(2)));
+        assert_eq!(search.step(true), Some(at(1)));
+        assert_eq!(search.step(true), Some(at(1)));
+
+        search.set_query("hello there".to_owned());
+        assert_eq!(search.step(true), None);
+        assert_eq!(search.set_results(vec![]), None);
+    }
+}
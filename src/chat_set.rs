@@ -0,0 +1,65 @@
+use crate::{log, message::Chat, search::thread_key};
+use std::{collections::BTreeSet, fs, path::PathBuf};
+
+/// Some of the chats, like the muted ones, remembered across runs by their threads.
+#[derive(Debug, Default)]
+pub struct ChatSet {
+    path: PathBuf,
+    /// What the chats are, for the log.
+    what: &'static str,
+    threads: BTreeSet<String>,
+}
+
+impl ChatSet {
+    pub fn load(path: PathBuf, what: &'static str) -> Self {
+        let threads = fs::read_to_string(&path)
+            .map(|chats| chats.lines().map(str::to_owned).collect())
+            .unwrap_or_default();
+
+        Self {
+            path,
+            what,
+            threads,
+        }
+    }
+
+    pub fn contains(&self, chat: &Chat) -> bool {
+        self.threads.contains(&thread_key(&chat.thread()))
+    }
+
+    pub fn insert(&mut self, chat: &Chat) {
+        if self.threads.insert(thread_key(&chat.thread())) {
+            self.save();
+        }
+    }
+
+    pub fn remove(&mut self, chat: &Chat) {
+        if self.threads.remove(&thread_key(&chat.thread())) {
+            self.save();
+        }
+    }
+
+    pub fn toggle(&mut self, chat: &Chat) {
+        if self.contains(chat) {
+            self.remove(chat);
+        } else {
+            self.insert(chat);
+        }
+    }
+
+    fn save(&self) {
+        let chats = self
+            .threads
+            .iter()
+            .fold(String::new(), |chats, key| chats + key + "\n");
+
+        if let Err(err) = self
+            .path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&self.path, chats))
+        {
+            log::error!("Failed to remember the {} chats: {}", self.what, err);
+        }
+    }
+}
@@ -0,0 +1,459 @@
+use crate::{
+    config::Config,
+    deep_link::DeepLink,
+    import,
+    manager_manager::{ManagerManager, ManagerManagerError},
+    message::{Chat, Message, ReceiptKind, SignalAction},
+};
+use iced::futures::{
+    StreamExt as _,
+    channel::{mpsc, oneshot},
+    executor::block_on,
+    stream,
+};
+use serde_json::{Value, json};
+use std::{collections::HashMap, io::BufRead as _, path::PathBuf};
+
+const USAGE: &str = "\
+Usage: foghorn [OPTIONS] [COMMAND | LINK]
+
+Without a command, the graphical client is started. Given a signal.me contact link or a
+signal.group invite link, it's opened in the graphical client, or the one already running.
+
+Options:
+  --servers <ENV>     The Signal environment to use, `production` (default) or `staging`.
+                      Every environment has its own database
+  --log-level <LEVEL> The most verbose level to log, overriding RUST_LOG
+  --log-format <FMT>  The format of the log file, `json` (default) or `pretty`
+  --no-log-file       Only log to stderr
+  --trace-startup     Record how long syncing takes on startup as a Chrome trace in the
+                      state directory
+  --image-quality <Q> The quality images are sent at unless chosen otherwise, `standard`,
+                      `high` or `original`, instead of the default chosen in the composer
+  --ctrl-enter-sends  Send messages with Ctrl+Enter, and start a new line with Enter
+  --clock <HOURS>     Write times with a `12` or `24` hour clock instead of the locale's
+  --quick-reaction <EMOJI>
+                      The emoji double clicking a message reacts with, ❤️ by default
+  --no-autocomplete   Don't offer recent emoji after a colon or close markdown markers
+  --avatar-size <PX>  The size of the avatars next to messages and chats, from 24 to 128
+                      pixels, 50 by default
+  --reduced-motion    Keep animated avatars still
+  --blank-lines <N>   The most blank lines in a row kept in received messages, 1 by default,
+                      or `all` to show them exactly as sent
+  --sealed-sender-icons
+                      Mark the messages received with sealed sender, which hides who sent
+                      them from Signal's servers, with a lock
+  --notifications <TO>
+                      Where notifications are shown, `auto` (default), `desktop`, `portal`
+                      or `none`. The one in use is logged on startup
+  --no-reaction-notifications
+                      Don't notify about reactions to your messages, only mark their chats
+  --no-read-receipts  Don't tell contacts when you read their messages
+  --browser <CMD>     The command links are opened with instead of the default browser,
+                      with `%s` standing for the link
+  --passphrase        Encrypt the database with a passphrase asked for on startup,
+                      instead of a key stored in the system keyring
+
+Commands:
+  daemon              Print incoming messages as JSON lines, and send the
+                      JSON lines read from stdin, e.g. {\"to\": \"...\", \"body\": \"...\"}
+  send <TO> <BODY>    Send a single message and exit
+  link                Print the URL to link this device with, and wait for it to be scanned
+  import-desktop [DIR]
+                      Import the message history of Signal Desktop, from its default
+                      data directory unless given
+
+<TO> is the UUID of a contact, or the name of a contact or group.";
+
+#[derive(Debug)]
+pub enum Command {
+    Daemon,
+    Send { to: String, body: String },
+    Link,
+    ImportDesktop(Option<PathBuf>),
+}
+
+/// What the command line asks for.
+#[derive(Debug)]
+pub enum Mode {
+    /// The graphical client, opening a Signal link if given.
+    Client(Option<String>),
+    /// A command run without the graphical client.
+    Command(Command),
+}
+
+impl Mode {
+    /// Parses the command line arguments.
+    pub fn parse(args: impl Iterator<Item = String>) -> Result<(Config, Self), &'static str> {
+        let mut args = args.peekable();
+        let mut config = Config::default();
+
+        while let Some(option) = args.next_if(|arg| arg.starts_with("--")) {
+            match option.as_str() {
+                "--servers" => {
+                    config.servers = args
+                        .next()
+                        .and_then(|servers| servers.parse().ok())
+                        .ok_or(USAGE)?;
+                }
+                "--log-level" => {
+                    config.log_level = Some(
+                        args.next()
+                            .and_then(|level| level.parse().ok())
+                            .ok_or(USAGE)?,
+                    );
+                }
+                "--log-format" => {
+                    config.log_format = args
+                        .next()
+                        .and_then(|format| format.parse().ok())
+                        .ok_or(USAGE)?;
+                }
+                "--image-quality" => {
+                    config.image_quality = Some(
+                        args.next()
+                            .and_then(|quality| quality.parse().ok())
+                            .ok_or(USAGE)?,
+                    );
+                }
+                "--browser" => {
+                    config.browser = Some(
+                        args.next()
+                            .filter(|command| !command.trim().is_empty())
+                            .ok_or(USAGE)?,
+                    );
+                }
+                "--clock" => {
+                    config.clock = Some(
+                        args.next()
+                            .and_then(|clock| clock.parse().ok())
+                            .ok_or(USAGE)?,
+                    );
+                }
+                "--avatar-size" => {
+                    config.avatar_size = args
+                        .next()
+                        .and_then(|size| size.parse().ok())
+                        .filter(|size| (24..=128).contains(size))
+                        .ok_or(USAGE)?;
+                }
+                "--blank-lines" => {
+                    config.blank_lines = match args.next().ok_or(USAGE)?.as_str() {
+                        "all" => None,
+                        lines => Some(lines.parse().map_err(|_| USAGE)?),
+                    };
+                }
+                "--notifications" => {
+                    config.notifications = args
+                        .next()
+                        .and_then(|backend| backend.parse().ok())
+                        .ok_or(USAGE)?;
+                }
+                "--quick-reaction" => config.quick_reaction = args.next().ok_or(USAGE)?,
+                "--ctrl-enter-sends" => config.ctrl_enter_sends = true,
+                "--no-autocomplete" => config.autocomplete = false,
+                "--reduced-motion" => config.reduced_motion = true,
+                "--sealed-sender-icons" => config.sealed_sender_icons = true,
+                "--no-reaction-notifications" => config.reaction_notifications = false,
+                "--no-read-receipts" => config.read_receipts = false,
+                "--no-log-file" => config.log_file = false,
+                "--trace-startup" => config.trace_startup = true,
+                "--passphrase" => config.ask_passphrase = true,
+                _ => return Err(USAGE),
+            }
+        }
+
+        let mode = match args.next().as_deref() {
+            None => Self::Client(None),
+            Some("daemon") => Self::Command(Command::Daemon),
+            Some("send") => {
+                let (Some(to), Some(body)) = (args.next(), args.next()) else {
+                    return Err(USAGE);
+                };
+                Self::Command(Command::Send { to, body })
+            }
+            Some("link") => Self::Command(Command::Link),
+            Some("import-desktop") => {
+                Self::Command(Command::ImportDesktop(args.next().map(PathBuf::from)))
+            }
+            Some(link) if DeepLink::parse(link).is_some() => Self::Client(Some(link.to_owned())),
+            Some(_) => return Err(USAGE),
+        };
+
+        if args.next().is_some() {
+            return Err(USAGE);
+        }
+
+        Ok((config, mode))
+    }
+}
+
+impl Command {
+    pub fn run(self, config: Config) -> Result<(), ManagerManagerError> {
+        let manager_manager = ManagerManager::new(config);
+
+        block_on(async move {
+            match self {
+                Self::Daemon => daemon(manager_manager).await,
+                Self::Send { to, body } => send(manager_manager, &to, body).await,
+                Self::Link => link(manager_manager).await,
+                Self::ImportDesktop(dir) => import_desktop(manager_manager, dir).await,
+            }
+        })
+    }
+}
+
+enum Input {
+    Received((Chat, SignalAction)),
+    Line(String),
+}
+
+async fn daemon(manager_manager: ManagerManager) -> Result<(), ManagerManagerError> {
+    manager_manager.clone().load_registered().await?;
+
+    let received = manager_manager
+        .clone()
+        .stream_mesages()
+        .await?
+        .map(Input::Received);
+    let mut inputs = stream::select(received, stdin_lines().map(Input::Line));
+    let mut chats = HashMap::new();
+
+    while let Some(input) = inputs.next().await {
+        match input {
+            Input::Received((chat, action)) => {
+                if let Some(event) = action_json(&chat, &action) {
+                    print_json(&event);
+                }
+                chats.insert(chat.name().to_owned(), chat);
+            }
+            Input::Line(line) => {
+                let request = serde_json::from_str::<Value>(&line).ok();
+                let request = request
+                    .as_ref()
+                    .and_then(|request| Some((request["to"].as_str()?, request["body"].as_str()?)));
+
+                let Some((to, body)) = request else {
+                    print_json(
+                        &json!({ "type": "error", "error": "invalid request", "request": line }),
+                    );
+                    continue;
+                };
+
+                let Some(chat) = find_chat(&chats, to) else {
+                    print_json(&json!({ "type": "error", "error": "unknown recipient", "to": to }));
+                    continue;
+                };
+
+                match manager_manager
+                    .clone()
+                    .send(
+                        chat.clone(),
+                        body.to_owned(),
+                        None,
+                        vec![],
+                        mpsc::unbounded().0,
+                    )
+                    .await
+                {
+                    Ok((chat, action)) => {
+                        if let Some(event) = action_json(&chat, &action) {
+                            print_json(&event);
+                        }
+                    }
+                    Err(err) => {
+                        print_json(&json!({ "type": "error", "error": err.to_string(), "to": to }));
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+async fn send(
+    manager_manager: ManagerManager,
+    to: &str,
+    body: String,
+) -> Result<(), ManagerManagerError> {
+    manager_manager.clone().load_registered().await?;
+
+    let chats = manager_manager
+        .clone()
+        .chats()
+        .await?
+        .into_iter()
+        .map(|chat| (chat.name().to_owned(), chat))
+        .collect::<HashMap<_, _>>();
+
+    let Some(chat) = find_chat(&chats, to).cloned() else {
+        return Err(ManagerManagerError::UnknownRecipient(to.to_owned()));
+    };
+
+    let (chat, action) = manager_manager
+        .send(chat, body, None, vec![], mpsc::unbounded().0)
+        .await?;
+    if let Some(event) = action_json(&chat, &action) {
+        print_json(&event);
+    }
+
+    Ok(())
+}
+
+async fn link(manager_manager: ManagerManager) -> Result<(), ManagerManagerError> {
+    let (tx, rx) = oneshot::channel();
+    let linked = manager_manager.link_secondary(tx);
+
+    #[expect(clippy::print_stdout)]
+    let print_url = async {
+        if let Ok(url) = rx.await {
+            println!("{url}");
+        }
+    };
+
+    iced::futures::join!(linked, print_url).0
+}
+
+#[expect(clippy::print_stdout)]
+async fn import_desktop(
+    manager_manager: ManagerManager,
+    dir: Option<PathBuf>,
+) -> Result<(), ManagerManagerError> {
+    let dir = dir
+        .or_else(import::default_desktop_dir)
+        .ok_or_else(|| ManagerManagerError::Import("Signal Desktop wasn't found".to_owned()))?;
+
+    manager_manager.clone().load_registered().await?;
+
+    let imported = manager_manager.import_desktop(dir).await?;
+    println!("Imported {imported} messages");
+
+    Ok(())
+}
+
+/// Reads stdin line by line on a separate thread, since reading it blocks.
+fn stdin_lines() -> mpsc::UnboundedReceiver<String> {
+    let (tx, rx) = mpsc::unbounded();
+
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+
+            if !line.trim().is_empty() && tx.unbounded_send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    rx
+}
+
+fn find_chat<'a>(chats: &'a HashMap<String, Chat>, to: &str) -> Option<&'a Chat> {
+    chats.get(to).or_else(|| {
+        chats
+            .values()
+            .find(|chat| chat.uuid().is_some_and(|uuid| uuid.to_string() == to))
+    })
+}
+
+fn action_json(chat: &Chat, action: &SignalAction) -> Option<Value> {
+    Some(match action {
+        // there's no solving captchas without the graphical client
+        SignalAction::Contact | SignalAction::Challenge(_) => return None,
+        SignalAction::Message(message, live) => json!({
+            "type": "message",
+            "chat": chat_json(chat),
+            "live": live,
+            "message": message_json(message),
+        }),
+        SignalAction::System(message) => json!({
+            "type": "system",
+            "chat": chat_json(chat),
+            "message": message_json(message),
+        }),
+        SignalAction::Receipt(receipt) => json!({
+            "type": "receipt",
+            "chat": chat_json(chat),
+            "kind": match receipt.kind {
+                ReceiptKind::Delivered => "delivered",
+                ReceiptKind::Read => "read",
+            },
+            "targets": receipt
+                .timestamps
+                .iter()
+                .map(|timestamp| timestamp.as_millisecond())
+                .collect::<Vec<_>>(),
+            "at": receipt.at.as_millisecond(),
+        }),
+        SignalAction::Typing(contact, started, at) => json!({
+            "type": "typing",
+            "chat": chat_json(chat),
+            "sender": {
+                "id": contact.id.raw_uuid().to_string(),
+                "name": contact.name,
+            },
+            "started": started,
+            "at": at.as_millisecond(),
+        }),
+        SignalAction::Replace(target, message) => json!({
+            "type": "edit",
+            "chat": chat_json(chat),
+            "target": target.timestamp.as_millisecond(),
+            "target_sender": target.sender.to_string(),
+            "message": message_json(message),
+        }),
+        SignalAction::Delete(target) => json!({
+            "type": "delete",
+            "chat": chat_json(chat),
+            "target": target.timestamp.as_millisecond(),
+            "target_sender": target.sender.to_string(),
+        }),
+        SignalAction::React(target, contact, emoji, live) => json!({
+            "type": "reaction",
+            "chat": chat_json(chat),
+            "live": live,
+            "sender": {
+                "id": contact.id.raw_uuid().to_string(),
+                "name": contact.name,
+            },
+            "target": target.timestamp.as_millisecond(),
+            "target_sender": target.sender.to_string(),
+            // no emoji when the reaction was taken back
+            "emoji": emoji,
+        }),
+    })
+}
+
+fn chat_json(chat: &Chat) -> Value {
+    json!({
+        "id": chat.uuid().map(|uuid| uuid.to_string()),
+        "name": chat.name(),
+        "group": matches!(chat, Chat::Group(_)),
+    })
+}
+
+fn message_json(message: &Message) -> Value {
+    json!({
+        "timestamp": message.timestamp.as_millisecond(),
+        "sender": {
+            "id": message.sender.id.raw_uuid().to_string(),
+            "name": message.sender.name,
+            "self": message.sender.is_self,
+        },
+        "body": message.original_body,
+        // what happened to the chat, for rows not sent by anyone
+        "system": message.system.as_ref().map(ToString::to_string),
+        "attachments": message.attachments.iter().map(|attachment| json!({
+            "content_type": attachment.mime.to_string(),
+            "size": attachment.ptr.size(),
+        })).collect::<Vec<_>>(),
+    })
+}
+
+#[expect(clippy::print_stdout)]
+fn print_json(value: &Value) {
+    println!("{value}");
+}
@@ -0,0 +1,600 @@
+use crate::{
+    app,
+    autocomplete::{MarkerEdit, RecentEmoji, emoji_query, marker_edit},
+    config::{Config, ImageQuality},
+    icons::edit,
+    image::Staged,
+    log,
+    message::{self, Quote},
+    stickers::parse_pack_link,
+};
+use iced::{
+    Center, Element, border,
+    widget::{button, column, container, image, row, rule, scrollable, space, text, text_editor},
+};
+use jiff::{Timestamp, Zoned, tz::TimeZone};
+use std::{
+    fs,
+    mem::{replace, take},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+#[derive(Clone, Debug)]
+pub enum Message {
+    Edit(text_editor::Action),
+    /// Replaces the `:query` before the cursor with an emoji.
+    CompleteEmoji(String),
+    CancelQuote,
+    RemoveAttachment(usize),
+    MoveAttachment(usize, usize),
+    ImageQuality(ImageQuality),
+    /// Sends images at the chosen quality from now on, also after a restart.
+    MakeImageQualityDefault,
+    InstallStickerPack(Vec<u8>, Vec<u8>),
+    Send,
+    EditLast,
+    FocusOlder,
+    FocusNewer,
+    QuoteFocused,
+    EditFocused,
+    InfoFocused,
+    /// A message of a widget shared with the rest of the app, like a link in the quote.
+    App(Box<app::Message>),
+}
+
+/// What was written, taken from the composer to be sent.
+#[derive(Clone, Debug)]
+pub struct Draft {
+    pub content: String,
+    /// The sent message the draft replaces, if it's an edit.
+    pub editing: Option<Timestamp>,
+    pub quote: Option<Quote>,
+    pub attachments: Vec<Staged>,
+    pub quality: ImageQuality,
+}
+
+impl Draft {
+    /// A message of just text, like a reply typed into a notification.
+    pub fn text(content: String) -> Self {
+        Self {
+            content,
+            editing: None,
+            quote: None,
+            attachments: vec![],
+            quality: ImageQuality::default(),
+        }
+    }
+}
+
+/// Where the next message is written, with what it replies to and the files sent with it.
+#[derive(Debug, Default)]
+pub struct Composer {
+    content: text_editor::Content,
+    quote: Option<Quote>,
+    editing: Option<Timestamp>,
+    /// The files dropped onto the window, sent with the next message.
+    attachments: Vec<Staged>,
+    /// The quality the attached images are sent at.
+    image_quality: ImageQuality,
+    default_image_quality: ImageQuality,
+    /// Where the default image quality is remembered.
+    image_quality_path: PathBuf,
+    /// Whether Ctrl+Enter sends instead of Enter, which then starts a new line.
+    ctrl_enter_sends: bool,
+    /// Whether recent emoji are offered after a colon and markdown markers are closed.
+    autocomplete: bool,
+    recent_emoji: RecentEmoji,
+}
+
+impl Composer {
+    pub fn new(config: &Config) -> Self {
+        let image_quality_path = config.image_quality_path();
+        let image_quality = config
+            .image_quality
+            .or_else(|| {
+                fs::read_to_string(&image_quality_path)
+                    .ok()?
+                    .trim()
+                    .parse()
+                    .ok()
+            })
+            .unwrap_or_default();
+
+        Self {
+            image_quality,
+            default_image_quality: image_quality,
+            image_quality_path,
+            ctrl_enter_sends: config.ctrl_enter_sends,
+            autocomplete: config.autocomplete,
+            recent_emoji: RecentEmoji::load(config.recent_emoji_path()),
+            ..Self::default()
+        }
+    }
+
+    /// Handles a message, returning what the app has to do for the ones concerning more than
+    /// the composer.
+    pub fn update(&mut self, message: Message) -> Option<app::Message> {
+        match message {
+            Message::Edit(action) => self.edit(action),
+            Message::CompleteEmoji(emoji) => self.complete_emoji(emoji),
+            Message::CancelQuote => self.quote = None,
+            Message::RemoveAttachment(index) => {
+                if index < self.attachments.len() {
+                    self.attachments.remove(index);
+                }
+
+                if self.attachments.is_empty() {
+                    self.image_quality = self.default_image_quality;
+                }
+            }
+            Message::MoveAttachment(from, to) => {
+                if from < self.attachments.len() && to < self.attachments.len() {
+                    self.attachments.swap(from, to);
+                }
+            }
+            Message::ImageQuality(quality) => self.image_quality = quality,
+            Message::MakeImageQualityDefault => {
+                self.default_image_quality = self.image_quality;
+
+                if let Err(err) = self
+                    .image_quality_path
+                    .parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|()| {
+                        fs::write(
+                            &self.image_quality_path,
+                            self.image_quality.label().to_lowercase(),
+                        )
+                    })
+                {
+                    log::error!("Failed to remember the image quality: {}", err);
+                }
+            }
+            Message::InstallStickerPack(id, key) => {
+                return Some(app::Message::InstallStickerPack(id, key));
+            }
+            Message::Send => return Some(app::Message::Send),
+            Message::EditLast => return Some(app::Message::EditLast),
+            Message::FocusOlder => return Some(app::Message::FocusOlder),
+            Message::FocusNewer => return Some(app::Message::FocusNewer),
+            Message::QuoteFocused => return Some(app::Message::QuoteFocused),
+            Message::EditFocused => return Some(app::Message::EditFocused),
+            Message::InfoFocused => return Some(app::Message::InfoFocused),
+            Message::App(message) => return Some(*message),
+        }
+
+        None
+    }
+
+    /// Edits the text, closing the markdown markers as they're typed.
+    fn edit(&mut self, action: text_editor::Action) {
+        let text_editor::Action::Edit(text_editor::Edit::Insert(typed)) = action else {
+            self.content.perform(action);
+            return;
+        };
+
+        let marker = self
+            .around_cursor()
+            .filter(|_| self.autocomplete)
+            .and_then(|(before, after)| marker_edit(&before, &after, typed));
+
+        match marker {
+            Some(MarkerEdit::StepOver) => {
+                self.content
+                    .perform(text_editor::Action::Move(text_editor::Motion::Right));
+            }
+            Some(MarkerEdit::Close) => {
+                self.content
+                    .perform(text_editor::Action::Edit(text_editor::Edit::Paste(
+                        Arc::new(format!("{typed}{typed}{typed}")),
+                    )));
+                self.content
+                    .perform(text_editor::Action::Move(text_editor::Motion::Left));
+                self.content
+                    .perform(text_editor::Action::Move(text_editor::Motion::Left));
+            }
+            None => self.content.perform(action),
+        }
+    }
+
+    fn complete_emoji(&mut self, emoji: String) {
+        let Some((before, _)) = self.around_cursor() else {
+            return;
+        };
+        let Some(len) = emoji_query(&before) else {
+            return;
+        };
+
+        for _ in before[before.len() - len..].chars() {
+            self.content
+                .perform(text_editor::Action::Select(text_editor::Motion::Left));
+        }
+        self.content
+            .perform(text_editor::Action::Edit(text_editor::Edit::Paste(
+                Arc::new(emoji),
+            )));
+    }
+
+    /// The text of the cursor's line before and after it, unless text is selected.
+    fn around_cursor(&self) -> Option<(String, String)> {
+        let cursor = self.content.cursor();
+        if cursor.selection.is_some() {
+            return None;
+        }
+
+        let line = self.content.line(cursor.position.line)?;
+        let before = line.text.get(..cursor.position.column)?;
+        let after = line.text.get(cursor.position.column..)?;
+
+        Some((before.to_owned(), after.to_owned()))
+    }
+
+    /// The recent emoji to offer for the `:query` before the cursor, if one is being typed.
+    fn emoji_suggestions(&self) -> Option<Element<'_, Message>> {
+        self.around_cursor()
+            .filter(|(before, _)| self.autocomplete && emoji_query(before).is_some())?;
+
+        let mut recent = self.recent_emoji.iter().peekable();
+        recent.peek()?;
+
+        Some(
+            row(recent.map(|emoji| {
+                button(text(emoji.to_owned()).size(18))
+                    .style(button::text)
+                    .padding([0, 4])
+                    .on_press(Message::CompleteEmoji(emoji.to_owned()))
+                    .into()
+            }))
+            .spacing(2)
+            .into(),
+        )
+    }
+
+    /// Empties the text, keeping what it replies to and the attachments.
+    pub fn clear(&mut self) {
+        self.content = text_editor::Content::new();
+    }
+
+    pub fn is_editing(&self) -> bool {
+        self.editing.is_some()
+    }
+
+    pub fn set_quote(&mut self, quote: Option<Quote>) {
+        self.quote = quote;
+    }
+
+    /// Starts editing one of our sent messages, with its text in markdown.
+    pub fn start_editing(&mut self, timestamp: Timestamp, content: &str) {
+        self.quote = None;
+        self.editing = Some(timestamp);
+        self.content = text_editor::Content::with_text(content);
+    }
+
+    /// Stops editing, dropping the text of the edit.
+    pub fn stop_editing(&mut self) {
+        if self.editing.take().is_some() {
+            self.clear();
+        }
+    }
+
+    pub fn is_attached(&self, path: &Path) -> bool {
+        self.attachments.iter().any(|staged| staged.path == path)
+    }
+
+    pub fn attach(&mut self, staged: Staged) {
+        if !self.is_attached(&staged.path) {
+            self.attachments.push(staged);
+        }
+    }
+
+    /// Takes what was written to send it, unless there's nothing to send. Edits can't change
+    /// the attachments, so they stay for the next message.
+    pub fn take_draft(&mut self) -> Option<Draft> {
+        let content = self.content.text().trim().to_owned();
+
+        if content.is_empty() && (self.editing.is_some() || self.attachments.is_empty()) {
+            return None;
+        }
+
+        self.clear();
+        self.recent_emoji.record(&content);
+
+        if let Some(editing) = self.editing.take() {
+            return Some(Draft {
+                content,
+                editing: Some(editing),
+                quote: None,
+                attachments: vec![],
+                quality: self.image_quality,
+            });
+        }
+
+        Some(Draft {
+            content,
+            editing: None,
+            quote: self.quote.take(),
+            attachments: take(&mut self.attachments),
+            quality: replace(&mut self.image_quality, self.default_image_quality),
+        })
+    }
+
+    /// Puts back a draft that couldn't be sent, unless something new was written since.
+    pub fn restore_draft(&mut self, draft: Draft) {
+        if self.editing.is_some() || !self.content.text().trim().is_empty() {
+            return;
+        }
+
+        self.content = text_editor::Content::with_text(&draft.content);
+
+        if draft.editing.is_some() {
+            self.editing = draft.editing;
+            return;
+        }
+
+        if draft.quote.is_some() {
+            self.quote = draft.quote;
+        }
+
+        for staged in draft.attachments.into_iter().rev() {
+            if !self.is_attached(&staged.path) {
+                self.attachments.insert(0, staged);
+            }
+        }
+
+        self.image_quality = draft.quality;
+    }
+
+    /// The composer, where `focused` tells whether a message is focused for the keys acting
+    /// on it.
+    pub fn as_iced_widget<'a>(
+        &'a self,
+        now: &Zoned,
+        tz: &TimeZone,
+        focused: bool,
+    ) -> Element<'a, Message> {
+        column![
+            self.quote.as_ref().map(|quote| {
+                row![
+                    quote
+                        .as_iced_widget(now, tz)
+                        .map(|message| Message::App(Box::new(message))),
+                    button(text("✕"))
+                        .style(button::text)
+                        .on_press(Message::CancelQuote),
+                ]
+                .spacing(5)
+                .align_y(Center)
+            }),
+            self.editing.as_ref().and(Some(
+                container(row![edit(), " Edit message"].align_y(Center))
+                    .padding(10)
+                    .style(|t: &iced::Theme| {
+                        let pair = t.palette().primary.weak;
+                        container::Style {
+                            background: Some(pair.color.into()),
+                            text_color: Some(pair.text),
+                            border: border::rounded(5),
+                            ..Default::default()
+                        }
+                    })
+            )),
+            parse_pack_link(&self.content.text()).map(|(id, key)| {
+                button(text("Install the linked sticker pack").size(12))
+                    .style(button::secondary)
+                    .on_press(Message::InstallStickerPack(id, key))
+            }),
+            (!self.attachments.is_empty()).then(|| self.attachment_list()),
+            rule::horizontal(1),
+            self.emoji_suggestions(),
+            row![
+                button(text("📎"))
+                    .style(button::text)
+                    .padding(0)
+                    .on_press(Message::App(Box::new(app::Message::PickAttachments))),
+                self.editor(focused),
+            ]
+            .spacing(5)
+            .align_y(Center),
+        ]
+        .spacing(5)
+        .into()
+    }
+
+    fn editor(&self, focused: bool) -> Element<'_, Message> {
+        text_editor(&self.content)
+            .min_height(20)
+            .on_action(Message::Edit)
+            .key_binding(move |key_press| {
+                let modifiers = key_press.modifiers;
+                let binding = text_editor::Binding::from_key_press(key_press)?;
+
+                Some(match binding {
+                    text_editor::Binding::Enter if self.ctrl_enter_sends => {
+                        if modifiers.command() {
+                            text_editor::Binding::Custom(Message::Send)
+                        } else {
+                            text_editor::Binding::Enter
+                        }
+                    }
+                    text_editor::Binding::Enter if !modifiers.shift() => {
+                        text_editor::Binding::Custom(Message::Send)
+                    }
+                    text_editor::Binding::Backspace
+                        if modifiers.command() && self.content.selection().is_none() =>
+                    {
+                        text_editor::Binding::Sequence(vec![
+                            text_editor::Binding::Select(text_editor::Motion::WordLeft),
+                            text_editor::Binding::Backspace,
+                        ])
+                    }
+                    text_editor::Binding::Delete
+                        if modifiers.command() && self.content.selection().is_none() =>
+                    {
+                        text_editor::Binding::Sequence(vec![
+                            text_editor::Binding::Select(text_editor::Motion::WordRight),
+                            text_editor::Binding::Delete,
+                        ])
+                    }
+                    text_editor::Binding::Move(text_editor::Motion::Up) if modifiers.alt() => {
+                        text_editor::Binding::Custom(Message::FocusOlder)
+                    }
+                    text_editor::Binding::Move(text_editor::Motion::Down) if modifiers.alt() => {
+                        text_editor::Binding::Custom(Message::FocusNewer)
+                    }
+                    text_editor::Binding::Insert('r' | 'R') if focused => {
+                        text_editor::Binding::Custom(Message::QuoteFocused)
+                    }
+                    text_editor::Binding::Insert('e' | 'E') if focused => {
+                        text_editor::Binding::Custom(Message::EditFocused)
+                    }
+                    text_editor::Binding::Insert('i' | 'I') if focused => {
+                        text_editor::Binding::Custom(Message::InfoFocused)
+                    }
+                    text_editor::Binding::Move(text_editor::Motion::Up)
+                        if self.content.line(0).is_none_or(|line| line.text.is_empty())
+                            && self.content.line_count() <= 1 =>
+                    {
+                        text_editor::Binding::Custom(Message::EditLast)
+                    }
+                    binding => binding,
+                })
+            })
+            .into()
+    }
+
+    /// The files to send with the next message, and the quality to send their images at.
+    fn attachment_list(&self) -> Element<'_, Message> {
+        let last = self.attachments.len() - 1;
+
+        let files = self.attachments.iter().enumerate().map(|(index, staged)| {
+            let preview: Element<'_, Message> = match &staged.preview {
+                Some(preview) => image(preview.clone()).width(64).height(64).into(),
+                None => container(text("📄").size(24)).center(64).into(),
+            };
+
+            let control = |label, message: Option<Message>| {
+                button(text(label).size(12))
+                    .style(button::text)
+                    .padding(0)
+                    .on_press_maybe(message)
+            };
+
+            container(
+                column![
+                    preview,
+                    text(staged.file_name()).size(12).width(80).center(),
+                    row![
+                        control(
+                            "‹",
+                            (index > 0).then(|| Message::MoveAttachment(index, index - 1))
+                        ),
+                        control("✕", Some(Message::RemoveAttachment(index))),
+                        control(
+                            "›",
+                            (index < last).then(|| Message::MoveAttachment(index, index + 1))
+                        ),
+                    ]
+                    .spacing(10),
+                ]
+                .spacing(2)
+                .align_x(Center),
+            )
+            .padding(5)
+            .style(container::rounded_box)
+            .into()
+        });
+
+        let total = self.attachments.iter().map(|staged| staged.len).sum();
+
+        let qualities = ImageQuality::ALL.map(|quality| {
+            button(text(quality.label()).size(12))
+                .style(if self.image_quality == quality {
+                    button::subtle
+                } else {
+                    button::text
+                })
+                .padding([0, 5])
+                .on_press(Message::ImageQuality(quality))
+                .into()
+        });
+
+        column![
+            scrollable(row(files).spacing(5)).horizontal(),
+            row![
+                text!(
+                    "{} {}, {}",
+                    self.attachments.len(),
+                    if self.attachments.len() == 1 {
+                        "file"
+                    } else {
+                        "files"
+                    },
+                    message::format_size(total)
+                )
+                .size(12),
+                space::horizontal(),
+                text("Images:").size(12),
+                row(qualities),
+                (self.image_quality != self.default_image_quality).then(|| {
+                    button(text("Make Default").size(12))
+                        .style(button::text)
+                        .padding([0, 5])
+                        .on_press(Message::MakeImageQualityDefault)
+                }),
+            ]
+            .spacing(5)
+            .align_y(Center),
+        ]
+        .spacing(5)
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn write(composer: &mut Composer, text: &str) {
+        composer.update(Message::Edit(text_editor::Action::Edit(
+            text_editor::Edit::Paste(text.to_owned().into()),
+        )));
+    }
+
+    #[test]
+    fn drafts() {
+        let mut composer = Composer::new(&Config::default());
+        assert!(composer.take_draft().is_none());
+
+        composer.attach(Staged {
+            path: PathBuf::from("a.png"),
+            len: 1,
+            preview: None,
+        });
+        composer.update(Message::ImageQuality(ImageQuality::Original));
+
+        // attachments are sent without a text
+        let draft = composer.take_draft().unwrap();
+        assert_eq!((draft.content.as_str(), draft.attachments.len()), ("", 1));
+        assert_eq!(draft.quality, ImageQuality::Original);
+        assert!(composer.take_draft().is_none());
+
+        composer.attach(Staged {
+            path: PathBuf::from("b.png"),
+            len: 1,
+            preview: None,
+        });
+        composer.start_editing(Timestamp::UNIX_EPOCH, " hello ");
+
+        let draft = composer.take_draft().unwrap();
+        assert_eq!(draft.content, "hello");
+        assert_eq!(draft.editing, Some(Timestamp::UNIX_EPOCH));
+
+        // the attachments wait for the next message
+        assert!(composer.is_attached(Path::new("b.png")));
+        assert!(!composer.is_editing());
+
+        write(&mut composer, "   ");
+        assert_eq!(composer.take_draft().unwrap().attachments.len(), 1);
+    }
+}
@@ -0,0 +1,316 @@
+use presage::libsignal_service::configuration::SignalServers;
+use std::{
+    fmt::{self, Debug, Formatter},
+    ops::Deref,
+    path::PathBuf,
+    str::FromStr,
+};
+use tracing::Level;
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// The Signal environment to link against.
+    pub servers: SignalServers,
+    /// Whether to encrypt the database with a passphrase instead of a key from the keyring.
+    pub ask_passphrase: bool,
+    /// The key the database is encrypted with, if any.
+    pub key: Option<DatabaseKey>,
+    /// Overrides the level set through `RUST_LOG`.
+    pub log_level: Option<Level>,
+    pub log_format: LogFormat,
+    /// Whether to write logs to a file in the state directory.
+    pub log_file: bool,
+    /// Whether to record the spans of the startup sync in a Chrome trace.
+    pub trace_startup: bool,
+    /// Overrides the quality images are sent at unless chosen otherwise, which is otherwise
+    /// the one last made the default in the composer.
+    pub image_quality: Option<ImageQuality>,
+    /// Whether messages are sent with Ctrl+Enter, leaving Enter to start a new line.
+    pub ctrl_enter_sends: bool,
+    /// Overrides whether times are written with a 12 or 24 hour clock, which otherwise follows
+    /// the locale.
+    pub clock: Option<Clock>,
+    /// The emoji double clicking a message reacts with.
+    pub quick_reaction: String,
+    /// Whether the composer offers recent emoji and closes markdown markers.
+    pub autocomplete: bool,
+    /// The size, in logical pixels, of the avatars next to messages and chats.
+    pub avatar_size: u16,
+    /// Whether animated avatars are kept on their first frame.
+    pub reduced_motion: bool,
+    /// The most blank lines in a row kept in received messages, which also lose the
+    /// whitespace at their ends, or `None` to show them as sent.
+    pub blank_lines: Option<usize>,
+    /// Whether messages received with sealed sender are marked with a lock.
+    pub sealed_sender_icons: bool,
+    pub notifications: NotificationBackend,
+    /// Whether reactions to our messages are notified about, apart from new messages.
+    pub reaction_notifications: bool,
+    /// Whether contacts are told when their messages were read.
+    pub read_receipts: bool,
+    /// The command links are opened with, with `%s` standing for the link, instead of the
+    /// default browser.
+    pub browser: Option<String>,
+}
+
+/// The key or passphrase a database is encrypted with, left out of the debug output so it
+/// can't end up in logs.
+#[derive(Clone)]
+pub struct DatabaseKey(String);
+
+impl DatabaseKey {
+    pub fn new(key: String) -> Self {
+        Self(key)
+    }
+}
+
+impl Deref for DatabaseKey {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Debug for DatabaseKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("DatabaseKey(..)")
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub enum LogFormat {
+    #[default]
+    Json,
+    Pretty,
+}
+
+impl FromStr for LogFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "pretty" => Ok(Self::Pretty),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Clock {
+    TwelveHour,
+    TwentyFourHour,
+}
+
+impl FromStr for Clock {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "12" => Ok(Self::TwelveHour),
+            "24" => Ok(Self::TwentyFourHour),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Where notifications are shown.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum NotificationBackend {
+    /// The XDG desktop portal when sandboxed or without a notification server, the desktop's
+    /// notifications otherwise.
+    #[default]
+    Auto,
+    Desktop,
+    Portal,
+    None,
+}
+
+impl FromStr for NotificationBackend {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "desktop" => Ok(Self::Desktop),
+            "portal" => Ok(Self::Portal),
+            "none" => Ok(Self::None),
+            _ => Err(()),
+        }
+    }
+}
+
+/// How much outgoing images are shrunk before they're uploaded.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ImageQuality {
+    #[default]
+    Standard,
+    High,
+    /// The file as it is.
+    Original,
+}
+
+impl ImageQuality {
+    pub const ALL: [Self; 3] = [Self::Standard, Self::High, Self::Original];
+
+    /// The largest dimension and the JPEG quality images are re-encoded at, if at all.
+    pub fn encoding(self) -> Option<(u32, u8)> {
+        match self {
+            Self::Standard => Some((1600, 75)),
+            Self::High => Some((3072, 90)),
+            Self::Original => None,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Standard => "Standard",
+            Self::High => "High",
+            Self::Original => "Original",
+        }
+    }
+}
+
+impl FromStr for ImageQuality {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "standard" => Ok(Self::Standard),
+            "high" => Ok(Self::High),
+            "original" => Ok(Self::Original),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            servers: SignalServers::Production,
+            ask_passphrase: false,
+            key: None,
+            log_level: None,
+            log_format: LogFormat::default(),
+            log_file: true,
+            trace_startup: false,
+            image_quality: None,
+            ctrl_enter_sends: false,
+            autocomplete: true,
+            avatar_size: 50,
+            reduced_motion: false,
+            blank_lines: Some(1),
+            sealed_sender_icons: false,
+            notifications: NotificationBackend::default(),
+            reaction_notifications: true,
+            read_receipts: true,
+            clock: None,
+            quick_reaction: "❤️".to_owned(),
+            browser: None,
+        }
+    }
+}
+
+impl Config {
+    /// Every environment gets its own database, so a staging account is never mixed with a
+    /// production one.
+    pub fn database_path(&self) -> &'static str {
+        match self.servers {
+            SignalServers::Production => concat!(env!("CARGO_MANIFEST_DIR"), "/foghorn.db"),
+            SignalServers::Staging => concat!(env!("CARGO_MANIFEST_DIR"), "/foghorn-staging.db"),
+        }
+    }
+
+    /// The full-text search index of the database's messages.
+    pub fn search_index_path(&self) -> &'static str {
+        match self.servers {
+            SignalServers::Production => concat!(env!("CARGO_MANIFEST_DIR"), "/foghorn-search.db"),
+            SignalServers::Staging => {
+                concat!(env!("CARGO_MANIFEST_DIR"), "/foghorn-staging-search.db")
+            }
+        }
+    }
+
+    /// Where logs and other state that isn't worth backing up are kept.
+    pub fn state_dir(&self) -> PathBuf {
+        dirs::state_dir()
+            .or_else(dirs::data_local_dir)
+            .map_or_else(|| PathBuf::from("."), |dir| dir.join("foghorn"))
+    }
+
+    /// When the profiles of the database's contacts were last fetched.
+    pub fn profiles_refreshed_path(&self) -> PathBuf {
+        self.state_dir().join(match self.servers {
+            SignalServers::Production => "profiles-refreshed",
+            SignalServers::Staging => "profiles-refreshed-staging",
+        })
+    }
+
+    /// The chats whose notifications are muted.
+    pub fn muted_path(&self) -> PathBuf {
+        self.state_dir().join(match self.servers {
+            SignalServers::Production => "muted",
+            SignalServers::Staging => "muted-staging",
+        })
+    }
+
+    /// The contacts that aren't on Signal anymore.
+    pub fn unregistered_path(&self) -> PathBuf {
+        self.state_dir().join(match self.servers {
+            SignalServers::Production => "unregistered",
+            SignalServers::Staging => "unregistered-staging",
+        })
+    }
+
+    /// The chat that was open and how far the chats were scrolled when the app was left.
+    pub fn session_path(&self) -> PathBuf {
+        self.state_dir().join(match self.servers {
+            SignalServers::Production => "session",
+            SignalServers::Staging => "session-staging",
+        })
+    }
+
+    /// The quality images are sent at unless chosen otherwise, as made the default in the
+    /// composer.
+    pub fn image_quality_path(&self) -> PathBuf {
+        self.state_dir().join("image-quality")
+    }
+
+    /// The emoji recently sent, offered by the composer.
+    pub fn recent_emoji_path(&self) -> PathBuf {
+        self.state_dir().join("recent-emoji")
+    }
+
+    /// The Chrome trace of the last startup, if it was recorded. It opens in `about:tracing`
+    /// and Perfetto.
+    pub fn startup_trace_path(&self) -> PathBuf {
+        self.state_dir().join("startup-trace.json")
+    }
+
+    /// Where downloaded attachments are kept, so they don't have to be downloaded again.
+    pub fn attachment_cache_dir(&self) -> PathBuf {
+        dirs::cache_dir()
+            .map_or_else(|| PathBuf::from("."), |dir| dir.join("foghorn"))
+            .join("attachments")
+    }
+
+    /// Where attachments are written to be opened with other applications, preferably the
+    /// runtime directory, which only the user can enter and is emptied when they log out.
+    pub fn opened_attachments_dir(&self) -> PathBuf {
+        dirs::runtime_dir()
+            .or_else(dirs::cache_dir)
+            .map_or_else(|| PathBuf::from("."), |dir| dir.join("foghorn"))
+            .join("opened")
+    }
+
+    /// The socket guarding the database against a second instance.
+    pub fn socket_path(&self) -> &'static str {
+        match self.servers {
+            SignalServers::Production => concat!(env!("CARGO_MANIFEST_DIR"), "/foghorn.sock"),
+            SignalServers::Staging => {
+                concat!(env!("CARGO_MANIFEST_DIR"), "/foghorn-staging.sock")
+            }
+        }
+    }
+}
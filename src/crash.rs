@@ -0,0 +1,91 @@
+use crate::{browser, log};
+use std::{
+    backtrace::Backtrace,
+    fmt::Write as _,
+    fs,
+    panic::{self, PanicHookInfo},
+    path::{Path, PathBuf},
+    thread,
+};
+
+/// The name of the crash report in the state directory.
+const REPORT: &str = "crash.txt";
+
+const NEW_ISSUE: &str = "https://github.com/foghorn-rs/foghorn/issues/new";
+
+/// The longest report put into an issue link, so the URL stays within what browsers accept.
+const MAX_ISSUE_REPORT: usize = 6000;
+
+/// Writes a crash report to the state directory whenever a thread panics.
+pub fn install_hook(dir: PathBuf) {
+    let default_hook = panic::take_hook();
+
+    panic::set_hook(Box::new(move |info| {
+        _ = fs::create_dir_all(&dir);
+
+        // the report only contains the panic, its backtrace, and where recent events were
+        // logged, so it never contains message content
+        if let Err(err) = fs::write(dir.join(REPORT), report(info)) {
+            log::error!("Failed to write the crash report: {}", err);
+        }
+
+        default_hook(info);
+    }));
+}
+
+/// Takes the report of the last crash, so it's only offered once.
+pub fn take_report(dir: &Path) -> Option<String> {
+    let path = dir.join(REPORT);
+    let report = fs::read_to_string(&path).ok()?;
+
+    // keep it around for the user to attach by hand
+    _ = fs::rename(&path, dir.join("crash.previous.txt"));
+
+    Some(report)
+}
+
+/// Opens the issue form in the browser, prefilled with the report.
+pub fn open_issue(report: &str) {
+    let report = match report.char_indices().nth(MAX_ISSUE_REPORT) {
+        Some((end, _)) => &report[..end],
+        None => report,
+    };
+
+    let body = format!(
+        "**What were you doing when Foghorn crashed?**\n\n\n\n**Crash report**\n\n```\n{report}\n```\n"
+    );
+    let url = format!(
+        "{NEW_ISSUE}?title={}&body={}",
+        percent_encode("Crash report"),
+        percent_encode(&body)
+    );
+
+    browser::open(&url, None);
+}
+
+fn report(info: &PanicHookInfo<'_>) -> String {
+    let mut report = format!(
+        "Foghorn {} panicked on thread '{}'\n{info}\n\nBacktrace:\n{}\n\nRecent events:\n",
+        env!("CARGO_PKG_VERSION"),
+        thread::current().name().unwrap_or("<unnamed>"),
+        Backtrace::force_capture(),
+    );
+
+    for event in log::recent() {
+        report += &event;
+        report.push('\n');
+    }
+
+    report
+}
+
+fn percent_encode(s: &str) -> String {
+    s.bytes().fold(String::new(), |mut encoded, byte| {
+        if byte.is_ascii_alphanumeric() || b"-_.~".contains(&byte) {
+            encoded.push(byte.into());
+        } else {
+            _ = write!(encoded, "%{byte:02X}");
+        }
+        encoded
+    })
+}
@@ -0,0 +1,209 @@
+use crate::{
+    config::{Config, DatabaseKey},
+    log,
+};
+use jiff::Timestamp;
+use presage::model::identity::OnNewIdentity;
+use presage_store_sqlite::SqliteStore;
+use sqlx::{ConnectOptions as _, Connection as _, sqlite::SqliteConnectOptions};
+use std::{
+    borrow::Cow,
+    error::Error,
+    fmt::Write as _,
+    fs::{self, File},
+    io::{self, Read as _},
+    path::{Path, PathBuf},
+};
+
+/// The service the database key is stored under in the system keyring.
+const KEYRING_SERVICE: &str = "foghorn";
+
+/// The header every unencrypted SQLite database starts with.
+const PLAINTEXT_HEADER: &[u8; 16] = b"SQLite format 3\0";
+
+/// Finds the key the database is encrypted with: a passphrase read from the terminal when
+/// asked for, or otherwise a random key kept in the system keyring.
+///
+/// Returns no key when there's no way to store one, in which case the database stays
+/// unencrypted, and fails when a passphrase was asked for but couldn't be read.
+pub fn key(config: &Config) -> io::Result<Option<DatabaseKey>> {
+    if config.ask_passphrase {
+        return read_passphrase().map(Some);
+    }
+
+    Ok(keyring_key(config).map(DatabaseKey::new))
+}
+
+fn keyring_key(config: &Config) -> Option<String> {
+    let entry = keyring::Entry::new(KEYRING_SERVICE, config.database_path())
+        .inspect_err(|err| log::warn!("Keyring unavailable, not encrypting the database: {}", err))
+        .ok()?;
+
+    match entry.get_password() {
+        Ok(key) => return Some(key),
+        Err(keyring::Error::NoEntry) => {}
+        Err(err) => {
+            log::warn!("Keyring unavailable, not encrypting the database: {}", err);
+            return None;
+        }
+    }
+
+    // a database encrypted with a passphrase can't be opened with a new key
+    if !Path::new(config.database_path()).exists() || is_plaintext(config.database_path()) {
+        let key = random_key()?;
+
+        match entry.set_password(&key) {
+            Ok(()) => return Some(key),
+            Err(err) => log::warn!("Failed to store the database key: {}", err),
+        }
+    } else {
+        log::error!(
+            "The database is encrypted, but the keyring has no key for it. Was it encrypted with --passphrase?"
+        );
+    }
+
+    None
+}
+
+/// Opens the database, encrypting it first if it was created before encryption was supported.
+pub async fn open(config: &Config) -> Result<SqliteStore, Box<dyn Error>> {
+    let path = config.database_path();
+
+    encrypt_if_plaintext(config, path).await?;
+
+    Ok(
+        SqliteStore::open_with_passphrase(path, config.key.as_deref(), OnNewIdentity::Trust)
+            .await?,
+    )
+}
+
+/// Encrypts one of our databases if it was created while there was no key, like before
+/// encryption was supported or while the keyring was unavailable.
+pub async fn encrypt_if_plaintext(config: &Config, path: &str) -> Result<(), Box<dyn Error>> {
+    if let Some(key) = &config.key
+        && is_plaintext(path)
+    {
+        log::info!("Encrypting the existing {}", path);
+        encrypt(path, key).await?;
+    }
+
+    Ok(())
+}
+
+/// Describes why the database couldn't be opened, including what an integrity check found.
+pub async fn diagnose(config: &Config, err: &dyn Error) -> String {
+    let mut report = err.to_string();
+
+    match integrity_check(config).await {
+        Ok(problems) if problems == ["ok"] => {}
+        Ok(problems) => {
+            report += "\n\nIntegrity check:\n";
+            report += &problems.join("\n");
+        }
+        Err(err) => _ = write!(report, "\n\nIntegrity check failed: {err}"),
+    }
+
+    report
+}
+
+/// Moves the database out of the way, so an empty one is created when it's opened next. The
+/// search index goes with it, since it holds the bodies and receipts of the same account.
+pub fn backup(config: &Config) -> io::Result<PathBuf> {
+    let now = Timestamp::now().as_second();
+    let path = config.database_path();
+    let backup = format!("{path}.{now}.bak");
+
+    move_aside(path, &backup)?;
+
+    let index = config.search_index_path();
+    match move_aside(index, &format!("{index}.{now}.bak")) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+
+    Ok(backup.into())
+}
+
+/// Renames a database along with the files SQLite keeps next to it.
+fn move_aside(path: &str, backup: &str) -> io::Result<()> {
+    fs::rename(path, backup)?;
+
+    for suffix in ["-wal", "-shm"] {
+        _ = fs::rename(format!("{path}{suffix}"), format!("{backup}{suffix}"));
+    }
+
+    Ok(())
+}
+
+async fn integrity_check(config: &Config) -> Result<Vec<String>, sqlx::Error> {
+    let mut options = SqliteConnectOptions::new()
+        .filename(config.database_path())
+        .read_only(true);
+
+    if let Some(key) = &config.key {
+        options = options.pragma("key", Cow::Owned(format!("'{}'", key.replace('\'', "''"))));
+    }
+
+    let mut connection = options.connect().await?;
+    let problems = sqlx::query_scalar("PRAGMA integrity_check")
+        .fetch_all(&mut connection)
+        .await?;
+    connection.close().await?;
+
+    Ok(problems)
+}
+
+/// Copies a plaintext database into an encrypted one, and replaces it with the copy.
+async fn encrypt(path: &str, key: &str) -> Result<(), Box<dyn Error>> {
+    let encrypted = format!("{path}.encrypted");
+    _ = fs::remove_file(&encrypted);
+
+    let mut connection = SqliteConnectOptions::new().filename(path).connect().await?;
+
+    sqlx::query("ATTACH DATABASE ? AS encrypted KEY ?")
+        .bind(&encrypted)
+        .bind(key)
+        .execute(&mut connection)
+        .await?;
+    sqlx::query("SELECT sqlcipher_export('encrypted')")
+        .fetch_optional(&mut connection)
+        .await?;
+    sqlx::query("DETACH DATABASE encrypted")
+        .execute(&mut connection)
+        .await?;
+
+    connection.close().await?;
+
+    fs::rename(encrypted, path)?;
+
+    Ok(())
+}
+
+fn is_plaintext(path: &str) -> bool {
+    let mut header = [0; PLAINTEXT_HEADER.len()];
+
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut header))
+        .is_ok_and(|()| &header == PLAINTEXT_HEADER)
+}
+
+fn random_key() -> Option<String> {
+    let mut bytes = [0_u8; 32];
+
+    getrandom::fill(&mut bytes)
+        .inspect_err(|err| log::error!("Failed to generate a database key: {}", err))
+        .ok()?;
+
+    Some(hex::encode(bytes))
+}
+
+/// Reads the passphrase from the terminal without showing it, which fails without one.
+fn read_passphrase() -> io::Result<DatabaseKey> {
+    let passphrase = rpassword::prompt_password("Foghorn: database passphrase: ")?;
+
+    if passphrase.is_empty() {
+        return Err(io::Error::other("the passphrase is empty"));
+    }
+
+    Ok(DatabaseKey::new(passphrase))
+}
@@ -0,0 +1,163 @@
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use presage::libsignal_service::zkgroup::GroupMasterKeyBytes;
+
+/// The hosts of contact links, on the web and as registered by Signal's apps.
+const CONTACT_PREFIXES: [&str; 2] = ["https://signal.me/#", "sgnl://signal.me/#"];
+
+/// The hosts of group invite links.
+const GROUP_PREFIXES: [&str; 2] = ["https://signal.group/#", "sgnl://signal.group/#"];
+
+/// A link to something inside Signal, rather than to a web page.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DeepLink {
+    /// A contact shared by their phone number.
+    Phone(String),
+    /// A contact shared by their username link, which is encrypted.
+    Username,
+    /// An invite to join a group.
+    Group(GroupMasterKeyBytes),
+}
+
+impl DeepLink {
+    pub fn parse(link: &str) -> Option<Self> {
+        if let Some(fragment) = CONTACT_PREFIXES
+            .iter()
+            .find_map(|prefix| link.strip_prefix(prefix))
+        {
+            if let Some(number) = fragment.strip_prefix("p/") {
+                let number = number.replace("%2B", "+");
+                return (number.starts_with('+')
+                    && number.len() > 1
+                    && number[1..].bytes().all(|byte| byte.is_ascii_digit()))
+                .then_some(Self::Phone(number));
+            }
+
+            return fragment.starts_with("eu/").then_some(Self::Username);
+        }
+
+        let fragment = GROUP_PREFIXES
+            .iter()
+            .find_map(|prefix| link.strip_prefix(prefix))?;
+        let invite = URL_SAFE_NO_PAD.decode(fragment).ok()?;
+
+        // GroupInviteLink { oneof { GroupInviteLinkContentsV1 v1Contents = 1; } }
+        // GroupInviteLinkContentsV1 { bytes groupMasterKey = 1; bytes inviteLinkPassword = 2; }
+        let contents = protobuf_field(&invite, 1)?;
+        let key = protobuf_field(contents, 1)?;
+
+        Some(Self::Group(key.try_into().ok()?))
+    }
+}
+
+/// The invite link of a group, as shared by Signal's apps.
+pub fn group_invite_link(key: &GroupMasterKeyBytes, password: &[u8]) -> String {
+    let mut contents = vec![];
+    push_protobuf_field(&mut contents, 1, key);
+    push_protobuf_field(&mut contents, 2, password);
+
+    let mut invite = vec![];
+    push_protobuf_field(&mut invite, 1, &contents);
+
+    format!("{}{}", GROUP_PREFIXES[0], URL_SAFE_NO_PAD.encode(invite))
+}
+
+fn push_protobuf_field(bytes: &mut Vec<u8>, number: u64, value: &[u8]) {
+    push_varint(bytes, (number << 3) | 2);
+    push_varint(bytes, value.len() as u64);
+    bytes.extend_from_slice(value);
+}
+
+fn push_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+/// Finds the length-delimited field `number` of an encoded protobuf message.
+fn protobuf_field(mut bytes: &[u8], number: u64) -> Option<&[u8]> {
+    while !bytes.is_empty() {
+        let key = varint(&mut bytes)?;
+
+        match key & 7 {
+            0 => _ = varint(&mut bytes)?,
+            2 => {
+                let len = usize::try_from(varint(&mut bytes)?).ok()?;
+                let (value, rest) = bytes.split_at_checked(len)?;
+                bytes = rest;
+
+                if key >> 3 == number {
+                    return Some(value);
+                }
+            }
+            // no other wire types are used by invite links
+            _ => return None,
+        }
+    }
+
+    None
+}
+
+fn varint(bytes: &mut &[u8]) -> Option<u64> {
+    let mut value = 0;
+
+    for shift in (0..64).step_by(7) {
+        let (byte, rest) = bytes.split_first()?;
+        *bytes = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+
+        if byte & 0x80 == 0 {
+            return Some(value);
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn links() {
+        assert_eq!(
+            DeepLink::parse("https://signal.me/#p/+4915112345678"),
+            Some(DeepLink::Phone("+4915112345678".to_owned()))
+        );
+        assert_eq!(
+            DeepLink::parse("sgnl://signal.me/#p/%2B4915112345678"),
+            Some(DeepLink::Phone("+4915112345678".to_owned()))
+        );
+        assert_eq!(
+            DeepLink::parse("https://signal.me/#eu/c2VjcmV0"),
+            Some(DeepLink::Username)
+        );
+        assert_eq!(DeepLink::parse("https://signal.me/#p/alice"), None);
+        assert_eq!(DeepLink::parse("https://signal.org/"), None);
+
+        let key = [7; 32];
+        let mut invite = vec![0x0a, 38, 0x0a, 32];
+        invite.extend(key);
+        invite.extend([0x12, 4, 1, 2, 3, 4]);
+
+        assert_eq!(
+            DeepLink::parse(&format!(
+                "https://signal.group/#{}",
+                URL_SAFE_NO_PAD.encode(&invite)
+            )),
+            Some(DeepLink::Group(key))
+        );
+        assert_eq!(
+            DeepLink::parse(&group_invite_link(&key, &[1, 2, 3, 4])),
+            Some(DeepLink::Group(key))
+        );
+        assert_eq!(
+            DeepLink::parse(&format!(
+                "sgnl://signal.group/#{}",
+                URL_SAFE_NO_PAD.encode(&invite[..20])
+            )),
+            None
+        );
+    }
+}
@@ -1,7 +1,7 @@
 use crate::app::Message;
 use iced::{
     Color, Element, Font,
-    widget::{center_x, column, container, qr_code, text},
+    widget::{center_x, column, container, qr_code, scrollable, text},
 };
 use iced_dialog::button;
 use std::borrow::Cow;
@@ -12,6 +12,13 @@ pub enum Action {
     None,
     Close,
     RetryLinking,
+    CancelLinking,
+    ReportCrash,
+    RecreateDatabase,
+    OpenLink,
+    OpenAttachment,
+    ClearHistory,
+    SolveCaptcha,
 }
 
 impl From<Action> for Vec<Element<'_, Message>> {
@@ -20,6 +27,31 @@ impl From<Action> for Vec<Element<'_, Message>> {
             Action::None => vec![],
             Action::Close => vec![button("Close", Message::CloseDialog).into()],
             Action::RetryLinking => vec![button("Retry Linking", Message::LinkSecondary).into()],
+            Action::CancelLinking => vec![button("Cancel", Message::CancelLinking).into()],
+            Action::ReportCrash => vec![
+                button("Report Issue", Message::ReportCrash).into(),
+                button("Continue", Message::DismissCrash).into(),
+            ],
+            Action::RecreateDatabase => {
+                vec![button("Back Up and Recreate", Message::RecreateDatabase).into()]
+            }
+            Action::OpenLink => vec![
+                button("Open", Message::OpenLink).into(),
+                button("Cancel", Message::CloseDialog).into(),
+            ],
+            Action::OpenAttachment => vec![
+                button("Open Anyway", Message::ConfirmOpenAttachment).into(),
+                button("Cancel", Message::CloseDialog).into(),
+            ],
+            Action::ClearHistory => vec![
+                button("Clear", Message::ConfirmClearHistory).into(),
+                button("Cancel", Message::CloseDialog).into(),
+            ],
+            Action::SolveCaptcha => vec![
+                button("Open Challenge", Message::OpenCaptcha).into(),
+                button("Paste and Send", Message::PasteCaptcha).into(),
+                button("Cancel", Message::CloseDialog).into(),
+            ],
         }
     }
 }
@@ -56,6 +88,11 @@ impl Dialog {
         self
     }
 
+    /// Replaces the text of the open dialog, keeping the rest.
+    pub fn set_content(&mut self, content: impl Into<Cow<'static, str>>) {
+        self.content = content.into();
+    }
+
     pub fn close(&mut self) {
         self.is_open = false;
     }
@@ -64,18 +101,20 @@ impl Dialog {
         &'a self,
         base: impl Into<Element<'a, Message>>,
     ) -> iced_dialog::Dialog<'a, Message> {
-        let content = column![
-            text(&*self.content).font(self.font),
-            self.qr_code.as_ref().map(|code| center_x(
-                container(qr_code(code).style(|_| qr_code::Style {
-                    cell: Color::BLACK,
-                    background: Color::WHITE,
-                }))
-                .padding(4)
-                .style(|_| container::background(Color::WHITE))
-            ))
-        ]
-        .spacing(8);
+        let content = scrollable(
+            column![
+                text(&*self.content).font(self.font),
+                self.qr_code.as_ref().map(|code| center_x(
+                    container(qr_code(code).style(|_| qr_code::Style {
+                        cell: Color::BLACK,
+                        background: Color::WHITE,
+                    }))
+                    .padding(4)
+                    .style(|_| container::background(Color::WHITE))
+                ))
+            ]
+            .spacing(8),
+        );
 
         iced_dialog::Dialog::with_buttons(self.is_open, base, content, self.action.into())
             .title(&*self.title)
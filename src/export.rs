@@ -0,0 +1,269 @@
+use crate::message::{Chat, Message, MessageId};
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use jiff::{Timestamp, tz::TimeZone};
+use std::{collections::HashMap, fmt::Write as _, fs, io, path::Path};
+
+/// How a chat's history is written.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    Text,
+    /// A page that can be archived on its own, with the images embedded.
+    Html,
+}
+
+impl Format {
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Text => "txt",
+            Self::Html => "html",
+        }
+    }
+}
+
+/// The bytes of the exported images, by message and attachment index.
+pub type Media = HashMap<(MessageId, usize), Vec<u8>>;
+
+/// Writes a chat's history to `path`.
+pub fn export<'a>(
+    path: &Path,
+    format: Format,
+    chat: &Chat,
+    messages: impl IntoIterator<Item = &'a Message>,
+    tz: &TimeZone,
+    media: &Media,
+) -> io::Result<()> {
+    let exported = match format {
+        Format::Text => to_text(chat, messages, tz),
+        Format::Html => to_html(chat, messages, tz, media),
+    };
+
+    fs::write(path, exported)
+}
+
+/// The name suggested for the export of a chat, dated today.
+pub fn file_name(chat: &Chat, format: Format, tz: &TimeZone) -> String {
+    // the name may come from anyone, so it can't be allowed to leave the directory
+    let name = chat
+        .name()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == ' ' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+
+    format!(
+        "{} {}.{}",
+        name.trim(),
+        Timestamp::now().to_zoned(tz.clone()).strftime("%Y-%m-%d"),
+        format.extension()
+    )
+}
+
+/// One line per message, with the names of its attachments.
+fn to_text<'a>(
+    chat: &Chat,
+    messages: impl IntoIterator<Item = &'a Message>,
+    tz: &TimeZone,
+) -> String {
+    let mut text = format!("{}\n\n", chat.name());
+
+    for message in messages {
+        let at = message.timestamp.to_zoned(tz.clone());
+        _ = write!(text, "[{}] ", at.strftime("%Y-%m-%d %H:%M"));
+
+        if let Some(system) = &message.system {
+            _ = writeln!(text, "{system}");
+            continue;
+        }
+
+        let body = message
+            .body
+            .iter()
+            .flatten()
+            .map(|span| &*span.text)
+            .collect::<String>();
+
+        _ = write!(text, "{}:", message.sender.name);
+        if !body.is_empty() {
+            _ = write!(text, " {body}");
+        }
+
+        for attachment in &message.attachments {
+            _ = write!(
+                text,
+                " [{}]",
+                attachment
+                    .ptr
+                    .file_name
+                    .as_deref()
+                    .unwrap_or(attachment.mime.essence_str())
+            );
+        }
+
+        if message.sticker.is_some() {
+            text.push_str(" [sticker]");
+        }
+
+        text.push('\n');
+    }
+
+    text
+}
+
+const STYLE: &str = "\
+body { font-family: sans-serif; background: #f0f0f0; max-width: 720px; margin: 0 auto; padding: 16px; }
+.message { margin: 6px 0; display: flex; flex-direction: column; align-items: flex-start; }
+.message.own { align-items: flex-end; }
+.bubble { background: #fff; border-radius: 12px; padding: 8px 12px; max-width: 80%; }
+.own .bubble { background: #2c6bed; color: #fff; }
+.sender { font-size: 12px; font-weight: bold; }
+.body { white-space: pre-wrap; overflow-wrap: anywhere; }
+.time { font-size: 11px; opacity: 0.7; }
+.system { text-align: center; font-size: 12px; color: #666; margin: 12px 0; }
+img { max-width: 100%; border-radius: 8px; display: block; margin: 4px 0; }
+.file { font-style: italic; }
+";
+
+/// The messages as chat bubbles, with the images in `media` embedded as data URIs.
+fn to_html<'a>(
+    chat: &Chat,
+    messages: impl IntoIterator<Item = &'a Message>,
+    tz: &TimeZone,
+    media: &Media,
+) -> String {
+    let title = escape(chat.name());
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>\n{STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>\n"
+    );
+
+    for message in messages {
+        let at = message.timestamp.to_zoned(tz.clone());
+        let at = at.strftime("%Y-%m-%d %H:%M");
+
+        if let Some(system) = &message.system {
+            _ = writeln!(
+                html,
+                "<div class=\"system\">{} · {at}</div>",
+                escape(&system.to_string())
+            );
+            continue;
+        }
+
+        _ = write!(
+            html,
+            "<div class=\"message{}\"><div class=\"bubble\">",
+            if message.sender.is_self { " own" } else { "" }
+        );
+
+        if matches!(chat, Chat::Group(_)) && !message.sender.is_self {
+            _ = write!(
+                html,
+                "<div class=\"sender\">{}</div>",
+                escape(&message.sender.name)
+            );
+        }
+
+        for (index, attachment) in message.attachments.iter().enumerate() {
+            match media.get(&(message.id(), index)) {
+                Some(bytes) => {
+                    _ = write!(
+                        html,
+                        "<img src=\"data:{};base64,{}\" alt=\"{}\">",
+                        attachment.mime.essence_str(),
+                        STANDARD.encode(bytes),
+                        escape(&attachment.file_name())
+                    )
+                }
+                None => {
+                    _ = write!(
+                        html,
+                        "<div class=\"file\">{}</div>",
+                        escape(&attachment.description())
+                    )
+                }
+            }
+        }
+
+        if message.sticker.is_some() {
+            html.push_str("<div class=\"file\">Sticker</div>");
+        }
+
+        let body = message
+            .body
+            .iter()
+            .flatten()
+            .map(|span| &*span.text)
+            .collect::<String>();
+
+        if !body.is_empty() {
+            _ = write!(html, "<div class=\"body\">{}</div>", escape(&body));
+        }
+
+        _ = writeln!(html, "<div class=\"time\">{at}</div></div></div>");
+    }
+
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+/// Escapes text for both the content and the attributes of HTML.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::message::test::{ALICE, contact, message};
+    use std::sync::Arc;
+
+    #[test]
+    fn text() {
+        let alice = Arc::new(contact(ALICE, "Alice"));
+        let messages = [
+            message(
+                Timestamp::from_second(60).unwrap(),
+                alice.clone(),
+                Some("hello"),
+            ),
+            message(Timestamp::from_second(120).unwrap(), alice.clone(), None),
+        ];
+
+        assert_eq!(
+            to_text(&Chat::Contact(alice.clone()), &messages, &TimeZone::UTC),
+            "Alice\n\n[1970-01-01 00:01] Alice: hello\n[1970-01-01 00:02] Alice:\n"
+        );
+
+        let escaped = [message(
+            Timestamp::from_second(60).unwrap(),
+            alice.clone(),
+            Some("<b>hi</b> & bye"),
+        )];
+        let html = to_html(
+            &Chat::Contact(alice),
+            &escaped,
+            &TimeZone::UTC,
+            &Media::new(),
+        );
+        assert!(html.contains(
+            "<div class=\"body\">&lt;b&gt;hi&lt;/b&gt; &amp; bye</div><div class=\"time\">1970-01-01 00:01</div>"
+        ));
+    }
+}
@@ -0,0 +1,431 @@
+use crate::{
+    app::Message,
+    backend::SignalBackend,
+    image::Image,
+    message::{Attachment, Chat, sent_at, target_timestamp, thread_contents},
+    parse::find_urls,
+};
+use iced::{
+    Center, Element, Fill,
+    mouse::Interaction,
+    widget::{
+        button, center, column, container, grid, mouse_area, opaque, row, scrollable, space, stack,
+        text,
+    },
+};
+use jiff::Timestamp;
+use presage::{
+    libsignal_service::content::ContentBody,
+    proto::{AttachmentPointer, DataMessage, EditMessage, SyncMessage, sync_message::Sent},
+    store::Thread,
+};
+use std::cmp::Reverse;
+
+/// How many thumbnails are shown at first, and how many more each time more are asked for.
+const PAGE: usize = 60;
+
+const COLUMNS: usize = 4;
+
+const THUMBNAIL_SIZE: f32 = 88.0;
+
+/// What was shared in a chat, newest first.
+#[derive(Clone, Debug, Default)]
+pub struct Shared {
+    /// The images and videos, with when they were sent.
+    pub media: Vec<(Timestamp, Attachment)>,
+    pub links: Vec<(Timestamp, String)>,
+    /// Every other file, like documents and voice notes.
+    pub docs: Vec<(Timestamp, Attachment)>,
+}
+
+/// Collects what was shared in a thread from its history in the store, which also has what
+/// was sent before the decoded messages. Of an edited message, only the edit counts.
+pub async fn shared(manager: &impl SignalBackend, thread: &Thread) -> Shared {
+    let mut shared = Shared::default();
+
+    for content in thread_contents(manager, thread).await {
+        // an edit is shown where the message it replaced was sent
+        let sent = sent_at(target_timestamp(&content).unwrap_or(content.metadata.timestamp));
+        let Some(message) = data_message(content.body) else {
+            continue;
+        };
+
+        for ptr in message.attachments {
+            let mut attachment = Attachment::new(ptr);
+            attachment.decode_placeholder();
+
+            if [mime::IMAGE, mime::VIDEO].contains(&attachment.mime.type_()) {
+                shared.media.push((sent, attachment));
+            } else {
+                shared.docs.push((sent, attachment));
+            }
+        }
+
+        if let Some(body) = message.body {
+            shared.links.extend(
+                find_urls(&body)
+                    .into_iter()
+                    .map(|url| (sent, body[url].to_owned())),
+            );
+        }
+    }
+
+    shared.media.sort_by_key(|(sent, _)| Reverse(*sent));
+    shared.links.sort_by_key(|(sent, _)| Reverse(*sent));
+    shared.docs.sort_by_key(|(sent, _)| Reverse(*sent));
+
+    shared
+}
+
+/// The message with the content of a stored message, whether it was received, sent from
+/// another device or edited.
+fn data_message(body: ContentBody) -> Option<DataMessage> {
+    match body {
+        ContentBody::DataMessage(message) => Some(message),
+        ContentBody::EditMessage(EditMessage { data_message, .. }) => data_message,
+        ContentBody::SynchronizeMessage(SyncMessage {
+            sent:
+                Some(Sent {
+                    message,
+                    edit_message,
+                    ..
+                }),
+            ..
+        }) => message.or_else(|| edit_message?.data_message),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Tab {
+    #[default]
+    Media,
+    Links,
+    Docs,
+}
+
+/// The panel with everything shared in a chat: a grid of its images and videos, and lists of
+/// its links and files.
+#[derive(Debug, Default)]
+pub struct Gallery {
+    /// The chat whose shared things are shown, while the panel is open.
+    chat: Option<Chat>,
+    tab: Tab,
+    /// What was shared, once it was collected from the store.
+    shared: Option<Shared>,
+    /// How many thumbnails are shown.
+    shown: usize,
+}
+
+impl Gallery {
+    pub fn is_open(&self) -> bool {
+        self.chat.is_some()
+    }
+
+    /// Whether the panel is open for `chat`, so what's collected for it still belongs.
+    pub fn is_open_for(&self, chat: &Chat) -> bool {
+        self.chat.as_ref() == Some(chat)
+    }
+
+    pub fn open(&mut self, chat: Chat) {
+        *self = Self {
+            chat: Some(chat),
+            shown: PAGE,
+            ..Self::default()
+        };
+    }
+
+    pub fn close(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn set_tab(&mut self, tab: Tab) {
+        self.tab = tab;
+    }
+
+    /// Takes what was shared, giving the images to download for the first thumbnails.
+    pub fn set_shared(&mut self, shared: Shared) -> Vec<(usize, AttachmentPointer)> {
+        self.shared = Some(shared);
+        self.thumbnails(0)
+    }
+
+    /// Shows another page of thumbnails, giving the images to download for them.
+    pub fn show_more(&mut self) -> Vec<(usize, AttachmentPointer)> {
+        let from = self.shown;
+        self.shown += PAGE;
+        self.thumbnails(from)
+    }
+
+    pub fn set_image(&mut self, index: usize, image: Image) {
+        if let Some((_, attachment)) = self
+            .shared
+            .as_mut()
+            .and_then(|shared| shared.media.get_mut(index))
+        {
+            attachment.image = Some(image);
+            attachment.downloaded = true;
+        }
+    }
+
+    /// The images of the thumbnails from `from` on that aren't downloaded yet, by their index.
+    fn thumbnails(&self, from: usize) -> Vec<(usize, AttachmentPointer)> {
+        self.shared
+            .iter()
+            .flat_map(|shared| shared.media.iter().enumerate())
+            .skip(from)
+            .take(self.shown.saturating_sub(from))
+            .filter(|(_, (_, attachment))| {
+                attachment.mime.type_() == mime::IMAGE && attachment.image.is_none()
+            })
+            .map(|(index, (_, attachment))| (index, attachment.ptr.clone()))
+            .collect()
+    }
+
+    pub fn as_iced_widget<'a>(
+        &'a self,
+        base: impl Into<Element<'a, Message>>,
+    ) -> Element<'a, Message> {
+        let base = base.into();
+
+        if !self.is_open() {
+            return base;
+        }
+
+        let tab = |label, tab| {
+            button(text(label).size(14))
+                .style(if tab == self.tab {
+                    button::primary
+                } else {
+                    button::text
+                })
+                .on_press(Message::GalleryTab(tab))
+        };
+
+        let content: Element<'a, Message> = match &self.shared {
+            None => center(text("Collecting what was shared…").style(text::secondary)).into(),
+            Some(shared) => match self.tab {
+                Tab::Media => self.media(&shared.media),
+                Tab::Links => links(&shared.links),
+                Tab::Docs => docs(&shared.docs),
+            },
+        };
+
+        let content = column![
+            row![
+                text("Media, links and docs").size(20),
+                space::horizontal(),
+                button(text("✕"))
+                    .style(button::text)
+                    .on_press(Message::CloseGallery),
+            ]
+            .align_y(Center),
+            row![
+                tab("Media", Tab::Media),
+                tab("Links", Tab::Links),
+                tab("Docs", Tab::Docs),
+            ]
+            .spacing(5),
+            content,
+        ]
+        .spacing(10)
+        .padding(20)
+        .max_width(420);
+
+        stack![
+            base,
+            opaque(
+                container(container(content).height(560).style(container::rounded_box))
+                    .center(Fill)
+                    .padding(20)
+            )
+        ]
+        .into()
+    }
+
+    fn media<'a>(&self, media: &'a [(Timestamp, Attachment)]) -> Element<'a, Message> {
+        if media.is_empty() {
+            return empty("No photos or videos yet");
+        }
+
+        let thumbnails = media.iter().take(self.shown).map(|(sent, attachment)| {
+            let thumbnail = attachment.as_thumbnail(THUMBNAIL_SIZE);
+
+            // videos aren't previewed, so they're told apart by a play button
+            let thumbnail = if attachment.mime.type_() == mime::VIDEO {
+                stack![thumbnail, center(text("▶").size(24))].into()
+            } else {
+                thumbnail
+            };
+
+            mouse_area(thumbnail)
+                .on_press(Message::JumpToMessage(*sent))
+                .interaction(Interaction::Pointer)
+                .into()
+        });
+
+        scrollable(
+            column![
+                grid(thumbnails)
+                    .columns(COLUMNS)
+                    .spacing(5)
+                    .width(THUMBNAIL_SIZE * COLUMNS as f32 + 5. * (COLUMNS - 1) as f32),
+                (media.len() > self.shown).then(|| {
+                    button(text("Show more").size(12))
+                        .style(button::secondary)
+                        .on_press(Message::GalleryMore)
+                }),
+            ]
+            .spacing(10)
+            .align_x(Center),
+        )
+        .height(Fill)
+        .into()
+    }
+}
+
+fn links(links: &[(Timestamp, String)]) -> Element<'_, Message> {
+    if links.is_empty() {
+        return empty("No links yet");
+    }
+
+    let links = links.iter().map(|(sent, link)| {
+        row![
+            button(text(link.as_str()).size(14))
+                .width(Fill)
+                .style(button::text)
+                .on_press(Message::LinkClicked(link.clone())),
+            button(text("Show").size(12))
+                .style(button::secondary)
+                .on_press(Message::JumpToMessage(*sent)),
+        ]
+        .spacing(5)
+        .align_y(Center)
+        .into()
+    });
+
+    scrollable(column(links).spacing(5)).height(Fill).into()
+}
+
+fn docs(docs: &[(Timestamp, Attachment)]) -> Element<'_, Message> {
+    if docs.is_empty() {
+        return empty("No files yet");
+    }
+
+    let docs = docs.iter().map(|(sent, attachment)| {
+        row![
+            button(
+                column![
+                    text(attachment.file_name()).size(14),
+                    text(attachment.description())
+                        .size(12)
+                        .style(text::secondary),
+                ]
+                .spacing(2),
+            )
+            .width(Fill)
+            .style(button::text)
+            .on_press(Message::JumpToMessage(*sent)),
+            button(text("Open").size(12))
+                .style(button::secondary)
+                .on_press(Message::OpenAttachment(attachment.clone())),
+            button(text("Save").size(12))
+                .style(button::secondary)
+                .on_press(Message::SaveAttachment(attachment.clone())),
+        ]
+        .spacing(5)
+        .align_y(Center)
+        .into()
+    });
+
+    scrollable(column(docs).spacing(5)).height(Fill).into()
+}
+
+fn empty(label: &str) -> Element<'_, Message> {
+    center(text(label).style(text::secondary)).into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::message::test::{ALICE, backend, contact, content};
+    use iced::futures::executor::block_on;
+    use std::sync::Arc;
+
+    fn file(content_type: &str) -> AttachmentPointer {
+        AttachmentPointer {
+            content_type: Some(content_type.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn collected() {
+        let thread = Thread::Contact(ALICE);
+        let mut backend = backend();
+        backend.messages.insert(
+            thread.clone(),
+            vec![
+                content(
+                    ALICE,
+                    10,
+                    ContentBody::DataMessage(DataMessage {
+                        body: Some("see https://example.com".to_owned()),
+                        attachments: vec![file("image/jpeg"), file("application/pdf")],
+                        ..Default::default()
+                    }),
+                ),
+                content(
+                    ALICE,
+                    20,
+                    ContentBody::SynchronizeMessage(SyncMessage {
+                        sent: Some(Sent {
+                            message: Some(DataMessage {
+                                attachments: vec![file("video/mp4")],
+                                ..Default::default()
+                            }),
+                            ..Default::default()
+                        }),
+                        ..Default::default()
+                    }),
+                ),
+                content(
+                    ALICE,
+                    30,
+                    ContentBody::EditMessage(EditMessage {
+                        target_sent_timestamp: Some(10),
+                        data_message: Some(DataMessage {
+                            body: Some("see https://example.org".to_owned()),
+                            attachments: vec![file("image/jpeg"), file("application/pdf")],
+                            ..Default::default()
+                        }),
+                    }),
+                ),
+            ],
+        );
+
+        // the edited message is only counted once, as edited
+        let shared = block_on(shared(&backend, &thread));
+        let at = |millisecond| Timestamp::from_millisecond(millisecond).unwrap();
+
+        let media = shared
+            .media
+            .iter()
+            .map(|(sent, attachment)| (*sent, attachment.mime.essence_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(media, [(at(20), "video/mp4"), (at(10), "image/jpeg")]);
+        assert_eq!(shared.links, [(at(10), "https://example.org".to_owned())]);
+        assert_eq!(shared.docs.len(), 1);
+
+        let mut gallery = Gallery::default();
+        gallery.open(Chat::Contact(Arc::new(contact(ALICE, "Alice"))));
+        let downloads = gallery.set_shared(shared);
+        assert_eq!(
+            downloads
+                .iter()
+                .map(|(index, _)| *index)
+                .collect::<Vec<_>>(),
+            [1]
+        );
+        assert!(gallery.show_more().is_empty());
+    }
+}
@@ -0,0 +1,134 @@
+use crate::{app::Message, image, message::Group, widget::Expandable};
+use iced::{
+    Center, Element, Fill,
+    widget::{button, column, container, opaque, row, scrollable, space, stack, text},
+};
+
+/// The panel about the open group, with its description, members and invite link.
+#[derive(Debug, Default)]
+pub struct GroupDetails {
+    is_open: bool,
+}
+
+impl GroupDetails {
+    pub fn is_open(&self) -> bool {
+        self.is_open
+    }
+
+    pub fn open(&mut self) {
+        self.is_open = true;
+    }
+
+    pub fn close(&mut self) {
+        self.is_open = false;
+    }
+
+    /// The details of `group`, taken from the open chat so they follow its changes.
+    pub fn as_iced_widget<'a>(
+        &self,
+        base: impl Into<Element<'a, Message>>,
+        group: Option<&'a Group>,
+    ) -> Element<'a, Message> {
+        let base = base.into();
+
+        let Some(group) = group.filter(|_| self.is_open) else {
+            return base;
+        };
+
+        // the link is shown to admins, who are the ones managing it
+        let is_admin = group
+            .members
+            .iter()
+            .any(|member| member.is_self && group.admins.contains(&member.id));
+
+        let invite_link = group
+            .invite_link
+            .as_deref()
+            .filter(|_| is_admin)
+            .map(|link| {
+                column![
+                    text("Group link").size(12).style(text::secondary),
+                    row![
+                        text(link).size(12).width(Fill),
+                        button(text("Copy").size(12))
+                            .style(button::secondary)
+                            .on_press(Message::CopyInviteLink(link.to_owned())),
+                    ]
+                    .spacing(5)
+                    .align_y(Center),
+                ]
+                .spacing(2)
+            });
+
+        let members = group.members.iter().map(|member| {
+            button(
+                row![
+                    text(member.name.clone()).size(14),
+                    space::horizontal(),
+                    group
+                        .admins
+                        .contains(&member.id)
+                        .then(|| text("Admin").size(12).style(text::secondary)),
+                ]
+                .align_y(Center),
+            )
+            .width(Fill)
+            .style(button::text)
+            .on_press(Message::Mention(member.id.raw_uuid()))
+            .into()
+        });
+
+        let content = column![
+            row![
+                group
+                    .avatar
+                    .clone()
+                    .map(|handle| image::avatar(handle, 64.)),
+                text(group.title.clone()).size(20),
+                space::horizontal(),
+                button(text("✕"))
+                    .style(button::text)
+                    .on_press(Message::CloseGroupDetails),
+            ]
+            .spacing(10)
+            .align_y(Center),
+            group.description.as_deref().map(|description| {
+                Expandable::new(text(description).size(14)).max_height(100.0)
+            }),
+            invite_link,
+            button(text("Media, links and docs").size(14))
+                .padding(0)
+                .style(button::text)
+                .on_press(Message::ShowGallery),
+            text!(
+                "{} {}",
+                group.members.len(),
+                if group.members.len() == 1 {
+                    "member"
+                } else {
+                    "members"
+                }
+            )
+            .size(12)
+            .style(text::secondary),
+            scrollable(column(members)).height(Fill),
+        ]
+        .spacing(10)
+        .padding(20)
+        .max_width(400);
+
+        stack![
+            base,
+            opaque(
+                container(
+                    container(content)
+                        .max_height(560)
+                        .style(container::rounded_box)
+                )
+                .center(Fill)
+                .padding(20)
+            )
+        ]
+        .into()
+    }
+}
@@ -1,18 +1,58 @@
+use iced::{
+    Color, Theme,
+    widget::{Text, text},
+};
+
 pub static LUCIDE_BYTES: &[u8] = include_bytes!("../Lucide.ttf");
 pub static LUCIDE_FONT: iced::Font = iced::Font::new("lucide");
 
+/// The codepoints of every Lucide icon, named like on <https://lucide.dev/icons> with
+/// underscores in place of dashes. Generated from the font's metadata by the build script.
+#[expect(dead_code, non_upper_case_globals, reason = "only some icons are used")]
+mod lucide {
+    include!(concat!(env!("OUT_DIR"), "/lucide.rs"));
+}
+
+/// The size of icons in logical pixels, unless they're sized otherwise.
+const SIZE: f32 = 16.0;
+
+/// Adds an accessor for the Lucide icon of the same name, or of the name after the `=`.
 macro_rules! icon {
-    ($name:ident = $icon:literal) => {
-        pub fn $name<'a>() -> ::iced::widget::Text<'a> {
-            ::iced::widget::text(const { ::core::char::from_u32($icon).unwrap() })
+    ($name:ident) => {
+        icon!($name = $name);
+    };
+    ($name:ident = $lucide:ident) => {
+        pub fn $name<'a>() -> Text<'a> {
+            text(lucide::$lucide)
                 .font(LUCIDE_FONT)
-                .width(16)
-                .height(16)
                 .line_height(1.0)
+                .sized(SIZE)
         }
     };
 }
 
-// https://unpkg.com/lucide-static@latest/font/info.json
-icon!(reply = 57898);
-icon!(edit = 57849);
+/// Sizing and coloring icons, which are glyphs of the icon font.
+pub trait Icon {
+    /// Sets the size of the glyph along with the square it takes up.
+    fn sized(self, size: f32) -> Self;
+
+    /// Colors the icon with a color picked from the theme, so it follows theme changes.
+    fn tinted(self, color: impl Fn(&Theme) -> Color + 'static) -> Self;
+}
+
+impl Icon for Text<'_> {
+    fn sized(self, size: f32) -> Self {
+        self.size(size).width(size).height(size)
+    }
+
+    fn tinted(self, color: impl Fn(&Theme) -> Color + 'static) -> Self {
+        self.style(move |theme| text::Style {
+            color: Some(color(theme)),
+        })
+    }
+}
+
+icon!(reply);
+icon!(edit = pencil);
+icon!(retry = rotate_ccw);
+icon!(discard = trash_2);
@@ -0,0 +1,47 @@
+use phonenumber::Mode;
+use presage::libsignal_service::protocol::ServiceId;
+use std::fmt::Write as _;
+
+/// What a contact without a name on their profile is called, by their phone number when it's
+/// known and by the start of their service id otherwise.
+pub fn unknown(phone: Option<&str>, id: ServiceId) -> String {
+    match phone {
+        Some(phone) => format!("Unknown ({})", format_phone(phone)),
+        None => format!("Unknown ({}…)", &id.raw_uuid().to_string()[..8]),
+    }
+}
+
+/// Writes an E.164 number like `+442070313000` grouped the way it's written internationally,
+/// like `+44 20 7031 3000`. Anything that isn't a phone number is left as it is.
+pub fn format_phone(number: &str) -> String {
+    let Ok(parsed) = phonenumber::parse(None, number) else {
+        return number.to_owned();
+    };
+
+    // the formatter fails rather than guessing for an unknown country code
+    let mut formatted = String::new();
+    match write!(formatted, "{}", parsed.format().mode(Mode::International)) {
+        Ok(()) => formatted,
+        Err(_) => number.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use presage::libsignal_service::prelude::Uuid;
+
+    #[test]
+    fn phones() {
+        assert_eq!(format_phone("+16502530000"), "+1 650-253-0000");
+        assert_eq!(format_phone("+442070313000"), "+44 20 7031 3000");
+        assert_eq!(format_phone("015123456789"), "015123456789");
+
+        let id = ServiceId::Aci(Uuid::from_u128(0x1234_5678 << 96).into());
+        assert_eq!(
+            unknown(Some("+442070313000"), id),
+            "Unknown (+44 20 7031 3000)"
+        );
+        assert_eq!(unknown(None, id), "Unknown (12345678…)");
+    }
+}
@@ -0,0 +1,64 @@
+#[cfg(all(unix, not(target_os = "macos")))]
+use crate::log::warn;
+use iced::futures::{Stream, stream};
+#[cfg(all(unix, not(target_os = "macos")))]
+use iced::futures::{StreamExt as _, future};
+#[cfg(all(unix, not(target_os = "macos")))]
+use zbus::{MatchRule, MessageStream, fdo::DBusProxy, message::Type};
+
+/// The interfaces of the screen savers telling when they start and stop, GNOME's and the one
+/// of the other desktops.
+#[cfg(all(unix, not(target_os = "macos")))]
+const SCREEN_SAVERS: [&str; 2] = ["org.freedesktop.ScreenSaver", "org.gnome.ScreenSaver"];
+
+/// Whether the user is away, told whenever the screen saver starts or stops, which includes
+/// locking the screen. Nothing is told where that can't be watched.
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn watch() -> impl Stream<Item = bool> {
+    stream::once(async {
+        match screen_saver_changes().await {
+            Ok(changes) => changes.left_stream(),
+            Err(err) => {
+                warn!("Can't tell when the screen saver is active: {err}");
+                stream::empty().right_stream()
+            }
+        }
+    })
+    .flatten()
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+pub fn watch() -> impl Stream<Item = bool> {
+    stream::empty()
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+async fn screen_saver_changes() -> zbus::Result<impl Stream<Item = bool>> {
+    let connection = zbus::Connection::session().await?;
+    let proxy = DBusProxy::new(&connection).await?;
+
+    for interface in SCREEN_SAVERS {
+        let rule = MatchRule::builder()
+            .msg_type(Type::Signal)
+            .interface(interface)?
+            .member("ActiveChanged")?
+            .build();
+        proxy.add_match_rule(rule).await?;
+    }
+
+    Ok(MessageStream::from(&connection).filter_map(|message| {
+        let active = message
+            .ok()
+            .filter(|message| {
+                let header = message.header();
+
+                header.message_type() == Type::Signal
+                    && header
+                        .member()
+                        .is_some_and(|member| member.as_str() == "ActiveChanged")
+            })
+            .and_then(|message| message.body().deserialize::<bool>().ok());
+
+        future::ready(active)
+    }))
+}
@@ -0,0 +1,413 @@
+use crate::{
+    config::{Config, ImageQuality},
+    log::warn,
+};
+use ::image::{
+    AnimationDecoder as _, DynamicImage, ImageDecoder as _, ImageFormat, ImageReader, ImageResult,
+    Limits,
+    codecs::{gif::GifDecoder, jpeg::JpegEncoder, webp::WebPDecoder},
+    imageops::FilterType,
+};
+use iced::{
+    ContentFit,
+    widget::{Image as ImageWidget, image::Handle},
+};
+use mime::Mime;
+use std::{
+    fs,
+    io::{self, Cursor},
+    path::PathBuf,
+    sync::OnceLock,
+    time::Duration,
+};
+use tokio::task::spawn_blocking;
+
+/// The largest dimension, in pixels, of an image shown inline in a message.
+const THUMBNAIL_SIZE: u32 = 1024;
+
+/// The largest dimension, in pixels, of the preview of a file about to be sent.
+const PREVIEW_SIZE: u32 = 64;
+
+/// How a decoded image is shrunk before it's shown.
+#[derive(Clone, Copy, Debug)]
+struct Thumbnail {
+    /// The largest dimension, in pixels.
+    size: u32,
+    /// Whether the image is cropped to the square in its middle first.
+    square: bool,
+}
+
+impl Thumbnail {
+    const fn new(size: u32) -> Self {
+        Self {
+            size,
+            square: false,
+        }
+    }
+
+    const fn square(mut self) -> Self {
+        self.square = true;
+        self
+    }
+
+    /// Crops and downscales the image, leaving images that already fit at their size.
+    fn apply(self, image: DynamicImage) -> Handle {
+        let image = if self.square {
+            let (x, y, side) = square(image.width(), image.height());
+            image.crop_imm(x, y, side, side)
+        } else {
+            image
+        };
+
+        let image = if image.width() > self.size || image.height() > self.size {
+            image.thumbnail(self.size, self.size)
+        } else {
+            image
+        }
+        .into_rgba8();
+
+        Handle::from_rgba(image.width(), image.height(), image.into_raw())
+    }
+
+    /// Decodes the image and applies the thumbnail on a blocking thread, since large photos
+    /// can take a while to decode.
+    async fn decode(self, bytes: Vec<u8>) -> (Option<Handle>, Vec<u8>) {
+        spawn_blocking(move || {
+            let handle = ::image::load_from_memory(&bytes)
+                .ok()
+                .map(|image| self.apply(image));
+
+            (handle, bytes)
+        })
+        .await
+        .unwrap()
+    }
+}
+
+/// An image attachment, decoded and downscaled for display in a message.
+#[derive(Clone, Debug)]
+pub struct Image {
+    /// The image at its original resolution, only uploaded when opened in the lightbox.
+    pub full: Handle,
+    /// The image downscaled to fit in a message bubble.
+    pub thumbnail: Handle,
+}
+
+impl Image {
+    /// Decodes and downscales the image off the UI thread.
+    pub async fn from_bytes(bytes: Vec<u8>) -> Self {
+        let (thumbnail, bytes) = Thumbnail::new(THUMBNAIL_SIZE).decode(bytes).await;
+        let full = Handle::from_bytes(bytes);
+
+        Self {
+            thumbnail: thumbnail.unwrap_or_else(|| full.clone()),
+            full,
+        }
+    }
+}
+
+static AVATAR_SIZE: OnceLock<u16> = OnceLock::new();
+
+/// The most frames of an animated avatar that are decoded, so long animations don't take up
+/// too much memory.
+const MAX_FRAMES: usize = 256;
+
+/// The largest width and height of an animated avatar that's decoded.
+const MAX_ANIMATION_SIZE: u32 = 2048;
+
+/// The most memory decoding an animated avatar may take at once, in bytes.
+const MAX_ANIMATION_ALLOC: u64 = 64 * 1024 * 1024;
+
+/// The shortest time a frame is shown, since avatars are animated at a low frame rate.
+pub const MIN_FRAME_DELAY: Duration = Duration::from_millis(100);
+
+/// The frames of an animated avatar, decoded once and shrunk to the size avatars are shown at.
+#[derive(Clone, Debug)]
+pub struct Animation {
+    frames: Vec<(Handle, Duration)>,
+    duration: Duration,
+}
+
+impl Animation {
+    /// Decodes every frame of an animated GIF or WebP on a blocking thread, shrunk to `size`
+    /// physical pixels, giving nothing for still images.
+    pub async fn decode(bytes: Vec<u8>, size: u32) -> Option<Self> {
+        spawn_blocking(move || {
+            let format = ::image::guess_format(&bytes).ok()?;
+            let cursor = Cursor::new(bytes);
+
+            // avatars come from contacts, who may send anything
+            let mut limits = Limits::default();
+            limits.max_image_width = Some(MAX_ANIMATION_SIZE);
+            limits.max_image_height = Some(MAX_ANIMATION_SIZE);
+            limits.max_alloc = Some(MAX_ANIMATION_ALLOC);
+
+            let frames = match format {
+                ImageFormat::Gif => {
+                    let mut decoder = GifDecoder::new(cursor).ok()?;
+                    decoder.set_limits(limits).ok()?;
+                    decoder.into_frames()
+                }
+                ImageFormat::WebP => {
+                    let mut decoder = WebPDecoder::new(cursor).ok()?;
+                    if !decoder.has_animation() {
+                        return None;
+                    }
+                    decoder.set_limits(limits).ok()?;
+                    decoder.into_frames()
+                }
+                _ => return None,
+            }
+            .take(MAX_FRAMES)
+            // every frame is shrunk as soon as it's decoded, so only one is kept at full size
+            .map(|frame| {
+                frame.map(|frame| {
+                    let (numer, denom) = frame.delay().numer_denom_ms();
+                    let delay = Duration::from_millis(u64::from(numer / denom.max(1)));
+
+                    (
+                        Thumbnail::new(size)
+                            .square()
+                            .apply(frame.into_buffer().into()),
+                        delay.max(MIN_FRAME_DELAY),
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .ok()?;
+
+            if frames.len() < 2 {
+                return None;
+            }
+
+            Some(Self {
+                duration: frames.iter().map(|(_, delay)| *delay).sum(),
+                frames,
+            })
+        })
+        .await
+        .inspect_err(|err| warn!("Failed to decode an animated avatar: {err}"))
+        .ok()
+        .flatten()
+    }
+
+    /// The frame shown after the animation played for `elapsed`, looping.
+    pub fn frame(&self, elapsed: Duration) -> &Handle {
+        let mut at =
+            Duration::from_nanos((elapsed.as_nanos() % self.duration.as_nanos().max(1)) as u64);
+
+        for (handle, delay) in &self.frames {
+            if at < *delay {
+                return handle;
+            }
+            at -= *delay;
+        }
+
+        &self.frames[0].0
+    }
+}
+
+/// Takes the size avatars are shown at from the config.
+pub fn init(config: &Config) {
+    _ = AVATAR_SIZE.set(config.avatar_size);
+}
+
+/// The size, in logical pixels, of the avatars next to messages and chats.
+pub fn avatar_size() -> f32 {
+    f32::from(
+        AVATAR_SIZE
+            .get()
+            .copied()
+            .unwrap_or_else(|| Config::default().avatar_size),
+    )
+}
+
+/// An avatar drawn `size` pixels wide, cropped to the square in the middle of the image and
+/// rounded into a circle, so wide or tall pictures don't end up as ovals.
+pub fn avatar(handle: Handle, size: f32) -> ImageWidget {
+    ImageWidget::new(handle)
+        .width(size)
+        .height(size)
+        .content_fit(ContentFit::Cover)
+        .border_radius(size / 2.)
+}
+
+/// The left and top edge and the side of the square in the middle of an image.
+fn square(width: u32, height: u32) -> (u32, u32, u32) {
+    let side = width.min(height);
+
+    ((width - side) / 2, (height - side) / 2, side)
+}
+
+/// A file picked to be sent with the next message.
+#[derive(Clone, Debug)]
+pub struct Staged {
+    pub path: PathBuf,
+    /// The size of the file on disk, before images are re-encoded.
+    pub len: u64,
+    /// A small preview of images.
+    pub preview: Option<Handle>,
+}
+
+impl Staged {
+    pub async fn read(path: PathBuf) -> io::Result<Self> {
+        spawn_blocking(move || {
+            let len = fs::metadata(&path)?.len();
+            let preview = ImageReader::open(&path)
+                .ok()
+                .and_then(|reader| reader.with_guessed_format().ok())
+                .and_then(|reader| reader.decode().ok())
+                .map(|image| Thumbnail::new(PREVIEW_SIZE).apply(image));
+
+            Ok(Self { path, len, preview })
+        })
+        .await
+        .unwrap()
+    }
+
+    pub fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .unwrap_or(self.path.as_os_str())
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+/// A file about to be uploaded as an attachment.
+#[derive(Clone, Debug)]
+pub struct Upload {
+    pub bytes: Vec<u8>,
+    pub mime: Mime,
+    pub file_name: Option<String>,
+    /// The dimensions of an image.
+    pub size: Option<(u32, u32)>,
+}
+
+impl Upload {
+    /// Reads a file on a blocking thread, re-encoding images at the given quality when that
+    /// makes them smaller.
+    pub async fn read(path: PathBuf, quality: ImageQuality) -> io::Result<Self> {
+        spawn_blocking(move || {
+            let bytes = fs::read(&path)?;
+            let file_name = |path: PathBuf| {
+                path.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            };
+            let format = ::image::guess_format(&bytes).ok();
+
+            // animated GIFs would be left with their first frame
+            if let Some((max_size, jpeg_quality)) = quality.encoding()
+                && let Some(format) = format.filter(|format| *format != ImageFormat::Gif)
+                && let Ok(image) = decode_oriented(&bytes, format)
+            {
+                let image = if image.width() > max_size || image.height() > max_size {
+                    image.resize(max_size, max_size, FilterType::Triangle)
+                } else {
+                    image
+                };
+
+                // JPEG has no transparency, so transparent images stay PNGs
+                let (encoded, mime, extension) = if is_transparent(&image) {
+                    let mut png = vec![];
+                    image
+                        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+                        .map_err(io::Error::other)?;
+                    (png, mime::IMAGE_PNG, "png")
+                } else {
+                    let mut jpeg = vec![];
+                    JpegEncoder::new_with_quality(&mut jpeg, jpeg_quality)
+                        .encode_image(&image.to_rgb8())
+                        .map_err(io::Error::other)?;
+                    (jpeg, mime::IMAGE_JPEG, "jpg")
+                };
+
+                if encoded.len() < bytes.len() {
+                    return Ok(Self {
+                        bytes: encoded,
+                        mime,
+                        file_name: file_name(path.with_extension(extension)),
+                        size: Some((image.width(), image.height())),
+                    });
+                }
+            }
+
+            let size = format.and_then(|format| {
+                ImageReader::with_format(Cursor::new(&bytes), format)
+                    .into_dimensions()
+                    .ok()
+            });
+
+            Ok(Self {
+                mime: format
+                    .and_then(|format| format.to_mime_type().parse().ok())
+                    .unwrap_or(mime::APPLICATION_OCTET_STREAM),
+                bytes,
+                file_name: file_name(path),
+                size,
+            })
+        })
+        .await
+        .unwrap()
+    }
+}
+
+/// Decodes an image turned the way its EXIF orientation says, since re-encoding it drops the
+/// EXIF data.
+fn decode_oriented(bytes: &[u8], format: ImageFormat) -> ImageResult<DynamicImage> {
+    let mut decoder = ImageReader::with_format(Cursor::new(bytes), format).into_decoder()?;
+    let orientation = decoder.orientation()?;
+
+    let mut image = DynamicImage::from_decoder(decoder)?;
+    image.apply_orientation(orientation);
+
+    Ok(image)
+}
+
+/// Whether any pixel of the image is see-through, unlike opaque images stored with an alpha
+/// channel anyway.
+fn is_transparent(image: &DynamicImage) -> bool {
+    image.color().has_alpha() && image.to_rgba8().pixels().any(|pixel| pixel[3] < u8::MAX)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn transparency() {
+        let mut image = ::image::RgbaImage::from_pixel(2, 2, ::image::Rgba([255, 0, 0, 255]));
+        assert!(!is_transparent(&image.clone().into()));
+
+        image.put_pixel(1, 1, ::image::Rgba([0, 0, 0, 0]));
+        assert!(is_transparent(&image.into()));
+    }
+
+    #[test]
+    fn square_crop() {
+        assert_eq!(square(64, 64), (0, 0, 64));
+        assert_eq!(square(160, 90), (35, 0, 90));
+        assert_eq!(square(90, 160), (0, 35, 90));
+        assert_eq!(square(101, 100), (0, 0, 100));
+        assert_eq!(square(1, 1000), (0, 499, 1));
+    }
+
+    #[test]
+    fn animation_loops() {
+        let (first, second) = (
+            Handle::from_rgba(1, 1, vec![0; 4]),
+            Handle::from_rgba(1, 1, vec![255; 4]),
+        );
+        let animation = Animation {
+            frames: vec![
+                (first.clone(), MIN_FRAME_DELAY),
+                (second.clone(), MIN_FRAME_DELAY * 3),
+            ],
+            duration: MIN_FRAME_DELAY * 4,
+        };
+
+        assert_eq!(animation.frame(Duration::ZERO), &first);
+        assert_eq!(animation.frame(MIN_FRAME_DELAY * 2), &second);
+        assert_eq!(animation.frame(MIN_FRAME_DELAY * 9 / 2), &first);
+    }
+}
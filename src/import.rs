@@ -0,0 +1,221 @@
+use crate::log;
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use presage::{
+    libsignal_service::{
+        content::{ContentBody, Metadata},
+        prelude::Content,
+        protocol::ServiceId,
+        zkgroup::GroupMasterKeyBytes,
+    },
+    proto::{
+        BodyRange, DataMessage, GroupContextV2, SyncMessage, body_range::AssociatedValue,
+        sync_message::Sent,
+    },
+    store::{ContentsStore as _, Thread},
+};
+use presage_store_sqlite::SqliteStore;
+use serde_json::Value;
+use sqlx::{ConnectOptions as _, Connection as _, sqlite::SqliteConnectOptions};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Where Signal Desktop keeps its data by default.
+pub fn default_desktop_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("Signal"))
+}
+
+/// A conversation of Signal Desktop, as far as the store cares.
+struct Conversation {
+    thread: Thread,
+    /// The master key, for groups.
+    group: Option<GroupMasterKeyBytes>,
+    profile_key: Option<Vec<u8>>,
+    id: Option<ServiceId>,
+}
+
+/// Copies the text messages of a Signal Desktop installation into the store, returning how many
+/// were imported.
+///
+/// Attachments aren't imported, since Signal's servers only keep them for a limited time anyway.
+pub async fn import_desktop(
+    dir: &Path,
+    store: &SqliteStore,
+    me: ServiceId,
+) -> Result<usize, Box<dyn Error>> {
+    let config = serde_json::from_str::<Value>(&fs::read_to_string(dir.join("config.json"))?)?;
+    let Some(key) = config["key"].as_str() else {
+        return Err(if config["encryptedKey"].is_string() {
+            "Signal Desktop encrypted its database key with the system keyring, which isn't \
+             supported; start it once with --password-store=basic to store the key in plain text"
+        } else {
+            "Signal Desktop's config.json contains no database key"
+        }
+        .into());
+    };
+
+    let mut connection = SqliteConnectOptions::new()
+        .filename(dir.join("sql").join("db.sqlite"))
+        .read_only(true)
+        .pragma("key", Cow::Owned(format!("\"x'{key}'\"")))
+        .connect()
+        .await?;
+
+    let conversations = sqlx::query_scalar::<_, String>("SELECT json FROM conversations")
+        .fetch_all(&mut connection)
+        .await?
+        .iter()
+        .filter_map(|json| {
+            let json = serde_json::from_str::<Value>(json).ok()?;
+            Some((json["id"].as_str()?.to_owned(), conversation(&json)?))
+        })
+        .collect::<HashMap<_, _>>();
+
+    let messages = sqlx::query_scalar::<_, String>("SELECT json FROM messages ORDER BY sent_at")
+        .fetch_all(&mut connection)
+        .await?;
+
+    connection.close().await?;
+
+    let mut imported = 0;
+
+    for json in messages {
+        let Ok(json) = serde_json::from_str::<Value>(&json) else {
+            continue;
+        };
+
+        let Some(conversation) = json["conversationId"]
+            .as_str()
+            .and_then(|id| conversations.get(id))
+        else {
+            continue;
+        };
+
+        let Some(content) = content(&json, conversation, me) else {
+            continue;
+        };
+
+        if let Err(err) = store.save_message(&conversation.thread, content).await {
+            log::warn!("Failed to import a message: {}", err);
+        } else {
+            imported += 1;
+        }
+    }
+
+    Ok(imported)
+}
+
+fn conversation(json: &Value) -> Option<Conversation> {
+    match json["type"].as_str()? {
+        "private" => {
+            let id = service_id(json.get("serviceId").or_else(|| json.get("uuid"))?)?;
+
+            Some(Conversation {
+                thread: Thread::Contact(id),
+                group: None,
+                profile_key: json["profileKey"]
+                    .as_str()
+                    .and_then(|key| STANDARD.decode(key).ok()),
+                id: Some(id),
+            })
+        }
+        "group" => {
+            let key = STANDARD
+                .decode(json["masterKey"].as_str()?)
+                .ok()?
+                .try_into()
+                .ok()?;
+
+            Some(Conversation {
+                thread: Thread::Group(key),
+                group: Some(key),
+                profile_key: None,
+                id: None,
+            })
+        }
+        _ => None,
+    }
+}
+
+fn content(json: &Value, conversation: &Conversation, me: ServiceId) -> Option<Content> {
+    let timestamp = json["sent_at"].as_u64()?;
+
+    let message = DataMessage {
+        body: json["body"].as_str().map(ToOwned::to_owned),
+        body_ranges: json["bodyRanges"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(body_range)
+            .collect(),
+        group_v2: conversation.group.map(|key| GroupContextV2 {
+            master_key: Some(key.to_vec()),
+            revision: None,
+            group_change: None,
+        }),
+        profile_key: conversation.profile_key.clone(),
+        timestamp: Some(timestamp),
+        ..Default::default()
+    };
+
+    let (sender, body) = match json["type"].as_str()? {
+        "incoming" => (
+            service_id(
+                json.get("sourceServiceId")
+                    .or_else(|| json.get("sourceUuid"))?,
+            )?,
+            ContentBody::DataMessage(message),
+        ),
+        "outgoing" => (
+            me,
+            SyncMessage {
+                sent: Some(Sent {
+                    destination_service_id: conversation.id.map(|id| id.raw_uuid().to_string()),
+                    timestamp: Some(timestamp),
+                    message: Some(message),
+                    ..Sent::default()
+                }),
+                ..SyncMessage::default()
+            }
+            .into(),
+        ),
+        // calls, group updates, and other events
+        _ => return None,
+    };
+
+    Some(Content {
+        metadata: Metadata {
+            sender,
+            destination: me,
+            sender_device: 1u32.try_into().ok()?,
+            timestamp,
+            needs_receipt: false,
+            unidentified_sender: false,
+            was_plaintext: false,
+            server_guid: None,
+        },
+        body,
+    })
+}
+
+fn body_range(json: &Value) -> Option<BodyRange> {
+    let associated_value = if let Some(style) = json["style"].as_i64() {
+        AssociatedValue::Style(style as i32)
+    } else {
+        AssociatedValue::MentionAci(json["mentionAci"].as_str()?.to_owned())
+    };
+
+    Some(BodyRange {
+        start: Some(json["start"].as_u64()? as u32),
+        length: Some(json["length"].as_u64()? as u32),
+        associated_value: Some(associated_value),
+    })
+}
+
+fn service_id(json: &Value) -> Option<ServiceId> {
+    ServiceId::parse_from_service_id_string(json.as_str()?)
+}
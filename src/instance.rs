@@ -0,0 +1,116 @@
+use crate::{config::Config, log};
+use iced::futures::channel::mpsc;
+
+/// What a second instance asks of the running one before exiting.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Request {
+    Raise,
+    /// Opens a Signal link, like one passed on the command line by a browser.
+    Open(String),
+}
+
+impl Request {
+    #[cfg(unix)]
+    fn to_line(&self) -> String {
+        match self {
+            Self::Raise => "raise\n".to_owned(),
+            Self::Open(link) => format!("open {link}\n"),
+        }
+    }
+
+    #[cfg(unix)]
+    fn from_line(line: &str) -> Option<Self> {
+        let line = line.strip_suffix('\n')?;
+
+        match line.split_once(' ') {
+            None if line == "raise" => Some(Self::Raise),
+            Some(("open", link)) => Some(Self::Open(link.to_owned())),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Instance {
+    /// No other instance is running. Yields whatever another one asked for.
+    Primary(mpsc::UnboundedReceiver<Request>),
+    /// Another instance already owns the database.
+    Secondary,
+}
+
+impl Instance {
+    /// Claims the database for this process, or detects the instance that already did.
+    #[cfg(unix)]
+    pub fn acquire(config: &Config) -> Self {
+        use std::{
+            io::{BufRead as _, BufReader, ErrorKind},
+            os::unix::net::{UnixListener, UnixStream},
+        };
+
+        let (tx, rx) = mpsc::unbounded();
+
+        let socket = config.socket_path();
+
+        // binding is what claims the database, so two instances starting at once can't both
+        let listener = match UnixListener::bind(socket) {
+            Err(err) if err.kind() == ErrorKind::AddrInUse => {
+                if UnixStream::connect(socket).is_ok() {
+                    return Self::Secondary;
+                }
+
+                // nobody is listening, so the socket is left over from an instance that crashed
+                _ = std::fs::remove_file(socket);
+                UnixListener::bind(socket)
+            }
+            bound => bound,
+        };
+
+        let listener = match listener {
+            Ok(listener) => listener,
+            // another instance cleaned up the stale socket and claimed it first
+            Err(err) if err.kind() == ErrorKind::AddrInUse => return Self::Secondary,
+            Err(err) => {
+                log::warn!("Failed to listen for other instances: {}", err);
+                return Self::Primary(rx);
+            }
+        };
+
+        std::thread::Builder::new()
+            .name("instance".to_owned())
+            .spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let mut line = String::new();
+
+                    if BufReader::new(stream).read_line(&mut line).is_ok()
+                        && let Some(request) = Request::from_line(&line)
+                        && tx.unbounded_send(request).is_err()
+                    {
+                        break;
+                    }
+                }
+            })
+            .unwrap();
+
+        Self::Primary(rx)
+    }
+
+    #[cfg(not(unix))]
+    pub fn acquire(_config: &Config) -> Self {
+        Self::Primary(mpsc::unbounded().1)
+    }
+
+    /// Hands the request over to the running instance.
+    #[cfg(unix)]
+    pub fn send(config: &Config, request: &Request) {
+        use std::{io::Write as _, os::unix::net::UnixStream};
+
+        if let Err(err) = UnixStream::connect(config.socket_path())
+            .and_then(|mut stream| stream.write_all(request.to_line().as_bytes()))
+        {
+            log::error!("Failed to reach the running instance: {}", err);
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn send(_config: &Config, _request: &Request) {}
+}
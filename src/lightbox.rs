@@ -0,0 +1,122 @@
+use crate::{app::Message, message};
+use iced::{
+    Center, Color, ContentFit, Element, Fill,
+    widget::{
+        button, center, column, container, image, mouse_area, opaque, row, space, stack, text,
+    },
+};
+use std::sync::Arc;
+
+#[derive(Debug, Default)]
+pub struct Lightbox {
+    message: Option<Arc<message::Message>>,
+    index: usize,
+}
+
+impl Lightbox {
+    pub fn new(message: Arc<message::Message>, index: usize) -> Self {
+        Self {
+            message: Some(message),
+            index,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.message.is_some()
+    }
+
+    pub fn close(&mut self) {
+        self.message = None;
+    }
+
+    pub fn next(&mut self) {
+        let count = self.count();
+
+        if count != 0 {
+            self.index = (self.index + 1) % count;
+        }
+    }
+
+    pub fn previous(&mut self) {
+        let count = self.count();
+
+        if count != 0 {
+            self.index = (self.index + count - 1) % count;
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.message
+            .as_deref()
+            .map_or(0, |message| message.images().count())
+    }
+
+    pub fn as_iced_widget<'a>(
+        &'a self,
+        base: impl Into<Element<'a, Message>>,
+    ) -> Element<'a, Message> {
+        let base = base.into();
+
+        let Some(attachment) = self
+            .message
+            .as_deref()
+            .and_then(|message| message.images().nth(self.index))
+        else {
+            return base;
+        };
+
+        let count = self.count();
+
+        let navigate = |label, message| {
+            button(text(label).size(32))
+                .style(button::text)
+                .on_press_maybe((count > 1).then_some(message))
+        };
+
+        let content = column![
+            row![
+                text!("{} / {count}", self.index + 1),
+                text(attachment.description()).size(14),
+                space::horizontal(),
+                button(text("Save"))
+                    .style(button::text)
+                    .on_press(Message::SaveAttachment(attachment.clone())),
+                button(text("✕"))
+                    .style(button::text)
+                    .on_press(Message::CloseLightbox),
+            ]
+            .spacing(20)
+            .align_y(Center),
+            row![
+                navigate("‹", Message::LightboxPrevious),
+                center(
+                    attachment
+                        .image
+                        .as_ref()
+                        .map(|image| image.full.clone())
+                        .map(|handle| image(handle).content_fit(ContentFit::Contain))
+                ),
+                navigate("›", Message::LightboxNext),
+            ]
+            .align_y(Center)
+            .height(Fill),
+        ]
+        .spacing(10)
+        .padding(20);
+
+        let backdrop = container(content)
+            .width(Fill)
+            .height(Fill)
+            .style(|_| container::Style {
+                background: Some(Color::BLACK.scale_alpha(0.85).into()),
+                text_color: Some(Color::WHITE),
+                ..Default::default()
+            });
+
+        stack![
+            base,
+            opaque(mouse_area(backdrop).on_press(Message::CloseLightbox))
+        ]
+        .into()
+    }
+}
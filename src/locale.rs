@@ -0,0 +1,161 @@
+use crate::config::{Clock, Config};
+use jiff::Zoned;
+use std::{env, sync::OnceLock};
+
+static LOCALE: OnceLock<Locale> = OnceLock::new();
+
+/// How dates, times and numbers are written.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Locale {
+    /// The `strftime` format of a short date.
+    date: &'static str,
+    /// The `strftime` format of a date with the names of its weekday and month.
+    long_date: &'static str,
+    twelve_hour: bool,
+    decimal_comma: bool,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self {
+            date: "%d.%m.%Y",
+            long_date: "%A, %d %B %Y",
+            twelve_hour: false,
+            decimal_comma: false,
+        }
+    }
+}
+
+/// Picks the formats from the environment's locale, with the clock set in the config.
+pub fn init(config: &Config) {
+    let locale_of = |category| {
+        ["LC_ALL", category, "LANG"]
+            .into_iter()
+            .find_map(|var| env::var(var).ok().filter(|name| !name.is_empty()))
+            .as_deref()
+            .map_or_else(Locale::default, Locale::parse)
+    };
+
+    // numbers can be set up apart from dates and times, like English dates with decimal commas
+    let mut locale = Locale {
+        decimal_comma: locale_of("LC_NUMERIC").decimal_comma,
+        ..locale_of("LC_TIME")
+    };
+
+    match config.clock {
+        Some(Clock::TwelveHour) => locale.twelve_hour = true,
+        Some(Clock::TwentyFourHour) => locale.twelve_hour = false,
+        None => {}
+    }
+
+    _ = LOCALE.set(locale);
+}
+
+/// The formats picked on startup, or the default ones before that.
+pub fn locale() -> Locale {
+    LOCALE.get().copied().unwrap_or_default()
+}
+
+impl Locale {
+    /// Reads a POSIX locale name like `en_US.UTF-8`, falling back to the default formats for the
+    /// `C` locale and unknown regions.
+    fn parse(name: &str) -> Self {
+        let name = name.split(['.', '@']).next().unwrap_or_default();
+        let (language, region) = name.split_once('_').unwrap_or((name, ""));
+
+        if matches!(language, "C" | "POSIX") {
+            return Self::default();
+        }
+
+        let (date, long_date) = match region {
+            "US" | "PH" => ("%m/%d/%Y", "%A, %B %-d, %Y"),
+            "CA" if language == "en" => ("%Y-%m-%d", "%A, %B %-d, %Y"),
+            "SE" | "LT" | "CN" | "JP" | "KR" | "TW" | "HU" | "CA" => ("%Y-%m-%d", "%A, %Y-%m-%d"),
+            "DE" | "AT" | "CH" | "RU" | "UA" | "PL" | "CZ" | "SK" | "FI" | "NO" | "DK" | "TR"
+            | "RO" => ("%d.%m.%Y", "%A, %-d %B %Y"),
+            _ => ("%d/%m/%Y", "%A, %-d %B %Y"),
+        };
+
+        Self {
+            date,
+            long_date,
+            twelve_hour: matches!(
+                region,
+                "US" | "CA" | "AU" | "NZ" | "IN" | "PH" | "PK" | "EG" | "SA"
+            ) && language != "fr",
+            decimal_comma: !matches!(
+                language,
+                "en" | "ja" | "zh" | "ko" | "th" | "he" | "hi" | "ga"
+            ) && region != "CH",
+        }
+    }
+
+    pub fn time(self, timestamp: &Zoned) -> String {
+        timestamp
+            .strftime(if self.twelve_hour {
+                "%-I:%M %p"
+            } else {
+                "%H:%M"
+            })
+            .to_string()
+    }
+
+    /// The date and time, like in the label of a message older than a day.
+    pub fn date_time(self, timestamp: &Zoned) -> String {
+        format!(
+            "{} at {}",
+            timestamp.strftime(self.date),
+            self.time(timestamp)
+        )
+    }
+
+    /// Everything about the time, down to the second and with the time zone.
+    pub fn full(self, timestamp: &Zoned) -> String {
+        format!(
+            "{} at {} {}",
+            timestamp.strftime(self.long_date),
+            timestamp.strftime(if self.twelve_hour {
+                "%-I:%M:%S %p"
+            } else {
+                "%H:%M:%S"
+            }),
+            timestamp.strftime("%Z")
+        )
+    }
+
+    /// A number with one decimal.
+    pub fn decimal(self, number: f64) -> String {
+        let number = format!("{number:.1}");
+
+        if self.decimal_comma {
+            number.replace('.', ",")
+        } else {
+            number
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use jiff::civil::date;
+
+    #[test]
+    fn formats() {
+        let at = date(2025, 3, 4).at(15, 6, 7, 0).in_tz("UTC").unwrap();
+
+        let default = Locale::default();
+        assert_eq!(default.date_time(&at), "04.03.2025 at 15:06");
+        assert_eq!(default.decimal(1.24), "1.2");
+
+        let us = Locale::parse("en_US.UTF-8");
+        assert_eq!(us.date_time(&at), "03/04/2025 at 3:06 PM");
+        assert_eq!(us.full(&at), "Tuesday, March 4, 2025 at 3:06:07 PM UTC");
+
+        let germany = Locale::parse("de_DE.UTF-8");
+        assert_eq!(germany.date_time(&at), "04.03.2025 at 15:06");
+        assert_eq!(germany.decimal(1.5), "1,5");
+
+        assert_eq!(Locale::parse("C.UTF-8"), Locale::default());
+    }
+}
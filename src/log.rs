@@ -1,31 +1,120 @@
 mod logger {
-    use std::{env, fs::File};
-    use tracing::Level;
-    pub use tracing::{debug, error, info, trace, warn};
+    use crate::config::{Config, LogFormat};
+    use jiff::Timestamp;
+    use std::{
+        collections::VecDeque,
+        env,
+        fs::{self, File},
+        io,
+        path::Path,
+        sync::{Mutex, PoisonError},
+    };
+    use tracing::{Event, Level, Subscriber};
+    pub use tracing::{Instrument, debug, error, info, info_span, instrument, trace, warn};
+    use tracing_chrome::{ChromeLayerBuilder, FlushGuard};
     use tracing_subscriber::{
+        Layer,
         filter::{LevelFilter, Targets},
         fmt,
+        layer::Context,
         prelude::*,
     };
 
-    pub fn init() -> Result<(), Box<dyn std::error::Error>> {
-        let env_rust_log = env::var("RUST_LOG")
-            .ok()
-            .as_deref()
-            .map(str::parse::<Level>)
-            .transpose()?;
+    /// The number of log files kept from previous launches.
+    const KEPT_LOGS: usize = 5;
+
+    /// The number of recent events kept for crash reports.
+    const RECENT_EVENTS: usize = 50;
+
+    static RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+    /// Writes the startup trace until it's finished.
+    static STARTUP_TRACE: Mutex<Option<FlushGuard>> = Mutex::new(None);
+
+    /// Remembers where the most recent events were logged. Their messages aren't kept, since
+    /// they may contain message content.
+    struct Recent;
+
+    impl<S: Subscriber> Layer<S> for Recent {
+        fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+            let metadata = event.metadata();
+            let line = format!(
+                "{} {} {} ({}:{})",
+                Timestamp::now(),
+                metadata.level(),
+                metadata.target(),
+                metadata.file().unwrap_or("?"),
+                metadata.line().unwrap_or_default(),
+            );
+
+            let mut recent = RECENT.lock().unwrap_or_else(PoisonError::into_inner);
+            if recent.len() == RECENT_EVENTS {
+                recent.pop_front();
+            }
+            recent.push_back(line);
+        }
+    }
+
+    /// Where the most recent events were logged, oldest first.
+    pub fn recent() -> Vec<String> {
+        // this is called from the panic hook, which may run while the lock is held
+        RECENT
+            .try_lock()
+            .map(|recent| recent.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn init(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+        let level = match config.log_level {
+            Some(level) => level,
+            None => env::var("RUST_LOG")
+                .ok()
+                .as_deref()
+                .map(str::parse::<Level>)
+                .transpose()?
+                .unwrap_or(Level::INFO),
+        };
+
+        let file_layer = if config.log_file {
+            let file = rotate(&config.state_dir())?;
+
+            Some(match config.log_format {
+                LogFormat::Json => fmt::layer().with_writer(file).json().boxed(),
+                LogFormat::Pretty => fmt::layer()
+                    .with_writer(file)
+                    .with_ansi(false)
+                    .pretty()
+                    .boxed(),
+            })
+        } else {
+            None
+        };
+
+        let trace_layer = if config.trace_startup {
+            fs::create_dir_all(config.state_dir())?;
+
+            let (layer, guard) = ChromeLayerBuilder::new()
+                .file(config.startup_trace_path())
+                .include_args(true)
+                .build();
+            *STARTUP_TRACE.lock().unwrap_or_else(PoisonError::into_inner) = Some(guard);
+
+            Some(layer)
+        } else {
+            None
+        };
 
         tracing_subscriber::registry()
             .with(
                 fmt::layer()
                     .compact()
-                    .with_filter(LevelFilter::from_level(env_rust_log.unwrap_or(Level::INFO))),
-            )
-            .with(
-                fmt::layer()
-                    .with_writer(File::create("debug_log.json")?)
-                    .json(),
+                    // stdout is reserved for the output of the headless modes
+                    .with_writer(std::io::stderr)
+                    .with_filter(LevelFilter::from_level(level)),
             )
+            .with(file_layer)
+            .with(trace_layer)
+            .with(Recent)
             .with(
                 Targets::default()
                     .with_target("foghorn", Level::TRACE)
@@ -36,7 +125,45 @@ mod logger {
 
         Ok(())
     }
+
+    /// Finishes the startup trace, if it's recorded, once everything was synced.
+    pub fn finish_startup_trace() {
+        let guard = STARTUP_TRACE
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+
+        if guard.is_some() {
+            info!("The startup trace was written");
+        }
+    }
+
+    /// Shifts the logs of previous launches, dropping the oldest, and creates a new log file.
+    fn rotate(dir: &Path) -> io::Result<File> {
+        let path = |index: usize| {
+            dir.join(if index == 0 {
+                "foghorn.log".to_owned()
+            } else {
+                format!("foghorn.{index}.log")
+            })
+        };
+
+        fs::create_dir_all(dir)?;
+
+        // a log that can't be moved would be overwritten, missing ones just weren't written yet
+        for index in (0..KEPT_LOGS).rev() {
+            match fs::rename(path(index), path(index + 1)) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                _ => {}
+            }
+        }
+
+        File::create(path(0))
+    }
 }
 
 #[expect(unused_imports)]
-pub use logger::{debug, error, info, init, trace, warn};
+pub use logger::{
+    Instrument, debug, error, finish_startup_trace, info, info_span, init, instrument, recent,
+    trace, warn,
+};
@@ -2,24 +2,125 @@ use app::App;
 use foghorn_widgets as widget;
 use iced::{Result, application};
 use icons::LUCIDE_BYTES;
+use instance::{Instance, Request};
+// only linked for SQLCipher support in the store
+use libsqlite3_sys as _;
+use std::cell::Cell;
 
 mod app;
+mod autocomplete;
+mod avatars;
+mod backend;
+mod backoff;
+mod browser;
+mod cache;
+mod calendar;
+mod chat_menu;
+mod chat_set;
+mod cli;
+mod composer;
+mod config;
+mod crash;
+mod database;
+mod deep_link;
 mod dialog;
+mod export;
+mod gallery;
+mod group_details;
 mod icons;
+mod identifier;
+mod idle;
+mod image;
+mod import;
+mod instance;
+mod lightbox;
+mod locale;
 mod log;
 mod manager_manager;
+mod member_card;
 mod message;
+mod notification;
 mod parse;
+mod platform;
+mod receipts;
+mod safety;
+mod search;
+mod session;
+mod stickers;
+mod typing;
 
 fn main() -> Result {
     #[expect(clippy::print_stderr)]
-    if let Err(error) = log::init() {
+    let (mut config, mode) = match cli::Mode::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(usage) => {
+            eprintln!("{usage}");
+            std::process::exit(2);
+        }
+    };
+
+    let instance = Instance::acquire(&config);
+
+    // a second instance would rotate away the log of the running one
+    if matches!(instance, Instance::Secondary) {
+        config.log_file = false;
+    }
+
+    #[expect(clippy::print_stderr)]
+    if let Err(error) = log::init(&config) {
         eprintln!("Foghorn: failed to initialize logger: {error}");
     }
 
-    application(App::create, App::update, App::view)
-        .subscription(App::subscription)
-        .antialiasing(true)
-        .font(LUCIDE_BYTES)
-        .run()
+    crash::install_hook(config.state_dir());
+    locale::init(&config);
+    image::init(&config);
+    parse::init(&config);
+
+    #[expect(clippy::print_stderr)]
+    if matches!(instance, Instance::Primary(_)) {
+        config.key = match database::key(&config) {
+            Ok(key) => key,
+            Err(err) => {
+                eprintln!("Foghorn: can't read the database passphrase: {err}");
+                std::process::exit(1);
+            }
+        };
+    }
+
+    #[expect(clippy::print_stderr)]
+    let link = match mode {
+        cli::Mode::Client(link) => link,
+        cli::Mode::Command(command) => {
+            if matches!(instance, Instance::Secondary) {
+                eprintln!("Foghorn: another instance is already running");
+                std::process::exit(1);
+            }
+
+            if let Err(error) = command.run(config) {
+                eprintln!("Foghorn: {error}");
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+    };
+
+    let Instance::Primary(requests) = instance else {
+        Instance::send(&config, &link.map_or(Request::Raise, Request::Open));
+        return Ok(());
+    };
+
+    // the receiver can only be handed out once, but booting takes a `Fn`
+    let requests = Cell::new(Some(requests));
+    let link = Cell::new(link);
+
+    application(
+        move || App::create(config.clone(), requests.take(), link.take()),
+        App::update,
+        App::view,
+    )
+    .subscription(App::subscription)
+    .antialiasing(true)
+    .exit_on_close_request(false)
+    .font(LUCIDE_BYTES)
+    .run()
 }
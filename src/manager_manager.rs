@@ -1,48 +1,258 @@
 use crate::{
-    log,
-    message::{Chat, Quote, SignalAction, decode_content, sync_contacts, sync_messages},
+    backend::SignalBackend as _,
+    backoff::{Retry, retry_fib},
+    cache::ChatCache,
+    config::Config,
+    database,
+    gallery::{self, Shared},
+    image::Upload,
+    import,
+    log::{self, Instrument as _},
+    message::{
+        Chat, Message, Quote, Receipt, SignalAction, decode_content, log_summary, pass_challenge,
+        refresh_profiles, resync_contacts, resync_groups, resync_thread, sync_contacts,
+        sync_messages,
+    },
     parse::markdown_to_body_ranges,
+    search::SearchIndex,
+    stickers::InstalledPack,
 };
 use iced::futures::{
-    SinkExt as _, Stream, StreamExt as _,
+    FutureExt as _, SinkExt as _, Stream, StreamExt as _,
     channel::{mpsc, oneshot},
+    future::{self, Either, Shared},
 };
 use jiff::Timestamp;
 use presage::{
-    libsignal_service::{configuration::SignalServers, content::Metadata, prelude::Content},
+    libsignal_service::{
+        content::{ContentBody, Metadata},
+        prelude::Content,
+        protocol::{
+            DeviceId, Fingerprint, IdentityKeyStore as _, ProtocolAddress, ServiceId,
+            SignalProtocolError,
+        },
+        push_service::{ProofRequired, ServiceError},
+        sender::{AttachmentSpec, MessageSenderError},
+    },
     manager::{Linking, Registered},
-    model::{identity::OnNewIdentity, messages::Received},
-    proto::{DataMessage, EditMessage, SyncMessage, sync_message::Sent},
-    store::{ContentsStore as _, Store},
+    model::messages::Received,
+    proto::{
+        AttachmentPointer, DataMessage, EditMessage, ReceiptMessage, SyncMessage,
+        data_message::{Flags, Reaction},
+        receipt_message,
+        sync_message::Sent,
+    },
+    store::{ContentsStore as _, Store, Thread},
 };
 use presage_store_sqlite::SqliteStore;
-use std::{cell::RefCell, collections::HashMap, rc::Rc, sync::Arc};
+use std::{
+    cell::RefCell,
+    cmp::Reverse,
+    error::Error,
+    fmt::{self, Display, Formatter},
+    fs, io,
+    mem::take,
+    path::{Path, PathBuf},
+    pin::pin,
+    rc::Rc,
+    sync::Arc,
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime},
+};
 use tokio::{
     runtime::Builder,
-    task::{self, LocalSet},
+    task::{AbortHandle, JoinSet, LocalSet, spawn_blocking},
+    time::{self, timeout},
 };
 
+/// How long pending store writes may take to finish when shutting down.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How old stored profiles may get before they're fetched again.
+const PROFILE_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// How long a downloaded attachment is kept in the disk cache.
+const ATTACHMENT_CACHE_MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// How large the disk cache of attachments may grow, in bytes.
+const ATTACHMENT_CACHE_MAX_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// How often sending a message is attempted while the connection fails.
+const SEND_ATTEMPTS: usize = 5;
+
+/// The first delay before sending is attempted again, growing with every attempt.
+const SEND_RETRY_BASE: Duration = Duration::from_secs(1);
+
+/// The version of safety numbers computed from ACIs instead of phone numbers.
+const SAFETY_NUMBER_VERSION: u32 = 2;
+
+/// How often the identity keys are hashed for a safety number.
+const SAFETY_NUMBER_ITERATIONS: u32 = 5200;
+
+/// The maximum number of messages a search returns.
+const SEARCH_RESULTS: u32 = 200;
+
 pub type RegisteredManager = presage::Manager<SqliteStore, Registered>;
 pub type LinkingManager = presage::Manager<SqliteStore, Linking>;
 pub type ManagerError = presage::Error<<SqliteStore as Store>::Error>;
 
+#[derive(Debug)]
+pub enum ManagerManagerError {
+    /// The background thread has shut down.
+    Disconnected,
+    /// No account has been loaded or linked yet.
+    NotRegistered,
+    /// The message is empty once its formatting is removed.
+    EmptyMessage,
+    /// The message was sent, but couldn't be decoded to be shown. It mustn't be sent again.
+    Decode,
+    /// The database can't be opened, with a report of what's wrong with it.
+    Database(String),
+    /// Importing the history of another client failed.
+    Import(String),
+    /// The search index is unavailable or the search failed.
+    Search(String),
+    /// An attachment couldn't be read or uploaded.
+    Upload(String),
+    /// The identity keys a safety number is computed from couldn't be read.
+    Identity(String),
+    /// No contact or group goes by the name or id a message was addressed to.
+    UnknownRecipient(String),
+    /// The recipient isn't registered with Signal, so sending again won't help.
+    Unregistered,
+    /// We left the group or were removed from it.
+    NotAMember,
+    /// Signal wants a captcha solved before sending more, answered with the token.
+    ProofRequired(String),
+    /// Signal refuses to send more for now, maybe telling for how long.
+    RateLimited(Option<Duration>),
+    /// Sending kept failing because of the connection, with the last error.
+    GaveUp(usize, ManagerError),
+    Manager(ManagerError),
+    Store(<SqliteStore as Store>::Error),
+}
+
+impl Display for ManagerManagerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Disconnected => f.write_str("the background task has shut down"),
+            Self::NotRegistered => f.write_str("no account is linked yet"),
+            Self::EmptyMessage => f.write_str("the message is empty"),
+            Self::Decode => f.write_str("the message was sent, but can't be shown"),
+            Self::Database(report) => write!(f, "the database can't be opened: {report}"),
+            Self::Import(err) => write!(f, "importing failed: {err}"),
+            Self::Search(err) => write!(f, "searching failed: {err}"),
+            Self::Upload(err) => write!(f, "uploading an attachment failed: {err}"),
+            Self::Identity(err) => write!(f, "the safety number can't be computed: {err}"),
+            Self::UnknownRecipient(to) => write!(f, "no contact or group is called {to}"),
+            Self::Unregistered => f.write_str("the recipient isn't registered with Signal"),
+            Self::NotAMember => f.write_str("you're not a member of the group anymore"),
+            Self::ProofRequired(_) => f.write_str("Signal asks to solve a challenge first"),
+            Self::RateLimited(Some(retry_after)) => write!(
+                f,
+                "too many messages, try again in {}s",
+                retry_after.as_secs().max(1)
+            ),
+            Self::RateLimited(None) => f.write_str("too many messages, try again later"),
+            Self::GaveUp(attempts, err) => {
+                write!(
+                    f,
+                    "sending failed {attempts} times, the last time with: {err}"
+                )
+            }
+            Self::Manager(err) => write!(f, "{err}"),
+            Self::Store(err) => write!(f, "store error: {err}"),
+        }
+    }
+}
+
+impl Error for ManagerManagerError {}
+
+impl From<ManagerError> for ManagerManagerError {
+    fn from(err: ManagerError) -> Self {
+        Self::Manager(err)
+    }
+}
+
 enum Event {
-    LoadRegistered(oneshot::Sender<ManagerError>),
-    LinkSecondary(oneshot::Sender<ManagerError>, oneshot::Sender<String>),
-    StreamMessages(mpsc::Sender<(Chat, SignalAction)>),
+    LoadRegistered(oneshot::Sender<Result<(), ManagerManagerError>>),
+    RecreateDatabase(oneshot::Sender<Result<(), ManagerManagerError>>),
+    LinkSecondary(
+        oneshot::Sender<String>,
+        oneshot::Sender<Result<(), ManagerManagerError>>,
+    ),
+    /// Stops waiting for the QR code to be scanned.
+    CancelLinking(oneshot::Sender<Result<(), ManagerManagerError>>),
+    StreamMessages(
+        mpsc::Sender<(Chat, SignalAction)>,
+        oneshot::Sender<Result<(), ManagerManagerError>>,
+    ),
+    Chats(oneshot::Sender<Result<Vec<Chat>, ManagerManagerError>>),
     SendMessage(
         Chat,
         String,
         Option<Quote>,
-        oneshot::Sender<(Chat, SignalAction)>,
+        Vec<Upload>,
+        mpsc::UnboundedSender<Retry>,
+        oneshot::Sender<Result<(Chat, SignalAction), ManagerManagerError>>,
     ),
     EditMessage(
         Chat,
         String,
         Timestamp,
-        oneshot::Sender<(Chat, SignalAction)>,
+        mpsc::UnboundedSender<Retry>,
+        oneshot::Sender<Result<(Chat, SignalAction), ManagerManagerError>>,
     ),
-    Shutdown,
+    SetTimer(
+        Chat,
+        u32,
+        oneshot::Sender<Result<(Chat, SignalAction), ManagerManagerError>>,
+    ),
+    React(
+        Chat,
+        Arc<Message>,
+        String,
+        bool,
+        oneshot::Sender<Result<(Chat, SignalAction), ManagerManagerError>>,
+    ),
+    SendReadReceipts(
+        Vec<(ServiceId, Vec<u64>)>,
+        oneshot::Sender<Result<(), ManagerManagerError>>,
+    ),
+    ClearHistory(Chat, oneshot::Sender<Result<(), ManagerManagerError>>),
+    RequestContacts(oneshot::Sender<Result<(), ManagerManagerError>>),
+    ResyncGroups(oneshot::Sender<Result<(), ManagerManagerError>>),
+    ResyncChat(Chat, oneshot::Sender<Result<(), ManagerManagerError>>),
+    SubmitCaptcha(
+        String,
+        String,
+        oneshot::Sender<Result<(), ManagerManagerError>>,
+    ),
+    SafetyNumber(
+        ServiceId,
+        oneshot::Sender<Result<Option<String>, ManagerManagerError>>,
+    ),
+    Shared(Chat, oneshot::Sender<Result<Shared, ManagerManagerError>>),
+    DownloadAttachment(
+        AttachmentPointer,
+        oneshot::Sender<Result<Vec<u8>, ManagerManagerError>>,
+    ),
+    ImportDesktop(PathBuf, oneshot::Sender<Result<usize, ManagerManagerError>>),
+    Search(
+        String,
+        Option<Thread>,
+        oneshot::Sender<Result<Vec<(Thread, Timestamp)>, ManagerManagerError>>,
+    ),
+    Receipts(oneshot::Sender<Result<Vec<(ServiceId, Receipt)>, ManagerManagerError>>),
+    StickerPacks(oneshot::Sender<Result<Vec<InstalledPack>, ManagerManagerError>>),
+    InstallStickerPack(
+        Vec<u8>,
+        Vec<u8>,
+        oneshot::Sender<Result<(), ManagerManagerError>>,
+    ),
+    UninstallStickerPack(Vec<u8>, oneshot::Sender<Result<(), ManagerManagerError>>),
+    /// Stops the background task, telling once the pending store writes are flushed.
+    Shutdown(Option<oneshot::Sender<()>>),
 }
 
 #[derive(Clone)]
@@ -53,343 +263,1504 @@ pub struct ManagerManager {
 
 struct Shutdown {
     sender: mpsc::Sender<Event>,
+    thread: Option<JoinHandle<()>>,
 }
 
 impl Drop for Shutdown {
     fn drop(&mut self) {
-        while self.sender.try_send(Event::Shutdown).is_err() {}
+        loop {
+            match self.sender.try_send(Event::Shutdown(None)) {
+                Err(err) if err.is_full() => std::thread::yield_now(),
+                _ => break,
+            }
+        }
+
+        // wait for pending store writes to be flushed, which the ui did already through
+        // `ManagerManager::shutdown`, so only the command line waits here
+        if let Some(thread) = self.thread.take() {
+            _ = thread.join();
+        }
     }
 }
 
 impl Default for ManagerManager {
     fn default() -> Self {
+        Self::new(Config::default())
+    }
+}
+
+impl ManagerManager {
+    pub fn new(config: Config) -> Self {
         let (sender, receiver) = mpsc::channel(100);
 
-        std::thread::Builder::new()
+        let thread = std::thread::Builder::new()
             .name("manager_manager".to_owned())
-            .spawn(|| {
+            .spawn(move || {
                 Builder::new_current_thread()
                     .enable_all()
                     .build()
                     .unwrap()
-                    .block_on(LocalSet::new().run_until(manager_manager(receiver)));
+                    .block_on(LocalSet::new().run_until(manager_manager(receiver, config)));
             })
             .unwrap();
 
         Self {
             sender: sender.clone(),
-            _shutdown: Arc::new(Shutdown { sender }),
+            _shutdown: Arc::new(Shutdown {
+                sender,
+                thread: Some(thread),
+            }),
         }
     }
-}
 
-impl ManagerManager {
-    pub async fn load_registered(mut self) -> Option<ManagerError> {
+    /// Stops the background task once the pending store writes are flushed, without blocking
+    /// the caller's thread while they are.
+    pub async fn shutdown(mut self) {
         let (tx, rx) = oneshot::channel();
 
-        self.sender.send(Event::LoadRegistered(tx)).await.unwrap();
+        if self.sender.send(Event::Shutdown(Some(tx))).await.is_ok() {
+            _ = rx.await;
+        }
+    }
 
-        rx.await.ok()
+    pub async fn load_registered(self) -> Result<(), ManagerManagerError> {
+        self.request(Event::LoadRegistered).await
     }
 
-    pub async fn link_secondary(mut self, url: oneshot::Sender<String>) -> Option<ManagerError> {
-        let (tx, rx) = oneshot::channel();
+    /// Backs up the damaged database and starts over with an empty one, which needs relinking.
+    pub async fn recreate_database(self) -> Result<(), ManagerManagerError> {
+        self.request(Event::RecreateDatabase).await
+    }
 
-        self.sender
-            .send(Event::LinkSecondary(tx, url))
-            .await
-            .unwrap();
+    /// Links this device, telling `url` the QR code to scan, and finishes once it's linked.
+    pub async fn link_secondary(
+        self,
+        url: oneshot::Sender<String>,
+    ) -> Result<(), ManagerManagerError> {
+        self.request(|tx| Event::LinkSecondary(url, tx)).await
+    }
 
-        rx.await.ok()
+    pub async fn cancel_linking(self) -> Result<(), ManagerManagerError> {
+        self.request(Event::CancelLinking).await
     }
 
-    pub async fn stream_mesages(mut self) -> impl Stream<Item = (Chat, SignalAction)> {
+    pub async fn stream_mesages(
+        self,
+    ) -> Result<impl Stream<Item = (Chat, SignalAction)>, ManagerManagerError> {
         let (tx, rx) = mpsc::channel(100);
 
-        self.sender.send(Event::StreamMessages(tx)).await.unwrap();
+        self.request(|c| Event::StreamMessages(tx, c)).await?;
+
+        Ok(rx)
+    }
 
-        rx
+    /// Every contact and group in the store, resolved like they are when messages are
+    /// streamed.
+    pub async fn chats(self) -> Result<Vec<Chat>, ManagerManagerError> {
+        self.request(Event::Chats).await
     }
 
+    /// Sends a message, telling `retries` whenever it's tried again because the connection
+    /// failed.
     pub async fn send(
-        mut self,
+        self,
         chat: Chat,
         content: String,
         quote: Option<Quote>,
-    ) -> Option<(Chat, SignalAction)> {
-        let (tx, rx) = oneshot::channel();
-
-        self.sender
-            .send(Event::SendMessage(chat, content, quote, tx))
+        attachments: Vec<Upload>,
+        retries: mpsc::UnboundedSender<Retry>,
+    ) -> Result<(Chat, SignalAction), ManagerManagerError> {
+        self.request(|tx| Event::SendMessage(chat, content, quote, attachments, retries, tx))
             .await
-            .unwrap();
-
-        rx.await.ok()
     }
 
     pub async fn edit(
-        mut self,
+        self,
         chat: Chat,
         content: String,
         timestamp: Timestamp,
-    ) -> Option<(Chat, SignalAction)> {
+        retries: mpsc::UnboundedSender<Retry>,
+    ) -> Result<(Chat, SignalAction), ManagerManagerError> {
+        self.request(|tx| Event::EditMessage(chat, content, timestamp, retries, tx))
+            .await
+    }
+
+    /// Sets how long until the messages of a contact's chat disappear, or turns it off with
+    /// zero seconds.
+    pub async fn set_timer(
+        self,
+        chat: Chat,
+        seconds: u32,
+    ) -> Result<(Chat, SignalAction), ManagerManagerError> {
+        self.request(|tx| Event::SetTimer(chat, seconds, tx)).await
+    }
+
+    /// Reacts to a message with an emoji, or takes the reaction back.
+    pub async fn react(
+        self,
+        chat: Chat,
+        target: Arc<Message>,
+        emoji: String,
+        remove: bool,
+    ) -> Result<(Chat, SignalAction), ManagerManagerError> {
+        self.request(|tx| Event::React(chat, target, emoji, remove, tx))
+            .await
+    }
+
+    /// Tells the senders of messages that they were read, given their timestamps by sender.
+    pub async fn send_read_receipts(
+        self,
+        read: Vec<(ServiceId, Vec<u64>)>,
+    ) -> Result<(), ManagerManagerError> {
+        self.request(|tx| Event::SendReadReceipts(read, tx)).await
+    }
+
+    /// Deletes the stored messages of a chat. Only this device forgets them.
+    pub async fn clear_history(self, chat: Chat) -> Result<(), ManagerManagerError> {
+        self.request(|tx| Event::ClearHistory(chat, tx)).await
+    }
+
+    /// Asks the primary device to send the contacts again, which arrive like any other
+    /// contact update.
+    pub async fn request_contacts(self) -> Result<(), ManagerManagerError> {
+        self.request(Event::RequestContacts).await
+    }
+
+    /// Resolves every group again, which arrive like any other group update.
+    pub async fn resync_groups(self) -> Result<(), ManagerManagerError> {
+        self.request(Event::ResyncGroups).await
+    }
+
+    /// Answers the challenge of a [`ManagerManagerError::ProofRequired`] with the solved
+    /// captcha, after which sending works again.
+    pub async fn submit_captcha(
+        self,
+        token: String,
+        captcha: String,
+    ) -> Result<(), ManagerManagerError> {
+        self.request(|tx| Event::SubmitCaptcha(token, captcha, tx))
+            .await
+    }
+
+    /// Decodes the whole history of a chat again, which arrives like received messages.
+    pub async fn resync_chat(self, chat: Chat) -> Result<(), ManagerManagerError> {
+        self.request(|tx| Event::ResyncChat(chat, tx)).await
+    }
+
+    /// The safety number to verify a contact's identity with, if their key is known yet.
+    pub async fn safety_number(self, id: ServiceId) -> Result<Option<String>, ManagerManagerError> {
+        self.request(|tx| Event::SafetyNumber(id, tx)).await
+    }
+
+    /// The media, links and files shared in a chat, from its whole history in the store.
+    pub async fn shared(self, chat: Chat) -> Result<Shared, ManagerManagerError> {
+        self.request(|tx| Event::Shared(chat, tx)).await
+    }
+
+    pub async fn download_attachment(
+        self,
+        ptr: AttachmentPointer,
+    ) -> Result<Vec<u8>, ManagerManagerError> {
+        self.request(|tx| Event::DownloadAttachment(ptr, tx)).await
+    }
+
+    /// Imports the message history of a Signal Desktop installation, returning how many
+    /// messages were imported.
+    pub async fn import_desktop(self, dir: PathBuf) -> Result<usize, ManagerManagerError> {
+        self.request(|tx| Event::ImportDesktop(dir, tx)).await
+    }
+
+    /// Searches the text of all messages, or only those of one thread, best matches first.
+    pub async fn search(
+        self,
+        query: String,
+        thread: Option<Thread>,
+    ) -> Result<Vec<(Thread, Timestamp)>, ManagerManagerError> {
+        self.request(|tx| Event::Search(query, thread, tx)).await
+    }
+
+    /// The receipts of our messages received in earlier runs, as the store doesn't keep them.
+    pub async fn receipts(self) -> Result<Vec<(ServiceId, Receipt)>, ManagerManagerError> {
+        self.request(Event::Receipts).await
+    }
+
+    /// The installed sticker packs, with their covers.
+    pub async fn sticker_packs(self) -> Result<Vec<InstalledPack>, ManagerManagerError> {
+        self.request(Event::StickerPacks).await
+    }
+
+    /// Downloads a sticker pack and keeps it in the store.
+    pub async fn install_sticker_pack(
+        self,
+        id: Vec<u8>,
+        key: Vec<u8>,
+    ) -> Result<(), ManagerManagerError> {
+        self.request(|tx| Event::InstallStickerPack(id, key, tx))
+            .await
+    }
+
+    pub async fn uninstall_sticker_pack(self, id: Vec<u8>) -> Result<(), ManagerManagerError> {
+        self.request(|tx| Event::UninstallStickerPack(id, tx)).await
+    }
+
+    /// Sends an event to the background thread and waits for its reply.
+    async fn request<T>(
+        mut self,
+        event: impl FnOnce(oneshot::Sender<Result<T, ManagerManagerError>>) -> Event,
+    ) -> Result<T, ManagerManagerError> {
         let (tx, rx) = oneshot::channel();
 
         self.sender
-            .send(Event::EditMessage(chat, content, timestamp, tx))
+            .send(event(tx))
             .await
-            .unwrap();
+            .map_err(|_| ManagerManagerError::Disconnected)?;
 
-        rx.await.ok()
+        rx.await.map_err(|_| ManagerManagerError::Disconnected)?
     }
 }
 
-async fn manager_manager(mut receiver: mpsc::Receiver<Event>) {
-    let store = SqliteStore::open(
-        concat!(env!("CARGO_MANIFEST_DIR"), "/foghorn.db"),
-        OnNewIdentity::Trust,
-    )
-    .await
-    .unwrap();
+async fn manager_manager(mut receiver: mpsc::Receiver<Event>, config: Config) {
+    let Some(store) = open_store(&mut receiver, &config).await else {
+        return;
+    };
+
+    let cache_dir = config.attachment_cache_dir();
+    let encrypted = config.key.is_some();
+    drop(spawn_blocking(move || {
+        prune_attachment_cache(&cache_dir, encrypted);
+    }));
+
+    let index = SearchIndex::open(&config)
+        .await
+        .inspect_err(|err| log::error!("Failed to open the search index: {}", err))
+        .ok();
 
     let manager = Rc::new(RefCell::new(None));
-    let cache = Rc::new(RefCell::new(HashMap::new()));
+    let cache = ChatCache::default();
+    // where the messages are streamed to, once they are
+    let mut streamed = None;
+
+    // tasks that write to the store, which are awaited on shutdown
+    let writers = Writers::default();
+    // everything else, which is cancelled on shutdown
+    let mut tasks = JoinSet::new();
+    // resolves on shutdown, for the writers that run until then
+    let (stop_tx, stop) = oneshot::channel::<()>();
+    let stop = stop.shared();
+    let mut flushed = None;
+    // the provisioning session waiting for its QR code to be scanned
+    let mut linking = None::<AbortHandle>;
+    // the timer refreshing the profiles, of which there's one however often messages are
+    // streamed, and which isn't aborted as it writes to the store
+    let mut refreshing = None::<AbortHandle>;
 
     while let Some(message) = receiver.next().await {
+        writers.reap();
+        while tasks.try_join_next().is_some() {}
+
         match message {
             Event::LoadRegistered(c) => {
                 let store = store.clone();
                 let manager = manager.clone();
-                task::spawn_local(async move {
+                tasks.spawn_local(async move {
                     match Box::pin(RegisteredManager::load_registered(store)).await {
-                        Ok(ok) => *manager.borrow_mut() = Some(ok),
-                        Err(err) => c.send(err).unwrap(),
+                        Ok(ok) => {
+                            *manager.borrow_mut() = Some(ok);
+                            _ = c.send(Ok(()));
+                        }
+                        Err(err) => _ = c.send(Err(err.into())),
                     }
                 });
             }
-            Event::LinkSecondary(c, url) => {
+            Event::RecreateDatabase(c) => {
+                // the database opened fine, so there's nothing to recreate
+                _ = c.send(Ok(()));
+            }
+            Event::LinkSecondary(url, c) => {
                 let (tx, rx) = oneshot::channel();
 
+                // a single session at a time, or their QR codes would replace each other
+                if let Some(linking) = linking.take() {
+                    linking.abort();
+                }
+
                 let store = store.clone();
                 let manager = manager.clone();
-                task::spawn_local(async move {
+                let servers = config.servers;
+                linking = Some(writers.spawn(async move {
                     match Box::pin(LinkingManager::link_secondary_device(
                         store,
-                        SignalServers::Production,
+                        servers,
                         "foghorn".to_owned(),
                         tx,
                     ))
                     .await
                     {
-                        Ok(ok) => *manager.borrow_mut() = Some(ok),
-                        Err(err) => c.send(err).unwrap(),
+                        Ok(ok) => {
+                            *manager.borrow_mut() = Some(ok);
+                            _ = c.send(Ok(()));
+                        }
+                        Err(err) => _ = c.send(Err(err.into())),
+                    }
+                }));
+
+                tasks.spawn_local(async {
+                    if let Ok(provisioning_url) = rx.await {
+                        _ = url.send(provisioning_url.to_string());
                     }
                 });
+            }
+            Event::CancelLinking(c) => {
+                if let Some(linking) = linking.take() {
+                    linking.abort();
+                }
 
-                task::spawn_local(async { url.send(rx.await.unwrap().to_string()) });
+                _ = c.send(Ok(()));
             }
-            Event::StreamMessages(mut c) => {
-                let mut manager = manager.borrow().clone().unwrap();
+            Event::StreamMessages(c, streaming) => {
+                let Some(mut manager) = manager.borrow().clone() else {
+                    _ = streaming.send(Err(ManagerManagerError::NotRegistered));
+                    continue;
+                };
+                _ = streaming.send(Ok(()));
                 let cache = cache.clone();
-                task::spawn_local(async move {
+                let mut c = indexed(c, index.clone(), &writers);
+                streamed = Some(c.clone());
+
+                if refreshing.as_ref().is_none_or(AbortHandle::is_finished) {
+                    refreshing = Some(writers.spawn(refresh_profiles_periodically(
+                        manager.clone(),
+                        cache.clone(),
+                        c.clone(),
+                        config.profiles_refreshed_path(),
+                        stop.clone(),
+                    )));
+                }
+
+                let mut stop = stop.clone();
+                let nested = writers.clone();
+                writers.spawn(async move {
                     let mut synced = false;
+                    let started = Instant::now();
 
-                    task::spawn_local({
+                    nested.spawn({
                         let mut manager = manager.clone();
-                        async move { Box::pin(manager.request_contacts()).await }
+                        async move {
+                            if let Err(err) = Box::pin(manager.request_contacts()).await {
+                                log::warn!("Failed to request the contacts: {}", err);
+                            }
+                        }
                     });
 
-                    Box::pin(sync_contacts(&mut manager, &cache, &mut c)).await;
-                    Box::pin(sync_messages(&mut manager, &cache, &mut c)).await;
+                    // the ui stopped listening
+                    if Box::pin(sync_contacts(&mut manager, &cache, &mut c))
+                        .await
+                        .is_err()
+                    {
+                        return;
+                    }
+                    let contacts = started.elapsed();
+                    if Box::pin(sync_messages(&mut manager, &cache, &mut c))
+                        .await
+                        .is_err()
+                    {
+                        return;
+                    }
+                    let messages = started.elapsed() - contacts;
 
-                    let mut stream = Box::pin(Box::pin(manager.receive_messages()).await.unwrap());
+                    let mut stream = match Box::pin(manager.receive_messages()).await {
+                        Ok(stream) => Box::pin(stream),
+                        Err(err) => {
+                            log::error!("Receiving messages failed: {}", err);
+                            return;
+                        }
+                    };
 
-                    while let Some(next) = stream.next().await {
+                    // a message that arrived is handled completely before shutting down
+                    while let Either::Right((Some(next), _)) =
+                        future::select(&mut stop, stream.next()).await
+                    {
                         match next {
                             Received::Content(message) => {
-                                let message_log = format!("{}, {}", message.metadata, message.body);
+                                let message_log = log_summary(&message);
+
+                                if let Some((sender, receipt)) =
+                                    delivery_receipt(&message, manager.aci())
+                                {
+                                    nested.spawn({
+                                        let mut manager = manager.clone();
+                                        async move {
+                                            send_delivery_receipt(&mut manager, sender, receipt)
+                                                .await;
+                                        }
+                                    });
+                                }
 
                                 if let Some(message) =
                                     Box::pin(decode_content(*message, &mut manager, &cache, synced))
                                         .await
                                 {
-                                    c.send(message).await.unwrap();
+                                    if c.send(message).await.is_err() {
+                                        // the ui stopped listening
+                                        return;
+                                    }
                                 } else {
                                     log::warn!("Decoding of message failed: {}", message_log);
                                 }
+
+                                if pass_challenge(&cache, &mut c).await.is_err() {
+                                    return;
+                                }
+                            }
+                            Received::QueueEmpty if !synced => {
+                                synced = true;
+
+                                let took = started.elapsed();
+                                log::info!(
+                                    "Startup took {:.2?}: contacts {:.2?}, messages {:.2?}, queue {:.2?}",
+                                    took,
+                                    contacts,
+                                    messages,
+                                    took - contacts - messages
+                                );
+                                log::finish_startup_trace();
                             }
-                            Received::QueueEmpty => synced = true,
+                            Received::QueueEmpty => {}
                             Received::Contacts => {
-                                Box::pin(sync_contacts(&mut manager, &cache, &mut c)).await;
+                                if Box::pin(resync_contacts(&mut manager, &cache, &mut c))
+                                    .await
+                                    .is_err()
+                                {
+                                    return;
+                                }
                             }
                         }
                     }
                 });
             }
-            Event::SendMessage(chat, content, quote, c) => {
-                let mut manager = manager.borrow().clone().unwrap();
+            Event::Chats(c) => {
+                let Some(mut manager) = manager.borrow().clone() else {
+                    _ = c.send(Err(ManagerManagerError::NotRegistered));
+                    continue;
+                };
                 let cache = cache.clone();
-                task::spawn_local(async move {
-                    let (body, body_ranges) = markdown_to_body_ranges(content.trim());
-                    if body.trim().is_empty() {
-                        return;
+
+                tasks.spawn_local(async move {
+                    let (mut tx, rx) = mpsc::channel(100);
+                    let synced = async move {
+                        // the chats are collected until it's done, so they're always listened to
+                        _ = Box::pin(sync_contacts(&mut manager, &cache, &mut tx)).await;
+                    };
+                    let chats = rx.map(|(chat, _)| chat).collect::<Vec<_>>();
+
+                    _ = c.send(Ok(future::join(synced, chats).await.1));
+                });
+            }
+            Event::SendMessage(chat, content, quote, attachments, retries, c) => {
+                let Some(mut manager) = manager.borrow().clone() else {
+                    _ = c.send(Err(ManagerManagerError::NotRegistered));
+                    continue;
+                };
+                let cache = cache.clone();
+                let index = index.clone();
+
+                writers.spawn(async move {
+                    let result = Box::pin(send_message(
+                        &mut manager,
+                        &cache,
+                        chat,
+                        &content,
+                        quote,
+                        attachments,
+                        &retries,
+                    ))
+                    .await;
+
+                    if let (Some(index), Ok((chat, action))) = (&index, &result) {
+                        index.apply(chat, action).await;
                     }
 
-                    let metadata = Metadata {
-                        sender: manager.registration_data().service_ids.aci().into(),
-                        destination: manager.registration_data().service_ids.aci().into(),
-                        sender_device: manager.device_id(),
+                    _ = c.send(result);
+                });
+            }
+            Event::EditMessage(chat, content, timestamp, retries, c) => {
+                let Some(mut manager) = manager.borrow().clone() else {
+                    _ = c.send(Err(ManagerManagerError::NotRegistered));
+                    continue;
+                };
+                let cache = cache.clone();
+                let index = index.clone();
 
-                        timestamp: Timestamp::now().as_millisecond() as u64,
-                        needs_receipt: true,
-                        unidentified_sender: false,
-                        was_plaintext: true,
-                        server_guid: None,
-                    };
+                writers.spawn(async move {
+                    let result = Box::pin(edit_message(
+                        &mut manager,
+                        &cache,
+                        chat,
+                        &content,
+                        timestamp,
+                        &retries,
+                    ))
+                    .await;
 
-                    let message = DataMessage {
-                        body: Some(body.clone()),
-                        attachments: vec![],
-                        group_v2: chat.group_context(),
-                        profile_key: chat.profile_key().map(Into::into),
-                        quote: quote.map(Into::into),
-                        body_ranges: body_ranges.clone(),
-                        ..Default::default()
-                    };
+                    if let (Some(index), Ok((chat, action))) = (&index, &result) {
+                        index.apply(chat, action).await;
+                    }
+
+                    _ = c.send(result);
+                });
+            }
+            Event::SetTimer(chat, seconds, c) => {
+                let Some(mut manager) = manager.borrow().clone() else {
+                    _ = c.send(Err(ManagerManagerError::NotRegistered));
+                    continue;
+                };
+                let cache = cache.clone();
 
-                    match &chat {
-                        Chat::Contact(contact) => Box::pin(manager.send_message(
-                            contact.id,
-                            message.clone(),
-                            metadata.timestamp,
-                        ))
+                writers.spawn(async move {
+                    _ = c.send(Box::pin(set_timer(&mut manager, &cache, chat, seconds)).await);
+                });
+            }
+            Event::React(chat, target, emoji, remove, c) => {
+                let Some(mut manager) = manager.borrow().clone() else {
+                    _ = c.send(Err(ManagerManagerError::NotRegistered));
+                    continue;
+                };
+                let cache = cache.clone();
+
+                writers.spawn(async move {
+                    _ = c.send(
+                        Box::pin(react(&mut manager, &cache, chat, &target, emoji, remove)).await,
+                    );
+                });
+            }
+            Event::SendReadReceipts(read, c) => {
+                let Some(mut manager) = manager.borrow().clone() else {
+                    _ = c.send(Err(ManagerManagerError::NotRegistered));
+                    continue;
+                };
+
+                tasks.spawn_local(async move {
+                    _ = c.send(Box::pin(send_read_receipts(&mut manager, read)).await);
+                });
+            }
+            Event::ClearHistory(chat, c) => {
+                let mut store = store.clone();
+                let index = index.clone();
+
+                writers.spawn(async move {
+                    let thread = chat.thread();
+                    let result = store
+                        .clear_thread(&thread)
                         .await
-                        .unwrap(),
-                        Chat::Group(group) => {
-                            Box::pin(manager.send_message_to_group(
-                                &group.key,
-                                message.clone(),
-                                metadata.timestamp,
-                            ))
-                            .await
-                            .unwrap();
-                        }
+                        .map_err(ManagerManagerError::Store);
+
+                    if let (Some(index), Ok(())) = (&index, &result) {
+                        index.clear(&thread).await;
                     }
 
-                    let message = Content {
-                        metadata,
-                        body: SyncMessage {
-                            sent: Some(Sent {
-                                destination_service_id: chat.uuid().map(|uuid| uuid.to_string()),
-                                message: Some(message),
-                                ..Sent::default()
-                            }),
-                            ..SyncMessage::default()
-                        }
-                        .into(),
-                    };
+                    _ = c.send(result);
+                });
+            }
+            Event::RequestContacts(c) => {
+                let Some(mut manager) = manager.borrow().clone() else {
+                    _ = c.send(Err(ManagerManagerError::NotRegistered));
+                    continue;
+                };
 
-                    manager
-                        .store()
-                        .save_message(&chat.thread(), message.clone())
-                        .await
-                        .unwrap();
+                tasks.spawn_local(async move {
+                    _ = c.send(
+                        Box::pin(manager.request_contacts())
+                            .await
+                            .map_err(ManagerManagerError::Manager),
+                    );
+                });
+            }
+            Event::SubmitCaptcha(token, captcha, c) => {
+                let Some(manager) = manager.borrow().clone() else {
+                    _ = c.send(Err(ManagerManagerError::NotRegistered));
+                    continue;
+                };
 
-                    c.send(
-                        Box::pin(decode_content(message, &mut manager, &cache, false))
+                tasks.spawn_local(async move {
+                    _ = c.send(
+                        Box::pin(manager.submit_recaptcha_challenge(&token, &captcha))
                             .await
-                            .unwrap(),
-                    )
-                    .unwrap();
+                            .map_err(ManagerManagerError::Manager),
+                    );
                 });
             }
-            Event::EditMessage(chat, content, timestamp, c) => {
-                let mut manager = manager.borrow().clone().unwrap();
+            Event::ResyncGroups(c) => {
+                let (Some(mut manager), Some(mut streamed)) =
+                    (manager.borrow().clone(), streamed.clone())
+                else {
+                    _ = c.send(Err(ManagerManagerError::NotRegistered));
+                    continue;
+                };
                 let cache = cache.clone();
 
-                task::spawn_local(async move {
-                    let (body, body_ranges) = markdown_to_body_ranges(content.trim());
-                    if body.trim().is_empty() {
-                        return;
-                    }
+                tasks.spawn_local(async move {
+                    let result = Box::pin(resync_groups(&mut manager, &cache, &mut streamed)).await;
+                    _ = c.send(result.map_err(|_| ManagerManagerError::Disconnected));
+                });
+            }
+            Event::ResyncChat(chat, c) => {
+                let (Some(mut manager), Some(mut streamed)) =
+                    (manager.borrow().clone(), streamed.clone())
+                else {
+                    _ = c.send(Err(ManagerManagerError::NotRegistered));
+                    continue;
+                };
+                let cache = cache.clone();
 
-                    let now = Timestamp::now().as_millisecond() as u64;
-
-                    let metadata = Metadata {
-                        sender: manager.registration_data().service_ids.aci().into(),
-                        destination: manager.registration_data().service_ids.aci().into(),
-                        sender_device: manager.device_id(),
-                        timestamp: now,
-                        needs_receipt: true,
-                        unidentified_sender: false,
-                        was_plaintext: true,
-                        server_guid: None,
-                    };
+                tasks.spawn_local(async move {
+                    let result = Box::pin(resync_thread(
+                        &chat.thread(),
+                        &mut manager,
+                        &cache,
+                        &mut streamed,
+                    ))
+                    .await;
+                    _ = c.send(result.map_err(|_| ManagerManagerError::Disconnected));
+                });
+            }
+            Event::SafetyNumber(id, c) => {
+                let Some(manager) = manager.borrow().clone() else {
+                    _ = c.send(Err(ManagerManagerError::NotRegistered));
+                    continue;
+                };
 
-                    let message = EditMessage {
-                        target_sent_timestamp: Some(timestamp.as_millisecond() as u64),
-                        data_message: Some(DataMessage {
-                            body: Some(body.clone()),
-                            attachments: vec![],
-                            group_v2: chat.group_context(),
-                            profile_key: chat.profile_key().map(Into::into),
-                            body_ranges: body_ranges.clone(),
-                            ..Default::default()
-                        }),
-                    };
+                tasks.spawn_local(async move {
+                    _ = c.send(
+                        safety_number(&manager, id)
+                            .await
+                            .map_err(|err| ManagerManagerError::Identity(err.to_string())),
+                    );
+                });
+            }
+            Event::Shared(chat, c) => {
+                let Some(manager) = manager.borrow().clone() else {
+                    _ = c.send(Err(ManagerManagerError::NotRegistered));
+                    continue;
+                };
+
+                tasks.spawn_local(async move {
+                    _ = c.send(Ok(gallery::shared(&manager, &chat.thread()).await));
+                });
+            }
+            Event::DownloadAttachment(ptr, c) => {
+                let Some(manager) = manager.borrow().clone() else {
+                    _ = c.send(Err(ManagerManagerError::NotRegistered));
+                    continue;
+                };
+
+                let cached = cached_attachment(&config, &ptr);
+                let span = log::info_span!("download_attachment", size = ptr.size());
 
-                    // delete the old message, so we don't load it again when starting up the next time
-                    let _ = manager
-                        .store()
-                        .clone()
-                        .delete_message(&chat.thread(), timestamp.as_millisecond() as u64)
-                        .await;
-
-                    match &chat {
-                        Chat::Contact(contact) => {
-                            Box::pin(manager.send_message(contact.id, message.clone(), now))
-                                .await
-                                .unwrap();
+                tasks.spawn_local(
+                    async move {
+                        if let Some(bytes) = cached.as_ref().and_then(|path| fs::read(path).ok()) {
+                            _ = c.send(Ok(bytes));
+                            return;
                         }
-                        Chat::Group(group) => {
-                            Box::pin(manager.send_message_to_group(
-                                &group.key,
-                                message.clone(),
-                                now,
-                            ))
-                            .await
-                            .unwrap();
+
+                        let result = Box::pin(manager.get_attachment(&ptr)).await;
+
+                        if let (Ok(bytes), Some(path)) = (&result, &cached)
+                            && let Err(err) = path
+                                .parent()
+                                .map_or(Ok(()), fs::create_dir_all)
+                                .and_then(|()| fs::write(path, bytes))
+                        {
+                            log::warn!("Failed to cache an attachment: {}", err);
                         }
+
+                        _ = c.send(result.map_err(Into::into));
                     }
+                    .instrument(span),
+                );
+            }
+            Event::ImportDesktop(dir, c) => {
+                let Some(manager) = manager.borrow().clone() else {
+                    _ = c.send(Err(ManagerManagerError::NotRegistered));
+                    continue;
+                };
+                let store = store.clone();
 
-                    let message = Content {
-                        metadata,
-                        body: SyncMessage {
-                            sent: Some(Sent {
-                                destination_service_id: chat.uuid().map(|uuid| uuid.to_string()),
-                                edit_message: Some(message),
-                                ..Sent::default()
-                            }),
-                            ..SyncMessage::default()
-                        }
-                        .into(),
-                    };
+                writers.spawn(async move {
+                    _ = c.send(
+                        import::import_desktop(&dir, &store, manager.aci())
+                            .await
+                            .map_err(|err| ManagerManagerError::Import(err.to_string())),
+                    );
+                });
+            }
+            Event::Search(query, thread, c) => {
+                let Some(index) = index.clone() else {
+                    _ = c.send(Err(ManagerManagerError::Search(
+                        "the search index couldn't be opened".to_owned(),
+                    )));
+                    continue;
+                };
 
-                    manager
-                        .store()
-                        .save_message(&chat.thread(), message.clone())
-                        .await
-                        .unwrap();
+                tasks.spawn_local(async move {
+                    _ = c.send(
+                        index
+                            .search(&query, thread.as_ref(), SEARCH_RESULTS)
+                            .await
+                            .map_err(|err| ManagerManagerError::Search(err.to_string())),
+                    );
+                });
+            }
+            Event::Receipts(c) => {
+                let Some(index) = index.clone() else {
+                    _ = c.send(Ok(vec![]));
+                    continue;
+                };
 
-                    c.send(
-                        Box::pin(decode_content(message, &mut manager, &cache, false))
+                tasks.spawn_local(async move {
+                    _ = c.send(Ok(index.receipts().await));
+                });
+            }
+            Event::StickerPacks(c) => {
+                let store = store.clone();
+
+                tasks.spawn_local(async move {
+                    _ = c.send(
+                        store
+                            .sticker_packs()
                             .await
-                            .unwrap(),
-                    )
-                    .unwrap();
+                            .map(|packs| packs.flatten().map(InstalledPack::from).collect())
+                            .map_err(ManagerManagerError::Store),
+                    );
                 });
             }
-            Event::Shutdown => return,
+            Event::InstallStickerPack(id, key, c) => {
+                let Some(mut manager) = manager.borrow().clone() else {
+                    _ = c.send(Err(ManagerManagerError::NotRegistered));
+                    continue;
+                };
+
+                writers.spawn(async move {
+                    _ = c.send(
+                        Box::pin(manager.add_sticker_pack(&id, &key))
+                            .await
+                            .map_err(Into::into),
+                    );
+                });
+            }
+            Event::UninstallStickerPack(id, c) => {
+                let Some(mut manager) = manager.borrow().clone() else {
+                    _ = c.send(Err(ManagerManagerError::NotRegistered));
+                    continue;
+                };
+
+                writers.spawn(async move {
+                    _ = c.send(
+                        Box::pin(manager.remove_sticker_pack(&id))
+                            .await
+                            .map(drop)
+                            .map_err(Into::into),
+                    );
+                });
+            }
+            Event::Shutdown(c) => {
+                flushed = c;
+                break;
+            }
+        }
+    }
+
+    tasks.shutdown().await;
+    drop(stop_tx);
+    // lets the search index finish with the messages on their way to the ui
+    drop(streamed);
+
+    if timeout(SHUTDOWN_TIMEOUT, writers.flush()).await.is_err() {
+        log::warn!("Pending store writes didn't finish before shutting down");
+    }
+
+    if let Some(flushed) = flushed {
+        _ = flushed.send(());
+    }
+}
+
+/// The tasks that write to the store, which are awaited on shutdown, including those spawned
+/// by other tasks.
+#[derive(Clone, Default)]
+struct Writers(Rc<RefCell<JoinSet<()>>>);
+
+impl Writers {
+    fn spawn(&self, task: impl Future<Output = ()> + 'static) -> AbortHandle {
+        self.0.borrow_mut().spawn_local(task)
+    }
+
+    /// Forgets the writers that finished.
+    fn reap(&self) {
+        while self.0.borrow_mut().try_join_next().is_some() {}
+    }
+
+    /// Waits for every writer, also those spawned while waiting.
+    async fn flush(&self) {
+        loop {
+            let mut writers = take(&mut *self.0.borrow_mut());
+            if writers.is_empty() {
+                break;
+            }
+
+            while writers.join_next().await.is_some() {}
+        }
+    }
+}
+
+/// Updates the search index with every decoded message on its way to the ui.
+fn indexed(
+    mut c: mpsc::Sender<(Chat, SignalAction)>,
+    index: Option<SearchIndex>,
+    writers: &Writers,
+) -> mpsc::Sender<(Chat, SignalAction)> {
+    let Some(index) = index else {
+        return c;
+    };
+
+    let (tx, mut rx) = mpsc::channel(100);
+
+    writers.spawn(async move {
+        while let Some((chat, action)) = rx.next().await {
+            index.apply(&chat, &action).await;
+
+            if c.send((chat, action)).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    tx
+}
+
+/// Fetches the profiles again whenever they're older than [`PROFILE_MAX_AGE`], remembering
+/// when that last happened across runs, until `stop` resolves.
+async fn refresh_profiles_periodically(
+    mut manager: RegisteredManager,
+    cache: ChatCache,
+    mut c: mpsc::Sender<(Chat, SignalAction)>,
+    stamp: PathBuf,
+    mut stop: Shared<oneshot::Receiver<()>>,
+) {
+    let refreshed = fs::read_to_string(&stamp)
+        .ok()
+        .and_then(|refreshed| refreshed.trim().parse::<Timestamp>().ok());
+
+    // profiles fetched before refreshing existed are as old as the first run with it
+    let Some(mut refreshed) = refreshed.or_else(|| write_stamp(&stamp)) else {
+        return;
+    };
+
+    loop {
+        let age =
+            Duration::try_from(Timestamp::now().duration_since(refreshed)).unwrap_or_default();
+        let due = pin!(time::sleep(PROFILE_MAX_AGE.saturating_sub(age)));
+        if let Either::Left(_) = future::select(&mut stop, due).await {
+            return;
+        }
+
+        log::info!("Refreshing profiles");
+        if Box::pin(refresh_profiles(&mut manager, &cache, &mut c))
+            .await
+            .is_err()
+        {
+            return;
+        }
+
+        let Some(now) = write_stamp(&stamp) else {
+            return;
+        };
+        refreshed = now;
+    }
+}
+
+fn write_stamp(path: &Path) -> Option<Timestamp> {
+    let now = Timestamp::now();
+
+    if let Err(err) = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|()| fs::write(path, now.to_string()))
+    {
+        log::error!("Failed to remember when profiles were refreshed: {}", err);
+        return None;
+    }
+
+    Some(now)
+}
+
+/// Where an attachment is cached on disk, named after the digest of its contents. Nothing is
+/// cached for an encrypted database, which would otherwise have its attachments lying around
+/// in plain text.
+fn cached_attachment(config: &Config, ptr: &AttachmentPointer) -> Option<PathBuf> {
+    if config.key.is_some() {
+        return None;
+    }
+
+    Some(
+        config
+            .attachment_cache_dir()
+            .join(hex::encode(ptr.digest.as_ref()?)),
+    )
+}
+
+/// Removes the cached attachments that are too old, and the oldest of the rest while the cache
+/// is too large. The whole cache is removed for an encrypted database, in case it was cached
+/// before the database was encrypted.
+fn prune_attachment_cache(dir: &Path, encrypted: bool) {
+    if encrypted {
+        if let Err(err) = fs::remove_dir_all(dir)
+            && err.kind() != io::ErrorKind::NotFound
+        {
+            log::warn!("Failed to remove the attachment cache: {}", err);
+        }
+
+        return;
+    }
+
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    let mut files = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok().filter(fs::Metadata::is_file)?;
+            Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+        })
+        .collect::<Vec<_>>();
+    files.sort_unstable_by_key(|(modified, ..)| Reverse(*modified));
+
+    let now = SystemTime::now();
+    let mut size = 0;
+
+    for (modified, len, path) in files {
+        size += len;
+
+        let too_old = now
+            .duration_since(modified)
+            .is_ok_and(|age| age > ATTACHMENT_CACHE_MAX_AGE);
+
+        if (too_old || size > ATTACHMENT_CACHE_MAX_SIZE)
+            && let Err(err) = fs::remove_file(&path)
+        {
+            log::warn!("Failed to remove a cached attachment: {}", err);
+        }
+    }
+}
+
+/// Opens the database. While it can't be opened, requests are answered with what's wrong
+/// with it, until the user chooses to recreate it.
+async fn open_store(receiver: &mut mpsc::Receiver<Event>, config: &Config) -> Option<SqliteStore> {
+    loop {
+        let report = match database::open(config).await {
+            Ok(store) => return Some(store),
+            Err(err) => database::diagnose(config, &*err).await,
+        };
+
+        log::error!("Failed to open the database: {}", report);
+
+        loop {
+            match receiver.next().await? {
+                Event::LoadRegistered(c) | Event::LinkSecondary(_, c) => {
+                    _ = c.send(Err(ManagerManagerError::Database(report.clone())));
+                }
+                Event::RecreateDatabase(c) => match database::backup(config) {
+                    Ok(backup) => {
+                        log::info!("Backed up the damaged database to {}", backup.display());
+                        _ = c.send(Ok(()));
+                        break;
+                    }
+                    Err(err) => _ = c.send(Err(ManagerManagerError::Database(err.to_string()))),
+                },
+                Event::SendMessage(.., c)
+                | Event::EditMessage(.., c)
+                | Event::SetTimer(.., c)
+                | Event::React(.., c) => {
+                    _ = c.send(Err(ManagerManagerError::NotRegistered));
+                }
+                Event::DownloadAttachment(_, c) | Event::ImportDesktop(_, c) => {
+                    _ = c.send(Err(ManagerManagerError::NotRegistered));
+                }
+                Event::Search(.., c) => _ = c.send(Err(ManagerManagerError::NotRegistered)),
+                Event::SafetyNumber(_, c) => _ = c.send(Err(ManagerManagerError::NotRegistered)),
+                Event::Shared(_, c) => _ = c.send(Err(ManagerManagerError::NotRegistered)),
+                Event::Chats(c) => _ = c.send(Err(ManagerManagerError::NotRegistered)),
+                Event::Receipts(c) => _ = c.send(Err(ManagerManagerError::NotRegistered)),
+                Event::StickerPacks(c)
+                | Event::RequestContacts(c)
+                | Event::ResyncGroups(c)
+                | Event::ResyncChat(_, c)
+                | Event::SubmitCaptcha(.., c) => {
+                    _ = c.send(Err(ManagerManagerError::NotRegistered));
+                }
+                Event::InstallStickerPack(.., c)
+                | Event::UninstallStickerPack(_, c)
+                | Event::SendReadReceipts(_, c)
+                | Event::ClearHistory(_, c) => {
+                    _ = c.send(Err(ManagerManagerError::NotRegistered));
+                }
+                Event::CancelLinking(c) => _ = c.send(Ok(())),
+                Event::StreamMessages(_, c) => _ = c.send(Err(ManagerManagerError::NotRegistered)),
+                Event::Shutdown(_) => return None,
+            }
+        }
+    }
+}
+
+async fn send_message(
+    manager: &mut RegisteredManager,
+    cache: &ChatCache,
+    chat: Chat,
+    content: &str,
+    quote: Option<Quote>,
+    attachments: Vec<Upload>,
+    retries: &mpsc::UnboundedSender<Retry>,
+) -> Result<(Chat, SignalAction), ManagerManagerError> {
+    let (body, body_ranges) = markdown_to_body_ranges(content.trim());
+    if body.trim().is_empty() && attachments.is_empty() {
+        return Err(ManagerManagerError::EmptyMessage);
+    }
+
+    let attachments = if attachments.is_empty() {
+        vec![]
+    } else {
+        Box::pin(upload_attachments(manager, attachments)).await?
+    };
+
+    let metadata = Metadata {
+        sender: manager.registration_data().service_ids.aci().into(),
+        destination: manager.registration_data().service_ids.aci().into(),
+        sender_device: manager.device_id(),
+
+        timestamp: Timestamp::now().as_millisecond() as u64,
+        needs_receipt: true,
+        unidentified_sender: false,
+        was_plaintext: true,
+        server_guid: None,
+    };
+
+    let message = DataMessage {
+        body: Some(body).filter(|body| !body.is_empty()),
+        attachments,
+        group_v2: chat.group_context(),
+        profile_key: chat.profile_key().map(Into::into),
+        quote: quote.map(Into::into),
+        body_ranges,
+        ..Default::default()
+    };
+
+    Box::pin(deliver(
+        manager,
+        &chat,
+        message.clone(),
+        metadata.timestamp,
+        retries,
+    ))
+    .await?;
+
+    save_sent(manager, cache, chat, metadata, message).await
+}
+
+/// Computes the safety number of our and a contact's identity keys like Signal's apps do, from
+/// both ACIs.
+async fn safety_number(
+    manager: &RegisteredManager,
+    id: ServiceId,
+) -> Result<Option<String>, SignalProtocolError> {
+    let store = manager.store().aci_protocol_store();
+    let ours = store.get_identity_key_pair().await?;
+
+    let device = DeviceId::try_from(1u32).expect("the primary device has a valid id");
+    let address = ProtocolAddress::new(id.service_id_string(), device);
+    let Some(theirs) = store.get_identity(&address).await? else {
+        return Ok(None);
+    };
+
+    Fingerprint::new(
+        SAFETY_NUMBER_VERSION,
+        SAFETY_NUMBER_ITERATIONS,
+        manager.aci().raw_uuid().as_bytes(),
+        ours.identity_key(),
+        id.raw_uuid().as_bytes(),
+        &theirs,
+    )?
+    .display_string()
+    .map(Some)
+}
+
+async fn set_timer(
+    manager: &mut RegisteredManager,
+    cache: &ChatCache,
+    chat: Chat,
+    seconds: u32,
+) -> Result<(Chat, SignalAction), ManagerManagerError> {
+    let metadata = Metadata {
+        sender: manager.registration_data().service_ids.aci().into(),
+        destination: manager.registration_data().service_ids.aci().into(),
+        sender_device: manager.device_id(),
+        timestamp: Timestamp::now().as_millisecond() as u64,
+        needs_receipt: false,
+        unidentified_sender: false,
+        was_plaintext: true,
+        server_guid: None,
+    };
+
+    let message = DataMessage {
+        flags: Some(Flags::ExpirationTimerUpdate as u32),
+        expire_timer: Some(seconds),
+        profile_key: chat.profile_key().map(Into::into),
+        ..Default::default()
+    };
+
+    Box::pin(deliver(
+        manager,
+        &chat,
+        message.clone(),
+        metadata.timestamp,
+        &mpsc::unbounded().0,
+    ))
+    .await?;
+
+    // decoded like the timer being changed on another of our devices
+    save_sent(manager, cache, chat, metadata, message).await
+}
+
+async fn react(
+    manager: &mut RegisteredManager,
+    cache: &ChatCache,
+    chat: Chat,
+    target: &Message,
+    emoji: String,
+    remove: bool,
+) -> Result<(Chat, SignalAction), ManagerManagerError> {
+    let metadata = Metadata {
+        sender: manager.registration_data().service_ids.aci().into(),
+        destination: manager.registration_data().service_ids.aci().into(),
+        sender_device: manager.device_id(),
+        timestamp: Timestamp::now().as_millisecond() as u64,
+        needs_receipt: false,
+        unidentified_sender: false,
+        was_plaintext: true,
+        server_guid: None,
+    };
+
+    let message = DataMessage {
+        reaction: Some(Reaction {
+            emoji: Some(emoji),
+            remove: Some(remove),
+            target_author_aci: Some(target.sender.id.raw_uuid().to_string()),
+            target_sent_timestamp: Some(target.timestamp.as_millisecond() as u64),
+            ..Default::default()
+        }),
+        group_v2: chat.group_context(),
+        profile_key: chat.profile_key().map(Into::into),
+        ..Default::default()
+    };
+
+    Box::pin(deliver(
+        manager,
+        &chat,
+        message.clone(),
+        metadata.timestamp,
+        &mpsc::unbounded().0,
+    ))
+    .await?;
+
+    // decoded like a reaction from another of our devices
+    save_sent(manager, cache, chat, metadata, message).await
+}
+
+/// The delivery receipt a received message asks for, with who it's sent to. Like the
+/// official apps, only messages someone wrote are confirmed, not receipts or typing.
+fn delivery_receipt(content: &Content, me: ServiceId) -> Option<(ServiceId, ReceiptMessage)> {
+    let metadata = &content.metadata;
+
+    if !metadata.needs_receipt
+        || metadata.sender == me
+        || !matches!(
+            content.body,
+            ContentBody::DataMessage(_) | ContentBody::EditMessage(_)
+        )
+    {
+        return None;
+    }
+
+    Some((
+        metadata.sender,
+        ReceiptMessage {
+            r#type: Some(receipt_message::Type::Delivery as i32),
+            timestamp: vec![metadata.timestamp],
+        },
+    ))
+}
+
+async fn send_delivery_receipt(
+    manager: &mut RegisteredManager,
+    sender: ServiceId,
+    receipt: ReceiptMessage,
+) {
+    if let Err(err) =
+        Box::pin(manager.send_message(sender, receipt, Timestamp::now().as_millisecond() as u64))
+            .await
+    {
+        log::warn!("Failed to send a delivery receipt: {}", err);
+    }
+}
+
+async fn send_read_receipts(
+    manager: &mut RegisteredManager,
+    read: Vec<(ServiceId, Vec<u64>)>,
+) -> Result<(), ManagerManagerError> {
+    for (sender, timestamps) in read {
+        let receipt = ReceiptMessage {
+            r#type: Some(receipt_message::Type::Read as i32),
+            timestamp: timestamps,
+        };
+
+        match Box::pin(manager.send_message(
+            sender,
+            receipt,
+            Timestamp::now().as_millisecond() as u64,
+        ))
+        .await
+        {
+            // who left Signal can't be told, but the others still can
+            Err(presage::Error::MessageSenderError(MessageSenderError::NotFound { .. })) => {
+                log::info!("Not sending read receipts to an unregistered contact");
+            }
+            result => result?,
+        }
+    }
+
+    Ok(())
+}
+
+/// Keeps a message we sent in the store, the way our other devices see it.
+async fn save_sent(
+    manager: &mut RegisteredManager,
+    cache: &ChatCache,
+    chat: Chat,
+    metadata: Metadata,
+    message: DataMessage,
+) -> Result<(Chat, SignalAction), ManagerManagerError> {
+    let message = Content {
+        metadata,
+        body: SyncMessage {
+            sent: Some(Sent {
+                destination_service_id: chat.uuid().map(|uuid| uuid.to_string()),
+                message: Some(message),
+                ..Sent::default()
+            }),
+            ..SyncMessage::default()
         }
+        .into(),
+    };
+
+    store_sent(manager, cache, &chat, message).await
+}
+
+/// Stores and decodes one of our messages once it was delivered. Nothing may fail like sending
+/// does from here on, since sending it again would deliver it twice.
+async fn store_sent(
+    manager: &mut RegisteredManager,
+    cache: &ChatCache,
+    chat: &Chat,
+    message: Content,
+) -> Result<(Chat, SignalAction), ManagerManagerError> {
+    if let Err(err) = manager
+        .store()
+        .save_message(&chat.thread(), message.clone())
+        .await
+    {
+        log::error!("Failed to store a sent message: {}", err);
+    }
+
+    Box::pin(decode_content(message, manager, cache, false))
+        .await
+        .ok_or(ManagerManagerError::Decode)
+}
+
+/// Sends a message to the members of a chat, trying again while the connection fails. Every
+/// attempt keeps the timestamp, so recipients who already got the message ignore it.
+async fn deliver(
+    manager: &mut RegisteredManager,
+    chat: &Chat,
+    message: impl Into<ContentBody> + Clone,
+    timestamp: u64,
+    retries: &mpsc::UnboundedSender<Retry>,
+) -> Result<(), ManagerManagerError> {
+    if let Chat::Group(group) = chat
+        && !group.is_member()
+    {
+        return Err(ManagerManagerError::NotAMember);
     }
+
+    let result = retry_fib(
+        SEND_RETRY_BASE,
+        SEND_ATTEMPTS,
+        is_transient,
+        |retry| {
+            log::warn!(
+                "Sending failed, attempt {} of {}, trying again in {:?}",
+                retry.attempt,
+                retry.attempts,
+                retry.delay
+            );
+            _ = retries.unbounded_send(retry);
+        },
+        async || match chat {
+            Chat::Contact(contact) => {
+                Box::pin(manager.send_message(contact.id, message.clone(), timestamp)).await
+            }
+            Chat::Group(group) => {
+                Box::pin(manager.send_message_to_group(&group.key, message.clone(), timestamp))
+                    .await
+            }
+        },
+    )
+    .await;
+
+    result.map_err(|err| match err {
+        presage::Error::MessageSenderError(MessageSenderError::NotFound { .. }) => {
+            ManagerManagerError::Unregistered
+        }
+        err if is_transient(&err) => ManagerManagerError::GaveUp(SEND_ATTEMPTS, err),
+        err => challenge(&err).unwrap_or_else(|| err.into()),
+    })
+}
+
+/// The challenge or limit Signal answered with instead of sending, if it did.
+pub fn challenge(err: &ManagerError) -> Option<ManagerManagerError> {
+    match err {
+        presage::Error::MessageSenderError(MessageSenderError::ProofRequired { token, .. })
+        | presage::Error::MessageSenderError(MessageSenderError::ServiceError(
+            ServiceError::ProofRequiredError(ProofRequired { token, .. }),
+        ))
+        | presage::Error::ServiceError(ServiceError::ProofRequiredError(ProofRequired {
+            token,
+            ..
+        })) => Some(ManagerManagerError::ProofRequired(token.clone())),
+        presage::Error::MessageSenderError(MessageSenderError::ServiceError(
+            ServiceError::RateLimitExceeded { retry_after },
+        ))
+        | presage::Error::ServiceError(ServiceError::RateLimitExceeded { retry_after }) => {
+            Some(ManagerManagerError::RateLimited(*retry_after))
+        }
+        _ => None,
+    }
+}
+
+/// Whether sending failed because of the connection, so it may work when tried again.
+fn is_transient(err: &ManagerError) -> bool {
+    let service = match err {
+        presage::Error::MessageSenderError(MessageSenderError::ServiceError(err))
+        | presage::Error::ServiceError(err) => err,
+        _ => return false,
+    };
+
+    matches!(
+        service,
+        ServiceError::Timeout { .. }
+            | ServiceError::SendError { .. }
+            | ServiceError::WsError { .. }
+    )
+}
+
+async fn upload_attachments(
+    manager: &RegisteredManager,
+    attachments: Vec<Upload>,
+) -> Result<Vec<AttachmentPointer>, ManagerManagerError> {
+    let attachments = attachments
+        .into_iter()
+        .map(|upload| {
+            let spec = AttachmentSpec {
+                content_type: upload.mime.to_string(),
+                length: upload.bytes.len(),
+                file_name: upload.file_name,
+                preview: None,
+                voice_note: None,
+                borderless: None,
+                width: upload.size.map(|(width, _)| width),
+                height: upload.size.map(|(_, height)| height),
+                caption: None,
+                blur_hash: None,
+            };
+
+            (spec, upload.bytes)
+        })
+        .collect();
+
+    Box::pin(manager.upload_attachments(attachments))
+        .await?
+        .into_iter()
+        .map(|result| result.map_err(|err| ManagerManagerError::Upload(err.to_string())))
+        .collect()
+}
+
+async fn edit_message(
+    manager: &mut RegisteredManager,
+    cache: &ChatCache,
+    chat: Chat,
+    content: &str,
+    timestamp: Timestamp,
+    retries: &mpsc::UnboundedSender<Retry>,
+) -> Result<(Chat, SignalAction), ManagerManagerError> {
+    let (body, body_ranges) = markdown_to_body_ranges(content.trim());
+    if body.trim().is_empty() {
+        return Err(ManagerManagerError::EmptyMessage);
+    }
+
+    let now = Timestamp::now().as_millisecond() as u64;
+
+    let metadata = Metadata {
+        sender: manager.registration_data().service_ids.aci().into(),
+        destination: manager.registration_data().service_ids.aci().into(),
+        sender_device: manager.device_id(),
+        timestamp: now,
+        needs_receipt: true,
+        unidentified_sender: false,
+        was_plaintext: true,
+        server_guid: None,
+    };
+
+    let message = EditMessage {
+        target_sent_timestamp: Some(timestamp.as_millisecond() as u64),
+        data_message: Some(DataMessage {
+            body: Some(body),
+            attachments: vec![],
+            group_v2: chat.group_context(),
+            profile_key: chat.profile_key().map(Into::into),
+            body_ranges,
+            ..Default::default()
+        }),
+    };
+
+    Box::pin(deliver(manager, &chat, message.clone(), now, retries)).await?;
+
+    // delete the old message, so we don't load it again when starting up the next time
+    _ = manager
+        .store()
+        .clone()
+        .delete_message(&chat.thread(), timestamp.as_millisecond() as u64)
+        .await;
+
+    let message = Content {
+        metadata,
+        body: SyncMessage {
+            sent: Some(Sent {
+                destination_service_id: chat.uuid().map(|uuid| uuid.to_string()),
+                edit_message: Some(message),
+                ..Sent::default()
+            }),
+            ..SyncMessage::default()
+        }
+        .into(),
+    };
+
+    store_sent(manager, cache, &chat, message).await
 }
@@ -0,0 +1,128 @@
+use crate::{app::Message, image, message::Contact};
+use iced::{
+    Center, Element, Fill, Font,
+    widget::{button, column, container, opaque, row, space, stack, text},
+};
+use std::sync::Arc;
+
+/// The popover about a contact mentioned in a message, opened without leaving the chat.
+#[derive(Debug, Default)]
+pub struct MemberCard {
+    contact: Option<Arc<Contact>>,
+    /// The safety number once it was asked for, or `None` inside if their key isn't known.
+    safety_number: Option<Option<String>>,
+}
+
+impl MemberCard {
+    pub fn is_open(&self) -> bool {
+        self.contact.is_some()
+    }
+
+    pub fn open(&mut self, contact: Arc<Contact>) {
+        self.contact = Some(contact);
+        self.safety_number = None;
+    }
+
+    pub fn close(&mut self) {
+        self.contact = None;
+        self.safety_number = None;
+    }
+
+    pub fn contact(&self) -> Option<&Arc<Contact>> {
+        self.contact.as_ref()
+    }
+
+    pub fn set_safety_number(&mut self, safety_number: Option<String>) {
+        self.safety_number = Some(safety_number);
+    }
+
+    pub fn as_iced_widget<'a>(
+        &'a self,
+        base: impl Into<Element<'a, Message>>,
+    ) -> Element<'a, Message> {
+        let base = base.into();
+
+        let Some(contact) = &self.contact else {
+            return base;
+        };
+
+        let safety_number: Element<'a, Message> = match &self.safety_number {
+            None => button(text("View safety number"))
+                .style(button::secondary)
+                .on_press(Message::ShowSafetyNumber(contact.id))
+                .into(),
+            Some(Some(safety_number)) => text(format_safety_number(safety_number))
+                .font(Font::MONOSPACE)
+                .into(),
+            Some(None) => text("There's no safety number until you exchanged messages.")
+                .size(12)
+                .style(text::secondary)
+                .into(),
+        };
+
+        let content = column![
+            row![
+                contact
+                    .avatar
+                    .clone()
+                    .map(|handle| image::avatar(handle, 64.)),
+                text(contact.name.clone()).size(20),
+                space::horizontal(),
+                button(text("✕"))
+                    .style(button::text)
+                    .on_press(Message::CloseMemberCard),
+            ]
+            .spacing(10)
+            .align_y(Center),
+            (!contact.is_self).then(|| {
+                button(text("Message"))
+                    .style(button::primary)
+                    .on_press(Message::MessageMember)
+            }),
+            (!contact.is_self).then_some(safety_number),
+        ]
+        .spacing(10)
+        .padding(20)
+        .max_width(360);
+
+        stack![
+            base,
+            opaque(
+                container(container(content).style(container::rounded_box))
+                    .center(Fill)
+                    .padding(20)
+            )
+        ]
+        .into()
+    }
+}
+
+/// Splits the digits into the groups of five Signal's apps show, four to a line.
+fn format_safety_number(digits: &str) -> String {
+    digits
+        .as_bytes()
+        .chunks(5)
+        .map(|group| str::from_utf8(group).unwrap_or_default())
+        .collect::<Vec<_>>()
+        .chunks(4)
+        .map(|line| line.join(" "))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn safety_number() {
+        let digits = (0..60)
+            .map(|i| char::from(b'0' + i % 10))
+            .collect::<String>();
+
+        assert_eq!(
+            format_safety_number(&digits),
+            "01234 56789 01234 56789\n01234 56789 01234 56789\n01234 56789 01234 56789"
+        );
+    }
+}
@@ -1,38 +1,75 @@
 use crate::{
-    log, manager_manager::RegisteredManager, parse::body_ranges_to_signal_spans, widget::SignalSpan,
+    backend::{ProfileError, SignalBackend},
+    cache::ChatCache,
+    deep_link::group_invite_link,
+    identifier,
+    image::Image,
+    log,
+    parse::{body_ranges_to_signal_spans, normalize_whitespace},
+    widget::SignalSpan,
 };
 use iced::{
-    futures::{SinkExt as _, StreamExt as _, channel::mpsc, stream::FuturesOrdered},
+    futures::{
+        SinkExt as _, StreamExt as _, TryStreamExt as _,
+        channel::mpsc::{self, SendError},
+        stream,
+    },
     widget::image,
 };
-use jiff::Timestamp;
+use jiff::{
+    Span, SpanRound, Timestamp, Unit,
+    fmt::friendly::{Designator, SpanPrinter},
+};
 use mime::Mime;
 use presage::{
     libsignal_service::{
-        content::ContentBody,
+        content::{ContentBody, Metadata},
         prelude::{Content, ProfileKey, Uuid},
         protocol::ServiceId,
-        zkgroup::{GroupMasterKeyBytes, ProfileKeyBytes},
+        zkgroup::{
+            GroupMasterKeyBytes, ProfileKeyBytes,
+            groups::{GroupMasterKey, GroupSecretParams},
+        },
     },
     proto::{
-        AttachmentPointer, BodyRange, DataMessage, EditMessage, GroupContextV2, SyncMessage,
+        AttachmentPointer, BodyRange, CallMessage, DataMessage, EditMessage, GroupContextV2,
+        ReceiptMessage, SyncMessage, TypingMessage,
+        body_range::{AssociatedValue, Style},
+        call_message,
         data_message::{
-            self, Delete,
+            self, Delete, Flags, Reaction,
             quote::{self, QuotedAttachment},
         },
+        receipt_message,
         sync_message::Sent,
+        typing_message,
     },
-    store::{ContentsStore as _, Thread},
+    store::Thread,
 };
 use std::{
-    cell::RefCell,
     collections::HashMap,
+    fmt::{self, Display, Formatter, Write as _},
     hash::{Hash, Hasher},
-    sync::Arc,
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
 };
 
 mod view;
 
+pub use view::format_size;
+
+/// The number of threads synced at the same time on startup.
+const SYNC_CONCURRENCY: usize = 4;
+
+/// The number of most recent messages per thread synced before the rest of the history.
+const RECENT_MESSAGES: usize = 50;
+
+/// The width blurhashes are decoded at, which is plenty for a blur that's scaled up.
+const PLACEHOLDER_WIDTH: u32 = 32;
+
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub enum Chat {
     Contact(Arc<Contact>),
@@ -79,12 +116,30 @@ impl Chat {
         }
     }
 
+    /// Whether this is the chat with ourselves, Note to Self.
+    pub fn is_self(&self) -> bool {
+        matches!(self, Self::Contact(contact) if contact.is_self)
+    }
+
     pub(crate) fn contact(&self) -> Option<Arc<Contact>> {
         match self {
             Self::Contact(contact) => Some(contact.clone()),
             Self::Group(_) => None,
         }
     }
+
+    /// Everyone but us who gets the messages sent here.
+    pub fn recipients(&self) -> Vec<Arc<Contact>> {
+        match self {
+            Self::Contact(contact) => vec![contact.clone()],
+            Self::Group(group) => group
+                .members
+                .iter()
+                .filter(|member| !member.is_self)
+                .cloned()
+                .collect(),
+        }
+    }
 }
 
 #[derive(Debug, Eq)]
@@ -92,6 +147,9 @@ pub struct Contact {
     pub key: ProfileKeyBytes,
     pub id: ServiceId,
     pub name: String,
+    /// What the contact wrote about themselves on their profile, with its emoji.
+    pub about: Option<String>,
+    pub about_emoji: Option<String>,
     pub avatar: Option<image::Handle>,
     pub is_self: bool,
 }
@@ -115,6 +173,41 @@ pub struct Group {
     pub title: String,
     pub avatar: Option<image::Handle>,
     pub members: Vec<Arc<Contact>>,
+    pub description: Option<String>,
+    /// The members allowed to change the group.
+    pub admins: Vec<ServiceId>,
+    /// The link to join the group with, while joining by link is allowed.
+    pub invite_link: Option<String>,
+    /// Whether the group is gone from the store, so only its history is known.
+    former: bool,
+}
+
+impl Group {
+    /// A group that's gone from the store after we left it, so only its history is known.
+    fn left(key: GroupMasterKeyBytes) -> Self {
+        Self {
+            key,
+            revision: 0,
+            title: "Former group".to_owned(),
+            avatar: None,
+            members: vec![],
+            description: None,
+            admins: vec![],
+            invite_link: None,
+            former: true,
+        }
+    }
+
+    /// Whether we're still in the group, rather than having left or been removed.
+    pub fn is_member(&self) -> bool {
+        self.members.iter().any(|member| member.is_self)
+    }
+
+    /// The identifier derived from the master key, which typing messages name the group by.
+    pub fn id(&self) -> [u8; 32] {
+        GroupSecretParams::derive_from_master_key(GroupMasterKey::new(self.key))
+            .get_group_identifier()
+    }
 }
 
 impl PartialEq for Group {
@@ -133,75 +226,439 @@ impl Hash for Group {
 pub struct Attachment {
     pub ptr: AttachmentPointer,
     pub mime: Mime,
-    pub image: Option<image::Handle>,
+    pub image: Option<Image>,
+    /// Whether the image was downloaded before, so it's reloaded from the disk cache after
+    /// being dropped to save memory.
+    pub downloaded: bool,
+    /// The blurred preview of an image, decoded from the blurhash the sender's app computed,
+    /// shown until the image is downloaded. It's only decoded while its chat is kept in memory.
+    pub placeholder: Option<image::Handle>,
 }
 
 impl Attachment {
-    async fn new(ptr: AttachmentPointer, manager: &RegisteredManager) -> Self {
+    pub fn new(ptr: AttachmentPointer) -> Self {
         let mime = ptr.content_type().parse::<Mime>().unwrap();
-        let image = if mime.type_() == mime::IMAGE {
-            Box::pin(manager.get_attachment(&ptr))
-                .await
-                .ok()
-                .map(image::Handle::from_bytes)
-        } else {
-            None
-        };
 
-        Self { ptr, mime, image }
+        Self {
+            ptr,
+            mime,
+            image: None,
+            downloaded: false,
+            placeholder: None,
+        }
+    }
+
+    /// Decodes the placeholder of an image, which is dropped with the image while its chat
+    /// isn't kept in memory.
+    pub fn decode_placeholder(&mut self) {
+        if self.placeholder.is_none() && self.mime.type_() == mime::IMAGE {
+            self.placeholder = placeholder(&self.ptr);
+        }
+    }
+
+    /// The name the sender's app gave the file, without any directories, or one made up from
+    /// its type.
+    pub fn file_name(&self) -> String {
+        self.ptr
+            .file_name
+            .as_deref()
+            .and_then(|name| Path::new(name).file_name())
+            .map_or_else(
+                || format!("attachment.{}", self.mime.subtype()),
+                |name| name.to_string_lossy().into_owned(),
+            )
+    }
+
+    /// The file name, size and dimensions the sender's app gave, as far as it did.
+    pub fn description(&self) -> String {
+        let mut description = format!(
+            "{}, {}",
+            self.ptr
+                .file_name
+                .as_deref()
+                .unwrap_or(self.mime.essence_str()),
+            format_size(self.ptr.size().into())
+        );
+
+        if self.ptr.width() > 0 && self.ptr.height() > 0 {
+            description += &format!(", {}×{}", self.ptr.width(), self.ptr.height());
+        }
+
+        description
     }
 }
 
+/// Decodes the blurhash of an image at its aspect ratio.
+fn placeholder(ptr: &AttachmentPointer) -> Option<image::Handle> {
+    let hash = ptr.blur_hash.as_deref()?;
+
+    // the dimensions come from the sender, so they may be anything
+    let height = if ptr.width() > 0 && ptr.height() > 0 {
+        (u64::from(PLACEHOLDER_WIDTH) * u64::from(ptr.height()) / u64::from(ptr.width()))
+            .clamp(1, u64::from(PLACEHOLDER_WIDTH) * 4) as u32
+    } else {
+        PLACEHOLDER_WIDTH * 3 / 4
+    };
+
+    let pixels = blurhash::decode(hash, PLACEHOLDER_WIDTH, height, 1.0).ok()?;
+
+    Some(image::Handle::from_rgba(PLACEHOLDER_WIDTH, height, pixels))
+}
+
 #[derive(Clone, Debug)]
 pub struct Message {
     pub timestamp: Timestamp,
-    pub body: Option<Vec<SignalSpan<'static>>>,
+    pub body: Option<Vec<SignalSpan<'static, String>>>,
     pub attachments: Vec<Attachment>,
     pub sticker: Option<Attachment>,
     pub sender: Arc<Contact>,
     pub quote: Option<Quote>,
     pub original_body: Option<String>,
     pub body_ranges: Vec<BodyRange>,
+    /// What happened to the chat, for rows not sent by anyone, like a group update.
+    pub system: Option<SystemMessage>,
+    /// How far one of our messages got, while it's shown before it was sent.
+    pub sending: Option<Sending>,
+    /// The emoji reacted with, one per contact, oldest first.
+    pub reactions: Vec<(Arc<Contact>, String)>,
+    /// The tags of the spoilers in the body that were revealed, kept for the session.
+    pub revealed_spoilers: Vec<usize>,
+    /// How the message reached us, for messages that were received or synced.
+    pub metadata: Option<Metadata>,
+}
+
+/// Which message is meant, by when it was sent and who sent it, as Signal refers to messages.
+/// Two contacts can send a message in the same millisecond.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct MessageId {
+    pub timestamp: Timestamp,
+    pub sender: Uuid,
+}
+
+impl MessageId {
+    pub fn new(timestamp: Timestamp, sender: ServiceId) -> Self {
+        Self {
+            timestamp,
+            sender: sender.raw_uuid(),
+        }
+    }
+
+    /// Comes before every message sent at the time, for looking messages up by time alone.
+    pub const fn first_at(timestamp: Timestamp) -> Self {
+        Self {
+            timestamp,
+            sender: Uuid::nil(),
+        }
+    }
+}
+
+/// One of our messages that's shown before it was sent.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Sending {
+    /// Tells the message apart until it's sent, since one of ours sent in the same
+    /// millisecond could already be in the chat.
+    pub key: Uuid,
+    pub failed: bool,
+}
+
+impl Sending {
+    fn new() -> Self {
+        static NEXT_KEY: AtomicU64 = AtomicU64::new(1);
+
+        // no contact has an id this low, and the nil one is taken by `MessageId::first_at`
+        Self {
+            key: Uuid::from_u64_pair(0, NEXT_KEY.fetch_add(1, Ordering::Relaxed)),
+            failed: false,
+        }
+    }
 }
 
 impl Message {
-    pub async fn new(
-        timestamp: u64,
+    pub fn new(
+        metadata: &Metadata,
         body: Option<String>,
         attachments: Vec<AttachmentPointer>,
-        sender: ServiceId,
         sticker: Option<data_message::Sticker>,
         quote: Option<data_message::Quote>,
-        cache: &RefCell<HashMap<Thread, Chat>>,
+        cache: &ChatCache,
         body_ranges: Vec<BodyRange>,
-        manager: &RegisteredManager,
     ) -> Self {
-        let sticker = if let Some(ptr) = sticker.and_then(|sticker| sticker.data) {
-            Some(Attachment::new(ptr, manager).await)
-        } else {
-            None
-        };
+        let (body, body_ranges) = normalize_whitespace(body, body_ranges);
 
-        let quote = if let Some(quote) = quote {
-            Some(Quote::new(quote, cache, manager).await)
-        } else {
-            None
-        };
+        let sticker = sticker
+            .and_then(|sticker| sticker.data)
+            .map(Attachment::new);
+
+        let quote = quote.map(|quote| Quote::new(quote, cache));
 
         Self {
-            timestamp: Timestamp::from_millisecond(timestamp as i64).unwrap(),
+            timestamp: sent_at(metadata.timestamp),
             body: body_ranges_to_signal_spans(body.as_deref(), &body_ranges, cache),
-            attachments: attachments
-                .into_iter()
-                .map(|ptr| Attachment::new(ptr, manager))
-                .collect::<FuturesOrdered<_>>()
-                .collect()
-                .await,
-            sender: cache.borrow()[&Thread::Contact(sender)].contact().unwrap(),
+            attachments: attachments.into_iter().map(Attachment::new).collect(),
+            sender: cache.contact(metadata.sender).unwrap(),
             sticker,
             quote,
             original_body: body,
             body_ranges,
+            system: None,
+            sending: None,
+            reactions: vec![],
+            revealed_spoilers: vec![],
+            metadata: Some(metadata.clone()),
+        }
+    }
+
+    /// Our message shown right away, until sending it finished.
+    pub fn pending(
+        timestamp: Timestamp,
+        sender: Arc<Contact>,
+        body: Option<Vec<SignalSpan<'static, String>>>,
+        quote: Option<Quote>,
+    ) -> Self {
+        Self {
+            timestamp,
+            body,
+            attachments: vec![],
+            sticker: None,
+            sender,
+            quote,
+            original_body: None,
+            body_ranges: vec![],
+            system: None,
+            sending: Some(Sending::new()),
+            reactions: vec![],
+            revealed_spoilers: vec![],
+            metadata: None,
+        }
+    }
+
+    fn system(timestamp: Timestamp, sender: Arc<Contact>, system: SystemMessage) -> Self {
+        Self {
+            timestamp,
+            body: None,
+            attachments: vec![],
+            sticker: None,
+            sender,
+            quote: None,
+            original_body: None,
+            body_ranges: vec![],
+            system: Some(system),
+            sending: None,
+            reactions: vec![],
+            revealed_spoilers: vec![],
+            metadata: None,
+        }
+    }
+
+    /// The attachments shown with the message, and the thumbnails of the message it quotes.
+    pub fn shown_attachments(&self) -> impl Iterator<Item = &Attachment> {
+        self.attachments
+            .iter()
+            .chain(self.quote.iter().flat_map(|quote| &quote.attachments))
+    }
+
+    pub fn shown_attachments_mut(&mut self) -> impl Iterator<Item = &mut Attachment> {
+        self.attachments.iter_mut().chain(
+            self.quote
+                .iter_mut()
+                .flat_map(|quote| &mut quote.attachments),
+        )
+    }
+
+    pub fn id(&self) -> MessageId {
+        if let Some(sending) = self.sending {
+            return MessageId {
+                timestamp: self.timestamp,
+                sender: sending.key,
+            };
+        }
+
+        // messages with invalid timestamps all share the latest time, and nothing can refer to
+        // them, so they're told apart by the envelope they came in
+        if let Some(guid) = self
+            .metadata
+            .as_ref()
+            .filter(|_| self.has_invalid_time())
+            .and_then(|metadata| metadata.server_guid)
+        {
+            return MessageId {
+                timestamp: self.timestamp,
+                sender: guid,
+            };
+        }
+
+        MessageId::new(self.timestamp, self.sender.id)
+    }
+
+    /// Whether the message came with a timestamp outside the range of times, which buggy
+    /// clients send, so it's shown at the latest time there is. It doesn't move its chat up
+    /// the chat list.
+    pub fn has_invalid_time(&self) -> bool {
+        self.metadata
+            .as_ref()
+            .is_some_and(|metadata| timestamp(metadata.timestamp).is_none())
+    }
+
+    /// Whether the message reached us with sealed sender, so Signal's servers didn't see who
+    /// sent it.
+    pub fn is_sealed_sender(&self) -> bool {
+        self.metadata
+            .as_ref()
+            .is_some_and(|metadata| metadata.unidentified_sender)
+    }
+
+    /// Everything known about how the message was sent, down to the raw body ranges and
+    /// attachment pointers, for telling what went wrong with it.
+    pub fn info(&self) -> String {
+        let mut info = format!("Timestamp      {}\n", self.timestamp.as_millisecond());
+
+        if let Some(metadata) = &self.metadata {
+            _ = writeln!(
+                info,
+                "Sender         {}, device {}",
+                metadata.sender.service_id_string(),
+                u32::from(metadata.sender_device)
+            );
+            _ = writeln!(
+                info,
+                "Sealed sender  {}",
+                if self.is_sealed_sender() { "yes" } else { "no" }
+            );
+            _ = writeln!(
+                info,
+                "Needs receipt  {}",
+                if metadata.needs_receipt { "yes" } else { "no" }
+            );
+            _ = writeln!(
+                info,
+                "Server GUID    {}",
+                metadata
+                    .server_guid
+                    .map_or_else(|| "none".to_owned(), |guid| guid.to_string())
+            );
+        } else {
+            _ = writeln!(
+                info,
+                "Sender         {}",
+                self.sender.id.service_id_string()
+            );
+        }
+
+        for (i, range) in self.body_ranges.iter().enumerate() {
+            let value = match &range.associated_value {
+                Some(AssociatedValue::MentionAci(aci)) => format!("mention {aci}"),
+                Some(AssociatedValue::Style(style)) => Style::try_from(*style).map_or_else(
+                    |_| format!("style {style}"),
+                    |style| style.as_str_name().to_lowercase(),
+                ),
+                None => "nothing".to_owned(),
+            };
+
+            _ = writeln!(
+                info,
+                "{:<15}{}..{} {value}",
+                if i == 0 { "Body ranges" } else { "" },
+                range.start(),
+                range.start() + range.length()
+            );
+        }
+
+        for (i, attachment) in self.attachments.iter().chain(&self.sticker).enumerate() {
+            let ptr = &attachment.ptr;
+
+            _ = writeln!(
+                info,
+                "{:<15}{}, {} bytes, {}×{}, cdn {} {:?}, uploaded at {}{}",
+                if i == 0 { "Attachments" } else { "" },
+                ptr.content_type(),
+                ptr.size(),
+                ptr.width(),
+                ptr.height(),
+                ptr.cdn_number(),
+                ptr.attachment_identifier,
+                ptr.upload_timestamp(),
+                ptr.file_name
+                    .as_deref()
+                    .map(|name| format!(", named {name}"))
+                    .unwrap_or_default()
+            );
+        }
+
+        info
+    }
+
+    /// Replaces the reaction of `sender`, or takes it back without an emoji.
+    pub fn react(&mut self, sender: Arc<Contact>, emoji: Option<String>) {
+        self.reactions
+            .retain(|(reactor, _)| reactor.id != sender.id);
+
+        if let Some(emoji) = emoji {
+            self.reactions.push((sender, emoji));
+        }
+    }
+
+    pub fn images(&self) -> impl Iterator<Item = &Attachment> {
+        self.attachments
+            .iter()
+            .filter(|attachment| attachment.mime.type_() == mime::IMAGE)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ReceiptKind {
+    Delivered,
+    Read,
+}
+
+/// A recipient's report that some of our messages, named by their timestamps, arrived or were
+/// read.
+#[derive(Clone, Debug)]
+pub struct Receipt {
+    pub kind: ReceiptKind,
+    pub timestamps: Vec<Timestamp>,
+    pub at: Timestamp,
+}
+
+/// Something that happened to a chat, shown as a row of its own.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SystemMessage {
+    /// The lines describing how a group changed.
+    Group(String),
+    /// Someone changed how long until messages disappear, or turned it off with zero seconds.
+    Timer { editor: String, seconds: u32 },
+    /// A contact changed their profile name.
+    Renamed { old: String, new: String },
+    /// A contact called, which can only be answered on another device.
+    Call { caller: String, video: bool },
+}
+
+impl Display for SystemMessage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Group(description) => f.write_str(description),
+            Self::Timer { editor, seconds: 0 } => {
+                write!(f, "{editor} turned off disappearing messages")
+            }
+            Self::Timer { editor, seconds } => {
+                let timer = Span::new()
+                    .seconds(*seconds)
+                    .round(SpanRound::new().largest(Unit::Week).days_are_24_hours())
+                    .map_err(|_| fmt::Error)?;
+
+                write!(
+                    f,
+                    "{editor} set messages to disappear after {}",
+                    SpanPrinter::new()
+                        .designator(Designator::Verbose)
+                        .span_to_string(&timer)
+                )
+            }
+            Self::Renamed { old, new } => write!(f, "{old} changed their name to {new}"),
+            Self::Call { caller, video } => write!(
+                f,
+                "{caller} started a {} call, answer it on your phone",
+                if *video { "video" } else { "voice" }
+            ),
         }
     }
 }
@@ -209,33 +666,33 @@ impl Message {
 #[derive(Clone, Debug)]
 pub struct Quote {
     pub timestamp: Timestamp,
-    pub body: Option<Vec<SignalSpan<'static>>>,
+    pub body: Option<Vec<SignalSpan<'static, String>>>,
     pub attachments: Vec<Attachment>,
     pub sender: Option<Arc<Contact>>,
+    pub original_body: Option<String>,
+    pub body_ranges: Vec<BodyRange>,
 }
 
 impl Quote {
-    async fn new(
-        quote: data_message::Quote,
-        cache: &RefCell<HashMap<Thread, Chat>>,
-        manager: &RegisteredManager,
-    ) -> Self {
+    fn new(quote: data_message::Quote, cache: &ChatCache) -> Self {
+        let (text, body_ranges) = normalize_whitespace(quote.text, quote.body_ranges);
+
         Self {
-            timestamp: Timestamp::from_millisecond(quote.id.unwrap_or_default() as i64).unwrap(),
-            body: body_ranges_to_signal_spans(quote.text.as_deref(), &quote.body_ranges, cache),
+            timestamp: timestamp(quote.id.unwrap_or_default()).unwrap_or_default(),
+            body: body_ranges_to_signal_spans(text.as_deref(), &body_ranges, cache),
             attachments: quote
                 .attachments
                 .into_iter()
                 .filter_map(|a| a.thumbnail)
-                .map(|ptr| Attachment::new(ptr, manager))
-                .collect::<FuturesOrdered<_>>()
-                .collect()
-                .await,
+                .map(Attachment::new)
+                .collect(),
             sender: quote
                 .author_aci
                 .as_deref()
                 .and_then(ServiceId::parse_from_service_id_string)
-                .and_then(|sender| cache.borrow().get(&Thread::Contact(sender))?.contact()),
+                .and_then(|sender| cache.contact(sender)),
+            original_body: text,
+            body_ranges,
         }
     }
 }
@@ -247,6 +704,8 @@ impl From<Message> for Quote {
             body: value.body,
             attachments: value.attachments,
             sender: Some(value.sender),
+            original_body: value.original_body,
+            body_ranges: value.body_ranges,
         }
     }
 }
@@ -259,10 +718,12 @@ impl From<Quote> for data_message::Quote {
             id: Some(value.timestamp.as_millisecond() as u64),
             author_aci_binary: id.as_ref().map(ServiceId::service_id_binary),
             author_aci: id.map(|id| id.raw_uuid().to_string()),
-            text: value
-                .body
-                .as_deref()
-                .map(|body| body.iter().map(|x| &*x.text).collect::<String>()),
+            text: value.original_body.or_else(|| {
+                value
+                    .body
+                    .as_deref()
+                    .map(|body| body.iter().map(|x| &*x.text).collect::<String>())
+            }),
             attachments: value
                 .attachments
                 .into_iter()
@@ -272,7 +733,7 @@ impl From<Quote> for data_message::Quote {
                     thumbnail: Some(attachment.ptr),
                 })
                 .collect(),
-            body_ranges: vec![],
+            body_ranges: value.body_ranges,
             r#type: Some(quote::Type::Normal as i32),
         }
     }
@@ -282,57 +743,84 @@ impl From<Quote> for data_message::Quote {
 pub enum SignalAction {
     Contact,
     Message(Arc<Message>, bool),
-    Replace(Timestamp, Arc<Message>),
-    Delete(Timestamp),
+    /// A row describing what happened to the chat, which comes with the changed chat.
+    System(Arc<Message>),
+    /// Some of our messages reached or were read by the contact of the chat.
+    Receipt(Receipt),
+    /// Someone started or stopped typing in the chat, at the given time.
+    Typing(Arc<Contact>, bool, Timestamp),
+    Replace(MessageId, Arc<Message>),
+    Delete(MessageId),
+    /// Someone reacted to a message with an emoji, or took their reaction back, and whether
+    /// it just arrived rather than being decoded from history.
+    React(MessageId, Arc<Contact>, Option<String>, bool),
+    /// Signal wants a captcha solved before fetching the profile of the chat's contact,
+    /// answered with the token.
+    Challenge(String),
 }
 
+/// Resolves every contact and group. Like the rest of syncing, it stops once nobody listens
+/// anymore.
+#[log::instrument(skip_all)]
 pub async fn sync_contacts(
-    manager: &mut RegisteredManager,
-    cache: &RefCell<HashMap<Thread, Chat>>,
+    manager: &mut impl SignalBackend,
+    cache: &ChatCache,
     c: &mut mpsc::Sender<(Chat, SignalAction)>,
-) {
-    let me = get_contact_cached(
-        ServiceId::Aci(manager.registration_data().service_ids.aci.into()),
-        manager.registration_data().profile_key().bytes,
-        manager,
-        cache,
-    )
-    .await
-    .unwrap();
-    c.send((me, SignalAction::Contact)).await.unwrap();
-
-    for contact in manager
-        .store()
-        .contacts()
-        .await
-        .into_iter()
-        .flatten()
-        .flatten()
+) -> Result<(), SendError> {
+    // fetching profiles can be rate limited, which shouldn't stop the other contacts
+    if let Some(me) = get_contact_cached(manager.aci(), manager.profile_key(), manager, cache).await
     {
-        if let Some(contact) = get_contact_cached(
-            ServiceId::Aci(contact.uuid.into()),
-            contact.profile_key,
-            manager,
-            cache,
-        )
-        .await
-        {
-            c.send((contact, SignalAction::Contact)).await.unwrap();
+        c.send((me, SignalAction::Contact)).await?;
+    } else {
+        log::error!("Failed to fetch your own profile");
+    }
+
+    for (id, profile_key) in manager.contacts().await {
+        if let Some(contact) = get_contact_cached(id, profile_key, manager, cache).await {
+            c.send((contact, SignalAction::Contact)).await?;
         }
     }
 
-    for group in manager
-        .store()
-        .groups()
-        .await
-        .into_iter()
-        .flatten()
-        .flatten()
-    {
+    sync_groups(manager, cache, c).await?;
+    pass_challenge(cache, c).await
+}
+
+/// Passes on the challenge Signal answered a profile fetch with, if it did since the last time.
+pub async fn pass_challenge(
+    cache: &ChatCache,
+    c: &mut mpsc::Sender<(Chat, SignalAction)>,
+) -> Result<(), SendError> {
+    if let Some((chat, token)) = cache.take_challenge() {
+        c.send((chat, SignalAction::Challenge(token))).await?;
+    }
+
+    Ok(())
+}
+
+/// Resolves every group again, fetching their avatars again, for when they're out of sync.
+#[log::instrument(skip_all)]
+pub async fn resync_groups(
+    manager: &mut impl SignalBackend,
+    cache: &ChatCache,
+    c: &mut mpsc::Sender<(Chat, SignalAction)>,
+) -> Result<(), SendError> {
+    for (key, _) in manager.groups().await {
+        cache.invalidate(&Thread::Group(key));
+    }
+
+    sync_groups(manager, cache, c).await
+}
+
+async fn sync_groups(
+    manager: &mut impl SignalBackend,
+    cache: &ChatCache,
+    c: &mut mpsc::Sender<(Chat, SignalAction)>,
+) -> Result<(), SendError> {
+    for (key, group) in manager.groups().await {
         if let Some(group) = get_group_cached(
             GroupContextV2 {
-                revision: Some(group.1.revision),
-                master_key: Some(group.0.into()),
+                revision: Some(group.revision),
+                master_key: Some(key.into()),
                 group_change: None,
             },
             manager,
@@ -340,58 +828,230 @@ pub async fn sync_contacts(
         )
         .await
         {
-            c.send((group, SignalAction::Contact)).await.unwrap();
+            c.send((group, SignalAction::Contact)).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Fetches every profile again, so renamed contacts and new avatars show up.
+#[log::instrument(skip_all)]
+pub async fn refresh_profiles(
+    manager: &mut impl SignalBackend,
+    cache: &ChatCache,
+    c: &mut mpsc::Sender<(Chat, SignalAction)>,
+) -> Result<(), SendError> {
+    if !manager.refetch_profiles().await {
+        log::warn!("Failed to refetch the stored profiles");
+        return Ok(());
+    }
+
+    resync_contacts(manager, cache, c).await
+}
+
+/// Resolves every contact again, after their profiles changed, telling who changed their name.
+pub async fn resync_contacts(
+    manager: &mut impl SignalBackend,
+    cache: &ChatCache,
+    c: &mut mpsc::Sender<(Chat, SignalAction)>,
+) -> Result<(), SendError> {
+    let old = cache.invalidate_contacts();
+    sync_contacts(manager, cache, c).await?;
+
+    for old in old.into_iter().filter(|old| !old.is_self) {
+        let Some(new) = cache.contact(old.id) else {
+            continue;
+        };
+
+        if new.name != old.name {
+            let renamed = SystemMessage::Renamed {
+                old: old.name.clone(),
+                new: new.name.clone(),
+            };
+            let message = Message::system(Timestamp::now(), new.clone(), renamed);
+
+            c.send((Chat::Contact(new), SignalAction::System(message.into())))
+                .await?;
         }
     }
+
+    Ok(())
 }
 
+#[log::instrument(skip_all)]
 pub async fn sync_messages(
-    manager: &mut RegisteredManager,
-    cache: &RefCell<HashMap<Thread, Chat>>,
+    manager: &mut impl SignalBackend,
+    cache: &ChatCache,
     c: &mut mpsc::Sender<(Chat, SignalAction)>,
-) {
-    for thread in manager
-        .store()
+) -> Result<(), SendError> {
+    let threads = manager
         .contacts()
         .await
         .into_iter()
-        .flatten()
-        .flatten()
-        .map(|c| Thread::Contact(ServiceId::Aci(c.uuid.into())))
+        .map(|(id, _)| Thread::Contact(id))
         .chain(
             manager
-                .store()
                 .groups()
                 .await
                 .into_iter()
-                .flatten()
-                .flatten()
-                .map(|g| Thread::Group(g.0)),
+                .map(|(key, _)| Thread::Group(key)),
         )
-    {
-        for message in manager
-            .store()
-            .messages(&thread, ..)
-            .await
-            .into_iter()
-            .flatten()
-            .flatten()
-        {
-            let message_log = format!("{}, {}", message.metadata, message.body);
+        .collect::<Vec<_>>();
 
-            if let Some(message) = Box::pin(decode_content(message, manager, cache, false)).await {
-                c.send(message).await.unwrap();
-            } else {
-                log::warn!("Decoding of message failed: {}", message_log);
+    // decode the newest messages of every thread first, so the chat list fills in quickly
+    let older = stream::iter(threads)
+        .map(|thread| {
+            let mut manager = manager.clone();
+            let mut c = c.clone();
+
+            async move {
+                let mut contents = thread_contents(&manager, &thread).await;
+
+                let recent = contents.split_off(contents.len().saturating_sub(RECENT_MESSAGES));
+                decode_all(recent, &mut manager, cache, &mut c)
+                    .await
+                    .map(|()| contents)
             }
+        })
+        .buffer_unordered(SYNC_CONCURRENCY)
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    // then the rest of the history
+    stream::iter(older)
+        .map(Ok)
+        .try_for_each_concurrent(SYNC_CONCURRENCY, |contents| {
+            let mut manager = manager.clone();
+            let mut c = c.clone();
+
+            async move { decode_all(contents, &mut manager, cache, &mut c).await }
+        })
+        .await
+}
+
+/// Decodes the whole history of a thread again, for when it's out of sync.
+#[log::instrument(skip_all)]
+pub async fn resync_thread(
+    thread: &Thread,
+    manager: &mut impl SignalBackend,
+    cache: &ChatCache,
+    c: &mut mpsc::Sender<(Chat, SignalAction)>,
+) -> Result<(), SendError> {
+    let contents = thread_contents(manager, thread).await;
+    decode_all(contents, manager, cache, c).await
+}
+
+/// The stored messages of a thread. History is decoded out of order, so only the latest edit
+/// or delete of each message is kept, and the messages they target are dropped.
+pub async fn thread_contents(manager: &impl SignalBackend, thread: &Thread) -> Vec<Content> {
+    let mut contents = manager.messages(thread).await;
+
+    // only the sender of a message can edit or delete it
+    let id = |content: &Content| {
+        MessageId::new(sent_at(content.metadata.timestamp), content.metadata.sender)
+    };
+    let target = |content: &Content| {
+        target_timestamp(content)
+            .map(|target| MessageId::new(sent_at(target), content.metadata.sender))
+    };
+
+    let latest = contents
+        .iter()
+        .filter_map(|content| Some((target(content)?, content.metadata.timestamp)))
+        .collect::<HashMap<_, _>>();
+    contents.retain(|content| {
+        !latest.contains_key(&id(content))
+            && target(content).is_none_or(|target| latest[&target] == content.metadata.timestamp)
+    });
+
+    contents
+}
+
+async fn decode_all(
+    contents: Vec<Content>,
+    manager: &mut impl SignalBackend,
+    cache: &ChatCache,
+    c: &mut mpsc::Sender<(Chat, SignalAction)>,
+) -> Result<(), SendError> {
+    for message in contents {
+        let message_log = log_summary(&message);
+
+        if let Some(message) = Box::pin(decode_content(message, manager, cache, false)).await {
+            c.send(message).await?;
+        } else {
+            log::warn!("Decoding of message failed: {}", message_log);
         }
     }
+
+    pass_challenge(cache, c).await
+}
+
+/// Describes a message for the logs, leaving out what it says, since the logs are kept in
+/// plain text files even when the store is encrypted.
+pub fn log_summary(content: &Content) -> String {
+    let kind = match &content.body {
+        ContentBody::DataMessage(_) => "data message",
+        ContentBody::EditMessage(_) => "edit",
+        ContentBody::SynchronizeMessage(_) => "sync message",
+        ContentBody::CallMessage(_) => "call message",
+        ContentBody::ReceiptMessage(_) => "receipt",
+        ContentBody::TypingMessage(_) => "typing message",
+        _ => "other message",
+    };
+
+    format!(
+        "{kind} from {} at {}",
+        content.metadata.sender.service_id_string(),
+        content.metadata.timestamp
+    )
+}
+
+/// The timestamp of the message targeted by an edit or a delete.
+pub fn target_timestamp(content: &Content) -> Option<u64> {
+    match &content.body {
+        ContentBody::EditMessage(EditMessage {
+            target_sent_timestamp,
+            ..
+        })
+        | ContentBody::DataMessage(DataMessage {
+            delete: Some(Delete {
+                target_sent_timestamp,
+            }),
+            ..
+        }) => *target_sent_timestamp,
+        ContentBody::SynchronizeMessage(SyncMessage {
+            sent: Some(sent), ..
+        }) => match sent {
+            Sent {
+                edit_message:
+                    Some(EditMessage {
+                        target_sent_timestamp,
+                        ..
+                    }),
+                ..
+            }
+            | Sent {
+                message:
+                    Some(DataMessage {
+                        delete:
+                            Some(Delete {
+                                target_sent_timestamp,
+                            }),
+                        ..
+                    }),
+                ..
+            } => *target_sent_timestamp,
+            _ => None,
+        },
+        _ => None,
+    }
 }
 
 pub async fn decode_content(
     content: Content,
-    manager: &mut RegisteredManager,
-    cache: &RefCell<HashMap<Thread, Chat>>,
+    manager: &mut impl SignalBackend,
+    cache: &ChatCache,
     synced: bool,
 ) -> Option<(Chat, SignalAction)> {
     match content.body {
@@ -418,32 +1078,27 @@ pub async fn decode_content(
             };
 
             let message = Message::new(
-                content.metadata.timestamp,
+                &content.metadata,
                 body,
                 attachments,
-                content.metadata.sender,
                 sticker,
                 quote,
                 cache,
                 body_ranges,
-                manager,
-            )
-            .await;
+            );
 
             debug_assert!(!message.sender.is_self);
 
             // delete the old message, so we don't load it again when starting up the next time
             manager
-                .store()
-                .clone()
                 .delete_message(&chat.thread(), target_sent_timestamp?)
                 .await
-                .ok()?;
+                .then_some(())?;
 
             Some((
                 chat,
                 SignalAction::Replace(
-                    Timestamp::from_millisecond(target_sent_timestamp? as i64).unwrap(),
+                    MessageId::new(timestamp(target_sent_timestamp?)?, message.sender.id),
                     message.into(),
                 ),
             ))
@@ -481,24 +1136,21 @@ pub async fn decode_content(
             };
 
             let message = Message::new(
-                content.metadata.timestamp,
+                &content.metadata,
                 body,
                 attachments,
-                content.metadata.sender,
                 sticker,
                 quote,
                 cache,
                 body_ranges,
-                manager,
-            )
-            .await;
+            );
 
             debug_assert!(message.sender.is_self);
 
             Some((
                 chat,
                 SignalAction::Replace(
-                    Timestamp::from_millisecond(target_sent_timestamp? as i64).unwrap(),
+                    MessageId::new(timestamp(target_sent_timestamp?)?, message.sender.id),
                     message.into(),
                 ),
             ))
@@ -521,9 +1173,10 @@ pub async fn decode_content(
 
             Some((
                 chat,
-                SignalAction::Delete(
-                    Timestamp::from_millisecond(target_sent_timestamp? as i64).unwrap(),
-                ),
+                SignalAction::Delete(MessageId::new(
+                    timestamp(target_sent_timestamp?)?,
+                    content.metadata.sender,
+                )),
             ))
         }
         ContentBody::SynchronizeMessage(SyncMessage {
@@ -555,22 +1208,19 @@ pub async fn decode_content(
 
             Some((
                 chat,
-                SignalAction::Delete(
-                    Timestamp::from_millisecond(target_sent_timestamp? as i64).unwrap(),
-                ),
+                SignalAction::Delete(MessageId::new(
+                    timestamp(target_sent_timestamp?)?,
+                    content.metadata.sender,
+                )),
             ))
         }
         ContentBody::DataMessage(DataMessage {
-            body,
-            attachments,
             group_v2,
             profile_key,
-            quote,
-            sticker,
-            body_ranges,
+            reaction: Some(reaction),
             ..
         }) => {
-            // a message sent not by us, or previously edited by us
+            // a reaction not by us
 
             let chat = if let Some(context) = group_v2 {
                 get_group_cached(context, manager, cache).await?
@@ -578,18 +1228,210 @@ pub async fn decode_content(
                 get_contact_cached(content.metadata.sender, profile_key?, manager, cache).await?
             };
 
-            let message = Message::new(
-                content.metadata.timestamp,
-                body,
-                attachments,
+            reacted(chat, content.metadata.sender, reaction, cache, synced)
+        }
+        ContentBody::SynchronizeMessage(SyncMessage {
+            sent:
+                Some(Sent {
+                    destination_service_id,
+                    message:
+                        Some(DataMessage {
+                            group_v2,
+                            profile_key,
+                            reaction: Some(reaction),
+                            ..
+                        }),
+                    ..
+                }),
+            ..
+        }) => {
+            // a reaction by us
+
+            let chat = if let Some(context) = group_v2 {
+                get_group_cached(context, manager, cache).await?
+            } else {
+                let id = ServiceId::parse_from_service_id_string(&destination_service_id?)?;
+                get_contact_cached(id, profile_key?, manager, cache).await?
+            };
+
+            reacted(chat, content.metadata.sender, reaction, cache, synced)
+        }
+        ContentBody::DataMessage(DataMessage {
+            body: None,
+            group_v2:
+                Some(
+                    context @ GroupContextV2 {
+                        group_change: Some(_),
+                        ..
+                    },
+                ),
+            ..
+        })
+        | ContentBody::SynchronizeMessage(SyncMessage {
+            sent:
+                Some(Sent {
+                    message:
+                        Some(DataMessage {
+                            body: None,
+                            group_v2:
+                                Some(
+                                    context @ GroupContextV2 {
+                                        group_change: Some(_),
+                                        ..
+                                    },
+                                ),
+                            ..
+                        }),
+                    ..
+                }),
+            ..
+        }) => {
+            // a group was updated, by us or someone else
+
+            group_changed(
+                context,
+                content.metadata.timestamp,
                 content.metadata.sender,
+                manager,
+                cache,
+            )
+            .await
+        }
+        ContentBody::TypingMessage(TypingMessage {
+            action, group_id, ..
+        }) => {
+            let sender = cache.contact(content.metadata.sender)?;
+            let chat = match group_id {
+                Some(id) => cache.group(&id)?,
+                None => Chat::Contact(sender.clone()),
+            };
+            let started =
+                typing_message::Action::try_from(action?).ok()? == typing_message::Action::Started;
+            let at = timestamp(content.metadata.timestamp)?;
+
+            Some((chat, SignalAction::Typing(sender, started, at)))
+        }
+        ContentBody::CallMessage(CallMessage {
+            offer: Some(offer), ..
+        }) => {
+            // only the offer is of interest, as calls can't be answered here
+            let caller = cache.contact(content.metadata.sender)?;
+            let video = call_message::offer::Type::try_from(offer.r#type?).ok()?
+                == call_message::offer::Type::OfferVideoCall;
+
+            let message = Message::system(
+                timestamp(content.metadata.timestamp)?,
+                caller.clone(),
+                SystemMessage::Call {
+                    caller: caller.name.clone(),
+                    video,
+                },
+            );
+
+            Some((Chat::Contact(caller), SignalAction::System(message.into())))
+        }
+        ContentBody::ReceiptMessage(ReceiptMessage {
+            r#type,
+            timestamp: sent,
+        }) => {
+            let chat = Chat::Contact(cache.contact(content.metadata.sender)?);
+            let kind = match receipt_message::Type::try_from(r#type?).ok()? {
+                receipt_message::Type::Delivery => ReceiptKind::Delivered,
+                // viewing a view-once message or a story also means reading it
+                receipt_message::Type::Read | receipt_message::Type::Viewed => ReceiptKind::Read,
+            };
+
+            let receipt = Receipt {
+                kind,
+                timestamps: sent.into_iter().filter_map(timestamp).collect(),
+                at: timestamp(content.metadata.timestamp)?,
+            };
+
+            Some((chat, SignalAction::Receipt(receipt)))
+        }
+        ContentBody::DataMessage(DataMessage {
+            flags: Some(flags),
+            expire_timer,
+            group_v2,
+            profile_key,
+            ..
+        }) if flags & Flags::ExpirationTimerUpdate as u32 != 0 => {
+            // the disappearing message timer was changed not by us
+
+            let chat = if let Some(context) = group_v2 {
+                get_group_cached(context, manager, cache).await?
+            } else {
+                get_contact_cached(content.metadata.sender, profile_key?, manager, cache).await?
+            };
+
+            timer_changed(
+                chat,
+                content.metadata.timestamp,
+                content.metadata.sender,
+                expire_timer,
+                cache,
+            )
+        }
+        ContentBody::SynchronizeMessage(SyncMessage {
+            sent:
+                Some(Sent {
+                    destination_service_id,
+                    message:
+                        Some(DataMessage {
+                            flags: Some(flags),
+                            expire_timer,
+                            group_v2,
+                            profile_key,
+                            ..
+                        }),
+                    ..
+                }),
+            ..
+        }) if flags & Flags::ExpirationTimerUpdate as u32 != 0 => {
+            // the disappearing message timer was changed by us
+
+            let chat = if let Some(context) = group_v2 {
+                get_group_cached(context, manager, cache).await?
+            } else {
+                let id = ServiceId::parse_from_service_id_string(&destination_service_id?)?;
+                get_contact_cached(id, profile_key?, manager, cache).await?
+            };
+
+            timer_changed(
+                chat,
+                content.metadata.timestamp,
+                content.metadata.sender,
+                expire_timer,
+                cache,
+            )
+        }
+        ContentBody::DataMessage(DataMessage {
+            body,
+            attachments,
+            group_v2,
+            profile_key,
+            quote,
+            sticker,
+            body_ranges,
+            ..
+        }) => {
+            // a message sent not by us, or previously edited by us
+
+            let chat = if let Some(context) = group_v2 {
+                get_group_cached(context, manager, cache).await?
+            } else {
+                get_contact_cached(content.metadata.sender, profile_key?, manager, cache).await?
+            };
+
+            let message = Message::new(
+                &content.metadata,
+                body,
+                attachments,
                 sticker,
                 quote,
                 cache,
                 body_ranges,
-                manager,
-            )
-            .await;
+            );
 
             Some((chat, SignalAction::Message(message.into(), synced)))
         }
@@ -622,17 +1464,14 @@ pub async fn decode_content(
             };
 
             let message = Message::new(
-                content.metadata.timestamp,
+                &content.metadata,
                 body,
                 attachments,
-                content.metadata.sender,
                 sticker,
                 quote,
                 cache,
                 body_ranges,
-                manager,
-            )
-            .await;
+            );
 
             debug_assert!(message.sender.is_self);
 
@@ -642,29 +1481,161 @@ pub async fn decode_content(
     }
 }
 
+/// Resolves a group again after it was changed, and describes what changed.
+async fn group_changed(
+    context: GroupContextV2,
+    timestamp: u64,
+    sender: ServiceId,
+    manager: &mut impl SignalBackend,
+    cache: &ChatCache,
+) -> Option<(Chat, SignalAction)> {
+    let thread = Thread::Group(context.master_key().try_into().ok()?);
+    let old = cache.get(&thread);
+
+    // the store already holds the new state of the group, fetched while receiving the change
+    cache.invalidate(&thread);
+    let chat = get_group_cached(context, manager, cache).await?;
+
+    let (old, Chat::Group(new)) = (old, &chat) else {
+        return None;
+    };
+    let old = match old {
+        Some(Chat::Group(old)) => Some(old),
+        _ => None,
+    };
+
+    // whoever removed themselves isn't a member anymore
+    let sender = cache.contact(sender).or_else(|| {
+        old.iter()
+            .flat_map(|old| &old.members)
+            .find(|member| member.id == sender)
+            .cloned()
+    })?;
+
+    let description = describe_group_change(old.as_deref(), new, &sender.name);
+    let message = Message::system(
+        sent_at(timestamp),
+        sender,
+        SystemMessage::Group(description),
+    );
+
+    Some((chat, SignalAction::System(message.into())))
+}
+
+fn reacted(
+    chat: Chat,
+    sender: ServiceId,
+    reaction: Reaction,
+    cache: &ChatCache,
+    live: bool,
+) -> Option<(Chat, SignalAction)> {
+    let sender = cache.contact(sender)?;
+    let author = ServiceId::Aci(Uuid::parse_str(&reaction.target_author_aci?).ok()?.into());
+    let target = MessageId::new(timestamp(reaction.target_sent_timestamp?)?, author);
+    let emoji = reaction.emoji.filter(|_| !reaction.remove());
+
+    Some((chat, SignalAction::React(target, sender, emoji, live)))
+}
+
+fn timer_changed(
+    chat: Chat,
+    timestamp: u64,
+    sender: ServiceId,
+    expire_timer: Option<u32>,
+    cache: &ChatCache,
+) -> Option<(Chat, SignalAction)> {
+    let sender = cache.contact(sender)?;
+    let timer = SystemMessage::Timer {
+        editor: sender.name.clone(),
+        seconds: expire_timer.unwrap_or_default(),
+    };
+    let message = Message::system(sent_at(timestamp), sender, timer);
+
+    Some((chat, SignalAction::System(message.into())))
+}
+
+fn describe_group_change(old: Option<&Group>, new: &Group, editor: &str) -> String {
+    let mut changes = vec![];
+
+    // without the previous state, there's nothing to compare against
+    let Some(old) = old else {
+        return format!("{editor} updated the group");
+    };
+
+    if old.title != new.title {
+        changes.push(format!("{editor} renamed the group to \"{}\"", new.title));
+    }
+
+    for member in &new.members {
+        if !old.members.contains(member) {
+            changes.push(format!("{} joined the group", member.name));
+        }
+    }
+
+    for member in &old.members {
+        if !new.members.contains(member) {
+            changes.push(format!("{} left the group", member.name));
+        }
+    }
+
+    if changes.is_empty() {
+        changes.push(format!("{editor} updated the group"));
+    }
+
+    changes.join("\n")
+}
+
+/// The time of a timestamp in milliseconds, unless it's outside the range of times.
+fn timestamp(millisecond: u64) -> Option<Timestamp> {
+    Timestamp::from_millisecond(i64::try_from(millisecond).ok()?).ok()
+}
+
+/// The time a message was sent at, where timestamps outside the range of times are clamped
+/// to its end, so the message is still shown.
+pub fn sent_at(millisecond: u64) -> Timestamp {
+    timestamp(millisecond).unwrap_or_else(|| {
+        log::warn!("Clamping the invalid timestamp {}", millisecond);
+        Timestamp::MAX
+    })
+}
+
 async fn get_group_cached(
     context: GroupContextV2,
-    manager: &mut RegisteredManager,
-    cache: &RefCell<HashMap<Thread, Chat>>,
+    manager: &mut impl SignalBackend,
+    cache: &ChatCache,
 ) -> Option<Chat> {
     let key = context.master_key().try_into().ok()?;
     let revision = context.revision();
 
     let chat = Thread::Group(key);
-    let group = manager.store().group(key).await.ok()??;
 
+    // the history of a group we left stays readable, even once the group is gone, but anyone
+    // can send a message naming a group we never were in
+    let Some(group) = manager.group(key).await else {
+        if let Some(chat) = cache.get(&chat) {
+            return Some(chat);
+        }
+
+        if !manager.has_history(&chat).await {
+            log::debug!("Dropped a message of a group that isn't known");
+            return None;
+        }
+
+        return Some(cache.insert(Chat::Group(Group::left(key).into())));
+    };
+
+    // a group that was only known from its history may have been stored since
     if group.revision == revision
-        && let Some(chat) = cache.borrow().get(&chat)
+        && let Some(chat) = cache.get(&chat)
+        && !matches!(&chat, Chat::Group(group) if group.former)
     {
-        return Some(chat.clone());
+        return Some(chat);
     }
 
     let mut members = vec![];
 
-    for member in group.members {
-        if let Some(member) =
-            get_contact_cached(member.aci.into(), member.profile_key.bytes, manager, cache).await
-        {
+    for (id, profile_key) in group.members {
+        if let Some(member) = get_contact_cached(id, profile_key, manager, cache).await {
             members.push(member.contact()?);
         }
     }
@@ -673,52 +1644,808 @@ async fn get_group_cached(
         key,
         revision,
         title: group.title,
-        avatar: Box::pin(manager.retrieve_group_avatar(context))
+        avatar: manager
+            .group_avatar(context)
             .await
-            .ok()?
             .map(image::Handle::from_bytes),
         members,
+        description: group.description,
+        admins: group.admins,
+        invite_link: group
+            .invite_link_password
+            .map(|password| group_invite_link(&key, &password)),
+        former: false,
     };
 
-    cache
-        .borrow_mut()
-        .insert(chat.clone(), Chat::Group(group.into()));
-
-    Some(cache.borrow()[&chat].clone())
+    Some(cache.insert(Chat::Group(group.into())))
 }
 
 async fn get_contact_cached(
     id: ServiceId,
     profile_key: impl TryInto<ProfileKeyBytes>,
-    manager: &mut RegisteredManager,
-    cache: &RefCell<HashMap<Thread, Chat>>,
+    manager: &mut impl SignalBackend,
+    cache: &ChatCache,
 ) -> Option<Chat> {
-    let chat = Thread::Contact(id);
+    let profile_key = ProfileKey::create(profile_key.try_into().ok()?);
 
-    if let Some(chat) = cache.borrow().get(&chat) {
-        return Some(chat.clone());
+    if let Some(contact) = cache.contact(id) {
+        if contact.key == profile_key.bytes && !cache.is_unresolved(&Thread::Contact(id)) {
+            return Some(Chat::Contact(contact));
+        }
+
+        // the contact shared a new profile key, so their profile may have changed, or it
+        // couldn't be fetched before
+        cache.invalidate(&Thread::Contact(id));
     }
 
-    let profile_key = ProfileKey::create(profile_key.try_into().ok()?);
+    let mut challenge = None;
+    let profile = match manager.profile(id, profile_key).await {
+        Ok(profile) => Some(profile),
+        Err(ProfileError::ProofRequired(token)) => {
+            challenge = Some(token);
+            None
+        }
+        Err(ProfileError::Failed) => None,
+    };
+    let fetched = profile.is_some();
+
+    let name = match profile.as_ref().and_then(|profile| profile.name.clone()) {
+        Some(name) => name,
+        None => identifier::unknown(manager.phone_number(id).await.as_deref(), id),
+    };
 
     let contact = Contact {
         key: profile_key.bytes,
         id,
-        name: Box::pin(manager.retrieve_profile_by_uuid(id.raw_uuid(), profile_key))
-            .await
-            .ok()?
-            .name?
-            .to_string(),
-        avatar: Box::pin(manager.retrieve_profile_avatar_by_uuid(id.raw_uuid(), profile_key))
+        name,
+        about: profile.as_ref().and_then(|profile| profile.about.clone()),
+        about_emoji: profile.and_then(|profile| profile.about_emoji),
+        avatar: manager
+            .profile_avatar(id, profile_key)
             .await
-            .ok()?
             .map(image::Handle::from_bytes),
-        is_self: id.raw_uuid() == manager.registration_data().service_ids.aci,
+        is_self: id == manager.aci(),
     };
 
-    cache
-        .borrow_mut()
-        .insert(chat.clone(), Chat::Contact(contact.into()));
+    // the fallback name is only kept until the profile can be fetched
+    let chat = Chat::Contact(contact.into());
+    if let Some(token) = challenge {
+        cache.set_challenge(chat.clone(), token);
+    }
+
+    Some(if fetched {
+        cache.insert(chat)
+    } else {
+        cache.insert_unresolved(chat)
+    })
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::backend::{StoredGroup, mock::MockBackend};
+    use iced::futures::executor::block_on;
+    use presage::libsignal_service::content::Metadata;
+    use std::iter;
+
+    pub const ME: Uuid = Uuid::from_u128(1);
+    pub const ALICE: Uuid = Uuid::from_u128(2);
+    const ALICE_KEY: ProfileKeyBytes = [2; 32];
+    const GROUP_KEY: GroupMasterKeyBytes = [3; 32];
+
+    /// A contact who isn't us, for the tests that don't decode messages.
+    pub fn contact(id: Uuid, name: &str) -> Contact {
+        Contact {
+            key: [0; 32],
+            id: ServiceId::Aci(id.into()),
+            name: name.to_owned(),
+            about: None,
+            about_emoji: None,
+            avatar: None,
+            is_self: false,
+        }
+    }
+
+    /// A plain message, already sent, for the tests that don't decode messages.
+    pub fn message(timestamp: Timestamp, sender: Arc<Contact>, body: Option<&str>) -> Message {
+        Message {
+            timestamp,
+            body: body.map(|body| vec![SignalSpan::new(body.to_owned())]),
+            attachments: vec![],
+            sticker: None,
+            sender,
+            quote: None,
+            original_body: body.map(ToOwned::to_owned),
+            body_ranges: vec![],
+            system: None,
+            sending: None,
+            reactions: vec![],
+            revealed_spoilers: vec![],
+            metadata: None,
+        }
+    }
+
+    /// Us, with Alice and a group with her in it.
+    pub fn backend() -> MockBackend {
+        MockBackend::new(ServiceId::Aci(ME.into()), "Me")
+            .with_contact(ServiceId::Aci(ALICE.into()), ALICE_KEY, "Alice")
+            .with_group(
+                GROUP_KEY,
+                StoredGroup {
+                    title: "Group".to_owned(),
+                    revision: 0,
+                    members: vec![(ServiceId::Aci(ALICE.into()), ALICE_KEY)],
+                    description: None,
+                    admins: vec![],
+                    invite_link_password: None,
+                },
+            )
+    }
+
+    /// A message from `sender` to us.
+    pub fn content(sender: Uuid, timestamp: u64, body: ContentBody) -> Content {
+        Content {
+            metadata: Metadata {
+                sender: ServiceId::Aci(sender.into()),
+                destination: ServiceId::Aci(ME.into()),
+                sender_device: 1u32.try_into().unwrap(),
+                timestamp,
+                needs_receipt: false,
+                unidentified_sender: false,
+                was_plaintext: false,
+                server_guid: None,
+            },
+            body,
+        }
+    }
+
+    fn data_message(body: &str) -> DataMessage {
+        DataMessage {
+            body: Some(body.to_owned()),
+            profile_key: Some(ALICE_KEY.to_vec()),
+            ..Default::default()
+        }
+    }
+
+    fn decode(backend: &mut MockBackend, content: Content) -> Option<(Chat, SignalAction)> {
+        block_on(decode_content(
+            content,
+            backend,
+            &ChatCache::default(),
+            false,
+        ))
+    }
+
+    /// Like [`decode`], with the contacts synced first as when streaming, and whether the
+    /// message just arrived.
+    fn decode_synced(
+        backend: &mut MockBackend,
+        content: Content,
+        live: bool,
+    ) -> Option<(Chat, SignalAction)> {
+        let cache = ChatCache::default();
+        let (mut tx, _rx) = mpsc::channel(100);
+        block_on(sync_contacts(backend, &cache, &mut tx)).unwrap();
+
+        block_on(decode_content(content, backend, &cache, live))
+    }
+
+    #[test]
+    fn message() {
+        let mut backend = backend();
+        let content = content(ALICE, 10, ContentBody::DataMessage(data_message("hi")));
+
+        let Some((chat, SignalAction::Message(message, false))) = decode(&mut backend, content)
+        else {
+            panic!("expected a message");
+        };
+
+        assert_eq!(chat.name(), "Alice");
+        assert_eq!(message.original_body.as_deref(), Some("hi"));
+        assert_eq!(message.timestamp.as_millisecond(), 10);
+        assert!(!message.sender.is_self);
+    }
+
+    #[test]
+    fn whitespace() {
+        let mut backend = backend();
+        let content = content(
+            ALICE,
+            10,
+            ContentBody::DataMessage(data_message("\n\nhi  \n\n\n\nthere\n")),
+        );
+
+        let Some((_, SignalAction::Message(message, false))) = decode(&mut backend, content) else {
+            panic!("expected a message");
+        };
+
+        assert_eq!(message.original_body.as_deref(), Some("hi\n\nthere"));
+    }
+
+    #[test]
+    fn invalid_time() {
+        let mut backend = backend();
+        let content = content(
+            ALICE,
+            u64::MAX,
+            ContentBody::DataMessage(data_message("hi")),
+        );
+
+        let Some((_, SignalAction::Message(message, _))) = decode(&mut backend, content) else {
+            panic!("expected a message");
+        };
+
+        assert_eq!(message.timestamp, Timestamp::MAX);
+        assert!(message.has_invalid_time());
+
+        // another one from the same sender isn't taken for the same message
+        let mut with_guid = |guid| {
+            let mut content = content(
+                ALICE,
+                u64::MAX,
+                ContentBody::DataMessage(data_message("hi")),
+            );
+            content.metadata.server_guid = Some(Uuid::from_u128(guid));
+
+            let Some((_, SignalAction::Message(message, _))) = decode(&mut backend, content) else {
+                panic!("expected a message");
+            };
+            message.id()
+        };
+        assert_ne!(with_guid(10), with_guid(11));
+
+        // what refers to a message by an invalid timestamp is dropped
+        let content = content(
+            ALICE,
+            20,
+            ContentBody::DataMessage(DataMessage {
+                body: None,
+                reaction: Some(Reaction {
+                    emoji: Some("👍".to_owned()),
+                    target_sent_timestamp: Some(u64::MAX),
+                    ..Default::default()
+                }),
+                ..data_message("")
+            }),
+        );
+        assert!(decode(&mut backend, content).is_none());
+    }
+
+    #[test]
+    fn unnamed_contact() {
+        let alice = ServiceId::Aci(ALICE.into());
+        let mut backend = backend()
+            .with_contact(alice, ALICE_KEY, "")
+            .with_phone(alice, "+4915123456789");
+        let content = content(ALICE, 10, ContentBody::DataMessage(data_message("hi")));
+
+        let Some((chat, SignalAction::Message(..))) = decode(&mut backend, content) else {
+            panic!("expected a message");
+        };
+
+        assert_eq!(chat.name(), "Unknown (+49 151 2345 6789)");
+    }
+
+    #[test]
+    fn group_message() {
+        let mut backend = backend();
+        let content = content(
+            ALICE,
+            10,
+            ContentBody::DataMessage(DataMessage {
+                group_v2: Some(GroupContextV2 {
+                    master_key: Some(GROUP_KEY.to_vec()),
+                    revision: Some(0),
+                    group_change: None,
+                }),
+                ..data_message("hi")
+            }),
+        );
+
+        let Some((Chat::Group(group), SignalAction::Message(message, _))) =
+            decode(&mut backend, content)
+        else {
+            panic!("expected a group message");
+        };
+
+        assert_eq!(group.title, "Group");
+        assert_eq!(group.members.len(), 1);
+        assert_eq!(message.sender.name, "Alice");
+    }
+
+    #[test]
+    fn left_group() {
+        let mut backend = backend();
+        let stored = backend.groups.remove(&GROUP_KEY).unwrap();
+        let content = content(
+            ALICE,
+            10,
+            ContentBody::DataMessage(DataMessage {
+                group_v2: Some(GroupContextV2 {
+                    master_key: Some(GROUP_KEY.to_vec()),
+                    revision: Some(0),
+                    group_change: None,
+                }),
+                ..data_message("hi")
+            }),
+        );
+        let cache = ChatCache::default();
+        let decode = |backend: &mut MockBackend| {
+            block_on(decode_content(content.clone(), backend, &cache, false))
+        };
+
+        // a group nothing was stored of is nobody's business
+        assert!(decode(&mut backend).is_none());
+
+        backend.messages.insert(
+            Thread::Group(GROUP_KEY),
+            vec![content.clone(), content.clone()],
+        );
+
+        let Some((Chat::Group(group), SignalAction::Message(..))) = decode(&mut backend) else {
+            panic!("expected a group message");
+        };
+
+        assert_eq!(group.title, "Former group");
+        assert!(!group.is_member());
+
+        // once the group is stored again, it's known by its title
+        backend.groups.insert(GROUP_KEY, stored);
+
+        let Some((Chat::Group(group), _)) = decode(&mut backend) else {
+            panic!("expected a group message");
+        };
+
+        assert_eq!(group.title, "Group");
+    }
+
+    #[test]
+    fn message_info() {
+        let mut backend = backend();
+        let content = content(
+            ALICE,
+            10,
+            ContentBody::DataMessage(DataMessage {
+                body_ranges: vec![BodyRange {
+                    start: Some(0),
+                    length: Some(2),
+                    associated_value: Some(AssociatedValue::Style(Style::Bold as i32)),
+                }],
+                ..data_message("hi")
+            }),
+        );
+
+        let Some((_, SignalAction::Message(message, _))) = decode(&mut backend, content) else {
+            panic!("expected a message");
+        };
+
+        let info = message.info();
+        assert!(info.contains(&format!("Sender         {ALICE}, device 1")));
+        assert!(info.contains("Sealed sender  no"));
+        assert!(info.contains("Body ranges    0..2 bold"));
+    }
+
+    #[test]
+    fn group_change() {
+        let mut backend = backend();
+        let cache = ChatCache::default();
+        let update = |revision, group_change, body: Option<&str>| {
+            content(
+                ALICE,
+                u64::from(revision) + 10,
+                ContentBody::DataMessage(DataMessage {
+                    body: body.map(ToOwned::to_owned),
+                    group_v2: Some(GroupContextV2 {
+                        master_key: Some(GROUP_KEY.to_vec()),
+                        revision: Some(revision),
+                        group_change,
+                    }),
+                    ..data_message("")
+                }),
+            )
+        };
+
+        block_on(decode_content(
+            update(0, None, Some("hi")),
+            &mut backend,
+            &cache,
+            false,
+        ))
+        .unwrap();
+
+        let group = backend.groups.get_mut(&GROUP_KEY).unwrap();
+        group.title = "Renamed".to_owned();
+        group.revision = 1;
+
+        let Some((Chat::Group(group), SignalAction::System(message))) = block_on(decode_content(
+            update(1, Some(vec![]), None),
+            &mut backend,
+            &cache,
+            false,
+        )) else {
+            panic!("expected a group update");
+        };
+
+        assert_eq!(group.title, "Renamed");
+        assert_eq!(
+            message.system,
+            Some(SystemMessage::Group(
+                "Alice renamed the group to \"Renamed\"".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn receipt() {
+        let mut backend = backend();
+        let content = content(
+            ALICE,
+            30,
+            ContentBody::ReceiptMessage(ReceiptMessage {
+                r#type: Some(receipt_message::Type::Read as i32),
+                timestamp: vec![10, 20],
+            }),
+        );
+
+        let Some((chat, SignalAction::Receipt(receipt))) =
+            decode_synced(&mut backend, content, false)
+        else {
+            panic!("expected a receipt");
+        };
+
+        assert_eq!(chat.uuid(), Some(ALICE));
+        assert_eq!(receipt.kind, ReceiptKind::Read);
+        assert_eq!(
+            receipt.timestamps,
+            [10, 20].map(|timestamp| Timestamp::from_millisecond(timestamp).unwrap())
+        );
+        assert_eq!(receipt.at.as_millisecond(), 30);
+    }
+
+    #[test]
+    fn call() {
+        let mut backend = backend();
+        let content = content(
+            ALICE,
+            30,
+            ContentBody::CallMessage(CallMessage {
+                offer: Some(call_message::Offer {
+                    r#type: Some(call_message::offer::Type::OfferVideoCall as i32),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+        );
+
+        let Some((chat, SignalAction::System(message))) =
+            decode_synced(&mut backend, content, false)
+        else {
+            panic!("expected a call");
+        };
+
+        assert_eq!(chat.uuid(), Some(ALICE));
+        assert_eq!(
+            message.system.as_ref().unwrap().to_string(),
+            "Alice started a video call, answer it on your phone"
+        );
+    }
+
+    #[test]
+    fn typing() {
+        let mut backend = backend();
+        let typing = |group_id| {
+            content(
+                ALICE,
+                30,
+                ContentBody::TypingMessage(TypingMessage {
+                    timestamp: Some(30),
+                    action: Some(typing_message::Action::Started as i32),
+                    group_id,
+                }),
+            )
+        };
+
+        let Some((chat, SignalAction::Typing(contact, true, at))) =
+            decode_synced(&mut backend, typing(None), true)
+        else {
+            panic!("expected typing");
+        };
+        assert_eq!(chat.uuid(), Some(ALICE));
+        assert_eq!(contact.name, "Alice");
+        assert_eq!(at.as_millisecond(), 30);
+
+        let group_id = GroupSecretParams::derive_from_master_key(GroupMasterKey::new(GROUP_KEY))
+            .get_group_identifier()
+            .to_vec();
+        let Some((Chat::Group(group), SignalAction::Typing(..))) =
+            decode_synced(&mut backend, typing(Some(group_id)), true)
+        else {
+            panic!("expected typing in the group");
+        };
+        assert_eq!(group.key, GROUP_KEY);
+    }
+
+    #[test]
+    fn timer() {
+        let mut backend = backend();
+        let timer = |expire_timer| {
+            content(
+                ALICE,
+                20,
+                ContentBody::DataMessage(DataMessage {
+                    body: None,
+                    flags: Some(Flags::ExpirationTimerUpdate as u32),
+                    expire_timer: Some(expire_timer),
+                    ..data_message("")
+                }),
+            )
+        };
+
+        let Some((_, SignalAction::System(message))) = decode(&mut backend, timer(8 * 60 * 60))
+        else {
+            panic!("expected a timer update");
+        };
+
+        assert_eq!(
+            message.system.as_ref().unwrap().to_string(),
+            "Alice set messages to disappear after 8 hours"
+        );
+
+        let Some((_, SignalAction::System(message))) = decode(&mut backend, timer(0)) else {
+            panic!("expected a timer update");
+        };
+
+        assert_eq!(
+            message.system.as_ref().unwrap().to_string(),
+            "Alice turned off disappearing messages"
+        );
+    }
+
+    #[test]
+    fn same_millisecond() {
+        let at = |millisecond| Timestamp::from_millisecond(millisecond).unwrap();
+        let alice = MessageId::new(at(10), ServiceId::Aci(ALICE.into()));
+        let bob = MessageId::new(at(10), ServiceId::Aci(Uuid::from_u128(1).into()));
+
+        assert_ne!(alice, bob);
+        assert!(MessageId::first_at(at(10)) < bob);
+        assert!(alice < MessageId::first_at(at(11)));
+    }
+
+    #[test]
+    fn pending() {
+        let at = Timestamp::from_millisecond(10).unwrap();
+        let me = Arc::new(contact(ME, "Me"));
+        let first = Message::pending(at, me.clone(), None, None);
+        let second = Message::pending(at, me.clone(), None, None);
+
+        assert_ne!(first.id(), second.id());
+        assert_ne!(first.id(), message(at, me, None).id());
+        assert_eq!(first.id().timestamp, at);
+    }
+
+    #[test]
+    fn delete() {
+        let mut backend = backend();
+        let content = content(
+            ALICE,
+            20,
+            ContentBody::DataMessage(DataMessage {
+                delete: Some(Delete {
+                    target_sent_timestamp: Some(10),
+                }),
+                ..data_message("")
+            }),
+        );
+
+        let Some((_, SignalAction::Delete(target))) = decode(&mut backend, content) else {
+            panic!("expected a delete");
+        };
+
+        assert_eq!(target.timestamp.as_millisecond(), 10);
+        assert_eq!(target.sender, ALICE);
+    }
+
+    #[test]
+    fn reaction() {
+        let mut backend = backend();
+        let reaction = |remove| {
+            content(
+                ALICE,
+                20,
+                ContentBody::DataMessage(DataMessage {
+                    body: None,
+                    reaction: Some(Reaction {
+                        emoji: Some("👍".to_owned()),
+                        remove: Some(remove),
+                        target_author_aci: Some(ALICE.to_string()),
+                        target_sent_timestamp: Some(10),
+                        ..Default::default()
+                    }),
+                    ..data_message("")
+                }),
+            )
+        };
+
+        let Some((_, SignalAction::React(target, sender, emoji, _))) =
+            decode(&mut backend, reaction(false))
+        else {
+            panic!("expected a reaction");
+        };
+
+        assert_eq!(target.timestamp.as_millisecond(), 10);
+        assert_eq!(target.sender, ALICE);
+        assert_eq!(emoji.as_deref(), Some("👍"));
+
+        let mut message = message(target.timestamp, sender.clone(), None);
+        assert_eq!(message.id(), target);
+        message.react(sender.clone(), emoji);
+        message.react(sender.clone(), Some("❤️".to_owned()));
+        assert_eq!(message.reactions, [(sender.clone(), "❤️".to_owned())]);
+
+        let Some((_, SignalAction::React(_, _, None, _))) = decode(&mut backend, reaction(true))
+        else {
+            panic!("expected a removed reaction");
+        };
+
+        message.react(sender, None);
+        assert!(message.reactions.is_empty());
+    }
+
+    #[test]
+    fn attachment_description() {
+        let attachment = |file_name: Option<&str>, width, height| {
+            Attachment::new(AttachmentPointer {
+                content_type: Some("image/jpeg".to_owned()),
+                file_name: file_name.map(str::to_owned),
+                size: Some(123_456),
+                width: Some(width),
+                height: Some(height),
+                ..Default::default()
+            })
+        };
+
+        assert_eq!(
+            attachment(Some("beach.jpg"), 1600, 1200).description(),
+            "beach.jpg, 123.5 kB, 1600×1200"
+        );
+        assert_eq!(attachment(None, 0, 0).description(), "image/jpeg, 123.5 kB");
+    }
+
+    #[test]
+    fn blurhash_placeholder() {
+        let ptr = |blur_hash: &str| AttachmentPointer {
+            content_type: Some("image/jpeg".to_owned()),
+            blur_hash: Some(blur_hash.to_owned()),
+            width: Some(1600),
+            height: Some(1200),
+            ..Default::default()
+        };
+
+        let decoded = |blur_hash| {
+            let mut attachment = Attachment::new(ptr(blur_hash));
+            assert!(attachment.placeholder.is_none());
+            attachment.decode_placeholder();
+            attachment.placeholder
+        };
+
+        assert!(decoded("LEHV6nWB2yk8pyo0adR*.7kCMdnj").is_some());
+        assert!(decoded("not a blurhash").is_none());
+
+        let mut tall = Attachment::new(AttachmentPointer {
+            height: Some(u32::MAX),
+            ..ptr("LEHV6nWB2yk8pyo0adR*.7kCMdnj")
+        });
+        tall.decode_placeholder();
+        assert!(tall.placeholder.is_some());
+    }
+
+    #[test]
+    fn edit() {
+        let mut backend = backend();
+        let content = content(
+            ALICE,
+            20,
+            ContentBody::EditMessage(EditMessage {
+                target_sent_timestamp: Some(10),
+                data_message: Some(data_message("edited")),
+            }),
+        );
+
+        let Some((chat, SignalAction::Replace(target, message))) = decode(&mut backend, content)
+        else {
+            panic!("expected a replacement");
+        };
+
+        assert_eq!(target.timestamp.as_millisecond(), 10);
+        assert_eq!(target.sender, ALICE);
+        assert_eq!(message.original_body.as_deref(), Some("edited"));
+        assert_eq!(*backend.deleted.borrow(), [(chat.thread(), 10)]);
+    }
+
+    #[test]
+    fn edited_in_history() {
+        let mut backend = backend();
+        let thread = Thread::Group(GROUP_KEY);
+        backend.messages.insert(
+            thread.clone(),
+            vec![
+                content(ALICE, 10, ContentBody::DataMessage(data_message("hi"))),
+                content(ME, 10, ContentBody::DataMessage(data_message("hello"))),
+                content(
+                    ALICE,
+                    20,
+                    ContentBody::EditMessage(EditMessage {
+                        target_sent_timestamp: Some(10),
+                        data_message: Some(data_message("hi!")),
+                    }),
+                ),
+            ],
+        );
+
+        let contents = block_on(thread_contents(&backend, &thread));
+        assert_eq!(
+            contents
+                .iter()
+                .map(|content| (content.metadata.sender, content.metadata.timestamp))
+                .collect::<Vec<_>>(),
+            [
+                (ServiceId::Aci(ME.into()), 10),
+                (ServiceId::Aci(ALICE.into()), 20)
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_sender() {
+        let mut backend = backend();
+        let content = content(
+            Uuid::from_u128(4),
+            10,
+            ContentBody::DataMessage(data_message("hi")),
+        );
+
+        assert!(decode(&mut backend, content).is_none());
+    }
 
-    Some(cache.borrow()[&chat].clone())
+    #[test]
+    fn about() {
+        let alice = ServiceId::Aci(ALICE.into());
+        let mut backend = backend().with_about(alice, "🌱", "Gardening");
+        let cache = ChatCache::default();
+        let (mut tx, _rx) = mpsc::channel(100);
+
+        block_on(sync_contacts(&mut backend, &cache, &mut tx)).unwrap();
+
+        let contact = cache.contact(alice).unwrap();
+        assert_eq!(contact.about.as_deref(), Some("Gardening"));
+        assert_eq!(contact.about_emoji.as_deref(), Some("🌱"));
+    }
+
+    #[test]
+    fn refresh() {
+        let mut backend = backend();
+        let cache = ChatCache::default();
+        let (mut tx, mut rx) = mpsc::channel(100);
+        let alice = ServiceId::Aci(ALICE.into());
+
+        block_on(sync_contacts(&mut backend, &cache, &mut tx)).unwrap();
+        backend.profiles.get_mut(&alice).unwrap().1 = "Alicia".to_owned();
+        block_on(refresh_profiles(&mut backend, &cache, &mut tx)).unwrap();
+
+        assert_eq!(cache.contact(alice).unwrap().name, "Alicia");
+
+        let renamed =
+            iter::from_fn(|| rx.try_next().ok().flatten()).find_map(|(_, action)| match action {
+                SignalAction::System(message) => message.system.clone(),
+                _ => None,
+            });
+        assert_eq!(
+            renamed,
+            Some(SystemMessage::Renamed {
+                old: "Alice".to_owned(),
+                new: "Alicia".to_owned(),
+            })
+        );
+    }
 }
@@ -1,16 +1,34 @@
-use super::{Chat, Message, Quote};
+use super::{Attachment, Chat, Message, Quote, Sending, SystemMessage};
 use crate::{
     app,
-    icons::{edit, reply},
-    widget::SignalRich,
+    avatars::Avatars,
+    icons::{Icon as _, discard, edit, reply, retry},
+    image::avatar_size,
+    locale::locale,
+    widget::{Expandable, SignalRich, Skeleton},
 };
 use iced::{
-    Alignment, Element, Fill, Shrink,
+    Alignment, ContentFit, Element, Fill, Shrink,
     border::{self, radius},
-    widget::{button, column, container, image, row, space, text, text::Wrapping},
+    mouse::Interaction,
+    widget::{
+        button, center, column, container, grid, image, mouse_area, row, space, stack, text,
+        text::Wrapping, tooltip, tooltip::Position,
+    },
 };
-use jiff::{Span, Unit, Zoned, fmt::friendly::SpanPrinter, tz::TimeZone};
-use std::sync::Arc;
+use jiff::{Span, Timestamp, Unit, Zoned, fmt::friendly::SpanPrinter, tz::TimeZone};
+use std::{cell::RefCell, collections::HashMap, sync::Arc};
+
+/// Roughly 30 lines of text at the default text size.
+const COLLAPSED_HEIGHT: f32 = 30.0 * 16.0 * 1.3;
+
+/// The maximum number of thumbnails shown in a message's image grid.
+const GRID_SIZE: usize = 4;
+
+thread_local! {
+    /// The labels of the timestamps shown, for the time they were formatted at.
+    static LABELS: RefCell<(Option<Zoned>, HashMap<Timestamp, String>)> = RefCell::default();
+}
 
 impl Chat {
     pub fn as_iced_widget(&self) -> Element<'_, app::Message> {
@@ -25,24 +43,34 @@ impl Chat {
             Self::Group(group) => &group.title,
         };
 
-        row![
-            match self {
-                Self::Contact(contact) => contact.avatar.clone(),
-                Self::Group(group) => group.avatar.clone(),
-            }
-            .map(|handle| image(handle).height(50).border_radius(25)),
-            space::horizontal(),
-            text(name)
-        ]
-        .align_y(Alignment::Center)
-        .height(Shrink)
-        .into()
+        let picture = if self.is_self() {
+            center(edit().sized(24.0).tinted(|t| t.palette().primary.weak.text))
+                .width(avatar_size())
+                .height(avatar_size())
+                .style(|t: &iced::Theme| container::Style {
+                    background: Some(t.palette().primary.weak.color.into()),
+                    border: border::rounded(avatar_size() / 2.),
+                    ..Default::default()
+                })
+                .into()
+        } else {
+            avatar(match self {
+                Self::Contact(contact) => contact.avatar.as_ref(),
+                Self::Group(group) => group.avatar.as_ref(),
+            })
+        };
+
+        row![picture, space::horizontal(), text(name)]
+            .align_y(Alignment::Center)
+            .height(Shrink)
+            .into()
     }
 }
 
 impl Quote {
     pub fn as_iced_widget(&self, now: &Zoned, tz: &TimeZone) -> Element<'_, app::Message> {
-        let timestamp = format_zoned(&self.timestamp.to_zoned(tz.clone()), now);
+        let zoned = self.timestamp.to_zoned(tz.clone());
+        let timestamp = format_zoned(&zoned, now);
 
         let head = self
             .sender
@@ -53,7 +81,7 @@ impl Quote {
 
         let content = row![
             column![
-                text(head).size(10),
+                header(head, &zoned),
                 self.body.as_deref().map(|body| {
                     if body.len() == 1 && body[0].is_simple_text() {
                         Element::from(body[0].as_text_widget().wrapping(Wrapping::WordOrGlyph))
@@ -61,15 +89,14 @@ impl Quote {
                         Element::from(
                             SignalRich::new()
                                 .with_spans(body)
-                                .wrapping(Wrapping::WordOrGlyph),
+                                .wrapping(Wrapping::WordOrGlyph)
+                                .on_link_hover(app::Message::LinkHovered)
+                                .on_link_click(app::Message::LinkClicked),
                         )
                     }
                 })
             ],
-            self.attachments
-                .first()
-                .and_then(|image| image.image.clone())
-                .map(|handle| container(image(handle)).max_height(50)),
+            self.attachments.first().map(Attachment::as_quoted),
         ]
         .align_y(Alignment::Center)
         .spacing(5);
@@ -95,42 +122,81 @@ impl Message {
         now: &Zoned,
         tz: &TimeZone,
         max_width: f32,
+        avatars: &Avatars,
+        sealed_sender_icons: bool,
     ) -> Element<'_, app::Message> {
-        let timestamp = format_zoned(&self.timestamp.to_zoned(tz.clone()), now);
+        let zoned = self.timestamp.to_zoned(tz.clone());
+        let timestamp = format_zoned(&zoned, now);
+
+        if let Some(system) = &self.system {
+            return system.as_iced_widget(&zoned, &timestamp);
+        }
+
+        let head = match self.sending {
+            Some(Sending { failed: false, .. }) => self.sender.name.clone() + ", ⏳ sending…",
+            Some(Sending { failed: true, .. }) => self.sender.name.clone() + ", ⚠ not sent",
+            None if self.has_invalid_time() => self.sender.name.clone() + ", invalid time",
+            None if sealed_sender_icons && self.is_sealed_sender() => {
+                self.sender.name.clone() + ", 🔒 " + &timestamp
+            }
+            None => self.sender.name.clone() + ", " + &timestamp,
+        };
 
-        let head = self.sender.name.clone() + ", " + &timestamp;
+        // a message that wasn't sent can't be edited, replied to, or looked into
+        let sent = self.sending.is_none();
+        let failed = self.sending.is_some_and(|sending| sending.failed);
 
         let content = column![
             self.quote
                 .as_ref()
                 .map(|quote| quote.as_iced_widget(now, tz)),
-            (!self.attachments.is_empty()).then(|| column(
-                self.attachments
-                    .iter()
-                    .filter_map(|attachment| attachment.image.clone())
-                    .map(|handle| image(handle).width(max_width / 2.).into()),
-            )),
+            self.images()
+                .next()
+                .is_some()
+                .then(|| self.gallery(max_width / 2.)),
             column![
-                text(head).size(10),
+                header(head, &zoned),
+                // even simple text is rich, so what's selected in it can be quoted
                 self.body.as_deref().map(|body| {
-                    if body.len() == 1 && body[0].is_simple_text() {
-                        Element::from(body[0].as_text_widget().wrapping(Wrapping::WordOrGlyph))
-                    } else {
-                        Element::from(
-                            SignalRich::new()
-                                .with_spans(body)
-                                .wrapping(Wrapping::WordOrGlyph)
-                                .on_mention_click(app::Message::Mention),
-                        )
-                    }
+                    Expandable::new(
+                        SignalRich::new()
+                            .with_spans(body)
+                            .wrapping(Wrapping::WordOrGlyph)
+                            .on_link_hover(app::Message::LinkHovered)
+                            .on_link_click(app::Message::LinkClicked)
+                            .on_mention_click(app::Message::Mention)
+                            .revealed_spoilers(&self.revealed_spoilers)
+                            .on_spoiler_reveal(|tag| app::Message::RevealSpoiler(self.clone(), tag))
+                            .on_selection(|selected| {
+                                app::Message::Selected(self.clone(), selected)
+                            }),
+                    )
+                    .max_height(COLLAPSED_HEIGHT)
                 })
-            ]
+            ],
+            (!self.attachments.is_empty()).then(|| {
+                column(self.attachments.iter().map(|attachment| {
+                    row![
+                        text(attachment.description())
+                            .size(12)
+                            .style(text::secondary)
+                            .width(Fill),
+                        button(text("Open").size(12))
+                            .padding([2, 6])
+                            .style(button::secondary)
+                            .on_press(app::Message::OpenAttachment(attachment.clone())),
+                    ]
+                    .spacing(5)
+                    .align_y(Alignment::Center)
+                    .into()
+                }))
+            }),
         ]
         .spacing(10);
 
         let content = container(content)
             .max_width(if self.attachments.is_empty() {
-                max_width - 5. * 4. - 2. * (16. + 10.) - 50.
+                max_width - 5. * 4. - 2. * (16. + 10.) - avatar_size()
             } else {
                 max_width / 2. + 10.
             })
@@ -143,22 +209,58 @@ impl Message {
                         radius(15).top_left(5)
                     })
                 })
-            })
-            .into();
+            });
+
+        // double clicking a message reacts to it, unless the click selects a word of its text,
+        // which captures it
+        let content: Element<'_, app::Message> = if sent {
+            mouse_area(content)
+                .on_double_click(app::Message::QuickReact(self.clone()))
+                .into()
+        } else {
+            content.into()
+        };
+
+        let content =
+            column![content, self.reactions()]
+                .spacing(2)
+                .align_x(if self.sender.is_self {
+                    Alignment::End
+                } else {
+                    Alignment::Start
+                });
 
         let mut buttons = [
-            self.sender.is_self.then(|| {
+            (sent && self.sender.is_self).then(|| {
+                button(text("ⓘ").size(14))
+                    .style(button::text)
+                    .padding(5)
+                    .on_press(app::Message::ShowDetails(self.clone()))
+            }),
+            (sent && self.sender.is_self).then(|| {
                 button(edit())
                     .style(button::text)
                     .padding(5)
                     .on_press(app::Message::Edit(Some(self.clone())))
             }),
-            Some(
+            sent.then(|| {
                 button(reply())
                     .style(button::text)
                     .padding(5)
-                    .on_press(app::Message::Quote(Some(self.clone()))),
-            ),
+                    .on_press(app::Message::Quote(Some(self.clone())))
+            }),
+            failed.then(|| {
+                button(retry())
+                    .style(button::text)
+                    .padding(5)
+                    .on_press(app::Message::RetryUnsent(self.id()))
+            }),
+            failed.then(|| {
+                button(discard())
+                    .style(button::text)
+                    .padding(5)
+                    .on_press(app::Message::DiscardUnsent(self.id()))
+            }),
         ];
 
         if self.sender.is_self {
@@ -166,10 +268,7 @@ impl Message {
         }
 
         let mut items = [
-            self.sender
-                .avatar
-                .clone()
-                .map(|handle| image(handle).height(50).border_radius(25).into()),
+            Some(avatar(avatars.get(&self.sender))),
             Some(content),
             Some(
                 row(buttons.into_iter().flatten().map(Element::from))
@@ -192,8 +291,256 @@ impl Message {
     }
 }
 
+impl SystemMessage {
+    pub fn as_iced_widget<'a>(&self, zoned: &Zoned, timestamp: &str) -> Element<'a, app::Message> {
+        container(tooltip(
+            text!("{self}, {timestamp}")
+                .size(12)
+                .center()
+                .style(text::secondary),
+            container(text(format_full(zoned)).size(12))
+                .padding(5)
+                .style(container::rounded_box),
+            Position::Top,
+        ))
+        .center_x(Fill)
+        .padding(5)
+        .into()
+    }
+}
+
+impl Message {
+    /// The emoji reacted with, each with how many reacted with it, in the order they were
+    /// first used. Clicking one reacts with it too, or takes our reaction back.
+    fn reactions(self: &Arc<Self>) -> Option<Element<'_, app::Message>> {
+        if self.reactions.is_empty() {
+            return None;
+        }
+
+        let mut counts: Vec<(&str, usize, bool)> = vec![];
+
+        for (reactor, emoji) in &self.reactions {
+            match counts.iter_mut().find(|(counted, ..)| counted == emoji) {
+                Some((_, count, ours)) => {
+                    *count += 1;
+                    *ours |= reactor.is_self;
+                }
+                None => counts.push((emoji, 1, reactor.is_self)),
+            }
+        }
+
+        Some(
+            row(counts.into_iter().map(|(emoji, count, ours)| {
+                button(if count == 1 {
+                    text(emoji).size(14)
+                } else {
+                    text!("{emoji} {count}").size(14)
+                })
+                .style(if ours {
+                    button::primary
+                } else {
+                    button::secondary
+                })
+                .padding([2, 6])
+                .on_press(app::Message::React(self.clone(), emoji.to_owned()))
+                .into()
+            }))
+            .spacing(5)
+            .into(),
+        )
+    }
+
+    fn gallery(self: &Arc<Self>, width: f32) -> Element<'_, app::Message> {
+        let count = self.images().count();
+        let open = |index, content| open_lightbox(self, index, content);
+
+        if self.images().any(|attachment| attachment.image.is_none()) {
+            let size = self.images().map(|attachment| attachment.ptr.size()).sum();
+
+            return stack![
+                if count == 1 {
+                    self.images().next().unwrap().as_iced_widget(width)
+                } else {
+                    Skeleton::new(width, width * 0.75).into()
+                },
+                center(
+                    button(text!("⤓ {}", format_size(size.into())))
+                        .style(button::secondary)
+                        .on_press(app::Message::DownloadAttachments(self.clone()))
+                )
+            ]
+            .into();
+        }
+
+        if count == 1 {
+            let attachment = self.images().next().unwrap();
+            return open(0, attachment.as_iced_widget(width));
+        }
+
+        let cell = (width - 5.) / 2.;
+        let overflow = count.saturating_sub(GRID_SIZE);
+
+        grid(
+            self.images()
+                .take(GRID_SIZE)
+                .enumerate()
+                .map(|(index, attachment)| {
+                    let thumbnail = attachment.as_thumbnail(cell);
+
+                    if overflow != 0 && index == GRID_SIZE - 1 {
+                        open(
+                            index,
+                            stack![
+                                thumbnail,
+                                center(text!("+{overflow}").size(24)).style(|t: &iced::Theme| {
+                                    container::Style {
+                                        background: Some(
+                                            t.palette()
+                                                .background
+                                                .base
+                                                .color
+                                                .scale_alpha(0.6)
+                                                .into(),
+                                        ),
+                                        ..Default::default()
+                                    }
+                                })
+                            ]
+                            .into(),
+                        )
+                    } else {
+                        open(index, thumbnail)
+                    }
+                }),
+        )
+        .columns(2)
+        .spacing(5)
+        .width(width)
+        .into()
+    }
+}
+
+impl Attachment {
+    pub fn as_iced_widget<'a>(&self, width: f32) -> Element<'a, app::Message> {
+        if let Some(handle) = self.image.as_ref().map(|image| image.thumbnail.clone()) {
+            return image(handle).width(width).into();
+        }
+
+        let height = if self.ptr.width() > 0 && self.ptr.height() > 0 {
+            width * self.ptr.height() as f32 / self.ptr.width() as f32
+        } else {
+            width * 0.75
+        };
+
+        // the blur takes the image's place at its size, so nothing moves once it's downloaded
+        match &self.placeholder {
+            Some(placeholder) => image(placeholder.clone())
+                .width(width)
+                .height(height)
+                .content_fit(ContentFit::Fill)
+                .into(),
+            None => Skeleton::new(width, height).into(),
+        }
+    }
+
+    /// The small preview of a quoted attachment, which is a placeholder until an image was
+    /// downloaded.
+    fn as_quoted<'a>(&self) -> Element<'a, app::Message> {
+        match self.image.as_ref() {
+            Some(loaded) => container(image(loaded.thumbnail.clone()))
+                .max_height(50)
+                .into(),
+            None if self.mime.type_() == mime::IMAGE => {
+                Skeleton::new(50, 50).animated(false).into()
+            }
+            None => text("📄").size(24).into(),
+        }
+    }
+
+    pub fn as_thumbnail<'a>(&self, size: f32) -> Element<'a, app::Message> {
+        self.image
+            .as_ref()
+            .map(|image| image.thumbnail.clone())
+            .or_else(|| self.placeholder.clone())
+            .map_or_else(
+                || Skeleton::new(size, size).into(),
+                |handle| {
+                    image(handle)
+                        .width(size)
+                        .height(size)
+                        .content_fit(ContentFit::Cover)
+                        .into()
+                },
+            )
+    }
+}
+
+fn open_lightbox<'a>(
+    message: &Arc<Message>,
+    index: usize,
+    content: Element<'a, app::Message>,
+) -> Element<'a, app::Message> {
+    mouse_area(content)
+        .on_press(app::Message::OpenLightbox(message.clone(), index))
+        .interaction(Interaction::Pointer)
+        .into()
+}
+
+fn avatar<'a>(handle: Option<&image::Handle>) -> Element<'a, app::Message> {
+    handle.cloned().map_or_else(
+        || {
+            Skeleton::new(avatar_size(), avatar_size())
+                .border_radius(avatar_size() / 2.)
+                .animated(false)
+                .into()
+        },
+        |handle| crate::image::avatar(handle, avatar_size()).into(),
+    )
+}
+
+fn header<'a>(head: String, timestamp: &Zoned) -> Element<'a, app::Message> {
+    tooltip(
+        text(head).size(10),
+        container(text(format_full(timestamp)).size(12))
+            .padding(5)
+            .style(container::rounded_box),
+        Position::Top,
+    )
+    .into()
+}
+
+fn format_full(timestamp: &Zoned) -> String {
+    locale().full(timestamp)
+}
+
+pub fn format_size(bytes: u64) -> String {
+    match bytes {
+        0..1_000 => format!("{bytes} B"),
+        1_000..1_000_000 => format!("{} kB", locale().decimal(bytes as f64 / 1e3)),
+        _ => format!("{} MB", locale().decimal(bytes as f64 / 1e6)),
+    }
+}
+
+/// Formats a timestamp relative to now, reusing the labels formatted since now last changed.
 fn format_zoned(timestamp: &Zoned, now: &Zoned) -> String {
-    if timestamp.date() == now.date() {
+    LABELS.with_borrow_mut(|(labeled_at, labels)| {
+        if labeled_at.as_ref() != Some(now) {
+            *labeled_at = Some(now.clone());
+            labels.clear();
+        }
+
+        labels
+            .entry(timestamp.timestamp())
+            .or_insert_with(|| format_relative(timestamp, now))
+            .clone()
+    })
+}
+
+fn format_relative(timestamp: &Zoned, now: &Zoned) -> String {
+    // now only moves on every minute, so newer messages can be ahead of it
+    if timestamp >= now {
+        "now".to_owned()
+    } else if timestamp.date() == now.date() {
         let diff = timestamp.since(now).unwrap().round(Unit::Minute).unwrap();
 
         if diff.is_zero() {
@@ -202,8 +549,8 @@ fn format_zoned(timestamp: &Zoned, now: &Zoned) -> String {
             SpanPrinter::new().span_to_string(&diff)
         }
     } else if timestamp.date() == now.date() - Span::new().days(1) {
-        timestamp.strftime("yesterday at %H:%M").to_string()
+        format!("yesterday at {}", locale().time(timestamp))
     } else {
-        timestamp.strftime("%d.%m.%Y at %H:%M").to_string()
+        locale().date_time(timestamp)
     }
 }
@@ -0,0 +1,321 @@
+use crate::{config::NotificationBackend, log::warn, platform};
+#[cfg(all(unix, not(target_os = "macos")))]
+use ashpd::desktop::notification::{Notification as PortalNotification, NotificationProxy};
+#[cfg(all(unix, not(target_os = "macos")))]
+use iced::futures::TryStreamExt as _;
+use iced::futures::{Stream, StreamExt as _, future, stream};
+#[cfg(all(unix, not(target_os = "macos")))]
+use notify_rust::Hint;
+use notify_rust::Notification;
+use std::fmt::{self, Display, Formatter};
+#[cfg(all(unix, not(target_os = "macos")))]
+use std::sync::atomic::{AtomicU32, Ordering};
+#[cfg(all(unix, not(target_os = "macos")))]
+use tokio::sync::OnceCell;
+use tokio::task::spawn_blocking;
+#[cfg(all(unix, not(target_os = "macos")))]
+use zbus::{MatchRule, MessageStream, fdo::DBusProxy, message::Type};
+
+/// The action of the notification servers offering a text field to answer in, like Plasma's.
+#[cfg(all(unix, not(target_os = "macos")))]
+const INLINE_REPLY: &str = "inline-reply";
+
+/// The next id of a notification shown through the portal, which leaves picking them to us.
+#[cfg(all(unix, not(target_os = "macos")))]
+static NEXT_PORTAL_ID: AtomicU32 = AtomicU32::new(1);
+
+/// The session bus connection listening for replies to notifications, shared by all of them.
+#[cfg(all(unix, not(target_os = "macos")))]
+static SESSION: OnceCell<zbus::Connection> = OnceCell::const_new();
+
+/// What became of a notification shown with [`Notifier::show_new`].
+#[derive(Clone, Debug)]
+pub enum Shown {
+    /// The notification is shown, with the id to replace it with where that's supported.
+    Id(Option<u32>),
+    /// The notification with the id was closed, maybe with a reply typed into it.
+    Closed(u32, Option<String>),
+}
+
+/// Where notifications are shown, picked once on startup.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Notifier {
+    /// The notification server on the session bus, or the system's notification center, and
+    /// whether it lets the user answer in the notification.
+    Desktop {
+        inline_reply: bool,
+    },
+    /// The XDG desktop portal, the only way out of some sandboxes.
+    Portal,
+    None,
+}
+
+impl Notifier {
+    /// Resolves the configured backend, looking for a notification server when it's left to us.
+    pub async fn detect(backend: NotificationBackend) -> Self {
+        match backend {
+            NotificationBackend::Desktop => Self::Desktop {
+                inline_reply: supports_inline_reply().await,
+            },
+            NotificationBackend::Portal => Self::Portal,
+            NotificationBackend::None => Self::None,
+            NotificationBackend::Auto if platform::is_sandboxed() => Self::Portal,
+            NotificationBackend::Auto => detect_server().await,
+        }
+    }
+
+    /// Shows a notification in place of the one with the id `replaces`, returning the id to
+    /// replace it with in turn where that's supported.
+    pub async fn show(self, summary: String, body: String, replaces: Option<u32>) -> Option<u32> {
+        match self {
+            Self::Desktop { inline_reply } => {
+                spawn_blocking(move || show_desktop(&summary, &body, replaces, inline_reply))
+                    .await
+                    .ok()
+                    .flatten()
+            }
+            Self::Portal => show_portal(&summary, &body, replaces).await,
+            Self::None => None,
+        }
+    }
+
+    /// Shows a new notification, and then waits for the text typed into it until it's closed.
+    /// The replies are listened for before it's shown, so an early one isn't missed.
+    pub fn show_new(self, summary: String, body: String) -> impl Stream<Item = Shown> {
+        stream::once(async move {
+            let replies = if self == (Self::Desktop { inline_reply: true }) {
+                Replies::subscribe().await
+            } else {
+                None
+            };
+
+            (self.show(summary, body, None).await, replies)
+        })
+        .flat_map(|(id, replies)| {
+            let closed = async move {
+                let id = id?;
+                Some(Shown::Closed(id, replies?.wait(id).await))
+            };
+
+            stream::once(future::ready(Shown::Id(id)))
+                .chain(stream::once(closed).filter_map(future::ready))
+        })
+    }
+}
+
+impl Display for Notifier {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Desktop {
+                inline_reply: false,
+            } => "the desktop notification server",
+            Self::Desktop { inline_reply: true } => {
+                "the desktop notification server, with inline replies"
+            }
+            Self::Portal => "the XDG desktop portal",
+            Self::None => "nowhere",
+        })
+    }
+}
+
+/// notify-rust fails silently on every notification without a server, so it's only used when
+/// one answers.
+#[cfg(all(unix, not(target_os = "macos")))]
+async fn detect_server() -> Notifier {
+    match spawn_blocking(notify_rust::get_server_information).await {
+        Ok(Ok(_)) => Notifier::Desktop {
+            inline_reply: supports_inline_reply().await,
+        },
+        _ => Notifier::Portal,
+    }
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+async fn detect_server() -> Notifier {
+    Notifier::Desktop {
+        inline_reply: false,
+    }
+}
+
+/// Whether the notification server implements Plasma's inline replies, the only ones on the
+/// session bus. Toasts can't be answered through notify-rust.
+#[cfg(all(unix, not(target_os = "macos")))]
+async fn supports_inline_reply() -> bool {
+    spawn_blocking(notify_rust::get_capabilities)
+        .await
+        .is_ok_and(|capabilities| {
+            capabilities.is_ok_and(|capabilities| {
+                capabilities
+                    .iter()
+                    .any(|capability| capability == INLINE_REPLY)
+            })
+        })
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+async fn supports_inline_reply() -> bool {
+    false
+}
+
+fn show_desktop(
+    summary: &str,
+    body: &str,
+    replaces: Option<u32>,
+    #[cfg_attr(not(all(unix, not(target_os = "macos"))), expect(unused_variables))]
+    inline_reply: bool,
+) -> Option<u32> {
+    let mut notification = Notification::new();
+    notification.summary(summary).body(body);
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    if inline_reply {
+        notification
+            .action(INLINE_REPLY, "Reply")
+            .hint(Hint::Custom(
+                "x-kde-reply-placeholder-text".to_owned(),
+                "Reply…".to_owned(),
+            ));
+    }
+
+    if let Some(id) = replaces {
+        notification.id(id);
+    }
+
+    match notification.show() {
+        #[cfg(all(unix, not(target_os = "macos")))]
+        Ok(handle) => Some(handle.id()),
+        #[cfg(not(all(unix, not(target_os = "macos"))))]
+        Ok(_) => None,
+        Err(err) => {
+            warn!("{err}");
+            None
+        }
+    }
+}
+
+/// The signals of the notification server, received from subscribing on.
+#[cfg(all(unix, not(target_os = "macos")))]
+struct Replies(MessageStream);
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+enum Replies {}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+impl Replies {
+    async fn subscribe() -> Option<Self> {
+        match session().await {
+            Ok(connection) => Some(Self(MessageStream::from(connection))),
+            Err(err) => {
+                warn!("{err}");
+                None
+            }
+        }
+    }
+
+    async fn wait(self, id: u32) -> Option<String> {
+        match replied(self.0, id).await {
+            Ok(reply) => reply,
+            Err(err) => {
+                warn!("{err}");
+                None
+            }
+        }
+    }
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+impl Replies {
+    async fn subscribe() -> Option<Self> {
+        None
+    }
+
+    async fn wait(self, _id: u32) -> Option<String> {
+        match self {}
+    }
+}
+
+/// The shared session bus connection, connected and told which signals to pass on the first
+/// time it's needed.
+#[cfg(all(unix, not(target_os = "macos")))]
+async fn session() -> zbus::Result<&'static zbus::Connection> {
+    const INTERFACE: &str = "org.freedesktop.Notifications";
+
+    SESSION
+        .get_or_try_init(|| async {
+            let connection = zbus::Connection::session().await?;
+            let proxy = DBusProxy::new(&connection).await?;
+
+            for member in ["NotificationReplied", "NotificationClosed"] {
+                let rule = MatchRule::builder()
+                    .msg_type(Type::Signal)
+                    .interface(INTERFACE)?
+                    .member(member)?
+                    .build();
+                proxy.add_match_rule(rule).await?;
+            }
+
+            Ok(connection)
+        })
+        .await
+}
+
+/// The reply typed into the notification with the id `id`, or none once it's closed.
+#[cfg(all(unix, not(target_os = "macos")))]
+async fn replied(mut messages: MessageStream, id: u32) -> zbus::Result<Option<String>> {
+    while let Some(message) = messages.try_next().await? {
+        let header = message.header();
+        if header.message_type() != Type::Signal {
+            continue;
+        }
+
+        match header.member().map(|member| member.as_str()) {
+            Some("NotificationReplied") => {
+                if let Ok((replied, text)) = message.body().deserialize::<(u32, String)>()
+                    && replied == id
+                {
+                    return Ok(Some(text));
+                }
+            }
+            Some("NotificationClosed") => {
+                if let Ok((closed, _)) = message.body().deserialize::<(u32, u32)>()
+                    && closed == id
+                {
+                    return Ok(None);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+async fn show_portal(summary: &str, body: &str, replaces: Option<u32>) -> Option<u32> {
+    // the portal replaces the notification with the same id
+    let id = replaces.unwrap_or_else(|| NEXT_PORTAL_ID.fetch_add(1, Ordering::Relaxed));
+
+    let shown = async {
+        NotificationProxy::new()
+            .await?
+            .add_notification(
+                &format!("foghorn-{id}"),
+                PortalNotification::new(summary).body(body),
+            )
+            .await
+    };
+
+    match shown.await {
+        Ok(()) => Some(id),
+        Err(err) => {
+            warn!("{err}");
+            None
+        }
+    }
+}
+
+#[cfg(not(all(unix, not(target_os = "macos"))))]
+async fn show_portal(_summary: &str, _body: &str, _replaces: Option<u32>) -> Option<u32> {
+    warn!("The XDG desktop portal is only available on Linux and the BSDs");
+    None
+}
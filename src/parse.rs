@@ -1,5 +1,6 @@
 use crate::{
-    message::Chat,
+    cache::ChatCache,
+    config::Config,
     widget::{
         SignalSpan,
         text::span::{BOLD, ITALIC, MENTION, MONOSPACE, SPOILER, STRIKETHROUGH},
@@ -11,204 +12,456 @@ use presage::{
         BodyRange,
         body_range::{AssociatedValue, Style},
     },
-    store::Thread,
 };
-use std::{cell::RefCell, collections::HashMap, mem::take};
+use std::{collections::HashMap, fmt::Write as _, mem::take, ops::Range, sync::OnceLock};
+
+/// The characters markers are made of, which can be escaped with a backslash.
+const MARKER_CHARS: [char; 6] = ['*', '|', '~', '`', '[', '\\'];
+
+/// The character a mention stands in for in a message body.
+const MENTION_PLACEHOLDER: char = '\u{fffc}';
+
+/// The beginnings of the links found in messages.
+const URL_SCHEMES: [&str; 6] = [
+    "https://", "http://", "sgnl://", "mailto:", "tel:", "file://",
+];
+
+static BLANK_LINES: OnceLock<Option<usize>> = OnceLock::new();
 
 /// bold: **text**
 /// italic:  *text*
 /// spoiler: ||text||
 /// strikethrough: ~~text~~
 /// monospace: `text`
+/// mention: [@name](mention:aci)
 /// escaping: \*test\*
+///
+/// Like in other markdown, a marker can only open a range when it's followed by something other
+/// than whitespace, and only close one when it follows something other than whitespace. Markers
+/// of the same style may be nested, and ranges of different styles may overlap. Markers that are
+/// never closed are kept as text.
 pub fn markdown_to_body_ranges(input: &str) -> (String, Vec<BodyRange>) {
-    let mut bold = None;
-    let mut italic = None;
-    let mut spoiler = None;
-    let mut strikethrough = None;
-    let mut monospace = None;
-
-    let mut iter = input.chars().peekable();
+    let chars = input.chars().collect::<Vec<_>>();
 
     let mut output = String::new();
-    let mut count = 0; // count codepoints, not bytes
+    let mut count = 0; // count codepoints, not bytes, until converting to UTF-16 at the end
     let mut ranges = vec![];
 
-    let mut push_range = |count, pos, style| {
-        ranges.push(BodyRange {
-            start: Some(pos),
-            length: Some(count - pos),
-            associated_value: Some(AssociatedValue::Style(style as i32)),
-        });
-    };
+    // the ranges opened so far, innermost last, with where they start in the output in
+    // codepoints and in bytes
+    let mut open = Vec::<(Style, u32, usize)>::new();
+    let is_open = |open: &[(Style, u32, usize)], style| open.iter().any(|(s, ..)| *s == style);
 
-    while let Some(ch) = iter.next() {
-        match ch {
-            '*' if iter.next_if_eq(&'*').is_some() => {
-                // we are starting or ending a bold range
+    let mut i = 0;
 
-                if let Some(bold) = bold.take() {
-                    push_range(count, bold, Style::Bold);
-                } else {
-                    bold = Some(count);
-                }
-            }
-            '*' => {
-                // we are starting or ending an italic range
+    while let Some(&ch) = chars.get(i) {
+        if ch == '\\'
+            && let Some(&escaped) = chars.get(i + 1)
+            && MARKER_CHARS.contains(&escaped)
+        {
+            // we are escaping a character
 
-                if let Some(italic) = italic.take() {
-                    push_range(count, italic, Style::Italic);
-                } else {
-                    italic = Some(count);
-                }
-            }
-            '|' if iter.next_if_eq(&'|').is_some() => {
-                // we are starting or ending a spoiler range
+            output.push(escaped);
+            count += 1;
+            i += 2;
+            continue;
+        }
 
-                if let Some(spoiler) = spoiler.take() {
-                    push_range(count, spoiler, Style::Spoiler);
-                } else {
-                    spoiler = Some(count);
-                }
-            }
-            '~' if iter.next_if_eq(&'~').is_some() => {
-                // we are starting or ending a strikethrough range
+        if ch == '['
+            && let Some((aci, consumed)) = parse_mention(&chars[i..])
+        {
+            ranges.push(BodyRange {
+                start: Some(count),
+                length: Some(1),
+                associated_value: Some(AssociatedValue::MentionAci(aci.to_string())),
+            });
 
-                if let Some(strikethrough) = strikethrough.take() {
-                    push_range(count, strikethrough, Style::Strikethrough);
-                } else {
-                    strikethrough = Some(count);
-                }
-            }
-            '`' => {
-                // we are starting or ending a monospace range
+            output.push(MENTION_PLACEHOLDER);
+            count += 1;
+            i += consumed;
+            continue;
+        }
 
-                if let Some(monospace) = monospace.take() {
-                    push_range(count, monospace, Style::Monospace);
-                } else {
-                    monospace = Some(count);
-                }
+        if !matches!(ch, '*' | '|' | '~' | '`') {
+            output.push(ch);
+            count += 1;
+            i += 1;
+            continue;
+        }
+
+        // we are at a run of marker characters, which may close and open several ranges
+
+        let run = chars[i..].iter().take_while(|&&c| c == ch).count();
+        let can_close = i.checked_sub(1).is_some_and(|j| !chars[j].is_whitespace());
+        let can_open = chars.get(i + run).is_some_and(|c| !c.is_whitespace());
+        i += run;
+
+        let mut left = run;
+
+        let mut close = |open: &mut Vec<(Style, u32, usize)>, style| {
+            let index = open.iter().rposition(|(s, ..)| *s == style).unwrap();
+            let (_, start, _) = open.remove(index);
+
+            ranges.push(BodyRange {
+                start: Some(start),
+                length: Some(count - start),
+                associated_value: Some(AssociatedValue::Style(style as i32)),
+            });
+        };
+
+        if can_close {
+            loop {
+                let style = match ch {
+                    // an odd run closes italic first, so *** closes both italic and bold
+                    '*' if left % 2 == 1 && is_open(&open, Style::Italic) => Style::Italic,
+                    '*' if left >= 2 && is_open(&open, Style::Bold) => Style::Bold,
+                    '|' if left >= 2 && is_open(&open, Style::Spoiler) => Style::Spoiler,
+                    '~' if left >= 2 && is_open(&open, Style::Strikethrough) => {
+                        Style::Strikethrough
+                    }
+                    '`' if left >= 1 && is_open(&open, Style::Monospace) => Style::Monospace,
+                    _ => break,
+                };
+
+                close(&mut open, style);
+                left -= marker(style).chars().count();
             }
-            '\\' if matches!(iter.peek(), Some(&'*' | &'|' | &'~' | &'`' | &'\\')) => {
-                // we are escaping a character
+        }
 
-                output.push(iter.next().unwrap());
-                count += 1;
+        if can_open {
+            loop {
+                let style = match ch {
+                    '*' if left >= 2 => Style::Bold,
+                    '*' if left >= 1 => Style::Italic,
+                    '|' if left >= 2 => Style::Spoiler,
+                    '~' if left >= 2 => Style::Strikethrough,
+                    '`' if left >= 1 => Style::Monospace,
+                    _ => break,
+                };
+
+                open.push((style, count, output.len()));
+                left -= marker(style).chars().count();
             }
-            _ => {
-                output.push(ch);
-                count += 1;
+        }
+
+        // whatever is left of the run is text
+        for _ in 0..left {
+            output.push(ch);
+            count += 1;
+        }
+    }
+
+    // put back the markers of ranges that were never closed, from the last to the first, so
+    // the positions of the earlier ones stay valid
+    for (style, pos, byte) in open.into_iter().rev() {
+        let marker = marker(style);
+        let diff = marker.chars().count() as u32;
+
+        output.insert_str(byte, marker);
+
+        for range in &mut ranges {
+            if range.start() >= pos {
+                range.start = Some(range.start() + diff);
+            } else if range.start() + range.length() > pos {
+                range.length = Some(range.length() + diff);
             }
         }
     }
 
-    // we skipped characters that are part of unfinished spans
-    // re-insert those, and update spans accordingly
+    let mut ranges = normalize(ranges);
+    ranges_to_utf16(&output, &mut ranges);
+
+    (output, ranges)
+}
+
+/// Parses a mention like `[@name](mention:aci)` at the start of the chars, returning the
+/// mentioned ACI and how many chars the mention takes up.
+fn parse_mention(chars: &[char]) -> Option<(Uuid, usize)> {
+    const TARGET: &str = "](mention:";
+
+    let rest = chars.strip_prefix(&['[', '@'])?.iter().collect::<String>();
+    let name_end = rest.find(TARGET)?;
+    let (aci, _) = rest[name_end + TARGET.len()..].split_once(')')?;
+
+    let consumed =
+        2 + rest[..name_end].chars().count() + TARGET.chars().count() + aci.chars().count() + 1;
+
+    Some((aci.parse().ok()?, consumed))
+}
+
+fn marker(style: Style) -> &'static str {
+    match style {
+        Style::Bold => "**",
+        Style::Italic => "*",
+        Style::Spoiler => "||",
+        Style::Strikethrough => "~~",
+        Style::Monospace => "`",
+        Style::None => "",
+    }
+}
 
-    let mut insert_at = |nth, str| {
-        let i = output
-            .char_indices()
-            .nth(nth as usize)
-            .map_or(count as usize, |i| i.0);
-        output.insert_str(i, str);
+/// Brings style ranges into a canonical order, merging the ranges of a style that overlap or
+/// touch, and dropping empty ones. Spoilers that only touch stay apart, since they're revealed
+/// separately.
+fn normalize(ranges: Vec<BodyRange>) -> Vec<BodyRange> {
+    let (mut styles, other) = ranges
+        .into_iter()
+        .filter(|range| range.length() != 0)
+        .partition::<Vec<_>, _>(|range| {
+            matches!(range.associated_value, Some(AssociatedValue::Style(_)))
+        });
 
-        let diff = str.chars().count() as u32;
-        count += diff;
-        diff
+    let style = |range: &BodyRange| match range.associated_value {
+        Some(AssociatedValue::Style(style)) => style,
+        _ => 0,
     };
 
-    let update_pos = |last: Option<&mut _>, pos, diff| {
-        if let Some(last) = last
-            && *last > pos
+    styles.sort_by_key(|range| (style(range), range.start(), range.length()));
+
+    let mut merged = Vec::<BodyRange>::with_capacity(styles.len());
+
+    for range in styles {
+        if let Some(last) = merged.last_mut()
+            && style(last) == style(&range)
         {
-            *last += diff;
-        }
-    };
+            let last_end = last.start() + last.length();
+            let touches = if style(&range) == Style::Spoiler as i32 {
+                range.start() < last_end
+            } else {
+                range.start() <= last_end
+            };
 
-    let mut update_ranges = |pos, diff| {
-        for range in &mut ranges {
-            if range.start() >= pos {
-                range.start = range.start.map(|start| start + diff);
-            } else if range.start() + range.length() > pos {
-                range.length = range.length.map(|length| length + diff);
+            if touches {
+                let end = last_end.max(range.start() + range.length());
+                last.length = Some(end - last.start());
+                continue;
             }
         }
-    };
 
-    if let Some(bold) = bold.take() {
-        let diff = insert_at(bold, "**");
-        update_pos(italic.as_mut(), bold, diff);
-        update_pos(spoiler.as_mut(), bold, diff);
-        update_pos(strikethrough.as_mut(), bold, diff);
-        update_pos(monospace.as_mut(), bold, diff);
-        update_ranges(bold, diff);
+        merged.push(range);
     }
 
-    if let Some(italic) = italic.take() {
-        let diff = insert_at(italic, "*");
-        update_pos(spoiler.as_mut(), italic, diff);
-        update_pos(strikethrough.as_mut(), italic, diff);
-        update_pos(monospace.as_mut(), italic, diff);
-        update_ranges(italic, diff);
+    merged.sort_by_key(|range| (range.start(), style(range)));
+    merged.extend(other);
+    merged
+}
+
+pub fn init(config: &Config) {
+    _ = BLANK_LINES.set(config.blank_lines);
+}
+
+/// Tidies up the whitespace of a received body as configured, see [`collapse_whitespace`].
+pub fn normalize_whitespace(
+    body: Option<String>,
+    body_ranges: Vec<BodyRange>,
+) -> (Option<String>, Vec<BodyRange>) {
+    let blank_lines = BLANK_LINES
+        .get()
+        .copied()
+        .unwrap_or_else(|| Config::default().blank_lines);
+
+    match (body, blank_lines) {
+        (Some(body), Some(blank_lines)) => {
+            let (body, body_ranges) = collapse_whitespace(&body, &body_ranges, blank_lines + 1);
+            (Some(body).filter(|body| !body.is_empty()), body_ranges)
+        }
+        (body, _) => (body, body_ranges),
     }
+}
 
-    if let Some(spoiler) = spoiler.take() {
-        let diff = insert_at(spoiler, "||");
-        update_pos(strikethrough.as_mut(), spoiler, diff);
-        update_pos(monospace.as_mut(), spoiler, diff);
-        update_ranges(spoiler, diff);
+/// Removes the whitespace at the end of every line and of the whole body, the blank lines at
+/// its start, and newlines beyond `max_newlines` in a row, moving the body ranges along.
+pub fn collapse_whitespace(
+    body: &str,
+    body_ranges: &[BodyRange],
+    max_newlines: usize,
+) -> (String, Vec<BodyRange>) {
+    let chars = body.chars().collect::<Vec<_>>();
+    let mut keep = vec![true; chars.len()];
+
+    // whitespace at the end of lines
+    let mut at_line_end = true;
+    for (i, c) in chars.iter().enumerate().rev() {
+        if *c == '\n' {
+            at_line_end = true;
+        } else if at_line_end && c.is_whitespace() {
+            keep[i] = false;
+        } else {
+            at_line_end = false;
+        }
+    }
+
+    // newlines in a row, with the blank lines between them emptied above
+    let mut newlines = 0;
+    for (i, c) in chars.iter().enumerate() {
+        if !keep[i] {
+            continue;
+        }
+
+        if *c == '\n' {
+            newlines += 1;
+            keep[i] = newlines <= max_newlines;
+        } else {
+            newlines = 0;
+        }
     }
 
-    if let Some(strikethrough) = strikethrough.take() {
-        let diff = insert_at(strikethrough, "~~");
-        update_pos(monospace.as_mut(), strikethrough, diff);
-        update_ranges(strikethrough, diff);
+    // blank lines at the start and the end
+    let kept = |i: &usize| keep[*i];
+    let newline = |i: &usize| chars[*i] == '\n';
+    let leading = (0..chars.len()).filter(kept).take_while(newline);
+    let trailing = (0..chars.len()).rev().filter(kept).take_while(newline);
+    for i in leading.chain(trailing).collect::<Vec<_>>() {
+        keep[i] = false;
     }
 
-    if let Some(monospace) = monospace.take() {
-        let diff = insert_at(monospace, "`");
-        update_ranges(monospace, diff);
+    let mut output = String::with_capacity(body.len());
+    // where every UTF-16 offset of the body ends up
+    let mut offsets = Vec::with_capacity(body.len() + 1);
+    let mut offset = 0;
+
+    for (c, keep) in chars.iter().zip(keep) {
+        offsets.extend(std::iter::repeat_n(offset, c.len_utf16()));
+
+        if keep {
+            output.push(*c);
+            offset += c.len_utf16() as u32;
+        }
     }
 
-    ranges.retain(|range| range.length() != 0);
+    offsets.push(offset);
+
+    let moved = |offset: u32| offsets[(offset as usize).min(offsets.len() - 1)];
+    let ranges = body_ranges
+        .iter()
+        .map(|range| {
+            let start = moved(range.start());
+            let end = moved(range.start().saturating_add(range.length()));
+
+            BodyRange {
+                start: Some(start),
+                length: Some(end - start),
+                ..range.clone()
+            }
+        })
+        .filter(|range| range.length() != 0)
+        .collect();
 
     (output, ranges)
 }
 
+/// Shrinks a style range to leave out the whitespace at its ends, where markers can't open or
+/// close it.
+fn trim_whitespace(chars: &[char], mut range: BodyRange) -> BodyRange {
+    if !matches!(range.associated_value, Some(AssociatedValue::Style(_))) {
+        return range;
+    }
+
+    let mut start = range.start() as usize;
+    let mut end = start + range.length() as usize;
+
+    while start < end && chars[start].is_whitespace() {
+        start += 1;
+    }
+
+    while end > start && chars[end - 1].is_whitespace() {
+        end -= 1;
+    }
+
+    range.start = Some(start as u32);
+    range.length = Some((end - start) as u32);
+    range
+}
+
+/// The offset in UTF-16 code units of every char of a text, followed by its length.
+fn utf16_offsets(text: &str) -> Vec<u32> {
+    let mut offsets = Vec::with_capacity(text.len() + 1);
+    let mut offset = 0;
+
+    for c in text.chars() {
+        offsets.push(offset);
+        offset += c.len_utf16() as u32;
+    }
+
+    offsets.push(offset);
+    offsets
+}
+
+/// Converts body ranges counting chars into ones counting UTF-16 code units, as Signal's do.
+fn ranges_to_utf16(text: &str, ranges: &mut [BodyRange]) {
+    let offsets = utf16_offsets(text);
+    let offset = |index: u32| offsets[(index as usize).min(offsets.len() - 1)];
+
+    for range in ranges {
+        let start = offset(range.start());
+        let end = offset(range.start() + range.length());
+
+        range.start = Some(start);
+        range.length = Some(end - start);
+    }
+}
+
+/// Converts Signal's body ranges, counting UTF-16 code units, into ones counting chars. Ranges
+/// reaching past the end of the text are cut off.
+fn ranges_from_utf16(text: &str, ranges: &[BodyRange]) -> Vec<BodyRange> {
+    let offsets = utf16_offsets(text);
+    let index = |offset: u32| {
+        offsets
+            .partition_point(|&o| o < offset)
+            .min(offsets.len() - 1) as u32
+    };
+
+    ranges
+        .iter()
+        .map(|range| {
+            let start = index(range.start());
+            let end = index(range.start().saturating_add(range.length()));
+
+            BodyRange {
+                start: Some(start),
+                length: Some(end - start),
+                ..range.clone()
+            }
+        })
+        .filter(|range| range.length() != 0)
+        .collect()
+}
+
 pub fn body_ranges_to_signal_spans(
     body: Option<&str>,
     body_ranges: &[BodyRange],
-    cache: &RefCell<HashMap<Thread, Chat>>,
-) -> Option<Vec<SignalSpan<'static>>> {
+    cache: &ChatCache,
+) -> Option<Vec<SignalSpan<'static, String>>> {
+    body_ranges_to_signal_spans_with(body, body_ranges, |id| {
+        cache.contact(id).map(|contact| contact.name.clone())
+    })
+}
+
+/// Like [`body_ranges_to_signal_spans`], looking up the names of mentioned contacts with
+/// `name`. Mentions of unknown contacts are left out.
+pub fn body_ranges_to_signal_spans_with(
+    body: Option<&str>,
+    body_ranges: &[BodyRange],
+    name: impl Fn(ServiceId) -> Option<String>,
+) -> Option<Vec<SignalSpan<'static, String>>> {
     let body = body.filter(|body| !body.is_empty())?;
+    let body_ranges = ranges_from_utf16(body, body_ranges);
 
     let mut flags = vec![0u8; body.chars().count()];
     let mut mentions = HashMap::new();
     let mut spoiler_tags = HashMap::new();
     let mut next_spoiler_tag = 0;
 
-    for range in body_ranges {
+    for range in &body_ranges {
         let start = range.start() as usize;
         let end = start + range.length() as usize;
 
-        let mut mention: Option<ServiceId> = None;
+        let mention = mentioned(range);
         let Some(style_flag) = range
             .associated_value
             .as_ref()
             .and_then(|value| match value {
-                AssociatedValue::MentionAci(aci) => {
-                    mention = aci
-                        .parse::<Uuid>()
-                        .ok()
-                        .map(|aci| ServiceId::Aci(aci.into()));
-
-                    Some(0)
-                }
-                AssociatedValue::MentionAciBinary(aci) => {
-                    mention = ServiceId::parse_from_service_id_binary(aci);
-
-                    Some(0)
-                }
+                AssociatedValue::MentionAci(_) | AssociatedValue::MentionAciBinary(_) => Some(0),
                 AssociatedValue::Style(style @ 1..=5) => Some(*style),
                 AssociatedValue::Style(_) => None,
             })
@@ -223,11 +476,7 @@ pub fn body_ranges_to_signal_spans(
         }
 
         if let Some(id) = mention
-            && let Some(name) = cache
-                .borrow()
-                .get(&Thread::Contact(id))?
-                .contact()
-                .map(|contact| contact.name.clone())
+            && let Some(name) = name(id)
         {
             mentions.insert(start, (id.raw_uuid(), name));
         }
@@ -275,21 +524,136 @@ pub fn body_ranges_to_signal_spans(
             .spoiler_tag_maybe(spoiler_tag),
     );
 
-    Some(spans)
+    Some(spans.into_iter().flat_map(split_links).collect())
+}
+
+/// The contact a body range mentions, if it's a mention.
+pub fn mentioned(range: &BodyRange) -> Option<ServiceId> {
+    match range.associated_value.as_ref()? {
+        AssociatedValue::MentionAci(aci) => aci
+            .parse::<Uuid>()
+            .ok()
+            .map(|aci| ServiceId::Aci(aci.into())),
+        AssociatedValue::MentionAciBinary(aci) => ServiceId::parse_from_service_id_binary(aci),
+        AssociatedValue::Style(_) => None,
+    }
+}
+
+/// Splits the urls in a span out into their own link spans.
+fn split_links(span: SignalSpan<'static, String>) -> Vec<SignalSpan<'static, String>> {
+    if span.mention() {
+        return vec![span];
+    }
+
+    let urls = find_urls(&span.text);
+
+    if urls.is_empty() {
+        return vec![span];
+    }
+
+    let piece = |range: Range<usize>, link: Option<&str>| SignalSpan {
+        text: span.text[range].to_owned().into(),
+        link: link.map(ToOwned::to_owned),
+        ..span.clone()
+    };
+
+    let mut spans = vec![];
+    let mut last_end = 0;
+
+    for url in urls {
+        if url.start > last_end {
+            spans.push(piece(last_end..url.start, None));
+        }
+
+        spans.push(piece(url.clone(), Some(&span.text[url.clone()])));
+        last_end = url.end;
+    }
+
+    if last_end < span.text.len() {
+        spans.push(piece(last_end..span.text.len(), None));
+    }
+
+    spans
+}
+
+/// Finds the byte ranges of the urls in the text, starting with one of the [`URL_SCHEMES`].
+pub fn find_urls(text: &str) -> Vec<Range<usize>> {
+    let mut urls = vec![];
+    let mut offset = 0;
+
+    while let Some((start, scheme)) = URL_SCHEMES
+        .into_iter()
+        .filter_map(|scheme| Some((text[offset..].find(scheme)? + offset, scheme)))
+        .min_by_key(|(start, _)| *start)
+    {
+        let rest = &text[start..];
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        offset = start + end.max(1);
+
+        if text[..start]
+            .chars()
+            .next_back()
+            .is_some_and(char::is_alphanumeric)
+        {
+            continue;
+        }
+
+        let mut url = &rest[..end];
+
+        // trailing punctuation is almost always part of the sentence, not the url,
+        // except for closing parentheses that match one inside the url
+        while let Some(last) = url.chars().next_back() {
+            let unbalanced = last == ')' && url.matches('(').count() < url.matches(')').count();
+
+            if unbalanced || matches!(last, '.' | ',' | ';' | ':' | '!' | '?' | '\'' | '"' | '>') {
+                url = &url[..url.len() - last.len_utf8()];
+            } else {
+                break;
+            }
+        }
+
+        if url.len() > scheme.len() {
+            urls.push(start..start + url.len());
+        }
+    }
+
+    urls
 }
 
 pub fn body_ranges_to_markdown(body: Option<&str>, body_ranges: &[BodyRange]) -> Option<String> {
+    body_ranges_to_markdown_with(body, body_ranges, |_| None)
+}
+
+/// Like [`body_ranges_to_markdown`], looking up the names of mentioned contacts with `name`.
+/// Mentions of unknown contacts are named by their ACI.
+pub fn body_ranges_to_markdown_with(
+    body: Option<&str>,
+    body_ranges: &[BodyRange],
+    name: impl Fn(ServiceId) -> Option<String>,
+) -> Option<String> {
     let body = body.filter(|body| !body.is_empty())?;
+    let chars = body.chars().collect::<Vec<_>>();
+    let body_ranges = normalize(
+        ranges_from_utf16(body, body_ranges)
+            .into_iter()
+            .map(|range| trim_whitespace(&chars, range))
+            .collect(),
+    );
 
     let mut range_starts = HashMap::new();
     let mut range_ends = HashMap::new();
+    let mut mentions = HashMap::new();
 
     let mut output = String::new();
 
-    for range in body_ranges {
+    for range in &body_ranges {
         let start = range.start() as usize;
         let end = start + range.length() as usize;
 
+        if let Some(id) = mentioned(range) {
+            mentions.insert(start, (id, end));
+        }
+
         let Some(style_flag) = range
             .associated_value
             .as_ref()
@@ -335,16 +699,30 @@ pub fn body_ranges_to_markdown(body: Option<&str>, body_ranges: &[BodyRange]) ->
         }
     };
 
-    for (i, ch) in body.chars().enumerate() {
+    let mut mention_end = 0;
+
+    for (i, ch) in chars.iter().copied().enumerate() {
         if let Some(flag) = range_starts.get(&i) {
             flag_to_markdown(&mut output, *flag, true);
         }
 
-        if matches!(ch, '|' | '~' | '*' | '`' | '\\') {
-            output.push('\\');
-        }
+        if let Some((id, end)) = mentions.get(&i) {
+            let aci = id.raw_uuid();
+            let name = name(*id).unwrap_or_else(|| aci.to_string());
+
+            _ = write!(output, "[@{}](mention:{aci})", name.replace("](", "] ("));
+            mention_end = *end;
+        } else if i < mention_end {
+            // the placeholder of the mention
+        } else {
+            let is_mention = ch == '[' && chars.get(i + 1) == Some(&'@');
 
-        output.push(ch);
+            if matches!(ch, '|' | '~' | '*' | '`' | '\\') || is_mention {
+                output.push('\\');
+            }
+
+            output.push(ch);
+        }
 
         if let Some(flag) = range_ends.get(&i) {
             flag_to_markdown(&mut output, *flag, false);
@@ -354,6 +732,78 @@ pub fn body_ranges_to_markdown(body: Option<&str>, body_ranges: &[BodyRange]) ->
     Some(output)
 }
 
+/// Writes spans back as markdown, like the text of a message was typed. Mentions are written
+/// with the names they're shown with.
+pub fn signal_spans_to_markdown<Link>(spans: &[SignalSpan<'_, Link>]) -> String {
+    const MARKERS: [(u8, &str); 5] = [
+        (SPOILER, "||"),
+        (STRIKETHROUGH, "~~"),
+        (BOLD, "**"),
+        (ITALIC, "*"),
+        (MONOSPACE, "`"),
+    ];
+
+    let mut output = String::new();
+    let mut open = 0;
+    // markers can't close after whitespace, so the trailing whitespace of a span waits for them
+    let mut pending_whitespace = String::new();
+
+    for span in spans {
+        let text = span.text.trim();
+        if text.is_empty() {
+            pending_whitespace.push_str(&span.text);
+            continue;
+        }
+
+        let flags = span.flags & !MENTION;
+        let leading = &span.text[..span.text.len() - span.text.trim_start().len()];
+        let trailing = &span.text[leading.len() + text.len()..];
+
+        for (flag, marker) in MARKERS.iter().rev() {
+            if open & !flags & flag != 0 {
+                output.push_str(marker);
+            }
+        }
+
+        output += &take(&mut pending_whitespace);
+        output += leading;
+
+        for (flag, marker) in MARKERS {
+            if flags & !open & flag != 0 {
+                output.push_str(marker);
+            }
+        }
+
+        if let Some(uuid) = span.mention {
+            let name = text.strip_prefix('@').unwrap_or(text);
+            _ = write!(output, "[@{}](mention:{uuid})", name.replace("](", "] ("));
+        } else {
+            let mut chars = text.chars().peekable();
+
+            while let Some(ch) = chars.next() {
+                if matches!(ch, '|' | '~' | '*' | '`' | '\\')
+                    || (ch == '[' && chars.peek() == Some(&'@'))
+                {
+                    output.push('\\');
+                }
+
+                output.push(ch);
+            }
+        }
+
+        open = flags;
+        pending_whitespace.push_str(trailing);
+    }
+
+    for (flag, marker) in MARKERS.iter().rev() {
+        if open & flag != 0 {
+            output.push_str(marker);
+        }
+    }
+
+    output + &pending_whitespace
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -412,7 +862,7 @@ mod test {
             },
         ];
 
-        const SIGNAL_SPANS: &[SignalSpan<'_>] = &[
+        const SIGNAL_SPANS: &[SignalSpan<'_, String>] = &[
             SignalSpan {
                 text: Fragment::Borrowed(r"testing "),
                 flags: 0,
@@ -518,14 +968,306 @@ mod test {
         assert_eq!(output, TEXT);
         assert_eq_order_independent(&body_ranges, BODY_RANGES);
 
-        let spans = body_ranges_to_signal_spans(
-            Some(output.as_str()),
+        let spans =
+            body_ranges_to_signal_spans(Some(output.as_str()), &body_ranges, &ChatCache::default())
+                .unwrap();
+
+        assert_eq_order_independent(&spans, SIGNAL_SPANS);
+    }
+
+    #[test]
+    fn other_links() {
+        const TEXT: &str = "call tel:+491234, join sgnl://signal.group/#abc or mailto:a@b.c.";
+
+        let urls = find_urls(TEXT)
+            .into_iter()
+            .map(|url| &TEXT[url])
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            urls,
+            ["tel:+491234", "sgnl://signal.group/#abc", "mailto:a@b.c"]
+        );
+    }
+
+    #[test]
+    fn links() {
+        const TEXT: &str = "see https://example.com/a_(b), or (http://example.org). nothttp://x";
+
+        let urls = find_urls(TEXT)
+            .into_iter()
+            .map(|url| &TEXT[url])
+            .collect::<Vec<_>>();
+
+        assert_eq!(urls, ["https://example.com/a_(b)", "http://example.org"]);
+
+        let spans = body_ranges_to_signal_spans(Some(TEXT), &[], &ChatCache::default()).unwrap();
+
+        let links = spans
+            .iter()
+            .filter_map(|span| Some((span.text.as_ref(), span.link.as_deref()?)))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            links,
+            [
+                ("https://example.com/a_(b)", "https://example.com/a_(b)"),
+                ("http://example.org", "http://example.org")
+            ]
+        );
+        assert_eq!(
+            spans
+                .iter()
+                .map(|span| span.text.as_ref())
+                .collect::<String>(),
+            TEXT
+        );
+    }
+
+    #[test]
+    fn utf16() {
+        const MARKDOWN: &str = "😀 **bold** 𝒳 *it*";
+        const TEXT: &str = "😀 bold 𝒳 it";
+
+        let (output, body_ranges) = markdown_to_body_ranges(MARKDOWN);
+
+        assert_eq!(output, TEXT);
+        assert_eq_order_independent(
             &body_ranges,
-            &RefCell::new(HashMap::new()),
-        )
+            &[
+                BodyRange {
+                    start: Some(3),
+                    length: Some(4),
+                    associated_value: Some(AssociatedValue::Style(Style::Bold as i32)),
+                },
+                BodyRange {
+                    start: Some(11),
+                    length: Some(2),
+                    associated_value: Some(AssociatedValue::Style(Style::Italic as i32)),
+                },
+            ],
+        );
+
+        let spans =
+            body_ranges_to_signal_spans(Some(TEXT), &body_ranges, &ChatCache::default()).unwrap();
+        let styled = spans
+            .iter()
+            .filter(|span| span.flags != 0)
+            .map(|span| (span.text.as_ref(), span.flags))
+            .collect::<Vec<_>>();
+
+        assert_eq!(styled, [("bold", BOLD), ("it", ITALIC)]);
+        assert_eq!(
+            body_ranges_to_markdown(Some(TEXT), &body_ranges).as_deref(),
+            Some(MARKDOWN)
+        );
+    }
+
+    #[test]
+    fn ranges_past_the_end() {
+        let body_ranges = [BodyRange {
+            start: Some(2),
+            length: Some(10),
+            associated_value: Some(AssociatedValue::Style(Style::Bold as i32)),
+        }];
+
+        let spans =
+            body_ranges_to_signal_spans(Some("abc"), &body_ranges, &ChatCache::default()).unwrap();
+
+        assert_eq!(spans.last().map(|span| span.flags), Some(BOLD));
+    }
+
+    #[test]
+    fn mentions() {
+        const TEXT: &str = "hi \u{fffc}!";
+        let bob = Uuid::from_u128(2);
+        let body_ranges = [BodyRange {
+            start: Some(3),
+            length: Some(1),
+            associated_value: Some(AssociatedValue::MentionAci(bob.to_string())),
+        }];
+        let text = |spans: Vec<SignalSpan<'static, String>>| {
+            spans
+                .iter()
+                .map(|span| span.text.as_ref())
+                .collect::<String>()
+        };
+
+        assert_eq!(mentioned(&body_ranges[0]), Some(ServiceId::Aci(bob.into())));
+
+        let unknown = body_ranges_to_signal_spans_with(Some(TEXT), &body_ranges, |_| None);
+        assert_eq!(text(unknown.unwrap()), "hi !");
+
+        let known = body_ranges_to_signal_spans_with(Some(TEXT), &body_ranges, |id| {
+            (id.raw_uuid() == bob).then(|| "Bob".to_owned())
+        })
         .unwrap();
+        assert!(known.iter().any(|span| span.mention == Some(bob)));
+        assert_eq!(text(known), "hi Bob!");
+    }
 
-        assert_eq_order_independent(&spans, SIGNAL_SPANS);
+    #[test]
+    fn markdown_mentions() {
+        let bob = Uuid::from_u128(2);
+        let markdown = format!("hi **[@Bob](mention:{bob})** \\[@x]");
+
+        let (output, body_ranges) = markdown_to_body_ranges(&markdown);
+
+        assert_eq!(output, "hi \u{fffc} [@x]");
+        assert_eq_order_independent(
+            &body_ranges,
+            &[
+                BodyRange {
+                    start: Some(3),
+                    length: Some(1),
+                    associated_value: Some(AssociatedValue::Style(Style::Bold as i32)),
+                },
+                BodyRange {
+                    start: Some(3),
+                    length: Some(1),
+                    associated_value: Some(AssociatedValue::MentionAci(bob.to_string())),
+                },
+            ],
+        );
+
+        let name = |id: ServiceId| (id.raw_uuid() == bob).then(|| "Bob".to_owned());
+        assert_eq!(
+            body_ranges_to_markdown_with(Some(&output), &body_ranges, name),
+            Some(markdown)
+        );
+    }
+
+    #[test]
+    fn nesting() {
+        let cases = [
+            ("**a **b** c**", "a b c", vec![(0, 5, Style::Bold)]),
+            (
+                "**a *b** c*",
+                "a b c",
+                vec![(0, 3, Style::Bold), (2, 3, Style::Italic)],
+            ),
+            ("*a **b**", "*a b", vec![(3, 1, Style::Bold)]),
+            ("a ** b", "a ** b", vec![]),
+            (
+                "***a* b**",
+                "a b",
+                vec![(0, 3, Style::Bold), (0, 1, Style::Italic)],
+            ),
+            (
+                "||a||||b||",
+                "ab",
+                vec![(0, 1, Style::Spoiler), (1, 1, Style::Spoiler)],
+            ),
+        ];
+
+        for (markdown, text, expected) in cases {
+            let (output, body_ranges) = markdown_to_body_ranges(markdown);
+            let expected = expected
+                .into_iter()
+                .map(|(start, length, style)| BodyRange {
+                    start: Some(start),
+                    length: Some(length),
+                    associated_value: Some(AssociatedValue::Style(style as i32)),
+                })
+                .collect::<Vec<_>>();
+
+            assert_eq!(output, text, "{markdown}");
+            assert_eq_order_independent(&body_ranges, &expected);
+        }
+    }
+
+    #[test]
+    fn spans_to_markdown() {
+        const MARKDOWN: &str = r"some **bold *and* italic** ~~gone~~ \*";
+
+        let (body, body_ranges) = markdown_to_body_ranges(MARKDOWN);
+        let spans = body_ranges_to_signal_spans_with(Some(&body), &body_ranges, |_| None).unwrap();
+
+        assert_eq!(signal_spans_to_markdown(&spans), MARKDOWN);
+        assert_eq!(
+            signal_spans_to_markdown(&[SignalSpan::<()>::new("@Alice").set_mention(Uuid::nil())]),
+            format!("[@Alice](mention:{})", Uuid::nil())
+        );
+    }
+
+    #[test]
+    fn whitespace() {
+        let style = |start, length| BodyRange {
+            start: Some(start),
+            length: Some(length),
+            associated_value: Some(AssociatedValue::Style(Style::Bold as i32)),
+        };
+
+        let (body, body_ranges) = collapse_whitespace(
+            "\n \nfirst  \n\n\n\n  second 😀\t\n\n",
+            &[style(3, 6), style(8, 5), style(13, 5), style(23, 3)],
+            2,
+        );
+
+        assert_eq!(body, "first\n\n  second 😀");
+        assert_eq!(
+            body_ranges,
+            [style(0, 5), style(5, 2), style(7, 4), style(16, 2)]
+        );
+    }
+
+    /// Turning random styled text into markdown and back gives the same text and ranges.
+    #[test]
+    fn round_trip() {
+        const CHARS: &[char] = &['a', 'b', ' ', '*', '|', '~', '`', '\\', '😀'];
+        const STYLES: [Style; 5] = [
+            Style::Bold,
+            Style::Italic,
+            Style::Spoiler,
+            Style::Strikethrough,
+            Style::Monospace,
+        ];
+
+        // xorshift, so failures can be reproduced
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let mut random = |bound: usize| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % bound as u64) as usize
+        };
+
+        for _ in 0..10_000 {
+            let text = (0..=random(12))
+                .map(|_| CHARS[random(CHARS.len())])
+                .collect::<String>();
+            let len = text.chars().count();
+
+            let body_ranges = (0..random(4))
+                .map(|_| {
+                    let start = random(len);
+                    BodyRange {
+                        start: Some(start as u32),
+                        length: Some((1 + random(len - start)) as u32),
+                        associated_value: Some(AssociatedValue::Style(
+                            STYLES[random(STYLES.len())] as i32,
+                        )),
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            // what the ranges look like once written as markdown
+            let chars = text.chars().collect::<Vec<_>>();
+            let mut expected = normalize(
+                body_ranges
+                    .into_iter()
+                    .map(|range| trim_whitespace(&chars, range))
+                    .collect(),
+            );
+            ranges_to_utf16(&text, &mut expected);
+            let body_ranges = expected.clone();
+
+            let markdown = body_ranges_to_markdown(Some(&text), &body_ranges).unwrap();
+            let (output, body_ranges) = markdown_to_body_ranges(&markdown);
+
+            assert_eq!(output, text, "{markdown}");
+            assert_eq!(body_ranges, expected, "{markdown}");
+        }
     }
 
     fn assert_eq_order_independent<T: PartialEq>(a: &[T], b: &[T]) {
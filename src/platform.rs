@@ -0,0 +1,93 @@
+#[cfg(all(unix, not(target_os = "macos")))]
+use crate::log::warn;
+#[cfg(all(unix, not(target_os = "macos")))]
+use ashpd::desktop::file_chooser::{OpenFileRequest, SaveFileRequest};
+use rfd::AsyncFileDialog;
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+/// Whether Foghorn runs in a Flatpak or Snap sandbox, where the desktop is only reached through
+/// the XDG desktop portals.
+pub fn is_sandboxed() -> bool {
+    Path::new("/.flatpak-info").exists() || env::var_os("SNAP").is_some()
+}
+
+/// Asks for the files to attach, returning none when cancelled.
+pub async fn pick_files() -> Vec<PathBuf> {
+    #[cfg(all(unix, not(target_os = "macos")))]
+    if is_sandboxed() {
+        return portal_pick_files().await;
+    }
+
+    AsyncFileDialog::new()
+        .set_title("Attach files")
+        .pick_files()
+        .await
+        .unwrap_or_default()
+        .iter()
+        .map(|file| file.path().to_owned())
+        .collect()
+}
+
+/// Asks where to save a file, suggesting `name` in the downloads directory.
+pub async fn save_file(title: &str, name: &str) -> Option<PathBuf> {
+    #[cfg(all(unix, not(target_os = "macos")))]
+    if is_sandboxed() {
+        return portal_save_file(title, name).await;
+    }
+
+    let mut dialog = AsyncFileDialog::new().set_title(title).set_file_name(name);
+
+    if let Some(dir) = dirs::download_dir() {
+        dialog = dialog.set_directory(dir);
+    }
+
+    dialog.save_file().await.map(|file| file.path().to_owned())
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+async fn portal_pick_files() -> Vec<PathBuf> {
+    let picked = async {
+        OpenFileRequest::default()
+            .title("Attach files")
+            .multiple(true)
+            .send()
+            .await?
+            .response()
+    };
+
+    match picked.await {
+        Ok(files) => files
+            .uris()
+            .iter()
+            .filter_map(|uri| uri.to_file_path().ok())
+            .collect(),
+        Err(err) => {
+            warn!("{err}");
+            vec![]
+        }
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+async fn portal_save_file(title: &str, name: &str) -> Option<PathBuf> {
+    let picked = async {
+        SaveFileRequest::default()
+            .title(title)
+            .current_name(name)
+            .send()
+            .await?
+            .response()
+    };
+
+    // the portal answers with an error when the dialog is cancelled, too
+    match picked.await {
+        Ok(files) => files.uris().first()?.to_file_path().ok(),
+        Err(err) => {
+            warn!("{err}");
+            None
+        }
+    }
+}
@@ -0,0 +1,165 @@
+use crate::{
+    app::Message,
+    locale::locale,
+    message::{self, Receipt, ReceiptKind},
+};
+use iced::{
+    Center, Element, Fill,
+    widget::{button, column, container, opaque, row, rule, scrollable, space, stack, text},
+};
+use jiff::{Timestamp, tz::TimeZone};
+use presage::libsignal_service::protocol::ServiceId;
+use std::{collections::HashMap, sync::Arc};
+
+/// When one of our messages reached a recipient, and when they read it.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Delivery {
+    pub delivered: Option<Timestamp>,
+    pub read: Option<Timestamp>,
+}
+
+/// The receipts of our messages, those stored in the search index and those received while
+/// running, keyed by the message's timestamp and the recipient who sent the receipt.
+#[derive(Debug, Default)]
+pub struct Receipts {
+    deliveries: HashMap<(Timestamp, ServiceId), Delivery>,
+}
+
+impl Receipts {
+    pub fn apply(&mut self, recipient: ServiceId, receipt: &Receipt) {
+        for timestamp in &receipt.timestamps {
+            let delivery = self.deliveries.entry((*timestamp, recipient)).or_default();
+
+            // receipts can arrive more than once, and stored ones after live ones, the first
+            // one counts
+            let at = match receipt.kind {
+                ReceiptKind::Delivered => &mut delivery.delivered,
+                ReceiptKind::Read => &mut delivery.read,
+            };
+            *at = Some(at.map_or(receipt.at, |at| at.min(receipt.at)));
+        }
+    }
+
+    pub fn get(&self, timestamp: Timestamp, recipient: ServiceId) -> Delivery {
+        self.deliveries
+            .get(&(timestamp, recipient))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// The screen listing how far one of our messages got to each recipient.
+#[derive(Debug, Default)]
+pub struct MessageDetails {
+    message: Option<(message::Chat, Arc<message::Message>)>,
+}
+
+impl MessageDetails {
+    pub fn is_open(&self) -> bool {
+        self.message.is_some()
+    }
+
+    pub fn open(&mut self, chat: message::Chat, message: Arc<message::Message>) {
+        self.message = Some((chat, message));
+    }
+
+    pub fn close(&mut self) {
+        self.message = None;
+    }
+
+    pub fn as_iced_widget<'a>(
+        &'a self,
+        base: impl Into<Element<'a, Message>>,
+        receipts: &Receipts,
+        tz: &TimeZone,
+    ) -> Element<'a, Message> {
+        let base = base.into();
+
+        let Some((chat, message)) = &self.message else {
+            return base;
+        };
+
+        let format = |timestamp: Timestamp| locale().date_time(&timestamp.to_zoned(tz.clone()));
+
+        let recipients = chat.recipients().into_iter().map(|recipient| {
+            let delivery = receipts.get(message.timestamp, recipient.id);
+            let state = match delivery {
+                Delivery {
+                    read: Some(read), ..
+                } => format!("Read {}", format(read)),
+                Delivery {
+                    delivered: Some(delivered),
+                    ..
+                } => format!("Delivered {}", format(delivered)),
+                Delivery { .. } => "Sent".to_owned(),
+            };
+
+            row![
+                text(recipient.name.clone()),
+                space::horizontal(),
+                text(state).size(12),
+            ]
+            .spacing(10)
+            .align_y(Center)
+            .into()
+        });
+
+        let content = column![
+            row![
+                text("Message details").size(20),
+                space::horizontal(),
+                button(text("✕"))
+                    .style(button::text)
+                    .on_press(Message::CloseDetails),
+            ]
+            .align_y(Center),
+            text!("Sent {}", format(message.timestamp)).size(12),
+            rule::horizontal(1),
+            scrollable(column(recipients).spacing(10)).spacing(5),
+        ]
+        .spacing(10)
+        .padding(20)
+        .max_width(480);
+
+        stack![
+            base,
+            opaque(
+                container(container(content).style(container::rounded_box))
+                    .center(Fill)
+                    .padding(20)
+            )
+        ]
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use presage::libsignal_service::prelude::Uuid;
+
+    #[test]
+    fn first_receipt_counts() {
+        let alice = ServiceId::Aci(Uuid::from_u128(2).into());
+        let at = |ms| Timestamp::from_millisecond(ms).unwrap();
+        let receipt = |kind, ms| Receipt {
+            kind,
+            timestamps: vec![at(10)],
+            at: at(ms),
+        };
+
+        let mut receipts = Receipts::default();
+        receipts.apply(alice, &receipt(ReceiptKind::Delivered, 25));
+        receipts.apply(alice, &receipt(ReceiptKind::Delivered, 20));
+        receipts.apply(alice, &receipt(ReceiptKind::Read, 30));
+
+        assert_eq!(
+            receipts.get(at(10), alice),
+            Delivery {
+                delivered: Some(at(20)),
+                read: Some(at(30)),
+            }
+        );
+        assert_eq!(receipts.get(at(20), alice), Delivery::default());
+    }
+}
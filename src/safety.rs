@@ -0,0 +1,216 @@
+use crate::{log::warn, message::Attachment};
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt as _, OpenOptionsExt as _, PermissionsExt as _};
+use std::{
+    fs,
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+};
+
+/// The types of pictures, videos, sounds, PDFs and plain text, which the applications opening
+/// them only show or play. Pictures drawn as SVG aren't among them, since they can hold scripts.
+const SAFE_TYPES: [&str; 27] = [
+    "application/pdf",
+    "audio/aac",
+    "audio/flac",
+    "audio/mp4",
+    "audio/mpeg",
+    "audio/ogg",
+    "audio/opus",
+    "audio/wav",
+    "audio/webm",
+    "audio/x-m4a",
+    "audio/x-wav",
+    "image/avif",
+    "image/bmp",
+    "image/gif",
+    "image/heic",
+    "image/heif",
+    "image/jpeg",
+    "image/png",
+    "image/tiff",
+    "image/webp",
+    "text/plain",
+    "video/3gpp",
+    "video/mp4",
+    "video/mpeg",
+    "video/quicktime",
+    "video/webm",
+    "video/x-matroska",
+];
+
+/// The extensions of the same kinds of files, which have to match as well since the system
+/// picks what a file is opened with by its extension.
+const SAFE_EXTENSIONS: [&str; 29] = [
+    "3gp", "aac", "avif", "bmp", "flac", "gif", "heic", "heif", "jpeg", "jpg", "m4a", "m4v", "mkv",
+    "mov", "mp3", "mp4", "mpeg", "oga", "ogg", "opus", "pdf", "png", "tif", "tiff", "txt", "wav",
+    "weba", "webm", "webp",
+];
+
+/// Whether the attachment can be opened without asking, judged by both its type and the
+/// extension of its name. Anything else could run code when opened, like programs, scripts,
+/// shortcuts, disk images and web pages do, so it's only opened once the user confirmed it,
+/// and no attachment is ever opened by itself.
+pub fn is_safe_to_open(attachment: &Attachment) -> bool {
+    let extension = Path::new(&attachment.file_name())
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+
+    SAFE_TYPES.contains(&attachment.mime.essence_str())
+        && extension.is_some_and(|extension| SAFE_EXTENSIONS.contains(&extension.as_str()))
+}
+
+/// Makes sure a written attachment can't be run as a program by itself, also when it replaced
+/// a file that could.
+#[cfg(unix)]
+pub fn strip_execute(path: &Path) -> io::Result<()> {
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_mode(permissions.mode() & !0o111);
+    fs::set_permissions(path, permissions)
+}
+
+#[cfg(not(unix))]
+pub fn strip_execute(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Writes an attachment about to be opened into a new directory of its own in `dir`. Only the
+/// user can enter those directories and the file is always created anew, so other users can
+/// neither read it nor have it replaced by a link to another file.
+pub fn write_for_opening(dir: &Path, file_name: &str, bytes: &[u8]) -> io::Result<PathBuf> {
+    create_private_dir(dir, true)?;
+
+    let dir = dir.join(format!(
+        "{:016x}",
+        getrandom::u64().map_err(io::Error::other)?
+    ));
+    create_private_dir(&dir, false)?;
+
+    let path = dir.join(file_name);
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    options.open(&path)?.write_all(bytes)?;
+
+    Ok(path)
+}
+
+/// Removes the attachments written to be opened before, which the applications they were
+/// opened with are done with by the next start.
+pub fn remove_opened(dir: &Path) {
+    match fs::remove_dir_all(dir) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => {
+            warn!("Failed to remove the attachments opened before: {err}");
+        }
+        _ => {}
+    }
+}
+
+/// Creates a directory only the user can enter. One that already exists is only used when it's
+/// a directory of theirs, and only they can enter it from then on.
+fn create_private_dir(dir: &Path, existing: bool) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(existing);
+    #[cfg(unix)]
+    builder.mode(0o700);
+    builder.create(dir)?;
+
+    if !fs::symlink_metadata(dir)?.is_dir() {
+        return Err(io::Error::other(format!(
+            "{} isn't a directory",
+            dir.display()
+        )));
+    }
+
+    // fails for directories of other users
+    #[cfg(unix)]
+    fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use presage::proto::AttachmentPointer;
+
+    fn attachment(content_type: &str, file_name: &str) -> Attachment {
+        Attachment::new(AttachmentPointer {
+            content_type: Some(content_type.to_owned()),
+            file_name: Some(file_name.to_owned()),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn safe_to_open() {
+        assert!(is_safe_to_open(&attachment("image/jpeg", "photo.jpg")));
+        assert!(is_safe_to_open(&attachment(
+            "application/pdf",
+            "Invoice.PDF"
+        )));
+        assert!(is_safe_to_open(&attachment("text/plain", "notes.txt")));
+        // named after their type
+        assert!(is_safe_to_open(&attachment("audio/aac", "")));
+
+        assert!(!is_safe_to_open(&attachment(
+            "application/x-msdownload",
+            "setup.exe"
+        )));
+        assert!(!is_safe_to_open(&attachment("image/jpeg", "photo.jpg.EXE")));
+        assert!(!is_safe_to_open(&attachment(
+            "application/octet-stream",
+            "invoice.pdf"
+        )));
+        assert!(!is_safe_to_open(&attachment("text/plain", "README")));
+        assert!(!is_safe_to_open(&attachment("text/plain", "shortcut.url")));
+        assert!(!is_safe_to_open(&attachment("image/svg+xml", "logo.svg")));
+        assert!(!is_safe_to_open(&attachment("text/html", "page.html")));
+        assert!(!is_safe_to_open(&attachment(
+            "application/x-iso9660-image",
+            "disk.iso"
+        )));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn not_executable_once_written() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("script.sh");
+        fs::write(&path, "#!/bin/sh\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        strip_execute(&path).unwrap();
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o644
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn written_privately() {
+        let dir = tempfile::tempdir().unwrap();
+        let opened = dir.path().join("opened");
+
+        let first = write_for_opening(&opened, "photo.jpg", b"first").unwrap();
+        let second = write_for_opening(&opened, "photo.jpg", b"second").unwrap();
+        assert_ne!(first, second);
+        assert_eq!(fs::read(&second).unwrap(), b"second");
+
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&opened), 0o700);
+        assert_eq!(mode(first.parent().unwrap()), 0o700);
+        assert_eq!(mode(&first), 0o600);
+
+        // a link in place of the directory isn't followed
+        let link = dir.path().join("link");
+        std::os::unix::fs::symlink(dir.path(), &link).unwrap();
+        assert!(write_for_opening(&link, "photo.jpg", b"").is_err());
+
+        remove_opened(&opened);
+        assert!(!opened.exists());
+    }
+}
@@ -0,0 +1,311 @@
+use crate::{
+    config::Config,
+    database, log,
+    message::{Chat, Message, MessageId, Receipt, ReceiptKind, SignalAction},
+};
+use jiff::Timestamp;
+use presage::{libsignal_service::protocol::ServiceId, store::Thread};
+use sqlx::{
+    SqlitePool,
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+};
+use std::{borrow::Cow, error::Error};
+
+/// Bumped when the schema changes, which drops the old index. It's filled again as the
+/// history is decoded.
+const SCHEMA_VERSION: i64 = 1;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS bodies (
+    id INTEGER PRIMARY KEY,
+    thread TEXT NOT NULL,
+    timestamp INTEGER NOT NULL,
+    sender TEXT NOT NULL,
+    body TEXT NOT NULL,
+    UNIQUE (thread, timestamp, sender)
+);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS search USING fts5(body, content = 'bodies', content_rowid = 'id');
+
+CREATE TRIGGER IF NOT EXISTS bodies_insert AFTER INSERT ON bodies BEGIN
+    INSERT INTO search (rowid, body) VALUES (new.id, new.body);
+END;
+
+CREATE TRIGGER IF NOT EXISTS bodies_delete AFTER DELETE ON bodies BEGIN
+    INSERT INTO search (search, rowid, body) VALUES ('delete', old.id, old.body);
+END;
+
+CREATE TRIGGER IF NOT EXISTS bodies_update AFTER UPDATE ON bodies BEGIN
+    INSERT INTO search (search, rowid, body) VALUES ('delete', old.id, old.body);
+    INSERT INTO search (rowid, body) VALUES (new.id, new.body);
+END;
+
+CREATE TABLE IF NOT EXISTS receipts (
+    timestamp INTEGER NOT NULL,
+    recipient TEXT NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('delivered', 'read')),
+    at INTEGER NOT NULL,
+    PRIMARY KEY (timestamp, recipient, kind)
+);
+";
+
+/// A full-text index of the plain text of every message, kept next to the store along with
+/// the receipts of our messages, which the store doesn't keep.
+#[derive(Clone, Debug)]
+pub struct SearchIndex {
+    pool: SqlitePool,
+}
+
+impl SearchIndex {
+    pub async fn open(config: &Config) -> Result<Self, Box<dyn Error>> {
+        // the store may have been encrypted since the index was created, and the receipts in
+        // it can't be restored from the store
+        database::encrypt_if_plaintext(config, config.search_index_path()).await?;
+
+        let mut options = SqliteConnectOptions::new()
+            .filename(config.search_index_path())
+            .create_if_missing(true);
+
+        // the index contains message content, so it's encrypted like the store
+        if let Some(key) = &config.key {
+            options = options.pragma("key", Cow::Owned(format!("'{}'", key.replace('\'', "''"))));
+        }
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await?;
+
+        // bodies used to be told apart by their timestamp alone
+        let version = sqlx::query_scalar::<_, i64>("PRAGMA user_version")
+            .fetch_one(&pool)
+            .await?;
+        if version < SCHEMA_VERSION {
+            sqlx::raw_sql("DROP TABLE IF EXISTS search; DROP TABLE IF EXISTS bodies;")
+                .execute(&pool)
+                .await?;
+        }
+
+        sqlx::raw_sql(SCHEMA).execute(&pool).await?;
+        sqlx::raw_sql(&format!("PRAGMA user_version = {SCHEMA_VERSION}"))
+            .execute(&pool)
+            .await?;
+
+        Ok(Self { pool })
+    }
+
+    /// Keeps the index in sync with a decoded message.
+    pub async fn apply(&self, chat: &Chat, action: &SignalAction) {
+        let thread = thread_key(&chat.thread());
+
+        let result = match action {
+            SignalAction::Contact
+            | SignalAction::System(_)
+            | SignalAction::Typing(..)
+            | SignalAction::React(..)
+            | SignalAction::Challenge(_) => return,
+            SignalAction::Receipt(receipt) => match chat.contact() {
+                Some(contact) => self.save_receipt(contact.id, receipt).await,
+                None => return,
+            },
+            SignalAction::Message(message, _) => self.upsert(&thread, message.id(), message).await,
+            SignalAction::Replace(target, message) => self.upsert(&thread, *target, message).await,
+            SignalAction::Delete(target) => {
+                sqlx::query("DELETE FROM bodies WHERE thread = ? AND timestamp = ? AND sender = ?")
+                    .bind(&thread)
+                    .bind(target.timestamp.as_millisecond())
+                    .bind(target.sender.to_string())
+                    .execute(&self.pool)
+                    .await
+                    .map(drop)
+            }
+        };
+
+        if let Err(err) = result {
+            log::warn!("Failed to update the search index: {}", err);
+        }
+    }
+
+    /// Forgets the messages of a thread whose history was cleared.
+    pub async fn clear(&self, thread: &Thread) {
+        if let Err(err) = sqlx::query("DELETE FROM bodies WHERE thread = ?")
+            .bind(thread_key(thread))
+            .execute(&self.pool)
+            .await
+        {
+            log::warn!("Failed to update the search index: {}", err);
+        }
+    }
+
+    /// The receipts of our messages received so far, the first of every kind for every
+    /// message and recipient.
+    pub async fn receipts(&self) -> Vec<(ServiceId, Receipt)> {
+        let rows = sqlx::query_as::<_, (i64, String, String, i64)>(
+            "SELECT timestamp, recipient, kind, at FROM receipts",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .inspect_err(|err| log::warn!("Failed to read the receipts: {}", err))
+        .unwrap_or_default();
+
+        rows.into_iter()
+            .filter_map(|(timestamp, recipient, kind, at)| {
+                let kind = match kind.as_str() {
+                    "delivered" => ReceiptKind::Delivered,
+                    "read" => ReceiptKind::Read,
+                    _ => return None,
+                };
+
+                Some((
+                    ServiceId::parse_from_service_id_string(&recipient)?,
+                    Receipt {
+                        kind,
+                        timestamps: vec![Timestamp::from_millisecond(timestamp).ok()?],
+                        at: Timestamp::from_millisecond(at).ok()?,
+                    },
+                ))
+            })
+            .collect()
+    }
+
+    /// Finds the messages containing every word of the query, best matches first.
+    pub async fn search(
+        &self,
+        query: &str,
+        thread: Option<&Thread>,
+        limit: u32,
+    ) -> Result<Vec<(Thread, Timestamp)>, sqlx::Error> {
+        // quote every word, so the query can't use FTS5's syntax by accident
+        let query = query
+            .split_whitespace()
+            .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        if query.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let rows = sqlx::query_as::<_, (String, i64)>(
+            "SELECT bodies.thread, bodies.timestamp FROM search
+            JOIN bodies ON bodies.id = search.rowid
+            WHERE search MATCH ? AND (? IS NULL OR bodies.thread = ?)
+            ORDER BY search.rank
+            LIMIT ?",
+        )
+        .bind(query)
+        .bind(thread.map(thread_key))
+        .bind(thread.map(thread_key))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|(thread, timestamp)| {
+                Some((
+                    parse_thread_key(&thread)?,
+                    Timestamp::from_millisecond(timestamp).ok()?,
+                ))
+            })
+            .collect())
+    }
+
+    async fn upsert(
+        &self,
+        thread: &str,
+        id: MessageId,
+        message: &Message,
+    ) -> Result<(), sqlx::Error> {
+        // the displayed text, with mentions resolved to names
+        let body = message
+            .body
+            .iter()
+            .flatten()
+            .map(|span| &*span.text)
+            .collect::<String>();
+
+        if body.is_empty() {
+            return Ok(());
+        }
+
+        sqlx::query(
+            "INSERT INTO bodies (thread, timestamp, sender, body) VALUES (?, ?, ?, ?)
+            ON CONFLICT (thread, timestamp, sender) DO UPDATE SET body = excluded.body
+            WHERE body != excluded.body",
+        )
+        .bind(thread)
+        .bind(id.timestamp.as_millisecond())
+        .bind(id.sender.to_string())
+        .bind(body)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn save_receipt(
+        &self,
+        recipient: ServiceId,
+        receipt: &Receipt,
+    ) -> Result<(), sqlx::Error> {
+        let kind = match receipt.kind {
+            ReceiptKind::Delivered => "delivered",
+            ReceiptKind::Read => "read",
+        };
+
+        // receipts can arrive more than once, the first one counts
+        for timestamp in &receipt.timestamps {
+            sqlx::query(
+                "INSERT INTO receipts (timestamp, recipient, kind, at) VALUES (?, ?, ?, ?)
+                ON CONFLICT (timestamp, recipient, kind) DO UPDATE SET at = excluded.at
+                WHERE excluded.at < at",
+            )
+            .bind(timestamp.as_millisecond())
+            .bind(recipient.service_id_string())
+            .bind(kind)
+            .bind(receipt.at.as_millisecond())
+            .execute(&self.pool)
+            .await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The key a thread is stored by, in the index and in other files.
+pub fn thread_key(thread: &Thread) -> String {
+    match thread {
+        Thread::Contact(id) => format!("contact:{}", id.service_id_string()),
+        Thread::Group(key) => format!("group:{}", hex::encode(key)),
+    }
+}
+
+fn parse_thread_key(key: &str) -> Option<Thread> {
+    if let Some(id) = key.strip_prefix("contact:") {
+        return ServiceId::parse_from_service_id_string(id).map(Thread::Contact);
+    }
+
+    let mut master_key = [0; 32];
+    hex::decode_to_slice(key.strip_prefix("group:")?, &mut master_key).ok()?;
+
+    Some(Thread::Group(master_key))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use presage::libsignal_service::prelude::Uuid;
+
+    #[test]
+    fn thread_keys() {
+        let threads = [
+            Thread::Contact(ServiceId::Aci(Uuid::from_u128(1).into())),
+            Thread::Group([0xab; 32]),
+        ];
+
+        for thread in threads {
+            assert_eq!(parse_thread_key(&thread_key(&thread)), Some(thread));
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use crate::{log, message::Chat, search::thread_key};
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+/// Where the app was left, restored on the next launch: the chat that was open and how far
+/// each chat was scrolled, by their threads.
+#[derive(Debug, Default)]
+pub struct Session {
+    path: PathBuf,
+    open: Option<String>,
+    /// The relative offsets of the chats' messages, from the top.
+    scrolled: BTreeMap<String, f32>,
+}
+
+impl Session {
+    pub fn load(path: PathBuf) -> Self {
+        let mut session = Self {
+            path,
+            ..Self::default()
+        };
+
+        let Ok(saved) = fs::read_to_string(&session.path) else {
+            return session;
+        };
+
+        for line in saved.lines() {
+            match line.split('\t').collect::<Vec<_>>()[..] {
+                ["open", key] => session.open = Some(key.to_owned()),
+                ["scrolled", key, offset] => {
+                    if let Ok(offset) = offset.parse() {
+                        session.scrolled.insert(key.to_owned(), offset);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        session
+    }
+
+    /// Whether the chat was open when the app was left.
+    pub fn was_open(&self, chat: &Chat) -> bool {
+        self.open
+            .as_deref()
+            .is_some_and(|key| key == thread_key(&chat.thread()))
+    }
+
+    pub fn scrolled(&self, chat: &Chat) -> Option<f32> {
+        self.scrolled.get(&thread_key(&chat.thread())).copied()
+    }
+
+    /// Remembers the chat that was opened, without writing it to disk yet.
+    pub fn open(&mut self, chat: &Chat) {
+        self.open = Some(thread_key(&chat.thread()));
+    }
+
+    /// Remembers how far a chat is scrolled, without writing it to disk yet.
+    pub fn scroll(&mut self, chat: &Chat, offset: f32) {
+        self.scrolled.insert(thread_key(&chat.thread()), offset);
+    }
+
+    pub fn save(&self) {
+        let open = self
+            .open
+            .iter()
+            .map(|key| format!("open\t{key}\n"))
+            .collect::<String>();
+
+        let saved = self.scrolled.iter().fold(open, |saved, (key, offset)| {
+            saved + &format!("scrolled\t{key}\t{offset}\n")
+        });
+
+        if let Err(err) = self
+            .path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|()| fs::write(&self.path, saved))
+        {
+            log::error!("Failed to remember the session: {}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::message::test::contact;
+    use presage::libsignal_service::prelude::Uuid;
+    use std::sync::Arc;
+
+    fn chat(id: u128) -> Chat {
+        Chat::Contact(Arc::new(contact(Uuid::from_u128(id), "Alice")))
+    }
+
+    #[test]
+    fn restored() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session");
+
+        let mut session = Session::load(path.clone());
+        session.open(&chat(2));
+        session.scroll(&chat(2), 0.5);
+        session.scroll(&chat(3), 1.);
+        session.save();
+
+        let session = Session::load(path);
+        assert!(session.was_open(&chat(2)));
+        assert!(!session.was_open(&chat(3)));
+        assert_eq!(session.scrolled(&chat(2)), Some(0.5));
+        assert_eq!(session.scrolled(&chat(3)), Some(1.));
+        assert_eq!(session.scrolled(&chat(4)), None);
+    }
+}
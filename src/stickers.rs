@@ -0,0 +1,175 @@
+use crate::app::Message;
+use iced::{
+    Center, Element, Fill,
+    widget::{button, column, container, image, opaque, row, rule, scrollable, space, stack, text},
+};
+use presage::model::stickers::StickerPack;
+
+/// The size of a pack's cover in the list.
+const COVER_SIZE: f32 = 48.0;
+
+/// An installed sticker pack, as far as the list of packs cares.
+#[derive(Clone, Debug)]
+pub struct InstalledPack {
+    pub id: Vec<u8>,
+    pub title: String,
+    pub author: String,
+    pub stickers: usize,
+    pub cover: Option<image::Handle>,
+}
+
+impl From<StickerPack> for InstalledPack {
+    fn from(pack: StickerPack) -> Self {
+        let manifest = pack.manifest;
+
+        Self {
+            id: pack.id,
+            title: manifest.title,
+            author: manifest.author,
+            stickers: manifest.stickers.len(),
+            cover: manifest
+                .cover
+                .or_else(|| manifest.stickers.into_iter().next())
+                .and_then(|sticker| sticker.bytes)
+                .map(image::Handle::from_bytes),
+        }
+    }
+}
+
+/// Finds the id and key of a sticker pack in a link shared from signal.art, like
+/// `https://signal.art/addstickers/#pack_id=...&pack_key=...`.
+pub fn parse_pack_link(text: &str) -> Option<(Vec<u8>, Vec<u8>)> {
+    let start = text
+        .find("https://signal.art/addstickers/#")
+        .or_else(|| text.find("sgnl://addstickers/?"))?;
+    let (_, params) = text[start..].split_once(['#', '?'])?;
+    let params = params.split_whitespace().next()?;
+
+    let mut id = None;
+    let mut key = None;
+
+    for param in params.split('&') {
+        match param.split_once('=') {
+            Some(("pack_id", value)) if !value.is_empty() => id = hex::decode(value).ok(),
+            Some(("pack_key", value)) if !value.is_empty() => key = hex::decode(value).ok(),
+            _ => {}
+        }
+    }
+
+    Some((id?, key?))
+}
+
+/// The screen managing the installed sticker packs.
+#[derive(Debug, Default)]
+pub struct Stickers {
+    packs: Option<Vec<InstalledPack>>,
+}
+
+impl Stickers {
+    pub fn is_open(&self) -> bool {
+        self.packs.is_some()
+    }
+
+    pub fn open(&mut self, packs: Vec<InstalledPack>) {
+        self.packs = Some(packs);
+    }
+
+    pub fn close(&mut self) {
+        self.packs = None;
+    }
+
+    pub fn as_iced_widget<'a>(
+        &'a self,
+        base: impl Into<Element<'a, Message>>,
+    ) -> Element<'a, Message> {
+        let base = base.into();
+
+        let Some(packs) = &self.packs else {
+            return base;
+        };
+
+        let list: Element<'a, Message> = if packs.is_empty() {
+            text(
+                "No sticker packs are installed. Paste a signal.art link into the message box to \
+                 install one.",
+            )
+            .into()
+        } else {
+            scrollable(
+                column(packs.iter().map(|pack| {
+                    row![
+                        container(
+                            pack.cover
+                                .clone()
+                                .map(|cover| image(cover).width(COVER_SIZE))
+                        )
+                        .width(COVER_SIZE)
+                        .height(COVER_SIZE),
+                        column![
+                            text(&pack.title),
+                            text!("{}, {} stickers", pack.author, pack.stickers).size(12),
+                        ],
+                        space::horizontal(),
+                        button(text("Remove"))
+                            .style(button::danger)
+                            .on_press(Message::UninstallStickerPack(pack.id.clone())),
+                    ]
+                    .spacing(10)
+                    .align_y(Center)
+                    .into()
+                }))
+                .spacing(10),
+            )
+            .spacing(5)
+            .into()
+        };
+
+        let content = column![
+            row![
+                text("Sticker packs").size(20),
+                space::horizontal(),
+                button(text("✕"))
+                    .style(button::text)
+                    .on_press(Message::CloseStickers),
+            ]
+            .align_y(Center),
+            rule::horizontal(1),
+            list,
+        ]
+        .spacing(10)
+        .padding(20)
+        .max_width(480);
+
+        stack![
+            base,
+            opaque(
+                container(container(content).style(container::rounded_box))
+                    .center(Fill)
+                    .padding(20)
+            )
+        ]
+        .into()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pack_links() {
+        assert_eq!(
+            parse_pack_link("https://signal.art/addstickers/#pack_id=0aff&pack_key=10"),
+            Some((vec![0x0a, 0xff], vec![0x10]))
+        );
+        assert_eq!(
+            parse_pack_link("look: sgnl://addstickers/?pack_key=01&pack_id=02 nice"),
+            Some((vec![0x02], vec![0x01]))
+        );
+        assert_eq!(
+            parse_pack_link("https://signal.art/addstickers/#pack_id=0aff"),
+            None
+        );
+        assert_eq!(parse_pack_link("https://signal.art"), None);
+    }
+}
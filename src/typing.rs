@@ -0,0 +1,144 @@
+use crate::message::{Chat, Contact};
+use jiff::{SignedDuration, Timestamp};
+use presage::libsignal_service::protocol::ServiceId;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+/// How long someone counts as typing without hearing from them again. Clients repeat that
+/// they started typing every few seconds while they keep going.
+pub const TYPING_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Who is typing in which chat, and since when they last said so.
+#[derive(Debug, Default)]
+pub struct Typing {
+    chats: HashMap<Chat, Vec<(Arc<Contact>, Timestamp)>>,
+}
+
+impl Typing {
+    /// Records that someone started or stopped typing. Returns whether it changed anything,
+    /// as events from before the timeout, like those received on startup, are ignored.
+    pub fn apply(
+        &mut self,
+        chat: Chat,
+        contact: Arc<Contact>,
+        started: bool,
+        at: Timestamp,
+        now: Timestamp,
+    ) -> bool {
+        // typing on one of our other devices
+        if contact.is_self {
+            return false;
+        }
+
+        if !started {
+            self.stop(&chat, contact.id);
+            return true;
+        }
+
+        if is_expired(at, now) {
+            return false;
+        }
+
+        let typing = self.chats.entry(chat).or_default();
+        typing.retain(|(typing, _)| typing.id != contact.id);
+        typing.push((contact, at));
+
+        true
+    }
+
+    /// Forgets that someone is typing, like when their message arrived.
+    pub fn stop(&mut self, chat: &Chat, id: ServiceId) {
+        if let Some(typing) = self.chats.get_mut(chat) {
+            typing.retain(|(contact, _)| contact.id != id);
+        }
+    }
+
+    /// Forgets who stopped typing without saying so.
+    pub fn expire(&mut self, now: Timestamp) {
+        self.chats.retain(|_, typing| {
+            typing.retain(|(_, at)| !is_expired(*at, now));
+            !typing.is_empty()
+        });
+    }
+
+    pub fn is_typing(&self, chat: &Chat) -> bool {
+        self.chats
+            .get(chat)
+            .is_some_and(|typing| !typing.is_empty())
+    }
+
+    /// Who is typing in the chat, like "Alice and Bob are typing…".
+    pub fn label(&self, chat: &Chat) -> Option<String> {
+        let names = self
+            .chats
+            .get(chat)?
+            .iter()
+            .map(|(contact, _)| contact.name.as_str())
+            .collect::<Vec<_>>();
+
+        Some(match names.as_slice() {
+            [] => return None,
+            [name] => format!("{name} is typing…"),
+            [first, second] => format!("{first} and {second} are typing…"),
+            [first, second, rest @ ..] => {
+                format!("{first}, {second} and {} more are typing…", rest.len())
+            }
+        })
+    }
+}
+
+fn is_expired(at: Timestamp, now: Timestamp) -> bool {
+    now.duration_since(at) >= SignedDuration::try_from(TYPING_TIMEOUT).unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::message::test;
+    use presage::libsignal_service::prelude::Uuid;
+
+    fn contact(id: u128, name: &str) -> Arc<Contact> {
+        Arc::new(test::contact(Uuid::from_u128(id), name))
+    }
+
+    #[test]
+    fn typing() {
+        let at = |s| Timestamp::from_second(s).unwrap();
+        let (alice, bob, carol, dave) = (
+            contact(2, "Alice"),
+            contact(3, "Bob"),
+            contact(4, "Carol"),
+            contact(5, "Dave"),
+        );
+        let chat = Chat::Contact(alice.clone());
+
+        let mut typing = Typing::default();
+        assert!(typing.apply(chat.clone(), alice.clone(), true, at(10), at(10)));
+        assert_eq!(typing.label(&chat).as_deref(), Some("Alice is typing…"));
+
+        typing.apply(chat.clone(), bob.clone(), true, at(12), at(12));
+        assert_eq!(
+            typing.label(&chat).as_deref(),
+            Some("Alice and Bob are typing…")
+        );
+
+        typing.apply(chat.clone(), carol, true, at(13), at(13));
+        typing.apply(chat.clone(), dave, true, at(13), at(13));
+        assert_eq!(
+            typing.label(&chat).as_deref(),
+            Some("Alice, Bob and 2 more are typing…")
+        );
+
+        typing.apply(chat.clone(), bob.clone(), false, at(14), at(14));
+        typing.stop(&chat, ServiceId::Aci(Uuid::from_u128(4).into()));
+        typing.stop(&chat, ServiceId::Aci(Uuid::from_u128(5).into()));
+        assert_eq!(typing.label(&chat).as_deref(), Some("Alice is typing…"));
+
+        // Alice didn't repeat that they're typing
+        typing.expire(at(25));
+        assert!(!typing.is_typing(&chat));
+
+        // late events, like those received on startup, don't count
+        assert!(!typing.apply(chat.clone(), bob, true, at(0), at(25)));
+        assert_eq!(typing.label(&chat), None);
+    }
+}
@@ -0,0 +1,394 @@
+use iced_widget::{
+    Renderer,
+    core::{
+        Animation, Color, Element, Event, Layout, Length, Pixels, Point, Rectangle, Shell, Size,
+        Text, Theme, Vector, Widget, alignment,
+        animation::Easing,
+        layout, mouse, overlay, renderer,
+        text::{self, LineHeight, Renderer as _, Shaping, Wrapping},
+        time::{Duration, Instant},
+        touch,
+        widget::{
+            Operation,
+            tree::{self, Tree},
+        },
+        window,
+    },
+};
+
+/// A container that clips its content to a maximum height, with a toggle
+/// to reveal the rest of it.
+#[expect(missing_debug_implementations)]
+pub struct Expandable<'a, Message> {
+    content: Element<'a, Message, Theme, Renderer>,
+    max_height: f32,
+    expand_label: &'a str,
+    collapse_label: &'a str,
+    size: Option<Pixels>,
+    style: StyleFn<'a, Theme>,
+}
+
+impl<'a, Message> Expandable<'a, Message> {
+    /// Creates a new [`Expandable`] with the given content.
+    pub fn new(content: impl Into<Element<'a, Message, Theme, Renderer>>) -> Self {
+        Self {
+            content: content.into(),
+            max_height: 480.0,
+            expand_label: "Read more",
+            collapse_label: "Show less",
+            size: None,
+            style: Box::new(default),
+        }
+    }
+
+    /// Sets the height above which the content of the [`Expandable`] is collapsed.
+    pub fn max_height(mut self, max_height: impl Into<Pixels>) -> Self {
+        self.max_height = max_height.into().0;
+        self
+    }
+
+    /// Sets the labels of the toggle of the [`Expandable`].
+    pub fn labels(mut self, expand: &'a str, collapse: &'a str) -> Self {
+        self.expand_label = expand;
+        self.collapse_label = collapse;
+        self
+    }
+
+    /// Sets the text size of the toggle of the [`Expandable`].
+    pub fn size(mut self, size: impl Into<Pixels>) -> Self {
+        self.size = Some(size.into());
+        self
+    }
+
+    /// Sets the style of the [`Expandable`].
+    pub fn style(mut self, style: impl Fn(&Theme) -> Style + 'a) -> Self {
+        self.style = Box::new(style);
+        self
+    }
+
+    fn toggle_height(&self, renderer: &Renderer) -> f32 {
+        let size = self.size.unwrap_or_else(|| renderer.default_size());
+
+        LineHeight::default().to_absolute(size).0 + 4.0
+    }
+}
+
+struct State {
+    expanded: Animation<bool>,
+    now: Instant,
+    full_height: f32,
+}
+
+impl State {
+    fn is_collapsible(&self, max_height: f32) -> bool {
+        self.full_height > max_height
+    }
+}
+
+impl<Message> Widget<Message, Theme, Renderer> for Expandable<'_, Message> {
+    fn tag(&self) -> tree::Tag {
+        tree::Tag::of::<State>()
+    }
+
+    fn state(&self) -> tree::State {
+        tree::State::new(State {
+            expanded: Animation::new(false)
+                .duration(Duration::from_millis(200))
+                .easing(Easing::EaseInOut),
+            now: Instant::now(),
+            full_height: 0.0,
+        })
+    }
+
+    fn children(&self) -> Vec<Tree> {
+        vec![Tree::new(&self.content)]
+    }
+
+    fn diff(&self, tree: &mut Tree) {
+        tree.diff_children(std::slice::from_ref(&self.content));
+    }
+
+    fn size(&self) -> Size<Length> {
+        Size {
+            width: self.content.as_widget().size().width,
+            height: Length::Shrink,
+        }
+    }
+
+    fn layout(
+        &mut self,
+        tree: &mut Tree,
+        renderer: &Renderer,
+        limits: &layout::Limits,
+    ) -> layout::Node {
+        let toggle_height = self.toggle_height(renderer);
+
+        let content = self.content.as_widget_mut().layout(
+            &mut tree.children[0],
+            renderer,
+            &layout::Limits::new(Size::ZERO, Size::new(limits.max().width, f32::INFINITY)),
+        );
+
+        let state = tree.state.downcast_mut::<State>();
+        let full_height = content.size().height;
+        state.full_height = full_height;
+
+        if !state.is_collapsible(self.max_height) {
+            return layout::Node::with_children(content.size(), vec![content]);
+        }
+
+        let visible_height = state
+            .expanded
+            .interpolate(self.max_height, full_height, state.now);
+
+        layout::Node::with_children(
+            Size::new(content.size().width, visible_height + toggle_height),
+            vec![content],
+        )
+    }
+
+    fn operate(
+        &mut self,
+        tree: &mut Tree,
+        layout: Layout<'_>,
+        renderer: &Renderer,
+        operation: &mut dyn Operation,
+    ) {
+        self.content.as_widget_mut().operate(
+            &mut tree.children[0],
+            layout.children().next().unwrap(),
+            renderer,
+            operation,
+        );
+    }
+
+    fn update(
+        &mut self,
+        tree: &mut Tree,
+        event: &Event,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        renderer: &Renderer,
+        shell: &mut Shell<'_, Message>,
+        viewport: &Rectangle,
+    ) {
+        let toggle_height = self.toggle_height(renderer);
+        let state = tree.state.downcast_mut::<State>();
+
+        let collapsible = state.is_collapsible(self.max_height);
+        let bounds = layout.bounds();
+        let (visible, toggle) = split(bounds, collapsible.then_some(toggle_height));
+
+        if let Event::Window(window::Event::RedrawRequested(now)) = event {
+            state.now = *now;
+
+            if state.expanded.is_animating(*now) {
+                shell.invalidate_layout();
+                shell.request_redraw();
+            }
+        }
+
+        if let Some(toggle) = toggle
+            && cursor.is_over(toggle)
+            && matches!(
+                event,
+                Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left))
+                    | Event::Touch(touch::Event::FingerPressed { .. })
+            )
+        {
+            let expanded = state.expanded.value();
+            state.expanded.go_mut(!expanded, Instant::now());
+
+            shell.invalidate_layout();
+            shell.request_redraw();
+            shell.capture_event();
+
+            return;
+        }
+
+        let content_cursor = if cursor.is_over(visible) {
+            cursor
+        } else {
+            mouse::Cursor::Unavailable
+        };
+
+        self.content.as_widget_mut().update(
+            &mut tree.children[0],
+            event,
+            layout.children().next().unwrap(),
+            content_cursor,
+            renderer,
+            shell,
+            &visible.intersection(viewport).unwrap_or_default(),
+        );
+    }
+
+    fn mouse_interaction(
+        &self,
+        tree: &Tree,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        viewport: &Rectangle,
+        renderer: &Renderer,
+    ) -> mouse::Interaction {
+        let state = tree.state.downcast_ref::<State>();
+        let (visible, toggle) = split(
+            layout.bounds(),
+            state
+                .is_collapsible(self.max_height)
+                .then(|| self.toggle_height(renderer)),
+        );
+
+        if toggle.is_some_and(|toggle| cursor.is_over(toggle)) {
+            return mouse::Interaction::Pointer;
+        }
+
+        if !cursor.is_over(visible) {
+            return mouse::Interaction::None;
+        }
+
+        self.content.as_widget().mouse_interaction(
+            &tree.children[0],
+            layout.children().next().unwrap(),
+            cursor,
+            viewport,
+            renderer,
+        )
+    }
+
+    fn draw(
+        &self,
+        tree: &Tree,
+        renderer: &mut Renderer,
+        theme: &Theme,
+        defaults: &renderer::Style,
+        layout: Layout<'_>,
+        cursor: mouse::Cursor,
+        viewport: &Rectangle,
+    ) {
+        let state = tree.state.downcast_ref::<State>();
+        let style = (self.style)(theme);
+
+        let collapsible = state.is_collapsible(self.max_height);
+        let (visible, toggle) = split(
+            layout.bounds(),
+            collapsible.then(|| self.toggle_height(renderer)),
+        );
+
+        let Some(clip) = visible.intersection(viewport) else {
+            return;
+        };
+
+        renderer.with_layer(clip, |renderer| {
+            self.content.as_widget().draw(
+                &tree.children[0],
+                renderer,
+                theme,
+                defaults,
+                layout.children().next().unwrap(),
+                cursor,
+                &clip,
+            );
+        });
+
+        if let Some(toggle) = toggle {
+            let size = self.size.unwrap_or_else(|| renderer.default_size());
+
+            renderer.fill_text(
+                Text {
+                    content: if state.expanded.value() {
+                        self.collapse_label
+                    } else {
+                        self.expand_label
+                    }
+                    .to_owned(),
+                    bounds: toggle.size(),
+                    size,
+                    line_height: LineHeight::default(),
+                    font: renderer.default_font(),
+                    align_x: text::Alignment::Left,
+                    align_y: alignment::Vertical::Bottom,
+                    shaping: Shaping::Basic,
+                    wrapping: Wrapping::None,
+                    hint_factor: renderer.scale_factor(),
+                    ellipsis: text::Ellipsis::None,
+                },
+                Point::new(toggle.x, toggle.y + toggle.height),
+                if cursor.is_over(toggle) {
+                    style.hovered_toggle
+                } else {
+                    style.toggle
+                },
+                toggle,
+            );
+        }
+    }
+
+    fn overlay<'b>(
+        &'b mut self,
+        tree: &'b mut Tree,
+        layout: Layout<'b>,
+        renderer: &Renderer,
+        viewport: &Rectangle,
+        translation: Vector,
+    ) -> Option<overlay::Element<'b, Message, Theme, Renderer>> {
+        self.content.as_widget_mut().overlay(
+            &mut tree.children[0],
+            layout.children().next().unwrap(),
+            renderer,
+            viewport,
+            translation,
+        )
+    }
+}
+
+/// Splits the bounds of an [`Expandable`] into the visible content area and the
+/// area of its toggle, if any.
+fn split(bounds: Rectangle, toggle_height: Option<f32>) -> (Rectangle, Option<Rectangle>) {
+    let Some(toggle_height) = toggle_height else {
+        return (bounds, None);
+    };
+
+    let visible = Rectangle {
+        height: (bounds.height - toggle_height).max(0.0),
+        ..bounds
+    };
+
+    let toggle = Rectangle {
+        y: visible.y + visible.height,
+        height: toggle_height,
+        ..bounds
+    };
+
+    (visible, Some(toggle))
+}
+
+impl<'a, Message> From<Expandable<'a, Message>> for Element<'a, Message, Theme, Renderer>
+where
+    Message: 'a,
+{
+    fn from(expandable: Expandable<'a, Message>) -> Self {
+        Element::new(expandable)
+    }
+}
+
+/// The appearance of an [`Expandable`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Style {
+    /// The [`Color`] of the toggle.
+    pub toggle: Color,
+    /// The [`Color`] of the hovered toggle.
+    pub hovered_toggle: Color,
+}
+
+/// A styling function for an [`Expandable`].
+pub type StyleFn<'a, Theme> = Box<dyn Fn(&Theme) -> Style + 'a>;
+
+pub fn default(theme: &Theme) -> Style {
+    let palette = theme.palette();
+
+    Style {
+        toggle: palette.primary.base.color,
+        hovered_toggle: palette.primary.strong.color,
+    }
+}
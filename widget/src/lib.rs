@@ -1,2 +1,6 @@
+pub mod expandable;
+pub use expandable::Expandable;
+pub mod skeleton;
+pub use skeleton::Skeleton;
 pub mod text;
 pub use text::{SignalRich, SignalSpan, Text};
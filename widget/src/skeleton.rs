@@ -0,0 +1,197 @@
+use iced_widget::{
+    Renderer,
+    core::{
+        Border, Color, Element, Event, Layout, Length, Rectangle, Renderer as _, Shell, Size,
+        Theme, Widget, border, layout, mouse,
+        renderer::{self, Quad},
+        time::{Duration, Instant},
+        widget::tree::{self, Tree},
+        window,
+    },
+};
+
+const PERIOD: Duration = Duration::from_millis(1500);
+
+/// A shimmering placeholder for content that is still loading.
+#[expect(missing_debug_implementations)]
+pub struct Skeleton<'a> {
+    width: Length,
+    height: Length,
+    border_radius: border::Radius,
+    animated: bool,
+    style: StyleFn<'a, Theme>,
+}
+
+impl<'a> Skeleton<'a> {
+    /// Creates a new [`Skeleton`] with the given size.
+    pub fn new(width: impl Into<Length>, height: impl Into<Length>) -> Self {
+        Self {
+            width: width.into(),
+            height: height.into(),
+            border_radius: border::radius(5),
+            animated: true,
+            style: Box::new(default),
+        }
+    }
+
+    /// Sets the border radius of the [`Skeleton`].
+    pub fn border_radius(mut self, radius: impl Into<border::Radius>) -> Self {
+        self.border_radius = radius.into();
+        self
+    }
+
+    /// Sets whether the [`Skeleton`] shimmers.
+    pub fn animated(mut self, animated: bool) -> Self {
+        self.animated = animated;
+        self
+    }
+
+    /// Sets the style of the [`Skeleton`].
+    pub fn style(mut self, style: impl Fn(&Theme) -> Style + 'a) -> Self {
+        self.style = Box::new(style);
+        self
+    }
+}
+
+struct State {
+    start: Instant,
+    now: Instant,
+}
+
+impl State {
+    fn phase(&self) -> f32 {
+        let elapsed = self.now.saturating_duration_since(self.start);
+        let t = (elapsed.as_secs_f32() / PERIOD.as_secs_f32()).fract();
+
+        (1.0 - (t * std::f32::consts::TAU).cos()) / 2.0
+    }
+}
+
+impl<Message> Widget<Message, Theme, Renderer> for Skeleton<'_> {
+    fn tag(&self) -> tree::Tag {
+        tree::Tag::of::<State>()
+    }
+
+    fn state(&self) -> tree::State {
+        let now = Instant::now();
+
+        tree::State::new(State { start: now, now })
+    }
+
+    fn size(&self) -> Size<Length> {
+        Size {
+            width: self.width,
+            height: self.height,
+        }
+    }
+
+    fn layout(
+        &mut self,
+        _tree: &mut Tree,
+        _renderer: &Renderer,
+        limits: &layout::Limits,
+    ) -> layout::Node {
+        layout::atomic(limits, self.width, self.height)
+    }
+
+    fn update(
+        &mut self,
+        tree: &mut Tree,
+        event: &Event,
+        layout: Layout<'_>,
+        _cursor: mouse::Cursor,
+        _renderer: &Renderer,
+        shell: &mut Shell<'_, Message>,
+        viewport: &Rectangle,
+    ) {
+        if !self.animated {
+            return;
+        }
+
+        if let Event::Window(window::Event::RedrawRequested(now)) = event {
+            tree.state.downcast_mut::<State>().now = *now;
+
+            if layout.bounds().intersects(viewport) {
+                shell.request_redraw();
+            }
+        }
+    }
+
+    fn draw(
+        &self,
+        tree: &Tree,
+        renderer: &mut Renderer,
+        theme: &Theme,
+        _defaults: &renderer::Style,
+        layout: Layout<'_>,
+        _cursor: mouse::Cursor,
+        viewport: &Rectangle,
+    ) {
+        if !layout.bounds().intersects(viewport) {
+            return;
+        }
+
+        let style = (self.style)(theme);
+
+        let color = if self.animated {
+            mix(
+                style.base,
+                style.highlight,
+                tree.state.downcast_ref::<State>().phase(),
+            )
+        } else {
+            style.base
+        };
+
+        renderer.fill_quad(
+            Quad {
+                bounds: layout.bounds(),
+                border: Border {
+                    radius: self.border_radius,
+                    ..Border::default()
+                },
+                ..Default::default()
+            },
+            color,
+        );
+    }
+}
+
+fn mix(a: Color, b: Color, factor: f32) -> Color {
+    Color {
+        r: a.r + (b.r - a.r) * factor,
+        g: a.g + (b.g - a.g) * factor,
+        b: a.b + (b.b - a.b) * factor,
+        a: a.a + (b.a - a.a) * factor,
+    }
+}
+
+impl<'a, Message> From<Skeleton<'a>> for Element<'a, Message, Theme, Renderer>
+where
+    Message: 'a,
+{
+    fn from(skeleton: Skeleton<'a>) -> Self {
+        Element::new(skeleton)
+    }
+}
+
+/// The appearance of a [`Skeleton`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Style {
+    /// The base [`Color`] of the [`Skeleton`].
+    pub base: Color,
+    /// The [`Color`] the [`Skeleton`] shimmers towards.
+    pub highlight: Color,
+}
+
+/// A styling function for a [`Skeleton`].
+pub type StyleFn<'a, Theme> = Box<dyn Fn(&Theme) -> Style + 'a>;
+
+pub fn default(theme: &Theme) -> Style {
+    let palette = theme.palette();
+
+    Style {
+        base: palette.background.weak.color,
+        highlight: palette.background.strong.color,
+    }
+}
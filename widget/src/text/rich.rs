@@ -16,12 +16,15 @@ use iced_widget::{
         text::{self, Paragraph as _, Renderer as _, Span},
         touch,
         widget::{
+            Operation,
+            operation::Focusable,
             text::{Alignment, LineHeight, Shaping, Wrapping},
             tree::{self, Tree},
         },
     },
     graphics::text::Paragraph,
 };
+use std::ops::Range;
 use uuid::Uuid;
 
 /// A bunch of [`SignalRich`] text.
@@ -41,7 +44,11 @@ pub struct SignalRich<'a, Link, Message> {
     hovered_mention: Option<usize>,
     hovered_spoiler: Option<usize>,
     on_link_click: Option<Box<dyn Fn(Link) -> Message + 'a>>,
+    on_link_hover: Option<Box<dyn Fn(Option<Link>) -> Message + 'a>>,
     on_mention_click: Option<Box<dyn Fn(Uuid) -> Message + 'a>>,
+    on_selection: Option<Box<dyn Fn(Vec<SignalSpan<'static, Link>>) -> Message + 'a>>,
+    revealed_spoilers: Option<&'a [usize]>,
+    on_spoiler_reveal: Option<Box<dyn Fn(usize) -> Message + 'a>>,
 }
 
 impl<'a, Link, Message> SignalRich<'a, Link, Message>
@@ -65,7 +72,11 @@ where
             hovered_mention: None,
             hovered_spoiler: None,
             on_link_click: None,
+            on_link_hover: None,
             on_mention_click: None,
+            on_selection: None,
+            revealed_spoilers: None,
+            on_spoiler_reveal: None,
         }
     }
 
@@ -137,6 +148,13 @@ where
         self
     }
 
+    /// Sets the message that will be produced when the hovered link of the [`SignalRich`]
+    /// text changes.
+    pub fn on_link_hover(mut self, on_link_hovered: impl Fn(Option<Link>) -> Message + 'a) -> Self {
+        self.on_link_hover = Some(Box::new(on_link_hovered));
+        self
+    }
+
     /// Sets the message that will be produced when a mention of the [`SignalRich`] text
     /// is clicked.
     pub fn on_mention_click(mut self, on_mention_clicked: impl Fn(Uuid) -> Message + 'a) -> Self {
@@ -144,6 +162,31 @@ where
         self
     }
 
+    /// Sets the message that will be produced when the selected text of the [`SignalRich`]
+    /// text changes, with the selected parts of its spans, which are none once the selection
+    /// is cleared.
+    pub fn on_selection(
+        mut self,
+        on_selection: impl Fn(Vec<SignalSpan<'static, Link>>) -> Message + 'a,
+    ) -> Self {
+        self.on_selection = Some(Box::new(on_selection));
+        self
+    }
+
+    /// Sets the tags of the revealed spoilers of the [`SignalRich`] text, so they stay revealed
+    /// when the widget is recreated. Without them, spoilers are revealed until then.
+    pub fn revealed_spoilers(mut self, revealed_spoilers: &'a [usize]) -> Self {
+        self.revealed_spoilers = Some(revealed_spoilers);
+        self
+    }
+
+    /// Sets the message that will be produced when a spoiler of the [`SignalRich`] text is
+    /// revealed, with its tag.
+    pub fn on_spoiler_reveal(mut self, on_spoiler_reveal: impl Fn(usize) -> Message + 'a) -> Self {
+        self.on_spoiler_reveal = Some(Box::new(on_spoiler_reveal));
+        self
+    }
+
     /// Sets the style of the [`SignalRich`] text.
     pub fn style(mut self, style: impl Fn(&Theme) -> Style + 'a) -> Self {
         self.style = Box::new(style);
@@ -160,7 +203,9 @@ where
     }
 }
 
-struct State<Link> {
+/// The state of a [`SignalRich`] text.
+#[expect(missing_debug_implementations)]
+pub struct State<Link> {
     spans: Vec<SignalSpan<'static, Link>>,
     span_pressed: Option<usize>,
     revealed_spoilers: Vec<usize>,
@@ -169,9 +214,197 @@ struct State<Link> {
     dragging: Option<Dragging>,
     last_click: Option<mouse::Click>,
     keyboard_modifiers: keyboard::Modifiers,
+    is_focused: bool,
+    hovered_link: Option<usize>,
+    word_anchor: Option<(SelectionEnd, SelectionEnd)>,
+}
+
+/// The [`SignalSpan`] under a point of a [`SignalRich`] text.
+#[derive(Debug)]
+pub struct SpanHit<'a, Link> {
+    /// The index of the [`SignalSpan`] among the spans of the text.
+    pub index: usize,
+    /// The [`SignalSpan`] itself.
+    pub span: &'a SignalSpan<'static, Link>,
+    /// The graphemes of the [`SignalSpan`], counted from the start of the text.
+    pub graphemes: Range<usize>,
+}
+
+impl<Link> State<Link> {
+    /// Finds the [`SignalSpan`] under the given point, relative to the top left of the text.
+    pub fn span_at(&self, point: Point) -> Option<SpanHit<'_, Link>> {
+        use unicode_segmentation::UnicodeSegmentation;
+
+        let index = self.paragraph.hit_span(point)?;
+        let span = self.spans.get(index)?;
+
+        let start = self.spans[..index]
+            .iter()
+            .map(|span| span.text.graphemes(true).count())
+            .sum();
+
+        Some(SpanHit {
+            index,
+            span,
+            graphemes: start..start + span.text.graphemes(true).count(),
+        })
+    }
 }
 
 impl<Link: Clone> State<Link> {
+    /// Whether keyboard selection is possible, either because the text is focused
+    /// or because a selection was started with the mouse.
+    fn has_cursor(&self) -> bool {
+        self.is_focused || self.selection != Selection::default()
+    }
+
+    /// Moves the caret in the given direction, collapsing any existing selection.
+    fn move_caret(&mut self, direction: key::Named) {
+        if self.selection.is_empty() {
+            match direction {
+                key::Named::ArrowLeft => self.selection.select_left(&self.paragraph),
+                key::Named::ArrowRight => self.selection.select_right(&self.paragraph),
+                key::Named::ArrowUp => self.selection.select_up(&self.paragraph),
+                key::Named::ArrowDown => self.selection.select_down(&self.paragraph),
+                _ => return,
+            }
+        }
+
+        let caret = if matches!(direction, key::Named::ArrowLeft | key::Named::ArrowUp) {
+            self.selection.start
+        } else {
+            self.selection.end
+        };
+
+        self.selection.select_range(caret, caret);
+    }
+
+    /// The selected parts of the spans, keeping their flags, links and mentions.
+    fn selected_spans(&self) -> Vec<SignalSpan<'static, Link>> {
+        use unicode_segmentation::UnicodeSegmentation;
+
+        let Selection { start, end, .. } = self.selection;
+        let (mut line, mut index) = (0, 0);
+        let mut selected = vec![];
+
+        for span in &self.spans {
+            let mut text = String::new();
+
+            for grapheme in span.text.graphemes(true) {
+                if (start.line, start.index) <= (line, index)
+                    && (line, index) < (end.line, end.index)
+                {
+                    text.push_str(grapheme);
+                }
+
+                if grapheme.contains('\n') {
+                    line += 1;
+                    index = 0;
+                } else {
+                    index += 1;
+                }
+            }
+
+            if !text.is_empty() {
+                selected.push(SignalSpan {
+                    text: text.into(),
+                    ..span.clone()
+                });
+            }
+        }
+
+        selected
+    }
+
+    /// Finds the grapheme bounds of the word around the given grapheme of a line.
+    ///
+    /// Word boundaries follow UAX #29 on the full line, so they don't depend on how
+    /// the line is split into spans. Runs of non-whitespace containing `://` are
+    /// treated as a single word, so urls are selected whole.
+    fn word_bounds(&self, line: usize, index: usize) -> (usize, usize) {
+        use unicode_segmentation::UnicodeSegmentation;
+
+        let Some(value) = self
+            .paragraph
+            .buffer()
+            .lines
+            .get(line)
+            .map(|line| line.text())
+        else {
+            return (index, index);
+        };
+
+        let mut words = vec![];
+        let mut start = 0;
+
+        for word in value.split_word_bounds() {
+            let end = start + word.graphemes(true).count();
+            words.push((word, start, end));
+            start = end;
+        }
+
+        let Some(position) = words
+            .iter()
+            .position(|(_, _, end)| index < *end)
+            .or_else(|| words.len().checked_sub(1))
+        else {
+            return (index, index);
+        };
+
+        let is_blank = |word: &str| word.chars().all(char::is_whitespace);
+
+        if is_blank(words[position].0) {
+            return (words[position].1, words[position].2);
+        }
+
+        let first = words[..position]
+            .iter()
+            .rposition(|(word, ..)| is_blank(word))
+            .map_or(0, |blank| blank + 1);
+        let last = words[position..]
+            .iter()
+            .position(|(word, ..)| is_blank(word))
+            .map_or(words.len(), |blank| blank + position)
+            - 1;
+
+        if words[first..=last]
+            .iter()
+            .map(|(word, ..)| *word)
+            .collect::<String>()
+            .contains("://")
+        {
+            (words[first].1, words[last].2)
+        } else {
+            (words[position].1, words[position].2)
+        }
+    }
+
+    fn select_word(&mut self, line: usize, index: usize) {
+        let (start, end) = self.word_bounds(line, index);
+        let start = SelectionEnd { line, index: start };
+        let end = SelectionEnd { line, index: end };
+
+        self.selection.select_range(start, end);
+        self.word_anchor = Some((start, end));
+    }
+
+    /// Extends the selection started by [`Self::select_word`] by whole words.
+    fn change_selection_by_word(&mut self, line: usize, index: usize) {
+        let Some((anchor_start, anchor_end)) = self.word_anchor else {
+            return;
+        };
+
+        let (start, end) = self.word_bounds(line, index);
+
+        if (line, index) < (anchor_start.line, anchor_start.index) {
+            self.selection
+                .select_range(SelectionEnd { line, index: start }, anchor_end);
+        } else {
+            self.selection
+                .select_range(anchor_start, SelectionEnd { line, index: end });
+        }
+    }
+
     fn grapheme_line_and_index(&self, point: Point) -> Option<(usize, usize)> {
         let cursor = self.paragraph.buffer().hit(point.x, point.y)?;
 
@@ -187,22 +420,83 @@ impl<Link: Clone> State<Link> {
         ))
     }
 
-    fn selection_end_points(&self) -> (usize, Point, Point) {
+    /// The rectangles covering the selection, one for each visual line, measured from the
+    /// laid out glyphs so wrapped lines and lines of different heights are covered exactly.
+    fn selection_bounds(&self, width: f32) -> Vec<Rectangle> {
+        use unicode_segmentation::UnicodeSegmentation;
+
         let Selection { start, end, .. } = self.selection;
 
-        let (start_row, start_position) = self
-            .grapheme_position(start.line, start.index)
-            .unwrap_or_default();
+        self.paragraph
+            .buffer()
+            .layout_runs()
+            .filter(|run| (start.line..=end.line).contains(&run.line_i))
+            .filter_map(|run| {
+                let from = if run.line_i == start.line {
+                    start.index
+                } else {
+                    0
+                };
+                let to = if run.line_i == end.line {
+                    end.index
+                } else {
+                    usize::MAX
+                };
+
+                let graphemes = run
+                    .text
+                    .grapheme_indices(true)
+                    .map(|(i, _)| i)
+                    .collect::<Vec<_>>();
+                let grapheme_at = |byte| graphemes.partition_point(|&i| i < byte);
 
-        let (end_row, end_position) = self
-            .grapheme_position(end.line, end.index)
-            .unwrap_or_default();
+                let mut left = f32::INFINITY;
+                let mut right = f32::NEG_INFINITY;
 
-        (
-            end_row.saturating_sub(start_row) + 1,
-            start_position,
-            end_position,
-        )
+                for glyph in run.glyphs {
+                    let first = grapheme_at(glyph.start);
+                    let last = grapheme_at(glyph.end).max(first + 1);
+
+                    let selected_first = first.max(from);
+                    let selected_last = last.min(to);
+                    if selected_first >= selected_last {
+                        continue;
+                    }
+
+                    // the graphemes of a ligature share its advance
+                    let count = (last - first) as f32;
+                    let mut x0 = (selected_first - first) as f32 / count * glyph.w;
+                    let mut x1 = (selected_last - first) as f32 / count * glyph.w;
+
+                    if glyph.level.is_rtl() {
+                        (x0, x1) = (glyph.w - x1, glyph.w - x0);
+                    }
+
+                    left = left.min(glyph.x + x0);
+                    right = right.max(glyph.x + x1);
+                }
+
+                // a selection going on past the run covers the rest of its visual line, so
+                // selected line breaks and empty lines show
+                let run_end =
+                    grapheme_at(run.glyphs.iter().map(|glyph| glyph.end).max().unwrap_or(0));
+                if to > run_end {
+                    left = left.min(if run.glyphs.is_empty() {
+                        0.0
+                    } else {
+                        run.line_w
+                    });
+                    right = width;
+                }
+
+                (left < right).then(|| Rectangle {
+                    x: left,
+                    y: run.line_top,
+                    width: right - left,
+                    height: run.line_height,
+                })
+            })
+            .collect()
     }
 
     fn grapheme_position(&self, line: usize, index: usize) -> Option<(usize, Point)> {
@@ -292,6 +586,20 @@ impl<Link: Clone> State<Link> {
     }
 }
 
+impl<Link> Focusable for State<Link> {
+    fn is_focused(&self) -> bool {
+        self.is_focused
+    }
+
+    fn focus(&mut self) {
+        self.is_focused = true;
+    }
+
+    fn unfocus(&mut self) {
+        self.is_focused = false;
+    }
+}
+
 impl<Link, Message> Widget<Message, Theme, Renderer> for SignalRich<'_, Link, Message>
 where
     Link: Clone + 'static,
@@ -310,6 +618,9 @@ where
             dragging: None,
             last_click: None,
             keyboard_modifiers: keyboard::Modifiers::default(),
+            is_focused: false,
+            hovered_link: None,
+            word_anchor: None,
         })
     }
 
@@ -326,8 +637,21 @@ where
         renderer: &Renderer,
         limits: &layout::Limits,
     ) -> layout::Node {
+        let state = tree.state.downcast_mut::<State<Link>>();
+
+        if let Some(revealed) = self.revealed_spoilers
+            && (revealed.len() != state.revealed_spoilers.len()
+                || revealed
+                    .iter()
+                    .any(|tag| !state.revealed_spoilers.contains(tag)))
+        {
+            state.revealed_spoilers = revealed.to_vec();
+            // the spans are shaped again with the spoilers revealed
+            state.spans.clear();
+        }
+
         layout(
-            tree.state.downcast_mut::<State<Link>>(),
+            state,
             renderer,
             limits,
             self.width,
@@ -342,6 +666,18 @@ where
         )
     }
 
+    fn operate(
+        &mut self,
+        tree: &mut Tree,
+        layout: Layout<'_>,
+        _renderer: &Renderer,
+        operation: &mut dyn Operation,
+    ) {
+        let state = tree.state.downcast_mut::<State<Link>>();
+
+        operation.focusable(None, layout.bounds(), state);
+    }
+
     fn draw(
         &self,
         tree: &Tree,
@@ -377,7 +713,8 @@ where
         };
 
         for (index, span) in self.spans.iter().enumerate() {
-            let link_hovered = self.on_link_click.is_some() && Some(index) == self.hovered_link;
+            let link_hovered = (self.on_link_click.is_some() || self.on_link_hover.is_some())
+                && Some(index) == state.hovered_link;
             let mention_hovered =
                 self.on_mention_click.is_some() && Some(index) == self.hovered_mention;
             let spoiler_hovered = span
@@ -483,43 +820,10 @@ where
         if !state.selection.is_empty() {
             let bounds = layout.bounds();
 
-            let (rows, mut start, mut end) = state.selection_end_points();
-            start += Vector::new(bounds.x, bounds.y);
-            end += Vector::new(bounds.x, bounds.y);
-
-            let line_height = self
-                .line_height
-                .to_absolute(self.size.unwrap_or_else(|| renderer.default_size()))
-                .0;
-
-            let baseline_y = bounds.y
-                + (((start.y - bounds.y) * 10.0).ceil() / 10.0 / line_height).floor() * line_height;
-
-            for row in 0..rows {
-                let (x, width) = if row == 0 {
-                    (
-                        start.x,
-                        if rows == 1 {
-                            end.x.min(bounds.x + bounds.width) - start.x
-                        } else {
-                            bounds.x + bounds.width - start.x
-                        },
-                    )
-                } else if row == rows - 1 {
-                    (bounds.x, end.x - bounds.x)
-                } else {
-                    (bounds.x, bounds.width)
-                };
-                let y = baseline_y + row as f32 * line_height;
-
+            for selection in state.selection_bounds(bounds.width) {
                 renderer.fill_quad(
                     Quad {
-                        bounds: Rectangle {
-                            x,
-                            y,
-                            width,
-                            height: line_height,
-                        },
+                        bounds: selection + Vector::new(bounds.x, bounds.y),
                         snap: true,
                         ..Default::default()
                     },
@@ -528,6 +832,37 @@ where
             }
         }
 
+        if state.is_focused && state.selection.is_empty() {
+            let bounds = layout.bounds();
+            let caret = state.selection.start;
+
+            let line_height = self
+                .line_height
+                .to_absolute(self.size.unwrap_or_else(|| renderer.default_size()))
+                .0;
+
+            let position = state
+                .grapheme_position(caret.line, caret.index)
+                .map(|(_, position)| position)
+                .unwrap_or_default();
+
+            renderer.fill_quad(
+                Quad {
+                    bounds: Rectangle {
+                        x: bounds.x + position.x,
+                        y: bounds.y
+                            + ((position.y * 10.0).ceil() / 10.0 / line_height).floor()
+                                * line_height,
+                        width: 1.0,
+                        height: line_height,
+                    },
+                    snap: true,
+                    ..Default::default()
+                },
+                style.color.unwrap_or(defaults.text_color),
+            );
+        }
+
         draw(
             renderer,
             defaults,
@@ -557,10 +892,16 @@ where
             && state.selection == Selection::default()
             && state.dragging.is_none()
         {
+            if state.hovered_link.take().is_some()
+                && let Some(on_link_hovered) = self.on_link_hover.as_deref()
+            {
+                shell.publish(on_link_hovered(None));
+            }
+
             return;
         }
 
-        let link_was_hovered = self.hovered_link;
+        let link_was_hovered = state.hovered_link;
         let mention_was_hovered = self.hovered_mention;
         let spoiler_was_hovered = self.hovered_spoiler;
         let selection_before = state.selection;
@@ -570,8 +911,7 @@ where
         self.hovered_spoiler = None;
 
         if let Some(position) = click_position
-            && let Some(index) = state.paragraph.hit_span(position)
-            && let Some(span) = self.spans.get(index)
+            && let Some(SpanHit { index, span, .. }) = state.span_at(position)
         {
             if span.spoiler()
                 && span
@@ -586,6 +926,18 @@ where
             }
         }
 
+        if state.hovered_link != self.hovered_link {
+            state.hovered_link = self.hovered_link;
+
+            if let Some(on_link_hovered) = self.on_link_hover.as_deref() {
+                shell.publish(on_link_hovered(
+                    self.hovered_link
+                        .and_then(|index| self.spans.get(index))
+                        .and_then(|span| span.link.clone()),
+                ));
+            }
+        }
+
         match event {
             Event::Mouse(mouse::Event::ButtonPressed(mouse::Button::Left))
             | Event::Touch(touch::Event::FingerPressed { .. }) => {
@@ -605,7 +957,24 @@ where
 
                     let (line, index) = state.grapheme_line_and_index(position).unwrap_or((0, 0));
 
+                    // mouse clicks wrap around from triple to double, so a quadruple
+                    // click is a double click following a triple click
+                    let quadruple = matches!(click.kind(), click::Kind::Double)
+                        && state
+                            .last_click
+                            .is_some_and(|last| matches!(last.kind(), click::Kind::Triple));
+
+                    // selecting words and lines takes the clicks, so they don't also act on
+                    // what holds the text
+                    if !matches!(click.kind(), click::Kind::Single) {
+                        shell.capture_event();
+                    }
+
                     match click.kind() {
+                        click::Kind::Double if quadruple => {
+                            state.selection.select_all(&state.paragraph);
+                            state.dragging = None;
+                        }
                         click::Kind::Single => {
                             let new_end = SelectionEnd { line, index };
 
@@ -618,7 +987,7 @@ where
                             state.dragging = Some(Dragging::Grapheme);
                         }
                         click::Kind::Double => {
-                            state.selection.select_word(line, index, &state.paragraph);
+                            state.select_word(line, index);
                             state.dragging = Some(Dragging::Word);
                         }
                         click::Kind::Triple => {
@@ -628,10 +997,12 @@ where
                     }
 
                     state.last_click = Some(click);
+                    state.is_focused = true;
 
                     shell.capture_event();
                 } else {
                     state.selection = Selection::default();
+                    state.is_focused = false;
                 }
             }
             Event::Mouse(mouse::Event::ButtonReleased(mouse::Button::Left))
@@ -666,6 +1037,10 @@ where
                         Some(tag) if Some(tag) == self.hovered_spoiler => {
                             state.revealed_spoilers.push(tag);
 
+                            if let Some(on_spoiler_reveal) = self.on_spoiler_reveal.as_deref() {
+                                shell.publish(on_spoiler_reveal(tag));
+                            }
+
                             refresh_spans(
                                 state,
                                 layout.bounds().size(),
@@ -700,7 +1075,7 @@ where
 
                             state.selection.change_selection(new_end);
                         }
-                        Dragging::Word => {}
+                        Dragging::Word => state.change_selection_by_word(line, index),
                         Dragging::Line => {
                             state
                                 .selection
@@ -720,16 +1095,14 @@ where
                     shell.capture_event();
                 }
                 keyboard::Key::Character("a")
-                    if state.keyboard_modifiers.command()
-                        && state.selection != Selection::default() =>
+                    if state.keyboard_modifiers.command() && state.has_cursor() =>
                 {
                     state.selection.select_all(&state.paragraph);
 
                     shell.capture_event();
                 }
                 keyboard::Key::Named(key::Named::Home)
-                    if state.keyboard_modifiers.shift()
-                        && state.selection != Selection::default() =>
+                    if state.keyboard_modifiers.shift() && state.has_cursor() =>
                 {
                     if state.keyboard_modifiers.jump() {
                         state.selection.select_beginning();
@@ -740,8 +1113,7 @@ where
                     shell.capture_event();
                 }
                 keyboard::Key::Named(key::Named::End)
-                    if state.keyboard_modifiers.shift()
-                        && state.selection != Selection::default() =>
+                    if state.keyboard_modifiers.shift() && state.has_cursor() =>
                 {
                     if state.keyboard_modifiers.jump() {
                         state.selection.select_end(&state.paragraph);
@@ -752,8 +1124,7 @@ where
                     shell.capture_event();
                 }
                 keyboard::Key::Named(key::Named::ArrowLeft)
-                    if state.keyboard_modifiers.shift()
-                        && state.selection != Selection::default() =>
+                    if state.keyboard_modifiers.shift() && state.has_cursor() =>
                 {
                     if state.keyboard_modifiers.macos_command() {
                         state.selection.select_line_beginning();
@@ -766,8 +1137,7 @@ where
                     shell.capture_event();
                 }
                 keyboard::Key::Named(key::Named::ArrowRight)
-                    if state.keyboard_modifiers.shift()
-                        && state.selection != Selection::default() =>
+                    if state.keyboard_modifiers.shift() && state.has_cursor() =>
                 {
                     if state.keyboard_modifiers.macos_command() {
                         state.selection.select_line_end(&state.paragraph);
@@ -780,8 +1150,7 @@ where
                     shell.capture_event();
                 }
                 keyboard::Key::Named(key::Named::ArrowUp)
-                    if state.keyboard_modifiers.shift()
-                        && state.selection != Selection::default() =>
+                    if state.keyboard_modifiers.shift() && state.has_cursor() =>
                 {
                     if state.keyboard_modifiers.macos_command() {
                         state.selection.select_beginning();
@@ -794,8 +1163,7 @@ where
                     shell.capture_event();
                 }
                 keyboard::Key::Named(key::Named::ArrowDown)
-                    if state.keyboard_modifiers.shift()
-                        && state.selection != Selection::default() =>
+                    if state.keyboard_modifiers.shift() && state.has_cursor() =>
                 {
                     if state.keyboard_modifiers.macos_command() {
                         state.selection.select_end(&state.paragraph);
@@ -807,9 +1175,20 @@ where
 
                     shell.capture_event();
                 }
+                keyboard::Key::Named(
+                    direction @ (key::Named::ArrowLeft
+                    | key::Named::ArrowRight
+                    | key::Named::ArrowUp
+                    | key::Named::ArrowDown),
+                ) if state.is_focused => {
+                    state.move_caret(direction);
+
+                    shell.capture_event();
+                }
                 keyboard::Key::Named(key::Named::Escape) => {
                     state.dragging = None;
                     state.selection = Selection::default();
+                    state.is_focused = false;
 
                     state.keyboard_modifiers = keyboard::Modifiers::default();
 
@@ -825,6 +1204,12 @@ where
             _ => {}
         }
 
+        if selection_before != state.selection
+            && let Some(on_selection) = self.on_selection.as_deref()
+        {
+            shell.publish(on_selection(state.selected_spans()));
+        }
+
         if link_was_hovered != self.hovered_link
             || spoiler_was_hovered != self.hovered_spoiler
             || mention_was_hovered != self.hovered_mention